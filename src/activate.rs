@@ -25,20 +25,16 @@ pub fn activate(dir: impl AsRef<Path>, no_r_environment: bool) -> Result<(), Act
     let (activate_source_path, rvr_source_path) = scripts_as_paths(is_home);
 
     write_activate_file(dir, is_home)?;
-    add_rprofile_source_call(dir, activate_source_path)?;
+    add_rprofile_source_call(dir, &activate_source_path)?;
     write_rvr_file(dir)?;
     if !no_r_environment {
-        add_rprofile_source_call(dir, rvr_source_path)?;
+        add_rprofile_source_call(dir, &rvr_source_path)?;
     }
     Ok(())
 }
 
-fn add_rprofile_source_call(
-    dir: impl AsRef<Path>,
-    source_file: impl AsRef<Path>,
-) -> Result<(), io::Error> {
+fn add_rprofile_source_call(dir: impl AsRef<Path>, source_file: &str) -> Result<(), io::Error> {
     let path = dir.as_ref().join(".Rprofile");
-    let source_file = source_file.as_ref();
 
     let content = if path.exists() {
         read_to_string(&path)?
@@ -46,10 +42,10 @@ fn add_rprofile_source_call(
         String::new()
     };
 
-    if content.contains(&*source_file.to_string_lossy()) {
+    if content.contains(source_file) {
         return Ok(());
     }
-    let source_str = format!(r#"source("{}")"#, source_file.display());
+    let source_str = source_call(source_file);
     let new_content = format!("{}\n{}", source_str, content);
     write(path, new_content)?;
 
@@ -68,10 +64,11 @@ pub fn deactivate(dir: impl AsRef<Path>) -> Result<(), ActivateError> {
     let (activate_path, rvr_path) = scripts_as_paths(is_home);
 
     let content = read_to_string(&rprofile_path)?;
+    let source_calls = [source_call(&activate_path), source_call(&rvr_path)];
     let new_content = content
         .lines()
-        .filter(|line| line != &format!(r#"source("{}")"#, activate_path.display()))
-        .filter(|line| line != &format!(r#"source("{}")"#, rvr_path.display()))
+        // Older versions of rv could write the path with backslashes on Windows
+        .filter(|line| !source_calls.contains(&line.replace('\\', "/")))
         .collect::<Vec<_>>()
         .join("\n");
 
@@ -86,15 +83,23 @@ fn is_home_dir(dir: impl AsRef<Path>) -> bool {
         .unwrap_or(false)
 }
 
-fn scripts_as_paths(is_home: bool) -> (PathBuf, PathBuf) {
+/// The paths to the scripts as used in the .Rprofile.
+/// They always use forward slashes: a backslash would be an escape in the R string.
+fn scripts_as_paths(is_home: bool) -> (String, String) {
     if is_home {
-        let home = PathBuf::from("~");
-        (home.join(ACTIVATE_FILE_NAME), home.join(RVR_FILE_NAME))
+        (
+            format!("~/{ACTIVATE_FILE_NAME}"),
+            format!("~/{RVR_FILE_NAME}"),
+        )
     } else {
-        (ACTIVATE_FILE_NAME.into(), RVR_FILE_NAME.into())
+        (ACTIVATE_FILE_NAME.to_string(), RVR_FILE_NAME.to_string())
     }
 }
 
+fn source_call(source_file: &str) -> String {
+    format!(r#"source("{source_file}")"#)
+}
+
 fn write_activate_file(dir: impl AsRef<Path>, is_home: bool) -> Result<(), ActivateError> {
     let template = ACTIVATE_FILE_TEMPLATE.to_string();
     let global_wd_content = if is_home {
@@ -166,7 +171,7 @@ impl From<io::Error> for ActivateError {
 mod tests {
     use crate::activate::RVR_FILE_NAME;
 
-    use super::{ACTIVATE_FILE_NAME, activate};
+    use super::{ACTIVATE_FILE_NAME, activate, deactivate, scripts_as_paths};
 
     #[test]
     fn test_activation() {
@@ -176,4 +181,26 @@ mod tests {
         assert!(tmp_dir.path().join(RVR_FILE_NAME).exists());
         assert!(tmp_dir.path().join(".Rprofile").exists());
     }
+
+    #[test]
+    fn rprofile_paths_use_forward_slashes() {
+        for is_home in [true, false] {
+            let (activate_path, rvr_path) = scripts_as_paths(is_home);
+            assert!(!activate_path.contains('\\'), "{activate_path}");
+            assert!(!rvr_path.contains('\\'), "{rvr_path}");
+        }
+    }
+
+    #[test]
+    fn deactivate_removes_backslash_source_calls() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let rprofile = tmp_dir.path().join(".Rprofile");
+        fs_err::write(
+            &rprofile,
+            "source(\"rv\\scripts\\activate.R\")\nsource(\"rv/scripts/rvr.R\")\noptions(a = 1)",
+        )
+        .unwrap();
+        deactivate(&tmp_dir).unwrap();
+        assert_eq!(fs_err::read_to_string(&rprofile).unwrap(), "options(a = 1)");
+    }
 }
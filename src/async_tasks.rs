@@ -0,0 +1,217 @@
+//! Async versions of loading, resolving and syncing a project, for services embedding rv in
+//! an async runtime, eg to build environments on demand.
+//!
+//! rv itself is synchronous: downloads, git and R all block. Each task runs on the blocking
+//! thread pool of tokio so it doesn't block the runtime. A sync can be cancelled through the
+//! [`Cancellation`] given to it: like a first Ctrl+C in the CLI, the packages being installed
+//! are finished but nothing else is started.
+use std::error::Error;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::lockfile::LockfileError;
+use crate::sync::SyncError;
+use crate::{
+    Cancellation, Context, Lockfile, RCommandLookup, Resolution, ResolveMode, SyncChange,
+    SyncHandler,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum TaskError {
+    #[error("{0}")]
+    Load(Box<dyn Error + Send + Sync>),
+    #[error("Failed to resolve all dependencies:\n{0}")]
+    Resolution(String),
+    #[error(transparent)]
+    Sync(#[from] SyncError),
+    #[error(transparent)]
+    Lockfile(#[from] LockfileError),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error("The task did not complete: {0}")]
+    Join(#[from] tokio::task::JoinError),
+}
+
+impl Context {
+    /// Same as [`Context::new_with_cache_dir`] followed by [`Context::load_for_resolve_mode`]
+    pub async fn load_async(
+        config_file: PathBuf,
+        r_command_lookup: RCommandLookup,
+        cache_dir: Option<PathBuf>,
+        resolve_mode: ResolveMode,
+    ) -> Result<Self, TaskError> {
+        tokio::task::spawn_blocking(move || {
+            let mut context =
+                Context::new_with_cache_dir(&config_file, r_command_lookup, cache_dir.as_deref())
+                    .map_err(TaskError::Load)?;
+            context
+                .load_for_resolve_mode(resolve_mode)
+                .map_err(TaskError::Load)?;
+            Ok(context)
+        })
+        .await?
+    }
+
+    /// Resolves the dependencies of the project, returning what the lockfile would contain
+    pub async fn resolve_async(
+        self: Arc<Self>,
+        resolve_mode: ResolveMode,
+    ) -> Result<Lockfile, TaskError> {
+        tokio::task::spawn_blocking(move || {
+            let resolution = self.resolve(resolve_mode);
+            self.new_lockfile(resolution)
+        })
+        .await?
+    }
+
+    /// Resolves the dependencies and syncs the library with them, saving the lockfile if it is
+    /// enabled. Nothing is saved if the sync was cancelled.
+    pub async fn sync_async(
+        self: Arc<Self>,
+        resolve_mode: ResolveMode,
+        cancellation: Arc<Cancellation>,
+    ) -> Result<Vec<SyncChange>, TaskError> {
+        tokio::task::spawn_blocking(move || {
+            let resolution = self.resolve(resolve_mode);
+            if !resolution.is_success() {
+                return Err(TaskError::Resolution(failure_message(&resolution)));
+            }
+
+            let mut handler = SyncHandler::new(&self, None);
+            handler.set_uses_lockfile(self.config.use_lockfile());
+            handler.set_cancellation(Arc::clone(&cancellation));
+            let changes = handler.handle(&resolution.found, &self.r_cmd)?;
+            if cancellation.is_cancelled() || !self.config.use_lockfile() {
+                return Ok(changes);
+            }
+
+            let lockfile = self.new_lockfile(resolution)?;
+            let lockfile_path = self.lockfile_path();
+            if lockfile.packages().is_empty() {
+                if lockfile_path.exists() {
+                    fs_err::remove_file(lockfile_path)?;
+                }
+            } else if self.lockfile.as_ref() != Some(&lockfile) {
+                lockfile.save_with_url_templates(lockfile_path, self.config.url_templates())?;
+            }
+            Ok(changes)
+        })
+        .await?
+    }
+
+    fn new_lockfile(&self, resolution: Resolution) -> Result<Lockfile, TaskError> {
+        if !resolution.is_success() {
+            return Err(TaskError::Resolution(failure_message(&resolution)));
+        }
+        Ok(
+            Lockfile::from_resolved(&self.r_version.major_minor(), resolution.found)
+                .with_repositories(self.config.repositories()),
+        )
+    }
+}
+
+fn failure_message(resolution: &Resolution) -> String {
+    resolution
+        .failed
+        .iter()
+        .map(|d| format!("    {d}"))
+        .chain(resolution.req_error_messages())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TempCache;
+    use crate::{RepositoryDatabase, SystemInfo, Version};
+
+    const REPO_URL: &str = "https://async.test/repo";
+
+    fn project(dependencies: &str) -> (tempfile::TempDir, TempCache) {
+        let project_dir = tempfile::tempdir().unwrap();
+        let r_version: Version = "4.5".parse().unwrap();
+        let cache = TempCache::new(&r_version, SystemInfo::from_os_info()).unwrap();
+        let (db_path, _) = cache.local().get_package_db_entry(REPO_URL);
+        RepositoryDatabase::from_packages_str(
+            REPO_URL,
+            "Package: a\nVersion: 1.0.0\nDepends: b\nNeedsCompilation: no\n\nPackage: b\nVersion: 2.0.0\nNeedsCompilation: no\n",
+        )
+        .persist(&db_path)
+        .unwrap();
+
+        fs_err::write(
+            project_dir.path().join("rproject.toml"),
+            format!(
+                r#"[project]
+name = "async"
+r_version = "4.5"
+repositories = [{{ alias = "test", url = "{REPO_URL}" }}]
+dependencies = [{dependencies}]
+"#
+            ),
+        )
+        .unwrap();
+        (project_dir, cache)
+    }
+
+    fn runtime() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+    }
+
+    fn load(project_dir: &tempfile::TempDir, cache: &TempCache) -> Arc<Context> {
+        let context = runtime()
+            .block_on(Context::load_async(
+                project_dir.path().join("rproject.toml"),
+                RCommandLookup::Skip,
+                Some(cache.path().to_path_buf()),
+                ResolveMode::Default,
+            ))
+            .unwrap();
+        Arc::new(context)
+    }
+
+    #[test]
+    fn can_resolve_async() {
+        let (project_dir, cache) = project(r#""a""#);
+        let context = load(&project_dir, &cache);
+
+        let lockfile = runtime()
+            .block_on(context.resolve_async(ResolveMode::Default))
+            .unwrap();
+        let mut names: Vec<_> = lockfile
+            .packages()
+            .iter()
+            .map(|p| p.name.as_str())
+            .collect();
+        names.sort();
+        assert_eq!(names, vec!["a", "b"]);
+    }
+
+    #[test]
+    fn resolution_failures_are_errors() {
+        let (project_dir, cache) = project(r#""missing""#);
+        let context = load(&project_dir, &cache);
+
+        let err = runtime()
+            .block_on(context.resolve_async(ResolveMode::Default))
+            .unwrap_err();
+        assert!(matches!(err, TaskError::Resolution(ref m) if m.contains("missing")));
+    }
+
+    #[test]
+    fn cancelled_sync_changes_nothing() {
+        let (project_dir, cache) = project(r#""a""#);
+        let context = load(&project_dir, &cache);
+        let cancellation = Arc::new(Cancellation::default());
+        cancellation.cancel();
+
+        let changes = runtime()
+            .block_on(context.sync_async(ResolveMode::Default, cancellation))
+            .unwrap();
+        assert!(changes.is_empty());
+        assert!(!project_dir.path().join("rproject.lock").exists());
+    }
+}
@@ -0,0 +1,273 @@
+//! Self contained bundles of a project that can be installed without network access, eg on
+//! offline compute clusters.
+//! A bundle is a `.tar.gz` containing a manifest, the config and lockfile and optionally the
+//! library. Symlinks into the cache are resolved when bundling so the library is relocatable.
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use fs_err as fs;
+use serde::{Deserialize, Serialize};
+
+use crate::consts::{LIBRARY_ROOT_DIR_NAME, RV_DIR_NAME, STAGING_DIR_NAME};
+
+pub const BUNDLE_MANIFEST_FILENAME: &str = "rv-bundle.json";
+const BUNDLE_PROJECT_DIR: &str = "project";
+const BUNDLE_LIBRARY_DIR: &str = "library";
+const BUNDLE_FORMAT_VERSION: u32 = 1;
+
+/// The R installation the library was built with
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RRuntimeReference {
+    pub version: String,
+    pub r_home: PathBuf,
+}
+
+/// Describes what is in the bundle and where it needs to go
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BundleManifest {
+    pub format_version: u32,
+    pub rv_version: String,
+    pub r_version: String,
+    /// `{R_Version}/{arch}/{library_identifier}` of the machine that created the bundle.
+    /// The library can only be installed on a machine with the same value.
+    pub platform: String,
+    /// Where the library was, relative to the project directory if it was inside it
+    pub library_path: Option<PathBuf>,
+    /// Name and version of every package in the bundled library
+    pub packages: Vec<(String, String)>,
+    pub r_runtime: Option<RRuntimeReference>,
+}
+
+impl BundleManifest {
+    pub fn new(r_version: &str, platform: &str) -> Self {
+        Self {
+            format_version: BUNDLE_FORMAT_VERSION,
+            rv_version: env!("CARGO_PKG_VERSION").to_string(),
+            r_version: r_version.to_string(),
+            platform: platform.to_string(),
+            library_path: None,
+            packages: Vec::new(),
+            r_runtime: None,
+        }
+    }
+
+    pub fn has_library(&self) -> bool {
+        self.library_path.is_some()
+    }
+}
+
+/// The platform string of the current machine for the given R version
+pub fn bundle_platform(system_info: &crate::SystemInfo, r_version: [u32; 2]) -> String {
+    crate::cache::utils::get_current_system_path(system_info, r_version)
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().into_owned())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Creates the bundle at `output`.
+/// `project_files` are added at the root of the project (eg rproject.toml and rv.lock) and
+/// `library` is only added if the manifest has a library path.
+pub fn create_bundle(
+    output: &Path,
+    manifest: &BundleManifest,
+    project_files: &[&Path],
+    library: Option<&Path>,
+) -> Result<(), BundleError> {
+    let file = fs::File::create(output)?;
+    let mut builder = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+    // Library packages are usually symlinks to the cache, we want the actual files
+    builder.follow_symlinks(true);
+
+    let manifest_json = serde_json::to_vec_pretty(manifest).expect("valid json");
+    let mut header = tar::Header::new_gnu();
+    header.set_size(manifest_json.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder.append_data(
+        &mut header,
+        BUNDLE_MANIFEST_FILENAME,
+        manifest_json.as_slice(),
+    )?;
+
+    for path in project_files {
+        if let Some(name) = path.file_name() {
+            builder.append_path_with_name(path, Path::new(BUNDLE_PROJECT_DIR).join(name))?;
+        }
+    }
+
+    if let Some(library) = library {
+        for entry in fs::read_dir(library)? {
+            let entry = entry?;
+            if entry.file_name() == STAGING_DIR_NAME {
+                continue;
+            }
+            let name = Path::new(BUNDLE_LIBRARY_DIR).join(entry.file_name());
+            if entry.path().is_dir() {
+                builder.append_dir_all(name, entry.path())?;
+            } else {
+                builder.append_path_with_name(entry.path(), name)?;
+            }
+        }
+    }
+
+    builder.into_inner()?.finish()?;
+    Ok(())
+}
+
+/// Reads only the manifest of a bundle
+pub fn read_bundle_manifest(bundle: &Path) -> Result<BundleManifest, BundleError> {
+    let mut archive = tar::Archive::new(GzDecoder::new(fs::File::open(bundle)?));
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if entry.path()?.as_ref() == Path::new(BUNDLE_MANIFEST_FILENAME) {
+            let mut content = String::new();
+            entry.read_to_string(&mut content)?;
+            return Ok(serde_json::from_str(&content)?);
+        }
+    }
+    Err(BundleError::MissingManifest)
+}
+
+/// Where the library of the bundle should be installed in `project_dir`
+fn target_library_path(manifest: &BundleManifest, project_dir: &Path) -> Option<PathBuf> {
+    let path = manifest.library_path.as_ref()?;
+    if path.is_absolute() {
+        // The library was outside of the project: put it where rv would by default
+        Some(
+            project_dir
+                .join(RV_DIR_NAME)
+                .join(LIBRARY_ROOT_DIR_NAME)
+                .join(&manifest.platform),
+        )
+    } else {
+        Some(project_dir.join(path))
+    }
+}
+
+/// Unpacks a bundle in `project_dir`.
+/// The config and lockfile are only written if they don't already exist, unless `force` is set.
+/// The library is only installed if `platform` matches the one of the bundle, unless `force` is
+/// set, and replaces any existing library at the same location.
+/// Returns the manifest and where the library was installed, if the bundle had one.
+pub fn install_bundle(
+    bundle: &Path,
+    project_dir: &Path,
+    platform: &str,
+    force: bool,
+) -> Result<(BundleManifest, Option<PathBuf>), BundleError> {
+    let manifest = read_bundle_manifest(bundle)?;
+    if manifest.format_version > BUNDLE_FORMAT_VERSION {
+        return Err(BundleError::UnsupportedVersion(manifest.format_version));
+    }
+    if manifest.has_library() && manifest.platform != platform && !force {
+        return Err(BundleError::PlatformMismatch {
+            bundle: manifest.platform.clone(),
+            current: platform.to_string(),
+        });
+    }
+
+    // We unpack in the project so moving the library in place is a rename
+    fs::create_dir_all(project_dir.join(RV_DIR_NAME))?;
+    let tmp_dir = tempfile::tempdir_in(project_dir.join(RV_DIR_NAME))?;
+    let mut archive = tar::Archive::new(GzDecoder::new(fs::File::open(bundle)?));
+    archive.unpack(tmp_dir.path())?;
+
+    let project_files = tmp_dir.path().join(BUNDLE_PROJECT_DIR);
+    if project_files.is_dir() {
+        for entry in fs::read_dir(&project_files)? {
+            let entry = entry?;
+            let destination = project_dir.join(entry.file_name());
+            if force || !destination.exists() {
+                fs::copy(entry.path(), destination)?;
+            }
+        }
+    }
+
+    let library_path = target_library_path(&manifest, project_dir);
+    if let Some(library_path) = &library_path {
+        let bundled_library = tmp_dir.path().join(BUNDLE_LIBRARY_DIR);
+        if library_path.exists() {
+            fs::remove_dir_all(library_path)?;
+        }
+        if let Some(parent) = library_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        if bundled_library.is_dir() {
+            fs::rename(bundled_library, library_path)?;
+        } else {
+            // An empty library
+            fs::create_dir_all(library_path)?;
+        }
+    }
+
+    Ok((manifest, library_path))
+}
+
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum BundleError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error("Invalid bundle manifest: {0}")]
+    InvalidManifest(#[from] serde_json::Error),
+    #[error("Not an rv bundle: {BUNDLE_MANIFEST_FILENAME} not found")]
+    MissingManifest,
+    #[error("Bundle format version {0} is not supported by this version of rv")]
+    UnsupportedVersion(u32),
+    #[error(
+        "The bundle library was built for {bundle} but this machine is {current}. Use --force to install it anyway"
+    )]
+    PlatformMismatch { bundle: String, current: String },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn can_roundtrip_bundle() {
+        let source = tempfile::tempdir().unwrap();
+        let config = source.path().join("rproject.toml");
+        fs::write(&config, "[project]\n").unwrap();
+        let library = source.path().join("rv/library/4.4/x86_64/jammy");
+        fs::create_dir_all(library.join("rlang")).unwrap();
+        fs::write(library.join("rlang").join("DESCRIPTION"), "Package: rlang").unwrap();
+        // A package in the cache symlinked in the library
+        let cache = source.path().join("cache");
+        fs::create_dir_all(cache.join("cli")).unwrap();
+        fs::write(cache.join("cli").join("DESCRIPTION"), "Package: cli").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(cache.join("cli"), library.join("cli")).unwrap();
+
+        let mut manifest = BundleManifest::new("4.4", "4.4/x86_64/jammy");
+        manifest.library_path = Some(PathBuf::from("rv/library/4.4/x86_64/jammy"));
+        manifest.packages = vec![("rlang".to_string(), "1.1.4".to_string())];
+        let bundle = source.path().join("bundle.tar.gz");
+        create_bundle(&bundle, &manifest, &[&config], Some(&library)).unwrap();
+        assert_eq!(read_bundle_manifest(&bundle).unwrap(), manifest);
+
+        let target = tempfile::tempdir().unwrap();
+        assert!(matches!(
+            install_bundle(&bundle, target.path(), "4.4/aarch64/jammy", false),
+            Err(BundleError::PlatformMismatch { .. })
+        ));
+        let (_, library_path) =
+            install_bundle(&bundle, target.path(), "4.4/x86_64/jammy", false).unwrap();
+        let library_path = library_path.unwrap();
+        assert_eq!(
+            library_path,
+            target.path().join("rv/library/4.4/x86_64/jammy")
+        );
+        assert!(library_path.join("rlang").join("DESCRIPTION").is_file());
+        #[cfg(unix)]
+        {
+            assert!(!library_path.join("cli").is_symlink());
+            assert!(library_path.join("cli").join("DESCRIPTION").is_file());
+        }
+        assert!(target.path().join("rproject.toml").is_file());
+    }
+}
@@ -0,0 +1,169 @@
+//! Binaries built with different options (configure args, env vars, compilers) are not
+//! interchangeable. Their folder in the cache gets a short hash of those options as suffix,
+//! eg `1.2.0-3f2a9c0b1d`, so switching options doesn't reuse a binary built with other ones.
+//! Packages built with the default options and binaries downloaded from a repository keep the
+//! plain folder.
+use std::collections::{BTreeMap, HashMap};
+
+use crate::cache::utils::hash_string;
+use crate::consts::BUILD_ENV_VARS;
+use crate::package::PackageType;
+
+/// Only changes how many jobs make runs, not what gets built
+const IGNORED_BUILD_ENV_VARS: [&str; 1] = ["MAKEFLAGS"];
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct BuildKeys {
+    /// For the packages having configure args or env vars in the config
+    packages: HashMap<String, String>,
+    /// For all other packages built from source, only set if the environment has some build
+    /// variables set
+    default: Option<String>,
+}
+
+impl BuildKeys {
+    /// `configure_args` and `env_vars` are the ones from the config for the current system,
+    /// by package name. The compilers are part of the key through `CC`/`CXX`: the ones
+    /// configured by R are not looked up so we don't need to start R.
+    pub(crate) fn new(
+        configure_args: &HashMap<String, Vec<String>>,
+        env_vars: &HashMap<String, HashMap<String, String>>,
+    ) -> Self {
+        let process_env: BTreeMap<_, _> = BUILD_ENV_VARS
+            .iter()
+            .filter(|k| !IGNORED_BUILD_ENV_VARS.contains(k))
+            .filter_map(|k| std::env::var(k).ok().map(|v| (k.to_string(), v)))
+            .collect();
+        Self::from_parts(&process_env, configure_args, env_vars)
+    }
+
+    fn from_parts(
+        process_env: &BTreeMap<String, String>,
+        configure_args: &HashMap<String, Vec<String>>,
+        env_vars: &HashMap<String, HashMap<String, String>>,
+    ) -> Self {
+        let no_args = Vec::new();
+        let no_env_vars = HashMap::new();
+        let mut packages = HashMap::new();
+
+        for name in configure_args.keys().chain(env_vars.keys()) {
+            let args = configure_args.get(name).unwrap_or(&no_args);
+            let vars = env_vars.get(name).unwrap_or(&no_env_vars);
+            if args.is_empty() && vars.is_empty() {
+                continue;
+            }
+            // Package env vars override the process ones
+            let mut all_vars = process_env.clone();
+            all_vars.extend(vars.iter().map(|(k, v)| (k.clone(), v.clone())));
+            packages.insert(name.clone(), fingerprint(&all_vars, args));
+        }
+
+        let default = (!process_env.is_empty()).then(|| fingerprint(process_env, &no_args));
+        Self { packages, default }
+    }
+
+    /// The suffix to add to the binary folder of that package, if any. A downloaded binary was
+    /// not built with the build variables of the environment so they don't change its key.
+    pub(crate) fn get(&self, package_name: &str, kind: PackageType) -> Option<&str> {
+        self.packages
+            .get(package_name)
+            .or(match kind {
+                PackageType::Source => self.default.as_ref(),
+                PackageType::Binary => None,
+            })
+            .map(|s| s.as_str())
+    }
+
+    /// Appends the key of the package to a folder name
+    pub(crate) fn apply(
+        &self,
+        folder: &str,
+        package_name: Option<&str>,
+        kind: PackageType,
+    ) -> String {
+        match package_name.and_then(|name| self.get(name, kind)) {
+            Some(key) => format!("{folder}-{key}"),
+            None => folder.to_string(),
+        }
+    }
+}
+
+fn fingerprint(env_vars: &BTreeMap<String, String>, configure_args: &[String]) -> String {
+    let mut input = String::new();
+    for (k, v) in env_vars {
+        input.push_str(&format!("{k}={v}\n"));
+    }
+    for arg in configure_args {
+        input.push_str(&format!("arg:{arg}\n"));
+    }
+    hash_string(&input)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn configure_args() -> HashMap<String, Vec<String>> {
+        HashMap::from([("sf".to_string(), vec!["--with-proj".to_string()])])
+    }
+
+    fn env_vars() -> HashMap<String, HashMap<String, String>> {
+        HashMap::from([(
+            "arrow".to_string(),
+            HashMap::from([("LIBARROW_MINIMAL".to_string(), "false".to_string())]),
+        )])
+    }
+
+    #[test]
+    fn default_options_have_no_key() {
+        let keys = BuildKeys::from_parts(&BTreeMap::new(), &HashMap::new(), &HashMap::new());
+        assert_eq!(keys, BuildKeys::default());
+        assert_eq!(
+            keys.apply("1.0.0", Some("dplyr"), PackageType::Source),
+            "1.0.0"
+        );
+    }
+
+    #[test]
+    fn packages_with_options_get_a_key() {
+        let keys = BuildKeys::from_parts(&BTreeMap::new(), &configure_args(), &env_vars());
+        assert!(keys.get("sf", PackageType::Source).is_some());
+        assert!(keys.get("arrow", PackageType::Source).is_some());
+        assert_ne!(
+            keys.get("sf", PackageType::Source),
+            keys.get("arrow", PackageType::Source)
+        );
+        assert_eq!(keys.get("dplyr", PackageType::Source), None);
+        assert_eq!(
+            keys.apply("1.0.0", Some("sf"), PackageType::Source),
+            format!("1.0.0-{}", keys.get("sf", PackageType::Source).unwrap())
+        );
+        assert_eq!(keys.apply("1.0.0", None, PackageType::Source), "1.0.0");
+
+        // Same options, same key
+        let again = BuildKeys::from_parts(&BTreeMap::new(), &configure_args(), &env_vars());
+        assert_eq!(keys, again);
+    }
+
+    #[test]
+    fn build_env_vars_change_all_keys() {
+        let process_env = BTreeMap::from([("CFLAGS".to_string(), "-O3".to_string())]);
+        let plain = BuildKeys::from_parts(&BTreeMap::new(), &configure_args(), &HashMap::new());
+        let keys = BuildKeys::from_parts(&process_env, &configure_args(), &HashMap::new());
+        assert!(keys.get("dplyr", PackageType::Source).is_some());
+        assert_ne!(
+            keys.get("sf", PackageType::Source),
+            plain.get("sf", PackageType::Source)
+        );
+        assert_ne!(
+            keys.get("sf", PackageType::Source),
+            keys.get("dplyr", PackageType::Source)
+        );
+        // Downloaded binaries are shared with every environment
+        assert_eq!(keys.get("dplyr", PackageType::Binary), None);
+        assert_eq!(
+            keys.get("sf", PackageType::Binary),
+            keys.get("sf", PackageType::Source)
+        );
+    }
+}
@@ -9,14 +9,15 @@ use url::Url;
 use walkdir::WalkDir;
 
 use crate::cache::InstallationStatus;
+use crate::cache::build_keys::BuildKeys;
 use crate::cache::utils::{
     get_current_system_path, get_packages_timeout, get_user_cache_dir, hash_string,
 };
 use crate::consts::{BUILD_LOG_FILENAME, BUILT_FROM_SOURCE_FILENAME};
 use crate::lockfile::Source;
-use crate::package::{BuiltinPackages, Package, get_builtin_versions_from_library};
+use crate::package::{BuiltinPackages, Package, PackageType, get_builtin_versions_from_library};
 use crate::system_req::{SysReqError, get_system_requirements};
-use crate::{RInstall, SystemInfo, Version};
+use crate::{RInstall, ResolvedDependency, SystemInfo, Version};
 
 #[derive(Debug, Clone)]
 pub struct PackagePaths {
@@ -40,6 +41,8 @@ pub struct DiskCache {
     /// Defaults to 3600s (1 hour)
     packages_timeout: u64,
     readonly: bool,
+    /// Suffixes of the binary folders of packages built with non-default options
+    build_keys: BuildKeys,
     // TODO: check if it's worth keeping a hashmap of repo_url -> encoded
     // TODO: or if the overhead is the same as base64 directly
 }
@@ -67,6 +70,10 @@ impl DiskCache {
         self
     }
 
+    pub(crate) fn set_build_keys(&mut self, build_keys: BuildKeys) {
+        self.build_keys = build_keys;
+    }
+
     pub(crate) fn new_in_dir(
         r_version: &Version,
         system_info: SystemInfo,
@@ -78,6 +85,7 @@ impl DiskCache {
             r_version: r_version.major_minor(),
             packages_timeout: get_packages_timeout(),
             readonly: false,
+            build_keys: BuildKeys::default(),
         })
     }
 
@@ -97,12 +105,19 @@ impl DiskCache {
         base_path.join(crate::consts::PACKAGE_DB_FILENAME)
     }
 
-    /// Gets the folder where a binary package would be located.
+    /// Gets the folder where a binary package would be located, depending on whether it is
+    /// downloaded or built from source.
     /// The folder may or may not exist depending on whether it's in the cache
-    fn get_binary_package_path(&self, repo_url: &str, name: &str, version: &str) -> PathBuf {
+    fn get_binary_package_path(
+        &self,
+        repo_url: &str,
+        name: &str,
+        version: &str,
+        kind: PackageType,
+    ) -> PathBuf {
         self.get_repo_root_binary_dir(repo_url)
             .join(name)
-            .join(version)
+            .join(self.build_keys.apply(version, Some(name), kind))
     }
 
     /// Gets the folder where the R build package stdout+stderr output should be stored
@@ -122,7 +137,7 @@ impl DiskCache {
                 hash_string(path.as_os_str().to_string_lossy().as_ref()),
                 sha.clone(),
             ),
-            Source::Builtin { .. } => unreachable!(),
+            Source::Builtin { .. } | Source::Provided { .. } => unreachable!(),
         };
 
         let mut p = self
@@ -142,6 +157,16 @@ impl DiskCache {
         p.join(BUILD_LOG_FILENAME)
     }
 
+    /// Where the build log of a resolved package is: only packages from repositories have their
+    /// version in the path
+    pub(crate) fn build_log_path_for(&self, dep: &ResolvedDependency) -> PathBuf {
+        if dep.source.is_repo() {
+            self.get_build_log_path(&dep.source, Some(&dep.name), Some(&dep.version.original))
+        } else {
+            self.get_build_log_path(&dep.source, None, None)
+        }
+    }
+
     /// Gets the folder where extracted source would be located
     /// The folder may or may not exist depending on whether it's in the cache
     fn get_source_package_path(&self, repo_url: &str, name: &str, version: &str) -> PathBuf {
@@ -194,35 +219,45 @@ impl DiskCache {
         (path, false)
     }
 
+    /// `kind` is whether the binary is downloaded or built from source: only the latter depends
+    /// on the build options
     pub(crate) fn get_package_paths(
         &self,
         source: &Source,
         pkg_name: Option<&str>,
         version: Option<&str>,
+        kind: PackageType,
     ) -> PackagePaths {
         match source {
             Source::Git { git, sha, .. } => PackagePaths {
                 source: self.get_git_clone_path(git.url()),
-                binary: self.get_repo_root_binary_dir(git.url()).join(&sha[..10]),
+                binary: self
+                    .get_repo_root_binary_dir(git.url())
+                    .join(self.build_keys.apply(&sha[..10], pkg_name, kind)),
             },
             Source::RUniverse { git, sha, .. } => PackagePaths {
                 source: self.get_git_clone_path(git.url()),
-                binary: self.get_repo_root_binary_dir(git.url()).join(&sha[..10]),
+                binary: self
+                    .get_repo_root_binary_dir(git.url())
+                    .join(self.build_keys.apply(&sha[..10], pkg_name, kind)),
             },
             Source::Url { url, sha } => PackagePaths {
                 source: self.get_url_download_path(url).join(&sha[..10]),
-                binary: self.get_repo_root_binary_dir(url.as_str()).join(&sha[..10]),
+                binary: self
+                    .get_repo_root_binary_dir(url.as_str())
+                    .join(self.build_keys.apply(&sha[..10], pkg_name, kind)),
             },
             Source::Repository { repository } => {
                 let name = pkg_name.unwrap();
                 let ver = version.unwrap();
                 PackagePaths {
                     source: self.get_source_package_path(repository.as_str(), name, ver),
-                    binary: self.get_binary_package_path(repository.as_str(), name, ver),
+                    binary: self.get_binary_package_path(repository.as_str(), name, ver, kind),
                 }
             }
             Source::Local { .. } => unreachable!("Not used for local paths"),
             Source::Builtin { .. } => unreachable!("Not used for builtin packages"),
+            Source::Provided { .. } => unreachable!("Not used for provided packages"),
         }
     }
 
@@ -233,20 +268,23 @@ impl DiskCache {
         pkg_name: &str,
         version: &str,
         source: &Source,
+        kind: PackageType,
     ) -> InstallationStatus {
         let (source_path, binary_path) = match source {
             Source::Git { .. } | Source::Url { .. } | Source::RUniverse { .. } => {
-                let paths = self.get_package_paths(source, None, None);
+                let paths = self.get_package_paths(source, Some(pkg_name), None, kind);
                 (paths.source, paths.binary.join(pkg_name))
             }
             Source::Repository { .. } => {
-                let paths = self.get_package_paths(source, Some(pkg_name), Some(version));
+                let paths = self.get_package_paths(source, Some(pkg_name), Some(version), kind);
                 (paths.source.join(pkg_name), paths.binary.join(pkg_name))
             }
             // TODO: can we cache local somehow?
             Source::Local { .. } => return InstallationStatus::Absent,
             // TODO: check if we have specific versions
-            Source::Builtin { .. } => return InstallationStatus::Binary(false),
+            Source::Builtin { .. } | Source::Provided { .. } => {
+                return InstallationStatus::Binary(false);
+            }
         };
 
         let from_source = if binary_path.is_dir() {
@@ -289,6 +327,29 @@ impl DiskCache {
         }
     }
 
+    /// The builtin packages saved for the most recent R version matching `r_version`, eg for
+    /// `4.4` the ones of 4.4.2 if both 4.4.1 and 4.4.2 were used. Used when R is not installed.
+    pub(super) fn find_builtin_packages_versions(
+        &self,
+        r_version: &Version,
+    ) -> Option<HashMap<String, Package>> {
+        let (_, path) = fs::read_dir(&self.root)
+            .ok()?
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                let version = path
+                    .file_name()?
+                    .to_str()?
+                    .strip_prefix("builtin-")?
+                    .strip_suffix(".mp")?
+                    .parse::<Version>()
+                    .ok()?;
+                r_version.hazy_match(&version).then_some((version, path))
+            })
+            .max_by(|(a, _), (b, _)| a.cmp(b))?;
+        BuiltinPackages::load(path).map(|b| b.packages)
+    }
+
     pub(super) fn get_system_requirements(
         &self,
     ) -> Result<HashMap<String, Vec<String>>, SysReqError> {
@@ -334,6 +395,80 @@ mod tests {
         fs::write(root.join(".git").join("config"), "[core]\n").unwrap();
     }
 
+    #[test]
+    fn binaries_built_with_options_are_not_shared() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cache = DiskCache::new_in_dir(
+            &"4.4".parse().unwrap(),
+            SystemInfo::new(crate::OsType::Linux("ubuntu"), None, None, "24.04"),
+            dir.path(),
+        )
+        .unwrap();
+        let source = Source::Repository {
+            repository: Url::parse("https://cran.r-project.org").unwrap(),
+        };
+        let paths =
+            cache.get_package_paths(&source, Some("sf"), Some("1.0.0"), PackageType::Source);
+        fs::create_dir_all(paths.binary.join("sf")).unwrap();
+        assert_eq!(
+            cache.get_installation_status("sf", "1.0.0", &source, PackageType::Source),
+            InstallationStatus::Binary(false)
+        );
+
+        let configure_args = HashMap::from([("sf".to_string(), vec!["--with-proj".to_string()])]);
+        cache.set_build_keys(BuildKeys::new(&configure_args, &HashMap::new()));
+        let keyed_paths =
+            cache.get_package_paths(&source, Some("sf"), Some("1.0.0"), PackageType::Source);
+        assert_ne!(keyed_paths.binary, paths.binary);
+        assert_eq!(
+            cache.get_installation_status("sf", "1.0.0", &source, PackageType::Source),
+            InstallationStatus::Absent
+        );
+    }
+
+    #[test]
+    fn finds_builtin_packages_of_matching_r_version() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = DiskCache::new_in_dir(
+            &"4.4".parse().unwrap(),
+            SystemInfo::new(crate::OsType::Linux("ubuntu"), None, None, "24.04"),
+            dir.path(),
+        )
+        .unwrap();
+        assert!(
+            cache
+                .find_builtin_packages_versions(&"4.4".parse().unwrap())
+                .is_none()
+        );
+
+        for version in ["4.3.3", "4.4.1", "4.4.2"] {
+            let mut builtin = BuiltinPackages::default();
+            let package = Package {
+                name: "Matrix".to_string(),
+                version: version.parse().unwrap(),
+                ..Default::default()
+            };
+            builtin.packages.insert(package.name.clone(), package);
+            builtin
+                .persist(dir.path().join(format!("builtin-{version}.mp")))
+                .unwrap();
+        }
+
+        let found = cache
+            .find_builtin_packages_versions(&"4.4".parse().unwrap())
+            .unwrap();
+        assert_eq!(found["Matrix"].version.original, "4.4.2");
+        let found = cache
+            .find_builtin_packages_versions(&"4.3.3".parse().unwrap())
+            .unwrap();
+        assert_eq!(found["Matrix"].version.original, "4.3.3");
+        assert!(
+            cache
+                .find_builtin_packages_versions(&"4.5".parse().unwrap())
+                .is_none()
+        );
+    }
+
     #[test]
     fn has_full_git_source_distinguishes_sparse_from_full() {
         let dir = tempfile::tempdir().unwrap();
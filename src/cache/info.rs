@@ -1,7 +1,9 @@
 use std::fmt;
 use std::path::PathBuf;
 
+use crate::fs::dir_size;
 use crate::lockfile::Source;
+use crate::utils::format_bytes;
 use crate::{Config, DiskCache, ResolvedDependency, hash_string};
 use serde::Serialize;
 
@@ -50,6 +52,8 @@ impl fmt::Display for CacheRepositoryInfo {
 #[derive(Debug, Serialize)]
 pub struct CacheInfo {
     root: PathBuf,
+    /// Total size of the cache in bytes
+    size: u64,
     repositories: Vec<CacheRepositoryInfo>,
     git: Vec<CacheUrlInfo>,
     urls: Vec<CacheUrlInfo>,
@@ -82,7 +86,7 @@ impl CacheInfo {
             if !d.source.is_git_or_url() {
                 continue;
             }
-            let paths = cache.get_package_paths(&d.source, None, None);
+            let paths = cache.get_package_paths(&d.source, Some(&d.name), None, d.kind);
             match d.source {
                 Source::Git { git, .. } => {
                     git_paths.push(CacheUrlInfo {
@@ -110,6 +114,7 @@ impl CacheInfo {
         }
 
         Self {
+            size: dir_size(&root),
             root,
             repositories,
             git: git_paths,
@@ -120,7 +125,7 @@ impl CacheInfo {
 
 impl fmt::Display for CacheInfo {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        writeln!(f, "{}", self.root.display())?;
+        writeln!(f, "{} ({})", self.root.display(), format_bytes(self.size))?;
         for r in &self.repositories {
             writeln!(f, "{}", r)?;
         }
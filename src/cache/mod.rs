@@ -1,10 +1,12 @@
+mod build_keys;
 pub mod disk;
 mod info;
 mod status;
 pub mod utils;
 
+pub(crate) use crate::cache::build_keys::BuildKeys;
 use crate::cache::utils::get_global_cache_dir;
-use crate::package::Package;
+use crate::package::{Package, PackageType};
 use crate::system_req::SysReqError;
 use crate::{RInstall, Source, SystemInfo, Version};
 pub use disk::{DiskCache, PackagePaths};
@@ -44,21 +46,45 @@ impl Cache {
         Ok(Self { local, global })
     }
 
-    /// Finds where a package is present in the cache depending on its source.
+    /// A cache only made of `root`, ignoring the global cache
+    #[cfg(any(test, feature = "test-utils"))]
+    pub(crate) fn new_isolated(
+        r_version: &Version,
+        system_info: SystemInfo,
+        root: impl AsRef<Path>,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let local = DiskCache::new_in_dir(r_version, system_info, root)?;
+        Ok(Self {
+            local,
+            global: None,
+        })
+    }
+
+    /// Binaries of packages built with non-default options get their own folders
+    pub(crate) fn set_build_keys(&mut self, build_keys: BuildKeys) {
+        if let Some(global) = self.global.as_mut() {
+            global.set_build_keys(build_keys.clone());
+        }
+        self.local.set_build_keys(build_keys);
+    }
+
+    /// Finds where a package is present in the cache depending on its source and on whether
+    /// its binary is downloaded or built from source.
     /// The version param is only used when the source is a repository
     pub fn get_installation_status(
         &self,
         pkg_name: &str,
         version: &str,
         source: &Source,
+        kind: PackageType,
     ) -> CacheStatus {
         let local = self
             .local
-            .get_installation_status(pkg_name, version, source);
+            .get_installation_status(pkg_name, version, source, kind);
         let global = self
             .global
             .as_ref()
-            .map(|g| g.get_installation_status(pkg_name, version, source));
+            .map(|g| g.get_installation_status(pkg_name, version, source, kind));
         CacheStatus { local, global }
     }
 
@@ -95,6 +121,18 @@ impl Cache {
         }
     }
 
+    /// Looks up builtin packages saved by a previous run with a R version matching
+    /// `r_version`, without needing R
+    pub fn find_builtin_packages_versions(
+        &self,
+        r_version: &Version,
+    ) -> Option<HashMap<String, Package>> {
+        self.global
+            .as_ref()
+            .and_then(|g| g.find_builtin_packages_versions(r_version))
+            .or_else(|| self.local.find_builtin_packages_versions(r_version))
+    }
+
     pub fn local(&self) -> &DiskCache {
         &self.local
     }
@@ -108,12 +146,15 @@ impl Cache {
         source: &Source,
         pkg_name: Option<&str>,
         version: Option<&str>,
+        kind: PackageType,
     ) -> (PackagePaths, Option<PackagePaths>) {
-        let local = self.local.get_package_paths(source, pkg_name, version);
+        let local = self
+            .local
+            .get_package_paths(source, pkg_name, version, kind);
         let global = self
             .global
             .as_ref()
-            .map(|x| x.get_package_paths(source, pkg_name, version));
+            .map(|x| x.get_package_paths(source, pkg_name, version, kind));
         (local, global)
     }
 }
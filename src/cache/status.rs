@@ -87,6 +87,20 @@ impl CacheStatus {
         }
     }
 
+    /// Stop considering the binary of the global cache, eg if it was built with other options
+    /// than the ones we need
+    pub fn ignore_global_binary(self) -> Self {
+        let global = self.global.map(|g| match g {
+            InstallationStatus::Binary(_) => InstallationStatus::Absent,
+            InstallationStatus::Both(_) => InstallationStatus::Source,
+            _ => g,
+        });
+        Self {
+            local: self.local,
+            global,
+        }
+    }
+
     pub fn local_binary_available(&self) -> bool {
         self.local.binary_available()
     }
@@ -41,6 +41,10 @@ pub fn get_user_cache_dir() -> Option<PathBuf> {
     if let Ok(p) = std::env::var(crate::consts::CACHE_DIR_ENV_VAR_NAME) {
         return Some(PathBuf::from(p));
     }
+    // The layout is different from renv's so we keep to our own folder in it
+    if let Ok(p) = std::env::var(crate::consts::RENV_PATHS_CACHE_ENV_VAR_NAME) {
+        return Some(PathBuf::from(p).join("rv"));
+    }
 
     etcetera::base_strategy::choose_base_strategy()
         .ok()
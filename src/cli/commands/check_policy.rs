@@ -0,0 +1,178 @@
+use std::fmt;
+use std::path::Path;
+
+use anyhow::{Result, bail};
+use serde::Serialize;
+use serde_json::{Value, json};
+
+use crate::Context;
+use crate::package::parse_description_file_in_folder;
+
+const LICENSE_NOT_ALLOWED: &str = "license-not-allowed";
+const LICENSE_UNKNOWN: &str = "license-unknown";
+
+/// A package of the library not following the policy of the project
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PolicyViolation {
+    pub rule: &'static str,
+    pub package: String,
+    pub version: String,
+    /// The `License` field of the package, if it has one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub license: Option<String>,
+}
+
+impl fmt::Display for PolicyViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.license {
+            Some(license) => write!(
+                f,
+                "{} {}: license `{license}` is not allowed",
+                self.package, self.version
+            ),
+            None => write!(
+                f,
+                "{} {}: no license found in its DESCRIPTION",
+                self.package, self.version
+            ),
+        }
+    }
+}
+
+/// The license names of a `License` field in lowercase, eg `GPL-2 | MIT + file LICENSE` gives
+/// `gpl-2` and `mit`. Version constraints are left out: `GPL (>= 2)` gives `gpl`.
+fn license_names(field: &str) -> impl Iterator<Item = String> + '_ {
+    field
+        .split('|')
+        .map(|license| {
+            let license = license.split_once('+').map_or(license, |(name, _)| name);
+            let license = license.split_once('(').map_or(license, |(name, _)| name);
+            license.trim().to_lowercase()
+        })
+        .filter(|name| !name.is_empty())
+}
+
+/// A package can be used if any of the licenses it is available under is allowed
+fn is_allowed(license: &str, allowed: &[String]) -> bool {
+    let allowed: Vec<_> = allowed.iter().flat_map(|a| license_names(a)).collect();
+    license_names(license).any(|name| allowed.contains(&name))
+}
+
+/// Checks the packages installed in the library against the `allowed_licenses` of the config.
+/// It reads their DESCRIPTION so it is meant to be run after `rv sync`.
+pub fn check_policy(context: &Context) -> Result<Vec<PolicyViolation>> {
+    let allowed = context.config.allowed_licenses();
+    if allowed.is_empty() {
+        bail!("No policy to check: set `allowed_licenses` in the config");
+    }
+
+    let mut packages: Vec<_> = context.library.packages.iter().collect();
+    packages.sort_unstable_by(|a, b| a.0.cmp(b.0));
+
+    let mut violations = Vec::new();
+    for (name, version) in packages {
+        let license = parse_description_file_in_folder(context.library.path().join(name))
+            .ok()
+            .map(|p| p.license)
+            .filter(|l| !l.is_empty());
+        let rule = match &license {
+            None => LICENSE_UNKNOWN,
+            Some(l) if !is_allowed(l, allowed) => LICENSE_NOT_ALLOWED,
+            Some(_) => continue,
+        };
+        violations.push(PolicyViolation {
+            rule,
+            package: name.clone(),
+            version: version.original.clone(),
+            license,
+        });
+    }
+
+    Ok(violations)
+}
+
+/// The violations in the SARIF format read by code scanning tools, eg to annotate pull requests
+/// on GitHub. They point to the lockfile since that's where the packages are pulled in.
+pub fn policy_sarif(violations: &[PolicyViolation], lockfile: &Path) -> Value {
+    let uri = lockfile.to_string_lossy().replace('\\', "/");
+    let results: Vec<_> = violations
+        .iter()
+        .map(|v| {
+            json!({
+                "ruleId": v.rule,
+                "level": "error",
+                "message": { "text": v.to_string() },
+                "locations": [{
+                    "physicalLocation": { "artifactLocation": { "uri": uri } }
+                }],
+            })
+        })
+        .collect();
+
+    json!({
+        "version": "2.1.0",
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "rv",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "informationUri": "https://github.com/A2-ai/rv",
+                    "rules": [
+                        {
+                            "id": LICENSE_NOT_ALLOWED,
+                            "shortDescription": { "text": "The license of the package is not in `allowed_licenses`" },
+                        },
+                        {
+                            "id": LICENSE_UNKNOWN,
+                            "shortDescription": { "text": "The package has no license" },
+                        },
+                    ],
+                }
+            },
+            "results": results,
+        }],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn can_check_licenses() {
+        let allowed = vec![
+            "MIT".to_string(),
+            "GPL (>= 2)".to_string(),
+            "GPL-3".to_string(),
+        ];
+        assert!(is_allowed("MIT + file LICENSE", &allowed));
+        assert!(is_allowed("GPL (>= 3)", &allowed));
+        assert!(is_allowed("AGPL-3 | GPL-3", &allowed));
+        assert!(is_allowed("gpl-3", &allowed));
+        assert!(!is_allowed("GPL-2", &allowed));
+        assert!(!is_allowed("AGPL-3", &allowed));
+        assert!(!is_allowed("file LICENSE", &allowed));
+    }
+
+    #[test]
+    fn can_output_sarif() {
+        let violations = vec![PolicyViolation {
+            rule: LICENSE_NOT_ALLOWED,
+            package: "pkg".to_string(),
+            version: "1.0.0".to_string(),
+            license: Some("AGPL-3".to_string()),
+        }];
+        let sarif = policy_sarif(&violations, Path::new("project/rv.lock"));
+        let result = &sarif["runs"][0]["results"][0];
+        assert_eq!(result["ruleId"], LICENSE_NOT_ALLOWED);
+        assert_eq!(
+            result["message"]["text"],
+            "pkg 1.0.0: license `AGPL-3` is not allowed"
+        );
+        assert_eq!(
+            result["locations"][0]["physicalLocation"]["artifactLocation"]["uri"],
+            "project/rv.lock"
+        );
+    }
+}
@@ -0,0 +1,91 @@
+use std::collections::HashSet;
+use std::path::Path;
+
+use anyhow::{Result, anyhow};
+use fs_err as fs;
+use url::Url;
+
+use crate::context::load_databases;
+use crate::{
+    Config, Context, GitExecutor, Lockfile, LockfileDiff, RCommandLookup, Repository,
+    RepositoryDiff, read_file_at_revision,
+};
+
+/// Compares the lockfile of the project with another one.
+/// `target` is either the path to a lockfile or a git revision (eg `HEAD~5`, a tag or a branch),
+/// in which case the lockfile of the project at that revision is used.
+pub fn diff_lockfile(config_file: &Path, target: &str) -> Result<LockfileDiff> {
+    let config = Config::from_file(config_file).map_err(|e| anyhow!("{e}"))?;
+    let project_dir = match config_file.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p,
+        _ => Path::new("."),
+    };
+
+    let lockfile_path = project_dir.join(config.lockfile_name());
+    if !lockfile_path.is_file() {
+        return Err(anyhow!("No lockfile found at {}", lockfile_path.display()));
+    }
+    let current = fs::read_to_string(&lockfile_path)?;
+    let current: Lockfile = current.parse().map_err(|e| anyhow!("{e}"))?;
+
+    let other = if Path::new(target).is_file() {
+        fs::read_to_string(target)?
+    } else {
+        read_file_at_revision(&GitExecutor, project_dir, target, config.lockfile_name()).map_err(
+            |e| {
+                anyhow!(
+                    "`{target}` is neither a lockfile nor a git revision containing {}: {}",
+                    config.lockfile_name(),
+                    e.to_string().trim()
+                )
+            },
+        )?
+    };
+    let other: Lockfile = other.parse().map_err(|e| anyhow!("{target}: {e}"))?;
+
+    Ok(other.diff(&current))
+}
+
+/// Compares the packages available in two repositories, eg two snapshot dates of Posit Package
+/// Manager. `old` and `new` are either URLs or aliases of repositories of the project.
+/// Unless `all` is set, only the packages of the project are kept: the ones in the lockfile or
+/// the dependencies in the config if there is no lockfile.
+pub fn diff_repositories(
+    config_file: &Path,
+    old: &str,
+    new: &str,
+    all: bool,
+) -> Result<RepositoryDiff> {
+    let context = Context::new(config_file, RCommandLookup::Skip).map_err(|e| anyhow!("{e}"))?;
+    let repository = |value: &str| -> Result<Repository> {
+        if let Some(repo) = context
+            .config
+            .repositories()
+            .iter()
+            .find(|r| r.alias == value)
+        {
+            return Ok(repo.clone());
+        }
+        let url = Url::parse(value)
+            .map_err(|e| anyhow!("`{value}` is neither a repository alias nor a URL: {e}"))?;
+        Ok(Repository::new(value.to_string(), url, false))
+    };
+    let repositories = [repository(old)?, repository(new)?];
+
+    let databases =
+        load_databases(&repositories, context.cache.local()).map_err(|e| anyhow!("{e}"))?;
+    let mut diff = databases[0].0.diff(&databases[1].0);
+    if !all {
+        let names: HashSet<&str> = match &context.lockfile {
+            Some(lockfile) => lockfile.package_names(),
+            None => context
+                .config
+                .dependencies()
+                .iter()
+                .map(|d| d.name())
+                .collect(),
+        };
+        diff.retain_packages(&names);
+    }
+    Ok(diff)
+}
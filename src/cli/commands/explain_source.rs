@@ -0,0 +1,349 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Result, bail};
+use fs_err as fs;
+use serde::Serialize;
+
+use crate::cli::commands::tree::roots_by_package;
+use crate::lockfile::Source;
+use crate::package::PackageType;
+use crate::{Context, PackageReport, ResolvedDependency, SyncReport, get_tarball_urls};
+
+/// Where the dependency comes from according to the resolution
+#[derive(Debug, Serialize)]
+pub struct ResolvedSource {
+    version: String,
+    source: Source,
+    package_type: PackageType,
+    /// Where the tarball is downloaded from, for packages from a repository
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+    /// The commit or the hash of the tarball, for git and URL dependencies
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sha: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct InstalledPackage {
+    version: String,
+    path: PathBuf,
+    /// When the package was linked into the library
+    #[serde(skip_serializing_if = "Option::is_none")]
+    installed_at: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CachedPackage {
+    /// `local` or `global`
+    cache: &'static str,
+    source_path: PathBuf,
+    binary_path: PathBuf,
+    binary_present: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LastSync {
+    finished_at: String,
+    #[serde(flatten)]
+    report: PackageReport,
+}
+
+/// Everything rv knows about how a package ended up in the library
+#[derive(Debug, Serialize)]
+pub struct SourceExplanation {
+    name: String,
+    /// The entry of the package in the config file, as written there
+    #[serde(skip_serializing_if = "Option::is_none")]
+    config_entry: Option<String>,
+    /// The dependencies from the config file requiring that package, including itself
+    required_by: Vec<String>,
+    /// The entry of the package in the lockfile, as written there
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lockfile_entry: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    resolved: Option<ResolvedSource>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    installed: Option<InstalledPackage>,
+    cache: Vec<CachedPackage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    build_log: Option<PathBuf>,
+    /// What the last sync did with the package, if it touched it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_sync: Option<LastSync>,
+}
+
+/// Finds the entry of the package in the dependencies of the config file and returns it as
+/// written, comments excluded
+fn find_config_entry(config_file: &Path, name: &str) -> Option<String> {
+    let content = fs::read_to_string(config_file).ok()?;
+    let doc = content.parse::<toml_edit::DocumentMut>().ok()?;
+    let project = doc.get("project")?.as_table_like()?;
+
+    for field in ["dependencies", "dev_dependencies", "suggests"] {
+        let Some(item) = project.get(field) else {
+            continue;
+        };
+        if let Some(array) = item.as_array() {
+            for value in array {
+                let matches = match value {
+                    toml_edit::Value::String(s) => s.value() == name,
+                    toml_edit::Value::InlineTable(t) => {
+                        t.get("name").and_then(|n| n.as_str()) == Some(name)
+                    }
+                    _ => false,
+                };
+                if matches {
+                    let mut value = value.clone();
+                    value.decor_mut().clear();
+                    return Some(value.to_string());
+                }
+            }
+        } else if let Some(tables) = item.as_array_of_tables() {
+            for table in tables {
+                if table.get("name").and_then(|n| n.as_str()) == Some(name) {
+                    return Some(table.to_string().trim().to_string());
+                }
+            }
+        }
+    }
+    None
+}
+
+fn resolved_source(context: &Context, dep: &ResolvedDependency) -> ResolvedSource {
+    let url = if dep.source.is_repo() {
+        get_tarball_urls(
+            dep,
+            &context.r_version.major_minor(),
+            context.cache.system_info(),
+        )
+        .ok()
+        .map(|urls| match (dep.kind, urls.binary) {
+            (PackageType::Binary, Some(binary)) => binary.to_string(),
+            _ => urls.source.to_string(),
+        })
+    } else {
+        None
+    };
+    ResolvedSource {
+        version: dep.version.original.clone(),
+        source: dep.source.clone(),
+        package_type: dep.kind,
+        url,
+        sha: dep
+            .source
+            .is_git_or_url()
+            .then(|| dep.source.sha().to_string()),
+    }
+}
+
+fn installed_package(context: &Context, name: &str) -> Option<InstalledPackage> {
+    let version = context.library.packages.get(name)?;
+    let path = context.library.path().join(name);
+    // The folder itself is created when linking, unlike its content which can be hardlinked
+    let installed_at = fs::symlink_metadata(&path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| jiff::Timestamp::try_from(t).ok())
+        .map(|t| t.to_string());
+    Some(InstalledPackage {
+        version: version.original.clone(),
+        path,
+        installed_at,
+    })
+}
+
+/// Gathers everything rv knows about where the package comes from: config, lockfile,
+/// resolution, library, cache and last sync.
+pub fn explain_source(
+    context: &Context,
+    config_file: &Path,
+    resolved: &[ResolvedDependency],
+    name: &str,
+) -> Result<SourceExplanation> {
+    let dep = resolved.iter().find(|d| d.name == name);
+    let config_entry = find_config_entry(config_file, name);
+    let lockfile_entry = context
+        .lockfile
+        .as_ref()
+        .and_then(|l| l.get_package(name, None))
+        .map(|p| p.to_toml_string().trim().to_string());
+    let installed = installed_package(context, name);
+    if dep.is_none() && config_entry.is_none() && lockfile_entry.is_none() && installed.is_none() {
+        bail!("{name} is not a dependency of the project and is not installed in its library");
+    }
+
+    let dependencies: HashMap<_, _> = resolved
+        .iter()
+        .map(|d| (d.name.as_ref(), d.all_dependencies_names()))
+        .collect();
+    let roots: Vec<_> = context
+        .config
+        .dependencies()
+        .iter()
+        .map(|d| d.name())
+        .filter(|n| dependencies.contains_key(n))
+        .collect();
+    let required_by = roots_by_package(&roots, &dependencies)
+        .remove(name)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|n| n.to_string())
+        .collect();
+
+    let mut cache = Vec::new();
+    let mut build_log = None;
+    if let Some(dep) = dep
+        && !dep.source.is_builtin()
+        && !dep.source.is_provided()
+    {
+        if dep.source.is_repo() || dep.source.is_git_or_url() {
+            let (local, global) = context.cache.get_package_paths(
+                &dep.source,
+                Some(&dep.name),
+                Some(&dep.version.original),
+                dep.kind,
+            );
+            cache.extend(
+                [("local", Some(local)), ("global", global)]
+                    .into_iter()
+                    .filter_map(|(kind, paths)| {
+                        let paths = paths?;
+                        Some(CachedPackage {
+                            cache: kind,
+                            binary_present: paths.binary.join(name).is_dir(),
+                            source_path: paths.source,
+                            binary_path: paths.binary,
+                        })
+                    }),
+            );
+        }
+
+        build_log = std::iter::once(context.cache.local())
+            .chain(context.cache.global())
+            .map(|disk| disk.build_log_path_for(dep))
+            .find(|p| p.is_file());
+    }
+
+    let last_sync = SyncReport::load(context.last_sync_report_path())
+        .ok()
+        .and_then(|report| {
+            let finished_at = report.finished_at;
+            report
+                .packages
+                .into_iter()
+                .find(|p| p.name == name)
+                .map(|report| LastSync {
+                    finished_at,
+                    report,
+                })
+        });
+
+    Ok(SourceExplanation {
+        name: name.to_string(),
+        config_entry,
+        required_by,
+        lockfile_entry,
+        resolved: dep.map(|d| resolved_source(context, d)),
+        installed,
+        cache,
+        build_log,
+        last_sync,
+    })
+}
+
+impl fmt::Display for SourceExplanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", self.name)?;
+
+        match &self.config_entry {
+            Some(entry) => writeln!(f, "  Config entry: {entry}")?,
+            None => writeln!(f, "  Config entry: none, installed as a dependency")?,
+        }
+        if !self.required_by.is_empty() {
+            writeln!(f, "  Required by: {}", self.required_by.join(", "))?;
+        }
+
+        match &self.resolved {
+            Some(resolved) => {
+                writeln!(
+                    f,
+                    "  Resolved: {} ({}) from {}",
+                    resolved.version,
+                    resolved.package_type,
+                    resolved.source.to_string().trim()
+                )?;
+                if let Some(url) = &resolved.url {
+                    writeln!(f, "  Download URL: {url}")?;
+                }
+                if let Some(sha) = &resolved.sha {
+                    writeln!(f, "  SHA: {sha}")?;
+                }
+            }
+            None => writeln!(f, "  Resolved: not part of the resolution")?,
+        }
+
+        match &self.lockfile_entry {
+            Some(entry) => {
+                writeln!(f, "  Lockfile entry:")?;
+                for line in entry.lines() {
+                    writeln!(f, "    {line}")?;
+                }
+            }
+            None => writeln!(f, "  Lockfile entry: none")?,
+        }
+
+        match &self.installed {
+            Some(installed) => {
+                write!(
+                    f,
+                    "  Installed: {} in {}",
+                    installed.version,
+                    installed.path.display()
+                )?;
+                if let Some(at) = &installed.installed_at {
+                    write!(f, " at {at}")?;
+                }
+                writeln!(f)?;
+            }
+            None => writeln!(f, "  Installed: no")?,
+        }
+
+        for cached in &self.cache {
+            writeln!(
+                f,
+                "  Cache ({}): binary {}{}, source {}",
+                cached.cache,
+                cached.binary_path.display(),
+                if cached.binary_present {
+                    ""
+                } else {
+                    " (absent)"
+                },
+                cached.source_path.display()
+            )?;
+        }
+        if let Some(log) = &self.build_log {
+            writeln!(f, "  Build log: {}", log.display())?;
+        }
+
+        if let Some(last_sync) = &self.last_sync {
+            let report = &last_sync.report;
+            write!(
+                f,
+                "  Last sync ({}): {}",
+                last_sync.finished_at, report.outcome
+            )?;
+            if report.cache_hit {
+                write!(f, " from the cache")?;
+            }
+            if let Some(error) = &report.error {
+                write!(f, ": {error}")?;
+            }
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
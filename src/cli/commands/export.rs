@@ -1,10 +1,23 @@
+use std::collections::HashSet;
 use std::path::Path;
 
 use anyhow::{Result, anyhow};
 
-use crate::{Config, Lockfile, renv::to_renv_lock};
+use crate::consts::VALIDATION_SIGNING_KEY_ENV_VAR_NAME;
+use crate::r_finder::find_r_install;
+use crate::system_req::{SysDep, check_installation_status};
+use crate::{
+    BundleManifest, Config, Context, Http, Lockfile, RCommandLookup, RRuntimeReference,
+    ValidatedPackage, ValidationPlatform, ValidationReport, bundle_platform, create_bundle,
+    hash_installed_package,
+    renv::{to_renv_lock, update_renv_lock},
+    to_conda_environment, to_nix_expression,
+};
 
-pub fn export_renv(config_file: &Path, output_file: &Path) -> Result<Vec<String>> {
+/// Exports the lockfile as a renv.lock.
+/// With `sync`, an existing renv.lock is updated in place rather than replaced so renv specific
+/// sections are kept, allowing to use renv and rv side by side.
+pub fn export_renv(config_file: &Path, output_file: &Path, sync: bool) -> Result<Vec<String>> {
     let config = Config::from_file(config_file).map_err(|e| anyhow!("{e}"))?;
 
     let project_dir = config_file
@@ -17,8 +30,229 @@ pub fn export_renv(config_file: &Path, output_file: &Path) -> Result<Vec<String>
 
     let (renv_json, warnings) = to_renv_lock(&lockfile, &config);
 
-    let json_string = serde_json::to_string_pretty(&renv_json)?;
+    let json_string = if sync && output_file.exists() {
+        let existing = fs_err::read_to_string(output_file)?;
+        let updated = update_renv_lock(&existing, &renv_json)
+            .map_err(|e| anyhow!("Failed to update {}: {e}", output_file.display()))?;
+        if updated == existing {
+            return Ok(warnings);
+        }
+        updated
+    } else {
+        serde_json::to_string_pretty(&renv_json)?
+    };
     fs_err::write(output_file, json_string)?;
 
     Ok(warnings)
 }
+
+pub fn export_conda(config_file: &Path, output_file: &Path) -> Result<Vec<String>> {
+    let config = Config::from_file(config_file).map_err(|e| anyhow!("{e}"))?;
+
+    let project_dir = config_file
+        .parent()
+        .ok_or_else(|| anyhow!("Could not determine project directory from config file path"))?;
+    let lockfile_path = project_dir.join(config.lockfile_name());
+    let lockfile = Lockfile::load(&lockfile_path)
+        .map_err(|e| anyhow!("{e}"))?
+        .ok_or_else(|| anyhow!("No valid lockfile found at {}", lockfile_path.display()))?;
+
+    let (environment, warnings) = to_conda_environment(&lockfile, &config);
+    fs_err::write(output_file, environment)?;
+
+    Ok(warnings)
+}
+
+pub fn export_nix(config_file: &Path, output_file: &Path) -> Result<Vec<String>> {
+    let mut context =
+        Context::new(config_file, RCommandLookup::Skip).map_err(|e| anyhow!("{e}"))?;
+    // System requirements are only known for some Linux distributions, we still export without them
+    context.load_system_requirements();
+
+    let lockfile = context.lockfile.as_ref().ok_or_else(|| {
+        anyhow!(
+            "No valid lockfile found at {}",
+            context.lockfile_path().display()
+        )
+    })?;
+
+    let (expression, warnings) = to_nix_expression(lockfile, &context.system_dependencies, &Http);
+    fs_err::write(output_file, expression)?;
+
+    Ok(warnings)
+}
+
+pub fn export_bundle(
+    config_file: &Path,
+    output_file: &Path,
+    with_library: bool,
+    with_r_reference: bool,
+) -> Result<Vec<String>> {
+    // We only need R if we want to record which one was used
+    let lookup = if with_r_reference {
+        RCommandLookup::Strict
+    } else {
+        RCommandLookup::Skip
+    };
+    let context = Context::new(config_file, lookup).map_err(|e| anyhow!("{e}"))?;
+    let lockfile_path = context.lockfile_path();
+    let lockfile = context
+        .lockfile
+        .as_ref()
+        .ok_or_else(|| anyhow!("No valid lockfile found at {}", lockfile_path.display()))?;
+
+    let mut warnings = Vec::new();
+    let mut manifest = BundleManifest::new(
+        &context.r_version.original,
+        &bundle_platform(context.cache.system_info(), context.r_version.major_minor()),
+    );
+
+    if with_library {
+        for pkg in lockfile.packages() {
+            if pkg.source.is_builtin() || pkg.source.is_provided() {
+                continue;
+            }
+            if context
+                .library
+                .packages
+                .get(&pkg.name)
+                .is_none_or(|v| v.original != pkg.version)
+            {
+                warnings.push(format!(
+                    "`{}` {} is not installed in the library, run `rv sync` before exporting",
+                    pkg.name, pkg.version
+                ));
+            }
+        }
+        if !context.additional_libraries.is_empty() {
+            warnings.push(
+                "additional libraries are not included in the bundle and need to be available where it is installed"
+                    .to_string(),
+            );
+        }
+
+        manifest.library_path = Some(
+            context
+                .library
+                .path
+                .strip_prefix(&context.project_dir)
+                .unwrap_or(&context.library.path)
+                .to_path_buf(),
+        );
+        let mut packages: Vec<_> = context
+            .library
+            .packages
+            .iter()
+            .map(|(name, version)| (name.clone(), version.original.clone()))
+            .collect();
+        packages.sort();
+        manifest.packages = packages;
+    }
+
+    if with_r_reference {
+        manifest.r_runtime = Some(RRuntimeReference {
+            version: context.r_cmd.version.original.clone(),
+            r_home: crate::r_cmd::get_r_home(&context.r_cmd.bin_path)?,
+        });
+    }
+
+    create_bundle(
+        output_file,
+        &manifest,
+        &[config_file, lockfile_path.as_path()],
+        with_library.then_some(context.library.path.as_path()),
+    )
+    .map_err(|e| anyhow!("{e}"))?;
+
+    Ok(warnings)
+}
+
+/// Writes the validation report as JSON in `output_file` and rendered as markdown next to it,
+/// with a `.md` extension. The report is signed if a key is set in the environment.
+pub fn export_validation_report(config_file: &Path, output_file: &Path) -> Result<Vec<String>> {
+    let mut context =
+        Context::new(config_file, RCommandLookup::Skip).map_err(|e| anyhow!("{e}"))?;
+    context.load_system_requirements();
+    let lockfile_path = context.lockfile_path();
+    let lockfile = context
+        .lockfile
+        .as_ref()
+        .ok_or_else(|| anyhow!("No valid lockfile found at {}", lockfile_path.display()))?;
+
+    let mut warnings = Vec::new();
+    let system_info = context.cache.system_info();
+    let mut report = ValidationReport::new(
+        context.config.project_name(),
+        &context.r_version.original,
+        jiff::Timestamp::now().to_string(),
+        ValidationPlatform::new(
+            system_info,
+            bundle_platform(system_info, context.r_version.major_minor()),
+        ),
+        &fs_err::read(config_file)?,
+        &fs_err::read(&lockfile_path)?,
+    );
+    report.r_installation =
+        find_r_install(&context.r_version, context.config.use_devel()).map(|r| r.version.original);
+    if report.r_installation.is_none() {
+        warnings.push(format!(
+            "no R installation matching {} was found",
+            context.r_version.original
+        ));
+    }
+
+    for pkg in lockfile.packages() {
+        let installed_version = context
+            .library
+            .packages
+            .get(&pkg.name)
+            .map(|v| v.original.clone());
+        let in_library = !pkg.source.is_builtin() && !pkg.source.is_provided();
+        if in_library && installed_version.as_deref() != Some(pkg.version.as_str()) {
+            warnings.push(format!(
+                "`{}` {} is not installed in the library, run `rv sync` before exporting",
+                pkg.name, pkg.version
+            ));
+        }
+        report.packages.push(ValidatedPackage {
+            name: pkg.name.clone(),
+            version: pkg.version.clone(),
+            source: pkg.source.clone(),
+            content_sha256: installed_version
+                .as_ref()
+                .and_then(|_| hash_installed_package(context.library.path().join(&pkg.name))),
+            installed_version,
+            sys_deps: context
+                .system_dependencies
+                .get(&pkg.name)
+                .cloned()
+                .unwrap_or_default(),
+        });
+    }
+
+    let sys_deps: HashSet<_> = report
+        .packages
+        .iter()
+        .flat_map(|p| p.sys_deps.iter().map(|s| s.as_str()))
+        .collect();
+    let statuses = check_installation_status(system_info, &sys_deps);
+    let mut system_dependencies: Vec<_> = statuses
+        .into_iter()
+        .map(|(name, status)| SysDep { name, status })
+        .collect();
+    system_dependencies.sort_by(|a, b| a.name.cmp(&b.name));
+    report.system_dependencies = system_dependencies;
+
+    let key = std::env::var(VALIDATION_SIGNING_KEY_ENV_VAR_NAME).ok();
+    if key.is_none() {
+        warnings.push(format!(
+            "{VALIDATION_SIGNING_KEY_ENV_VAR_NAME} is not set, the report is only sealed with its SHA-256"
+        ));
+    }
+    report.seal(key.as_deref().map(str::as_bytes));
+
+    fs_err::write(output_file, serde_json::to_string_pretty(&report)?)?;
+    fs_err::write(output_file.with_extension("md"), report.to_markdown())?;
+
+    Ok(warnings)
+}
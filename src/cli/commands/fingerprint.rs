@@ -0,0 +1,38 @@
+use anyhow::{Result, anyhow};
+
+use crate::{Context, Lockfile, ResolveMode};
+
+/// The fingerprint of the environment `rv sync` would create, see `Lockfile::fingerprint`.
+/// An up to date lockfile is used as is, without needing the repositories. Otherwise the project
+/// is resolved to get what the lockfile would be after a sync, unless `locked` is set.
+pub fn environment_fingerprint(
+    context: &mut Context,
+    resolve_mode: ResolveMode,
+    locked: bool,
+) -> Result<String> {
+    if resolve_mode == ResolveMode::Default
+        && let Some(lockfile) = &context.lockfile
+        && lockfile.r_version().major_minor() == context.r_version.major_minor()
+        && lockfile.can_resolve(context.config.dependencies(), context.config.repositories())
+    {
+        log::debug!("Lockfile is up to date, using it for the fingerprint");
+        return Ok(lockfile.fingerprint(context.cache.system_info()));
+    }
+
+    if locked {
+        return Err(anyhow!(
+            "the lockfile {} is missing or needs to be updated but --locked was passed to prevent this",
+            context.config.lockfile_name()
+        ));
+    }
+
+    context.load_databases().map_err(|e| anyhow!("{e}"))?;
+    let resolution = context.resolve(resolve_mode);
+    if !resolution.is_success() {
+        resolution.print_failures();
+        return Err(anyhow!("failed to resolve all dependencies"));
+    }
+    let lockfile = Lockfile::from_resolved(&context.r_version.major_minor(), resolution.found)
+        .with_repositories(context.config.repositories());
+    Ok(lockfile.fingerprint(context.cache.system_info()))
+}
@@ -1,17 +1,27 @@
 use std::{
+    fmt,
     io::{self, Read},
     path::Path,
     process::Command,
 };
 
 use fs_err::write;
+use serde::Serialize;
 use url::Url;
 
-use crate::{Repository, consts::LIBRARY_ROOT_DIR_NAME};
+use crate::repository_urls::get_distro_name;
+use crate::{
+    OsType, Repository, SystemInfo,
+    consts::{
+        CONFIG_FILENAME, CRAN_HOSTS, LAST_SYNC_REPORT_FILENAME, LIBRARY_ROOT_DIR_NAME, PPM_HOSTS,
+        SIMULATED_SYNC_REPORT_FILENAME, STARTUP_BENCH_FILENAME, SYNC_STAMP_FILENAME,
+    },
+};
 
 const GITIGNORE_PATH: &str = "rv/.gitignore";
 const LIBRARY_PATH: &str = "rv/library";
-const CONFIG_FILENAME: &str = "rproject.toml";
+/// rv adds the Linux distribution to that URL itself when downloading binaries
+const PPM_CRAN_URL: &str = "https://packagemanager.posit.co/cran/latest";
 
 const INITIAL_CONFIG: &str = r#"[project]
 name = "%project_name%"
@@ -108,7 +118,106 @@ fn render_config(
         .replace("%dependencies%", &deps)
 }
 
+/// Where a repository of the generated config comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RepositoryOrigin {
+    /// Given with `rv init --repository`
+    CommandLine,
+    /// The `repos` option R sets without any profile
+    RDefault,
+    /// Set by the site profile, eg `Rprofile.site`
+    SiteProfile,
+    /// Set by the user profile, eg `~/.Rprofile`
+    UserProfile,
+    /// Posit Package Manager replacing a CRAN mirror with `rv init --use-binaries`
+    UseBinaries,
+    /// R couldn't be run without the profiles to tell
+    Unknown,
+}
+
+impl fmt::Display for RepositoryOrigin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CommandLine => write!(f, "command line"),
+            Self::RDefault => write!(f, "R default"),
+            Self::SiteProfile => write!(f, "site profile"),
+            Self::UserProfile => write!(f, "user profile"),
+            Self::UseBinaries => write!(f, "--use-binaries"),
+            Self::Unknown => write!(f, "unknown origin"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FoundRepository {
+    #[serde(flatten)]
+    pub repository: Repository,
+    pub origin: RepositoryOrigin,
+}
+
+/// Parses a repository given as `ALIAS=URL` on the command line
+pub fn parse_repository_arg(value: &str) -> Result<Repository, InitError> {
+    let invalid = |reason: &str| InitError {
+        source: InitErrorKind::InvalidRepository(format!("`{value}`: {reason}")),
+    };
+    let (alias, url) = value
+        .split_once('=')
+        .ok_or_else(|| invalid("expected ALIAS=URL"))?;
+    let alias = alias.trim();
+    if alias.is_empty() {
+        return Err(invalid("the alias is empty"));
+    }
+    let url = Url::parse(url.trim()).map_err(|e| invalid(&e.to_string()))?;
+    Ok(Repository::new(alias.to_string(), url, false))
+}
+
+/// The repositories set in the R `repos` option
 pub fn find_r_repositories() -> Result<Vec<Repository>, InitError> {
+    get_repos_option(&[])
+}
+
+/// Same as [`find_r_repositories`] but also finds which profile set each of them by comparing
+/// with the option of R sessions ignoring the user and site profiles
+pub fn find_r_repositories_with_origin() -> Result<Vec<FoundRepository>, InitError> {
+    let repositories = get_repos_option(&[])?;
+    // If those fail, we can't tell where the repositories come from
+    let without_user = get_repos_option(&["--no-init-file"]).ok();
+    let without_profiles = get_repos_option(&["--no-init-file", "--no-site-file"]).ok();
+
+    Ok(with_origin(
+        repositories,
+        without_user.as_deref(),
+        without_profiles.as_deref(),
+    ))
+}
+
+fn with_origin(
+    repositories: Vec<Repository>,
+    without_user: Option<&[Repository]>,
+    without_profiles: Option<&[Repository]>,
+) -> Vec<FoundRepository> {
+    repositories
+        .into_iter()
+        .map(|repository| {
+            let origin = match (without_user, without_profiles) {
+                (Some(without_user), Some(without_profiles)) => {
+                    if without_profiles.contains(&repository) {
+                        RepositoryOrigin::RDefault
+                    } else if without_user.contains(&repository) {
+                        RepositoryOrigin::SiteProfile
+                    } else {
+                        RepositoryOrigin::UserProfile
+                    }
+                }
+                _ => RepositoryOrigin::Unknown,
+            };
+            FoundRepository { repository, origin }
+        })
+        .collect()
+}
+
+fn get_repos_option(rscript_args: &[&str]) -> Result<Vec<Repository>, InitError> {
     let r_code = r#"
     repos <- getOption("repos")
     cat(paste(names(repos), repos, sep = "\t", collapse = "\n"))
@@ -120,6 +229,7 @@ pub fn find_r_repositories() -> Result<Vec<Repository>, InitError> {
 
     let mut command = Command::new("Rscript");
     command
+        .args(rscript_args)
         .arg("-e")
         .arg(r_code)
         .stdout(send.try_clone().map_err(|e| InitError {
@@ -159,6 +269,66 @@ pub fn find_r_repositories() -> Result<Vec<Repository>, InitError> {
         .collect::<Vec<_>>())
 }
 
+/// A CRAN mirror only has source packages for Linux, while Posit Package Manager has binaries
+/// for most distributions
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BinaryRepository {
+    pub alias: String,
+    /// The URL of the CRAN mirror
+    pub source_url: String,
+    pub binary_url: String,
+    /// The distribution as named by Posit Package Manager, eg `noble`
+    pub distribution: String,
+}
+
+impl BinaryRepository {
+    pub fn repository(&self) -> Repository {
+        Repository::new(
+            self.alias.clone(),
+            Url::parse(&self.binary_url).expect("valid url"),
+            false,
+        )
+    }
+}
+
+/// Returns the Posit Package Manager repository to use instead of the first CRAN mirror when
+/// on a Linux distribution it has binaries for. Nothing is returned if a Posit Package Manager
+/// repository is already used.
+pub fn find_binary_repository(
+    repositories: &[Repository],
+    sysinfo: &SystemInfo,
+) -> Option<BinaryRepository> {
+    let OsType::Linux(distro) = sysinfo.os_type else {
+        return None;
+    };
+    let distribution = get_distro_name(sysinfo, distro)?;
+    let urls: Vec<_> = repositories
+        .iter()
+        .filter_map(|r| Url::parse(r.url()).ok().map(|url| (r, url)))
+        .collect();
+    if urls
+        .iter()
+        .any(|(_, url)| url.host_str().is_some_and(|h| PPM_HOSTS.contains(&h)))
+    {
+        return None;
+    }
+
+    let (repo, _) = urls.into_iter().find(|(_, url)| is_cran_mirror(url))?;
+    Some(BinaryRepository {
+        alias: repo.alias.clone(),
+        source_url: repo.url().to_string(),
+        binary_url: PPM_CRAN_URL.to_string(),
+        distribution,
+    })
+}
+
+/// Only the main CRAN hosts are known to have exactly what Posit Package Manager has: other
+/// hosts can look like a mirror but be an internal repository with its own packages
+fn is_cran_mirror(url: &Url) -> bool {
+    url.host_str()
+        .is_some_and(|host| CRAN_HOSTS.contains(&host))
+}
+
 fn strip_linux_url(url: &str) -> String {
     if !url.contains("__linux__") {
         return url.to_string();
@@ -197,7 +367,9 @@ fn create_gitignore(project_directory: impl AsRef<Path>) -> Result<(), InitError
         return Ok(());
     }
 
-    let content = format!("{LIBRARY_ROOT_DIR_NAME}\n");
+    let content = format!(
+        "{LIBRARY_ROOT_DIR_NAME}\n{LAST_SYNC_REPORT_FILENAME}\n{SIMULATED_SYNC_REPORT_FILENAME}\n{SYNC_STAMP_FILENAME}\n{STARTUP_BENCH_FILENAME}\n"
+    );
 
     write(path, content)?;
     Ok(())
@@ -218,6 +390,8 @@ pub enum InitErrorKind {
     Command(std::io::Error),
     #[error("Failed to find repositories: {0}")]
     CommandFailed(String),
+    #[error("Invalid repository {0}")]
+    InvalidRepository(String),
 }
 
 impl From<io::Error> for InitError {
@@ -233,11 +407,15 @@ mod tests {
     use std::str::FromStr;
 
     use crate::{
-        Repository, Version,
-        cli::commands::init::{CONFIG_FILENAME, GITIGNORE_PATH, LIBRARY_PATH},
+        OsType, Repository, SystemInfo, Version,
+        cli::commands::init::{GITIGNORE_PATH, LIBRARY_PATH},
+        consts::CONFIG_FILENAME,
     };
 
-    use super::{init, strip_linux_url};
+    use super::{
+        RepositoryOrigin, find_binary_repository, init, parse_repository_arg, strip_linux_url,
+        with_origin,
+    };
     use tempfile::tempdir;
     use url::Url;
 
@@ -282,4 +460,96 @@ mod tests {
         let cleaned_urls = urls.iter().map(|u| strip_linux_url(u)).collect::<Vec<_>>();
         assert_eq!(cleaned_urls[0], cleaned_urls[1]);
     }
+
+    #[test]
+    fn can_parse_repository_arg() {
+        let repo = parse_repository_arg("CRAN=https://cloud.r-project.org").unwrap();
+        assert_eq!(repo.alias, "CRAN");
+        assert_eq!(repo.url(), "https://cloud.r-project.org/");
+        assert!(parse_repository_arg("https://cloud.r-project.org").is_err());
+        assert!(parse_repository_arg("=https://cloud.r-project.org").is_err());
+        assert!(parse_repository_arg("CRAN=not a url").is_err());
+    }
+
+    #[test]
+    fn can_find_repository_origin() {
+        let repo = |alias: &str, url: &str| {
+            Repository::new(alias.to_string(), Url::parse(url).unwrap(), false)
+        };
+        let cran = repo("CRAN", "https://cloud.r-project.org");
+        let site = repo("site", "https://site.example.com");
+        let user = repo("user", "https://user.example.com");
+        // The user profile overrides the CRAN URL of the site profile
+        let user_cran = repo("CRAN", "https://packagemanager.posit.co/cran/latest");
+
+        let found = with_origin(
+            vec![user_cran.clone(), site.clone(), user.clone(), cran.clone()],
+            Some(&[cran.clone(), site.clone()]),
+            Some(std::slice::from_ref(&cran)),
+        );
+        let origins: Vec<_> = found.iter().map(|f| f.origin).collect();
+        assert_eq!(
+            origins,
+            vec![
+                RepositoryOrigin::UserProfile,
+                RepositoryOrigin::SiteProfile,
+                RepositoryOrigin::UserProfile,
+                RepositoryOrigin::RDefault,
+            ]
+        );
+        assert_eq!(found[1].repository, site);
+
+        // R failed without the profiles
+        let found = with_origin(vec![cran.clone()], Some(&[]), None);
+        assert_eq!(found[0].origin, RepositoryOrigin::Unknown);
+    }
+
+    #[test]
+    fn can_find_binary_repository() {
+        let repo = |alias: &str, url: &str| {
+            Repository::new(alias.to_string(), Url::parse(url).unwrap(), false)
+        };
+        let noble = SystemInfo::new(OsType::Linux("ubuntu"), None, Some("noble".into()), "24.04");
+        let cran = vec![
+            repo("internal", "https://r.example.com/repo"),
+            repo("CRAN", "https://cloud.r-project.org"),
+        ];
+
+        let binary = find_binary_repository(&cran, &noble).unwrap();
+        assert_eq!(binary.alias, "CRAN");
+        assert_eq!(binary.source_url, "https://cloud.r-project.org/");
+        assert_eq!(binary.distribution, "noble");
+        assert_eq!(
+            binary.repository().url(),
+            "https://packagemanager.posit.co/cran/latest"
+        );
+
+        assert!(
+            find_binary_repository(&[repo("CRAN", "https://cran.rstudio.com")], &noble).is_some()
+        );
+
+        // Already using PPM
+        let with_ppm = vec![
+            repo("PPM", "https://packagemanager.posit.co/cran/2024-12-16"),
+            repo("CRAN", "https://cloud.r-project.org"),
+        ];
+        assert_eq!(find_binary_repository(&with_ppm, &noble), None);
+        // Not known to be a CRAN mirror
+        for url in [
+            "https://r.example.com/repo",
+            "https://cran.example.com",
+            "https://artifactory.example.com/CRAN/",
+        ] {
+            assert_eq!(
+                find_binary_repository(&[repo("internal", url)], &noble),
+                None
+            );
+        }
+        // Binaries are already available on macOS
+        let macos = SystemInfo::new(OsType::MacOs, None, None, "15.0");
+        assert_eq!(find_binary_repository(&cran, &macos), None);
+        // No binaries for that distribution
+        let arch = SystemInfo::new(OsType::Linux("arch"), None, None, "rolling");
+        assert_eq!(find_binary_repository(&cran, &arch), None);
+    }
 }
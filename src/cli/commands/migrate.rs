@@ -1,8 +1,4 @@
-use std::{
-    fs::File,
-    io::Write,
-    path::{Path, absolute},
-};
+use std::path::{Path, absolute};
 
 use anyhow::{Result, anyhow};
 
@@ -26,11 +22,14 @@ dependencies = [
 ]
 "#;
 
+/// Converts a renv.lock to the content of a rv config, returning it alongside the packages that
+/// could not be resolved.
+/// If `only` is not empty, only those packages are migrated.
 pub fn migrate_renv(
     renv_file: impl AsRef<Path>,
-    config_file: impl AsRef<Path>,
     strict_r_version: bool,
-) -> Result<Vec<UnresolvedRenv>> {
+    only: &[String],
+) -> Result<(String, Vec<UnresolvedRenv>)> {
     // project name is the parent directory of the renv project
     let abs_renv_file = absolute(renv_file.as_ref())?;
     let project_name = abs_renv_file
@@ -40,7 +39,17 @@ pub fn migrate_renv(
         .unwrap_or("renv migrated project");
 
     // use the repositories and r version from the renv.lock to determine the repository databases
-    let renv_lock = RenvLock::parse_renv_lock(&renv_file)?;
+    let mut renv_lock = RenvLock::parse_renv_lock(&renv_file)?;
+    if !only.is_empty() {
+        let missing = renv_lock.retain_packages(only);
+        if !missing.is_empty() {
+            return Err(anyhow!(
+                "Packages not found in {}: {}",
+                renv_file.as_ref().display(),
+                missing.join(", ")
+            ));
+        }
+    }
     let cache = match DiskCache::new(renv_lock.r_version(), SystemInfo::from_os_info()) {
         Ok(c) => c,
         Err(e) => return Err(anyhow!(e)),
@@ -51,7 +60,6 @@ pub fn migrate_renv(
     // resolve the renv.lock file to determine the true source of packages
     let (resolved, unresolved) = renv_lock.resolve(&databases);
 
-    // Write config out to the config file specified in the cli, even if config file is outside of the renv.lock project
     let r_version = if strict_r_version {
         &renv_lock.r_version().original
     } else {
@@ -66,9 +74,7 @@ pub fn migrate_renv(
         &renv_lock.config_repositories(),
         &resolved,
     );
-    let mut file = File::create(&config_file)?;
-    file.write_all(config.as_bytes())?;
-    Ok(unresolved)
+    Ok((config, unresolved))
 }
 
 fn render_config(
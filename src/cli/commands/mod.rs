@@ -1,9 +1,23 @@
+mod check_policy;
+mod diff;
+mod explain_source;
 mod export;
+mod fingerprint;
 mod init;
 mod migrate;
+mod suggests;
 mod tree;
 
-pub use export::export_renv;
-pub use init::{find_r_repositories, init, init_structure};
+pub use check_policy::{PolicyViolation, check_policy, policy_sarif};
+pub use diff::{diff_lockfile, diff_repositories};
+pub use explain_source::{SourceExplanation, explain_source};
+pub use export::{export_bundle, export_conda, export_nix, export_renv, export_validation_report};
+pub use fingerprint::environment_fingerprint;
+pub use init::{
+    BinaryRepository, FoundRepository, RepositoryOrigin, find_binary_repository,
+    find_r_repositories, find_r_repositories_with_origin, init, init_structure,
+    parse_repository_arg,
+};
 pub use migrate::migrate_renv;
-pub use tree::tree;
+pub use suggests::preview_suggests;
+pub use tree::{sysdeps_tree, tree};
@@ -0,0 +1,154 @@
+use std::collections::{BTreeSet, HashSet};
+
+use anyhow::{Result, anyhow};
+use serde::Serialize;
+
+use crate::package::PackageType;
+use crate::{Context, ResolveMode};
+
+#[derive(Debug, Serialize)]
+pub struct SuggestedPackage {
+    name: String,
+    version: String,
+    package_type: PackageType,
+    /// Whether it will be built from source since no binary is available or cached
+    needs_compilation: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    sys_deps: Vec<String>,
+}
+
+/// What enabling `install_suggestions` on some dependencies would add to the project
+#[derive(Debug, Serialize)]
+pub struct SuggestsPreview {
+    dependencies: Vec<String>,
+    packages: Vec<SuggestedPackage>,
+    /// Packages that could not be resolved once the suggestions are enabled
+    unresolved: Vec<String>,
+}
+
+impl SuggestsPreview {
+    pub fn num_to_compile(&self) -> usize {
+        self.packages.iter().filter(|p| p.needs_compilation).count()
+    }
+
+    pub fn sys_deps(&self) -> BTreeSet<&str> {
+        self.packages
+            .iter()
+            .flat_map(|p| p.sys_deps.iter().map(|s| s.as_str()))
+            .collect()
+    }
+
+    pub fn print(&self) {
+        let dependencies = self.dependencies.join(", ");
+        if self.packages.is_empty() {
+            println!("Enabling install_suggestions for {dependencies} would not add any packages");
+        } else {
+            println!(
+                "Enabling install_suggestions for {dependencies} would add {} package(s) ({} to compile):",
+                self.packages.len(),
+                self.num_to_compile(),
+            );
+            for package in &self.packages {
+                let mut line = format!(
+                    "  + {} {} ({}",
+                    package.name, package.version, package.package_type
+                );
+                if package.needs_compilation {
+                    line.push_str(", compile");
+                }
+                line.push(')');
+                if !package.sys_deps.is_empty() {
+                    line.push_str(&format!(" [sys deps: {}]", package.sys_deps.join(", ")));
+                }
+                println!("{line}");
+            }
+            let sys_deps = self.sys_deps();
+            if !sys_deps.is_empty() {
+                println!(
+                    "\nSystem dependencies: {}",
+                    sys_deps.into_iter().collect::<Vec<_>>().join(", ")
+                );
+            }
+        }
+
+        if !self.unresolved.is_empty() {
+            println!("\nCould not resolve: {}", self.unresolved.join(", "));
+        }
+    }
+}
+
+/// Resolves the project again with `install_suggestions` enabled on the given dependencies and
+/// compares it to the current resolution. The config on disk is not modified.
+pub fn preview_suggests(
+    context: &mut Context,
+    dependencies: &[String],
+    resolve_mode: ResolveMode,
+) -> Result<SuggestsPreview> {
+    for name in dependencies {
+        if !context
+            .config
+            .dependencies()
+            .iter()
+            .any(|d| d.name() == name)
+        {
+            return Err(anyhow!("{name} is not a dependency of the project"));
+        }
+    }
+
+    let (current, current_failed): (HashSet<_>, HashSet<_>) = {
+        let resolution = context.resolve(resolve_mode);
+        (
+            resolution
+                .found
+                .iter()
+                .map(|d| d.name.to_string())
+                .collect(),
+            resolution
+                .failed
+                .iter()
+                .map(|d| d.name.to_string())
+                .collect(),
+        )
+    };
+
+    for dep in context.config.dependencies_mut() {
+        if dependencies.iter().any(|name| name == dep.name()) {
+            dep.enable_install_suggestions();
+        }
+    }
+
+    let resolution = context.resolve(resolve_mode);
+    let mut packages: Vec<_> = resolution
+        .found
+        .iter()
+        .filter(|d| !current.contains(d.name.as_ref()) && !d.ignored)
+        .map(|d| SuggestedPackage {
+            name: d.name.to_string(),
+            version: d.version.original.clone(),
+            package_type: d.kind,
+            needs_compilation: d.kind == PackageType::Source
+                && !d.cache_status.binary_available()
+                && !d.source.is_builtin()
+                && !d.source.is_provided(),
+            sys_deps: context
+                .system_dependencies
+                .get(d.name.as_ref())
+                .cloned()
+                .unwrap_or_default(),
+        })
+        .collect();
+    packages.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let unresolved: BTreeSet<_> = resolution
+        .failed
+        .iter()
+        .map(|d| d.name.to_string())
+        .filter(|name| !current_failed.contains(name))
+        .collect();
+
+    Ok(SuggestsPreview {
+        dependencies: dependencies.to_vec(),
+        packages,
+        unresolved: unresolved.into_iter().collect(),
+    })
+}
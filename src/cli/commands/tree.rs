@@ -1,7 +1,7 @@
 use crate::Context;
 use crate::lockfile::Source;
 use crate::package::PackageType;
-use crate::{ResolvedDependency, UnresolvedDependency, Version};
+use crate::{DependencyType, ResolvedDependency, UnresolvedDependency, Version};
 use serde::Serialize;
 use std::collections::{HashMap, HashSet};
 
@@ -48,6 +48,9 @@ pub struct TreeNode<'a> {
     sys_deps: Option<&'a Vec<String>>,
     children: Vec<TreeNode<'a>>,
     state: NodeState<'a>,
+    /// Why the parent depends on that package, not set for the dependencies from the config
+    #[serde(skip_serializing_if = "Option::is_none")]
+    edge_type: Option<DependencyType>,
     #[serde(skip_serializing_if = "std::ops::Not::not")]
     is_duplicate: bool,
 }
@@ -69,6 +72,7 @@ impl<'a> TreeNode<'a> {
                 package_type: dependency.kind,
                 ignored: dependency.ignored,
             },
+            edge_type: None,
             is_duplicate: false,
         }
     }
@@ -105,6 +109,7 @@ impl<'a> TreeNode<'a> {
             sys_deps,
             children: vec![],
             state: NodeState::Unresolved { error, version_req },
+            edge_type: None,
             is_duplicate: false,
         }
     }
@@ -129,6 +134,13 @@ impl<'a> TreeNode<'a> {
         }
     }
 
+    fn get_edge_type(&self, show_edge_types: bool) -> String {
+        match self.edge_type {
+            Some(edge_type) if show_edge_types => format!(" ({edge_type})"),
+            _ => String::new(),
+        }
+    }
+
     fn get_details(&self, show_sys_deps: bool) -> String {
         let sys_deps = self.get_sys_deps(show_sys_deps);
 
@@ -175,6 +187,7 @@ impl<'a> TreeNode<'a> {
         current_depth: usize,
         max_depth: Option<usize>,
         show_sys_deps: bool,
+        show_edge_types: bool,
     ) {
         if let Some(d) = max_depth
             && current_depth > d
@@ -184,9 +197,10 @@ impl<'a> TreeNode<'a> {
 
         let dup_marker = if self.is_duplicate { " (*)" } else { "" };
         println!(
-            "{prefix}{} {} [{}]{dup_marker}",
+            "{prefix}{} {}{} [{}]{dup_marker}",
             kind.prefix(),
             self.name,
+            self.get_edge_type(show_edge_types),
             self.get_details(show_sys_deps)
         );
 
@@ -206,6 +220,7 @@ impl<'a> TreeNode<'a> {
                 current_depth + 1,
                 max_depth,
                 show_sys_deps,
+                show_edge_types,
             );
         }
     }
@@ -224,6 +239,7 @@ fn recursive_finder<'d>(
     deps_by_name: &HashMap<&'d str, &'d ResolvedDependency>,
     unresolved_deps_by_name: &HashMap<&'d str, &'d UnresolvedDependency>,
     context: &'d Context,
+    edge_types: &[DependencyType],
     ancestors: &mut Vec<&'d str>,
     visited: &mut HashSet<&'d str>,
 ) -> TreeNode<'d> {
@@ -255,15 +271,20 @@ fn recursive_finder<'d>(
         dep_names.sort_unstable();
         let children: Vec<_> = dep_names
             .into_iter()
-            .map(|dep_name| {
-                recursive_finder(
+            .map(|dep_name| (dep_name, resolved.dependency_type(dep_name)))
+            .filter(|(_, edge_type)| edge_types.is_empty() || edge_types.contains(edge_type))
+            .map(|(dep_name, edge_type)| {
+                let mut node = recursive_finder(
                     dep_name,
                     deps_by_name,
                     unresolved_deps_by_name,
                     context,
+                    edge_types,
                     ancestors,
                     visited,
-                )
+                );
+                node.edge_type = Some(edge_type);
+                node
             })
             .collect();
         ancestors.pop();
@@ -290,7 +311,7 @@ pub struct Tree<'a> {
 }
 
 impl Tree<'_> {
-    pub fn print(&self, max_depth: Option<usize>, show_sys_deps: bool) {
+    pub fn print(&self, max_depth: Option<usize>, show_sys_deps: bool, show_edge_types: bool) {
         for (i, tree) in self.nodes.iter().enumerate() {
             let dup_marker = if tree.is_duplicate { " (*)" } else { "" };
             println!(
@@ -307,6 +328,7 @@ impl Tree<'_> {
                         2,
                         max_depth,
                         show_sys_deps,
+                        show_edge_types,
                     );
                 }
             }
@@ -323,10 +345,12 @@ impl Tree<'_> {
     }
 }
 
+/// The dependency tree of the project, only following the edges of the given types if any
 pub fn tree<'a>(
     context: &'a Context,
     resolved_deps: &'a [ResolvedDependency],
     unresolved_deps: &'a [UnresolvedDependency],
+    edge_types: &[DependencyType],
 ) -> Tree<'a> {
     let deps_by_name: HashMap<_, _> = resolved_deps.iter().map(|d| (d.name.as_ref(), d)).collect();
     let unresolved_deps_by_name: HashMap<_, _> = unresolved_deps
@@ -349,6 +373,7 @@ pub fn tree<'a>(
                 &deps_by_name,
                 &unresolved_deps_by_name,
                 context,
+                edge_types,
                 &mut ancestors,
                 &mut visited,
             ));
@@ -363,3 +388,168 @@ pub fn tree<'a>(
 
     Tree { nodes }
 }
+
+/// An R package requiring a system dependency
+#[derive(Debug, PartialEq, Serialize)]
+pub struct SysDepPackage<'a> {
+    name: &'a str,
+    /// The dependencies from the config pulling that package in
+    root_dependencies: Vec<&'a str>,
+}
+
+/// A system dependency and all the R packages requiring it
+#[derive(Debug, PartialEq, Serialize)]
+pub struct SysDepUsage<'a> {
+    name: &'a str,
+    required_by: Vec<SysDepPackage<'a>>,
+}
+
+/// The tree inverted around system dependencies, to know why each of them is needed
+#[derive(Debug, Serialize)]
+pub struct SysDepsTree<'a> {
+    sys_deps: Vec<SysDepUsage<'a>>,
+}
+
+impl SysDepsTree<'_> {
+    pub fn print(&self) {
+        for (i, sys_dep) in self.sys_deps.iter().enumerate() {
+            println!("▶ {}", sys_dep.name);
+            for (j, pkg) in sys_dep.required_by.iter().enumerate() {
+                println!(
+                    "{} {} [from: {}]",
+                    child_kind(j, sys_dep.required_by.len()).prefix(),
+                    pkg.name,
+                    pkg.root_dependencies.join(", ")
+                );
+            }
+            if i + 1 < self.sys_deps.len() {
+                println!();
+            }
+        }
+    }
+}
+
+/// Which roots each package can be reached from, sorted
+pub(crate) fn roots_by_package<'a>(
+    roots: &[&'a str],
+    dependencies: &HashMap<&'a str, Vec<&'a str>>,
+) -> HashMap<&'a str, Vec<&'a str>> {
+    let mut roots_by_package: HashMap<&str, Vec<&str>> = HashMap::new();
+    for root in roots {
+        let mut visited = HashSet::new();
+        let mut queue = vec![*root];
+        while let Some(name) = queue.pop() {
+            if !visited.insert(name) {
+                continue;
+            }
+            roots_by_package.entry(name).or_default().push(root);
+            queue.extend(dependencies.get(name).into_iter().flatten());
+        }
+    }
+    for pkg_roots in roots_by_package.values_mut() {
+        pkg_roots.sort_unstable();
+        pkg_roots.dedup();
+    }
+    roots_by_package
+}
+
+fn invert_sys_deps<'a>(
+    roots: &[&'a str],
+    dependencies: &HashMap<&'a str, Vec<&'a str>>,
+    sys_deps: &'a HashMap<String, Vec<String>>,
+) -> Vec<SysDepUsage<'a>> {
+    let mut by_sys_dep: HashMap<&str, Vec<SysDepPackage>> = HashMap::new();
+    for (name, pkg_roots) in roots_by_package(roots, dependencies) {
+        let Some((pkg_name, pkg_sys_deps)) = sys_deps.get_key_value(name) else {
+            continue;
+        };
+        for sys_dep in pkg_sys_deps {
+            by_sys_dep
+                .entry(sys_dep.as_str())
+                .or_default()
+                .push(SysDepPackage {
+                    name: pkg_name.as_str(),
+                    root_dependencies: pkg_roots.clone(),
+                });
+        }
+    }
+
+    let mut out: Vec<_> = by_sys_dep
+        .into_iter()
+        .map(|(name, mut required_by)| {
+            required_by.sort_unstable_by_key(|p| p.name);
+            SysDepUsage { name, required_by }
+        })
+        .collect();
+    out.sort_unstable_by_key(|s| s.name);
+    out
+}
+
+pub fn sysdeps_tree<'a>(
+    context: &'a Context,
+    resolved_deps: &'a [ResolvedDependency],
+) -> SysDepsTree<'a> {
+    let dependencies: HashMap<_, _> = resolved_deps
+        .iter()
+        .map(|d| (d.name.as_ref(), d.all_dependencies_names()))
+        .collect();
+    let roots: Vec<_> = context
+        .config
+        .dependencies()
+        .iter()
+        .map(|d| d.name())
+        .filter(|name| dependencies.contains_key(name))
+        .collect();
+
+    SysDepsTree {
+        sys_deps: invert_sys_deps(&roots, &dependencies, &context.system_dependencies),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn can_invert_tree_around_sys_deps() {
+        let dependencies = HashMap::from([
+            ("httr", vec!["curl", "openssl"]),
+            ("sf", vec!["units"]),
+            ("gh", vec!["httr"]),
+            ("curl", vec![]),
+            ("openssl", vec![]),
+            ("units", vec![]),
+        ]);
+        let sys_deps = HashMap::from([
+            ("curl".to_string(), vec!["libcurl4-openssl-dev".to_string()]),
+            ("openssl".to_string(), vec!["libssl-dev".to_string()]),
+            (
+                "sf".to_string(),
+                vec!["libgdal-dev".to_string(), "libssl-dev".to_string()],
+            ),
+            ("units".to_string(), vec!["libudunits2-dev".to_string()]),
+        ]);
+
+        let out = invert_sys_deps(&["gh", "httr", "sf"], &dependencies, &sys_deps);
+        let summary: Vec<_> = out
+            .iter()
+            .map(|s| {
+                let packages: Vec<_> = s
+                    .required_by
+                    .iter()
+                    .map(|p| format!("{} <- {}", p.name, p.root_dependencies.join("+")))
+                    .collect();
+                format!("{}: {}", s.name, packages.join(", "))
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                "libcurl4-openssl-dev: curl <- gh+httr",
+                "libgdal-dev: sf <- sf",
+                "libssl-dev: openssl <- gh+httr, sf <- sf",
+                "libudunits2-dev: units <- sf",
+            ]
+        );
+    }
+}
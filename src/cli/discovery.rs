@@ -0,0 +1,149 @@
+use std::path::{Path, PathBuf};
+
+use walkdir::WalkDir;
+
+use crate::consts::{CONFIG_FILENAME, RV_DIR_NAME};
+
+/// How deep we look for projects inside a project.
+/// The whole tree could be huge (eg data folders) and projects are usually near the top.
+const MAX_CHILD_DEPTH: usize = 4;
+
+/// Other rv projects around a project, which make commands pick up a different config
+/// depending on the current directory
+#[derive(Debug, Default, PartialEq)]
+pub struct NestedProjects {
+    /// Projects containing this one, closest first
+    pub parents: Vec<PathBuf>,
+    /// Projects inside this one
+    pub children: Vec<PathBuf>,
+}
+
+impl NestedProjects {
+    pub fn is_empty(&self) -> bool {
+        self.parents.is_empty() && self.children.is_empty()
+    }
+
+    pub fn warnings(&self, project_dir: &Path) -> Vec<String> {
+        let mut out = Vec::new();
+        for parent in &self.parents {
+            out.push(format!(
+                "rv project {} is nested inside the rv project {}",
+                project_dir.display(),
+                parent.display()
+            ));
+        }
+        for child in &self.children {
+            out.push(format!(
+                "rv project {} contains the rv project {}",
+                project_dir.display(),
+                child.display()
+            ));
+        }
+        out
+    }
+}
+
+fn is_project(dir: &Path) -> bool {
+    dir.join(CONFIG_FILENAME).is_file()
+}
+
+/// Finds the closest directory containing a config file, starting from `start` and walking up
+/// its parents, the same way git and cargo find their project.
+pub fn find_project_dir(start: &Path) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .find(|dir| is_project(dir))
+        .map(Path::to_path_buf)
+}
+
+/// Looks for rv projects above and below `project_dir`.
+/// The home directory is ignored: it is where the global project lives when activating rv
+/// globally, so it is expected to contain other projects.
+pub fn find_nested_projects(project_dir: &Path, home_dir: Option<&Path>) -> NestedProjects {
+    let is_home = |dir: &Path| home_dir == Some(dir);
+
+    let parents = project_dir
+        .ancestors()
+        .skip(1)
+        .filter(|dir| !is_home(dir) && is_project(dir))
+        .map(Path::to_path_buf)
+        .collect();
+
+    if is_home(project_dir) {
+        return NestedProjects {
+            parents,
+            children: Vec::new(),
+        };
+    }
+
+    let children = WalkDir::new(project_dir)
+        .min_depth(1)
+        .max_depth(MAX_CHILD_DEPTH)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|e| {
+            let name = e.file_name().to_string_lossy();
+            // Skip the library and hidden folders like .git
+            e.file_type().is_dir() && name != RV_DIR_NAME && !name.starts_with('.')
+        })
+        .filter_map(Result::ok)
+        .filter(|e| is_project(e.path()))
+        .map(|e| e.into_path())
+        .collect();
+
+    NestedProjects { parents, children }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_project(dir: &Path) {
+        fs_err::create_dir_all(dir).unwrap();
+        fs_err::write(dir.join(CONFIG_FILENAME), "").unwrap();
+    }
+
+    #[test]
+    fn can_find_project_dir_in_parents() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().join("project");
+        create_project(&root);
+        let subdir = root.join("scripts").join("analysis");
+        fs_err::create_dir_all(&subdir).unwrap();
+
+        assert_eq!(find_project_dir(&root), Some(root.clone()));
+        assert_eq!(find_project_dir(&subdir), Some(root.clone()));
+        create_project(&subdir);
+        assert_eq!(find_project_dir(&subdir), Some(subdir.clone()));
+    }
+
+    #[test]
+    fn can_find_nested_projects() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        create_project(root);
+        let project = root.join("analysis");
+        create_project(&project);
+        create_project(&project.join("subproject"));
+        create_project(&project.join("a").join("b").join("deep"));
+        // Ignored folders
+        create_project(&project.join(RV_DIR_NAME).join("library"));
+        create_project(&project.join(".git").join("other"));
+
+        let nested = find_nested_projects(&project, None);
+        assert_eq!(nested.parents, vec![root.to_path_buf()]);
+        assert_eq!(
+            nested.children,
+            vec![
+                project.join("a").join("b").join("deep"),
+                project.join("subproject")
+            ]
+        );
+
+        // Same as if the root was the home directory
+        let nested = find_nested_projects(&project, Some(root));
+        assert!(nested.parents.is_empty());
+        let nested = find_nested_projects(root, Some(root));
+        assert!(nested.is_empty());
+    }
+}
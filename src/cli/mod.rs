@@ -1,10 +1,23 @@
 mod commands;
+mod discovery;
+mod plan_cache;
 mod resolution;
 mod sync;
+mod sync_stamp;
 pub mod utils;
 
 pub use crate::{Context, RCommandLookup, ResolveMode};
-pub use commands::{export_renv, find_r_repositories, init, init_structure, migrate_renv, tree};
+pub use commands::{
+    BinaryRepository, FoundRepository, PolicyViolation, RepositoryOrigin, SourceExplanation,
+    check_policy, diff_lockfile, diff_repositories, environment_fingerprint, explain_source,
+    export_bundle, export_conda, export_nix, export_renv, export_validation_report,
+    find_binary_repository, find_r_repositories, find_r_repositories_with_origin, init,
+    init_structure, migrate_renv, parse_repository_arg, policy_sarif, preview_suggests,
+    sysdeps_tree, tree,
+};
+pub use discovery::{NestedProjects, find_nested_projects, find_project_dir};
+pub use plan_cache::PlanCache;
 pub use resolution::resolve_dependencies;
 pub use sync::SyncHelper;
+pub use sync_stamp::SyncStamp;
 pub use utils::OutputFormat;
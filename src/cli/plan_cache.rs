@@ -0,0 +1,128 @@
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use fs_err as fs;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::cli::sync_stamp::dependency_urls;
+use crate::{ConfigDependency, Context};
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedPlan {
+    key: String,
+    output: String,
+}
+
+/// Keeps the output of the last `rv plan` of a project around so that repeated invocations,
+/// eg from an IDE, are instant when nothing changed.
+///
+/// The key covers the config and the URLs it expands from environment variables, the lockfile,
+/// the library content, the repository databases and the R/rv versions so any change to them
+/// invalidates the cached plan.
+#[derive(Debug)]
+pub struct PlanCache {
+    path: PathBuf,
+    key: String,
+}
+
+impl PlanCache {
+    /// Returns `None` if the plan can't be cached, eg a repository database needs to be refreshed
+    /// or a dependency can change without the config changing (git branches, local paths).
+    /// `args` should contain every CLI argument affecting the plan output.
+    pub fn new(context: &Context, config_file: &Path, args: &str) -> Option<Self> {
+        for dep in context.config.dependencies() {
+            match dep {
+                ConfigDependency::Local { .. } => return None,
+                ConfigDependency::Git {
+                    commit: None,
+                    tag: None,
+                    ..
+                } => return None,
+                _ => (),
+            }
+        }
+
+        let mut key = String::new();
+        writeln!(key, "rv={}", env!("CARGO_PKG_VERSION")).unwrap();
+        writeln!(key, "args={args}").unwrap();
+        writeln!(key, "r_version={}", context.r_version.original).unwrap();
+        writeln!(key, "config={}", fs::read_to_string(config_file).ok()?).unwrap();
+        let lockfile = fs::read_to_string(context.lockfile_path()).unwrap_or_default();
+        writeln!(key, "lockfile={lockfile}").unwrap();
+
+        for repo in context.config.repositories() {
+            let (path, fresh) = context.cache.local().get_package_db_entry(repo.url());
+            if !fresh {
+                return None;
+            }
+            let mtime = path.metadata().ok()?.modified().ok()?;
+            writeln!(key, "repo={} {mtime:?} {}", repo.url(), repo.force_source).unwrap();
+        }
+        for url in dependency_urls(&context.config) {
+            writeln!(key, "dependency={url}").unwrap();
+        }
+
+        let mut library: Vec<_> = context
+            .library
+            .packages
+            .iter()
+            .map(|(name, version)| format!("{name}={version}"))
+            .chain(context.library.broken.iter().map(|name| format!("{name}=")))
+            .chain(
+                context
+                    .provided_packages
+                    .iter()
+                    .map(|(name, (_, pkg))| format!("provided:{name}={}", pkg.version)),
+            )
+            .collect();
+        library.sort();
+        writeln!(key, "library={}", library.join(",")).unwrap();
+
+        let path = context
+            .cache
+            .local()
+            .root
+            .join("plans")
+            .join(format!("{}.json", context.project_id()));
+
+        Some(Self {
+            path,
+            key: hex::encode(Sha256::digest(key.as_bytes())),
+        })
+    }
+
+    /// Returns the output of the last plan if it is still valid
+    pub fn get(&self) -> Option<String> {
+        let content = fs::read_to_string(&self.path).ok()?;
+        let cached: CachedPlan = serde_json::from_str(&content).ok()?;
+        if cached.key == self.key {
+            log::debug!("Using cached plan from {}", self.path.display());
+            Some(cached.output)
+        } else {
+            None
+        }
+    }
+
+    /// Failing to save the plan is not an error, we will just resolve again next time
+    pub fn save(&self, output: &str) {
+        let cached = CachedPlan {
+            key: self.key.clone(),
+            output: output.to_string(),
+        };
+        let res = self
+            .path
+            .parent()
+            .map(fs::create_dir_all)
+            .transpose()
+            .and_then(|_| {
+                fs::write(
+                    &self.path,
+                    serde_json::to_string(&cached).expect("valid json"),
+                )
+            });
+        if let Err(e) = res {
+            log::debug!("Failed to save plan cache: {e}");
+        }
+    }
+}
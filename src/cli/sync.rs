@@ -1,14 +1,18 @@
-use std::collections::{BTreeMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Write;
 use std::path::PathBuf;
-use std::time::Duration;
+use std::time::Instant;
 
 use anyhow::Result;
 use fs_err::{self as fs};
 use serde::Serialize;
 
-use crate::cli::{Context, OutputFormat, ResolveMode, resolve_dependencies};
+use crate::cli::{Context, OutputFormat, PlanCache, ResolveMode, resolve_dependencies};
 use crate::sync::OutputSection;
-use crate::{Lockfile, Resolution, SyncChange, SyncHandler, system_req, timeit};
+use crate::{
+    FailureAction, Lockfile, Resolution, SyncChange, SyncHandler, SyncReport, format_duration,
+    system_req, timeit,
+};
 
 #[derive(Debug, Default, Serialize)]
 struct SyncChanges {
@@ -38,6 +42,19 @@ pub struct SyncHelper {
     pub save_install_logs_in: Option<PathBuf>,
     pub exit_on_failure: bool,
     pub locked: bool,
+    /// Only used for dry runs: where to save the plan output for the next invocation
+    pub plan_cache: Option<PlanCache>,
+    /// Load every newly installed package in R after the sync to catch load time failures
+    pub smoke_test: bool,
+    /// Dependencies that were just added to the config. If they don't affect the packages
+    /// already locked, only them are resolved and installed, see [`Context::resolve_added`].
+    pub added: Vec<String>,
+    /// Go through the whole sync without installing anything, failing the packages given.
+    /// Unlike a plain dry run, the progress bar is shown and the report is written.
+    pub simulate_failures: Option<Vec<String>>,
+    /// Ask whether to retry, skip or abort when a package fails to install rather than aborting.
+    /// Only for a terminal, skipped packages make the sync fail once everything else is done.
+    pub interactive: bool,
 }
 
 impl Default for SyncHelper {
@@ -48,6 +65,11 @@ impl Default for SyncHelper {
             save_install_logs_in: None,
             exit_on_failure: true,
             locked: false,
+            plan_cache: None,
+            smoke_test: false,
+            added: Vec::new(),
+            simulate_failures: None,
+            interactive: false,
         }
     }
 }
@@ -64,19 +86,81 @@ impl SyncHelper {
             ));
         }
 
-        let sync_start = std::time::Instant::now();
+        // A read-only project is only planned and we error if it doesn't match the config
+        let readonly = if self.dry_run || self.simulate_failures.is_some() {
+            None
+        } else {
+            context.readonly_reason()
+        };
+        let dry_run = self.dry_run || readonly.is_some() || self.simulate_failures.is_some();
+
+        let sync_start = Instant::now();
         // TODO: exit on failure without println? and move that to main.rs
         // otherwise callers will think everything is fine
-        let resolution = resolve_dependencies(context, resolve_mode, self.exit_on_failure);
+        let incremental = if !self.added.is_empty()
+            && resolve_mode == ResolveMode::Default
+            && context.config.use_lockfile()
+            && !self.locked
+        {
+            context.resolve_added(&self.added)
+        } else {
+            None
+        };
+        let is_incremental = incremental.is_some();
+        let resolution = match incremental {
+            Some(resolution) => {
+                log::debug!("Only resolving the added dependencies");
+                if !resolution.is_success() && self.exit_on_failure {
+                    resolution.print_failures();
+                    ::std::process::exit(1)
+                }
+                resolution
+            }
+            None => resolve_dependencies(context, resolve_mode, self.exit_on_failure),
+        };
         if !resolution.is_success() {
             return Ok(resolution);
         }
+        // What the lockfile should contain after the sync
+        let new_lockfile = || {
+            match (&context.lockfile, is_incremental) {
+                (Some(lockfile), true) => lockfile.with_added(resolution.found.clone()),
+                _ => Lockfile::from_resolved(
+                    &context.r_version.major_minor(),
+                    resolution.found.clone(),
+                ),
+            }
+            .with_repositories(context.config.repositories())
+        };
+
+        for message in resolution
+            .provided_warning_messages()
+            .into_iter()
+            .chain(resolution.archived_warning_messages())
+        {
+            eprintln!("WARNING: {message}");
+        }
+        if let Some(lockfile) = &context.lockfile {
+            let moves = lockfile.repository_moves(&resolution.found);
+            if !moves.is_empty() {
+                eprintln!(
+                    "The repositories changed since the lockfile was written, packages moving to another repository:"
+                );
+                for m in moves {
+                    eprintln!("  - {m}");
+                }
+            }
+        }
+        for name in self.simulate_failures.iter().flatten() {
+            if !resolution.found.iter().any(|d| d.name.as_ref() == name) {
+                eprintln!("WARNING: {name} is not a dependency of the project, it can't fail");
+            }
+        }
 
         if self.locked {
-            let new_lockfile =
-                Lockfile::from_resolved(&context.r_version.major_minor(), resolution.found.clone());
+            let new_lockfile = new_lockfile();
             if let Some(lockfile) = &context.lockfile {
-                if lockfile != &new_lockfile {
+                if lockfile.needs_update(&new_lockfile) {
                     return Err(anyhow::anyhow!(
                         "the lockfile {} needs to be updated but --locked was passed to prevent this",
                         context.config.lockfile_name()
@@ -90,44 +174,61 @@ impl SyncHelper {
             }
         }
 
+        let mut handler = SyncHandler::new(context, self.save_install_logs_in.clone());
+        if let Some(failures) = &self.simulate_failures {
+            handler.simulate(failures.iter().cloned());
+        } else if dry_run {
+            handler.dry_run();
+        } else if self.interactive {
+            handler.set_on_failure(|name, e| ask_failure_action(name, e));
+        }
+        if context.show_progress_bar {
+            handler.show_progress_bar();
+        }
+        handler.set_uses_lockfile(context.config.use_lockfile());
+        if is_incremental {
+            handler.additive();
+        }
+
         match timeit!(
-            if self.dry_run {
+            if dry_run {
                 "Planned dependencies"
             } else {
                 "Synced dependencies"
             },
-            {
-                let mut handler = SyncHandler::new(context, self.save_install_logs_in.clone());
-                if self.dry_run {
-                    handler.dry_run();
-                }
-                if context.show_progress_bar {
-                    handler.show_progress_bar();
-                }
-                handler.set_uses_lockfile(context.config.use_lockfile());
-                handler.handle(&resolution.found, &context.r_cmd)
-            }
+            handler.handle(&resolution.found, &context.r_cmd)
         ) {
             Ok(mut changes) => {
-                if !self.dry_run && context.config.use_lockfile() && !self.locked {
-                    if resolution.found.is_empty() {
+                let lockfile_outdated = readonly.is_some()
+                    && context.config.use_lockfile()
+                    && !self.locked
+                    && context
+                        .lockfile
+                        .as_ref()
+                        .is_none_or(|l| l.needs_update(&new_lockfile()))
+                    && !(context.lockfile.is_none() && resolution.found.is_empty());
+                if !dry_run && context.config.use_lockfile() && !self.locked {
+                    if resolution.found.is_empty() && !is_incremental {
                         // delete the lockfiles if there are no dependencies
                         let lockfile_path = context.lockfile_path();
                         if lockfile_path.exists() {
                             fs::remove_file(lockfile_path)?;
                         }
                     } else {
-                        let lockfile = Lockfile::from_resolved(
-                            &context.r_version.major_minor(),
-                            resolution.found.clone(),
-                        );
+                        let lockfile = new_lockfile();
                         if let Some(existing_lockfile) = &context.lockfile {
                             if existing_lockfile != &lockfile {
-                                lockfile.save(context.lockfile_path())?;
+                                lockfile.save_with_url_templates(
+                                    context.lockfile_path(),
+                                    context.config.url_templates(),
+                                )?;
                                 log::debug!("Lockfile changed, saving it.");
                             }
                         } else {
-                            lockfile.save(context.lockfile_path())?;
+                            lockfile.save_with_url_templates(
+                                context.lockfile_path(),
+                                context.config.url_templates(),
+                            )?;
                         }
                     }
                 }
@@ -144,6 +245,12 @@ impl SyncHelper {
                     change.update_sys_deps_status(&sysdeps_status);
                 }
 
+                let skipped = handler.skipped();
+                let mut report = self.new_report(context, sync_start);
+                report.add_changes(&changes, context.cache.local());
+                report.add_skipped(&skipped);
+                self.save_report(context, &report, dry_run);
+
                 if let Some(log_folder) = &self.save_install_logs_in {
                     fs::create_dir_all(log_folder)?;
                     for change in changes.iter().filter(|x| x.installed) {
@@ -154,20 +261,32 @@ impl SyncHelper {
                     }
                 }
 
+                let num_changes = changes.len();
+                let smoke_test_packages: HashSet<_> = changes
+                    .iter()
+                    .filter(|c| c.installed && !c.is_builtin() && !c.is_provided())
+                    .map(|c| c.name.clone())
+                    .collect();
+
                 if let Some(format) = &self.output_format {
                     if format.is_json() {
-                        println!(
-                            "{}",
+                        let output = format!(
+                            "{}\n",
                             serde_json::to_string_pretty(&SyncChanges::from_changes(changes))
                                 .expect("valid json")
                         );
+                        print!("{output}");
+                        self.save_plan(&output);
                     } else {
                         let installed_count = changes.iter().filter(|c| c.installed).count();
                         let removed_count = changes.iter().filter(|c| !c.installed).count();
 
-                        print_grouped_changes(&changes, self.dry_run, !sysdeps_status.is_empty());
+                        let output =
+                            format_grouped_changes(&changes, dry_run, !sysdeps_status.is_empty());
+                        print!("{output}");
+                        self.save_plan(&output);
 
-                        if !self.dry_run {
+                        if !dry_run {
                             println!(
                                 "sync completed in {} ({} installed, {} removed)",
                                 format_duration(sync_start.elapsed()),
@@ -178,10 +297,42 @@ impl SyncHelper {
                     }
                 }
 
+                if let Some(reason) = readonly
+                    && (num_changes > 0 || lockfile_outdated)
+                {
+                    let mut drift = vec![format!("{num_changes} package(s) to change")];
+                    if lockfile_outdated {
+                        drift.push(format!("{} to update", context.config.lockfile_name()));
+                    }
+                    return Err(anyhow::anyhow!(
+                        "The project is read-only ({reason}) and out of sync: {}. It needs to be updated from where it is managed, eg CI.",
+                        drift.join(", ")
+                    ));
+                }
+
+                if self.smoke_test && !dry_run {
+                    self.run_smoke_test(context, &resolution, &smoke_test_packages)?;
+                }
+
+                if !skipped.is_empty() {
+                    return Err(anyhow::anyhow!(
+                        "{} package(s) were skipped and are not in the library: {}. Run `rv sync` again once they are fixed.",
+                        skipped.len(),
+                        skipped
+                            .iter()
+                            .map(|(name, _)| name.as_str())
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ));
+                }
+
                 Ok(resolution)
             }
             Err(e) => {
-                if context.staging_path().is_dir() {
+                let mut report = self.new_report(context, sync_start);
+                report.set_error(&e);
+                self.save_report(context, &report, dry_run);
+                if readonly.is_none() && context.staging_path().is_dir() {
                     fs::remove_dir_all(context.staging_path())?;
                 }
                 Err(e.into())
@@ -190,11 +341,147 @@ impl SyncHelper {
     }
 }
 
-/// Print changes grouped by section with aligned columns
-fn print_grouped_changes(changes: &[SyncChange], dry_run: bool, supports_sysdeps: bool) {
+impl SyncHelper {
+    fn run_smoke_test(
+        &self,
+        context: &Context,
+        resolution: &Resolution,
+        installed: &HashSet<String>,
+    ) -> Result<()> {
+        let packages = dependency_order(installed, resolution);
+        let failures = timeit!(
+            "Smoke tested installed packages",
+            crate::smoke_test(
+                &context.r_cmd.bin_path,
+                &context.library.path,
+                &context.additional_libraries,
+                context.config.env_vars(),
+                &packages,
+            )?
+        );
+
+        for failure in &failures {
+            eprintln!("Failed to load {}: {}", failure.package, failure.error);
+        }
+        if failures.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow::anyhow!(
+                "{} of {} installed package(s) failed to load",
+                failures.len(),
+                packages.len()
+            ))
+        }
+    }
+
+    fn new_report(&self, context: &Context, sync_start: Instant) -> SyncReport {
+        let mut report = SyncReport::new(
+            &context.r_version.original,
+            jiff::Timestamp::now().to_string(),
+            sync_start.elapsed(),
+        );
+        report.simulated = self.simulate_failures.is_some();
+        report
+    }
+
+    /// Not being able to write the report should not fail the sync.
+    /// A simulated sync writes its own report to keep the one of the last real sync.
+    fn save_report(&self, context: &Context, report: &SyncReport, dry_run: bool) {
+        let path = if report.simulated {
+            context.simulated_sync_report_path()
+        } else if dry_run {
+            return;
+        } else {
+            context.last_sync_report_path()
+        };
+        if let Err(e) = report.save(&path) {
+            log::warn!("Failed to write sync report to {}: {e}", path.display());
+        }
+    }
+
+    fn save_plan(&self, output: &str) {
+        if self.dry_run
+            && let Some(plan_cache) = &self.plan_cache
+        {
+            plan_cache.save(output);
+        }
+    }
+}
+
+/// Asks on the terminal what to do with a package that failed to install, aborting if there
+/// is no answer
+fn ask_failure_action(package_name: &str, error: &dyn std::fmt::Display) -> FailureAction {
+    let indented = error.to_string().replace('\n', "\n    ");
+    eprintln!("Failed to install {package_name}:\n    {indented}");
+    loop {
+        eprint!("[r]etry, [s]kip {package_name} and the packages depending on it, or [a]bort? ");
+        let mut answer = String::new();
+        if !matches!(std::io::stdin().read_line(&mut answer), Ok(n) if n > 0) {
+            return FailureAction::Abort;
+        }
+        match answer.trim().to_lowercase().as_str() {
+            "r" | "retry" => return FailureAction::Retry,
+            "s" | "skip" => return FailureAction::Skip,
+            "" | "a" | "abort" => return FailureAction::Abort,
+            _ => eprintln!("Please answer r, s or a"),
+        }
+    }
+}
+
+/// Orders the packages so that dependencies come before the packages needing them
+fn dependency_order<'a>(packages: &HashSet<String>, resolution: &'a Resolution) -> Vec<&'a str> {
+    fn visit<'a>(
+        name: &'a str,
+        deps: &HashMap<&'a str, Vec<&'a str>>,
+        packages: &HashSet<String>,
+        seen: &mut HashSet<&'a str>,
+        out: &mut Vec<&'a str>,
+    ) {
+        if !seen.insert(name) {
+            return;
+        }
+        for dep in deps.get(name).into_iter().flatten() {
+            visit(dep, deps, packages, seen, out);
+        }
+        if packages.contains(name) {
+            out.push(name);
+        }
+    }
+
+    let deps: HashMap<_, _> = resolution
+        .found
+        .iter()
+        .map(|d| {
+            let mut names: Vec<_> = d.dependencies.iter().map(|x| x.name()).collect();
+            if d.install_suggests {
+                names.extend(d.suggests.iter().map(|x| x.name()));
+            }
+            (d.name.as_ref(), names)
+        })
+        .collect();
+    // Newly installed packages are always part of the resolution
+    let mut roots: Vec<_> = resolution
+        .found
+        .iter()
+        .map(|d| d.name.as_ref())
+        .filter(|name| packages.contains(*name))
+        .collect();
+    roots.sort_unstable();
+
+    let mut seen = HashSet::new();
+    let mut out = Vec::with_capacity(packages.len());
+    for name in roots {
+        visit(name, &deps, packages, &mut seen, &mut out);
+    }
+    out
+}
+
+/// Format changes grouped by section with aligned columns
+fn format_grouped_changes(changes: &[SyncChange], dry_run: bool, supports_sysdeps: bool) -> String {
+    let mut out = String::new();
     if changes.is_empty() {
-        println!("Nothing to do");
-        return;
+        writeln!(out, "Nothing to do").unwrap();
+        return out;
     }
 
     // Group by section
@@ -228,9 +515,10 @@ fn print_grouped_changes(changes: &[SyncChange], dry_run: bool, supports_sysdeps
         .unwrap_or(0);
 
     // Section order
-    let section_order: [OutputSection; 5] = [
+    let section_order: [OutputSection; 6] = [
         OutputSection::GlobalCache,
         OutputSection::LocalCache,
+        OutputSection::SiteLibrary,
         OutputSection::Downloaded,
         OutputSection::LocalPath,
         OutputSection::Removed,
@@ -238,7 +526,7 @@ fn print_grouped_changes(changes: &[SyncChange], dry_run: bool, supports_sysdeps
 
     for section in section_order {
         if let Some(items) = sections.get(&section) {
-            println!("{} ({}):", section.header(dry_run), items.len());
+            writeln!(out, "{} ({}):", section.header(dry_run), items.len()).unwrap();
             for c in items {
                 if c.installed {
                     let timing_str = if !dry_run {
@@ -247,7 +535,8 @@ fn print_grouped_changes(changes: &[SyncChange], dry_run: bool, supports_sysdeps
                         String::new()
                     };
                     let sys_deps_str = format_sys_deps(c, supports_sysdeps);
-                    println!(
+                    writeln!(
+                        out,
                         "  + {:<name_w$}  {:>ver_w$}  {:<kind_w$}  {:<src_w$}{}{}",
                         c.name,
                         c.version.as_ref().unwrap(),
@@ -259,14 +548,29 @@ fn print_grouped_changes(changes: &[SyncChange], dry_run: bool, supports_sysdeps
                         ver_w = max_ver,
                         kind_w = max_kind,
                         src_w = max_source,
-                    );
+                    )
+                    .unwrap();
                 } else {
-                    println!("  - {}", c.name);
+                    writeln!(out, "  - {}", c.name).unwrap();
                 }
             }
-            println!();
+            writeln!(out).unwrap();
         }
     }
+
+    let mut notes: Vec<_> = changes
+        .iter()
+        .filter_map(|c| c.builtin_override_note())
+        .collect();
+    notes.sort();
+    if !notes.is_empty() {
+        writeln!(out, "Notes:").unwrap();
+        for note in notes {
+            writeln!(out, "  {note}").unwrap();
+        }
+        writeln!(out).unwrap();
+    }
+    out
 }
 
 /// Format sys deps for display
@@ -294,13 +598,3 @@ fn format_sys_deps(change: &SyncChange, supports_sysdeps: bool) -> String {
 
     format!("  [sys deps: {}]", deps.join(", "))
 }
-
-/// Format duration for display (e.g., "1.2s" or "234ms")
-fn format_duration(d: Duration) -> String {
-    let ms = d.as_millis();
-    if ms >= 1000 {
-        format!("{:.1}s", ms as f64 / 1000.0)
-    } else {
-        format!("{}ms", ms)
-    }
-}
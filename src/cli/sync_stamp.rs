@@ -0,0 +1,163 @@
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use fs_err as fs;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::consts::{
+    LIBRARY_DIR_ENV_VAR_NAME, RENV_PATHS_LIBRARY_ENV_VAR_NAME, RV_DIR_NAME, STAGING_DIR_NAME,
+    SYNC_STAMP_FILENAME,
+};
+use crate::{Config, ConfigDependency, Context, SystemInfo};
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Stamp {
+    key: String,
+    library: PathBuf,
+}
+
+/// Remembers the state of a project after its last successful `rv sync` so running it again
+/// when nothing changed exits immediately, without looking for R, loading the repository
+/// databases or reading every DESCRIPTION file of the library.
+///
+/// The key covers the config and the URLs it expands from environment variables, the lockfile,
+/// the rv version, the system and a listing of the library and additional libraries (entry names
+/// and mtimes) so any change to them invalidates the stamp. Like for [`crate::cli::PlanCache`], projects with dependencies that can change
+/// without the config changing (local paths, unpinned git) are never stamped.
+#[derive(Debug)]
+pub struct SyncStamp;
+
+impl SyncStamp {
+    /// Whether the project is in the same state as after the last successful sync
+    pub fn is_fresh(config_file: &Path) -> bool {
+        let Some(project_dir) = config_file.parent() else {
+            return false;
+        };
+        let Ok(content) = fs::read_to_string(stamp_path(project_dir)) else {
+            return false;
+        };
+        let Ok(stamp) = serde_json::from_str::<Stamp>(&content) else {
+            return false;
+        };
+        let Ok(config) = Config::from_file(config_file) else {
+            return false;
+        };
+        compute_key(config_file, &config, &stamp.library).is_some_and(|key| key == stamp.key)
+    }
+
+    /// Failing to save the stamp is not an error, the next sync will just do the full check
+    pub fn save(context: &Context, config_file: &Path) {
+        let path = stamp_path(&context.project_dir);
+        let Some(key) = compute_key(config_file, &context.config, context.library_path()) else {
+            // Remove a stamp that doesn't apply anymore, eg a local dependency was added
+            let _ = fs::remove_file(&path);
+            return;
+        };
+        let stamp = Stamp {
+            key,
+            library: context.library_path().to_path_buf(),
+        };
+        let res = path
+            .parent()
+            .map(fs::create_dir_all)
+            .transpose()
+            .and_then(|_| fs::write(&path, serde_json::to_string(&stamp).expect("valid json")));
+        if let Err(e) = res {
+            log::debug!("Failed to save sync stamp: {e}");
+        }
+    }
+}
+
+fn stamp_path(project_dir: &Path) -> PathBuf {
+    project_dir.join(RV_DIR_NAME).join(SYNC_STAMP_FILENAME)
+}
+
+/// Names and mtimes of the entries of a library, without looking inside the packages
+fn list_library(path: &Path) -> Option<String> {
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(path).ok()? {
+        let entry = entry.ok()?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name == STAGING_DIR_NAME {
+            continue;
+        }
+        let metadata = fs::symlink_metadata(entry.path()).ok()?;
+        let target = fs::read_link(entry.path()).ok();
+        entries.push(format!("{name} {:?} {target:?}", metadata.modified().ok()?));
+    }
+    entries.sort();
+    Some(entries.join(","))
+}
+
+/// The URLs of the git and URL dependencies, once environment variables are expanded. They can
+/// change without the config file changing so they need to be part of the keys.
+pub(crate) fn dependency_urls(config: &Config) -> impl Iterator<Item = &str> {
+    config.dependencies().iter().filter_map(|dep| match dep {
+        ConfigDependency::Git { git, .. } => Some(git.url()),
+        ConfigDependency::Url { url, .. } => Some(url.as_str()),
+        _ => None,
+    })
+}
+
+fn compute_key(config_file: &Path, config: &Config, library: &Path) -> Option<String> {
+    if !config.use_lockfile() || config.r_version().is_r_devel() {
+        return None;
+    }
+    for dep in config.dependencies() {
+        match dep {
+            ConfigDependency::Local { .. } => return None,
+            ConfigDependency::Git {
+                commit: None,
+                tag: None,
+                ..
+            } => return None,
+            _ => (),
+        }
+    }
+    let project_dir = config_file.parent()?;
+
+    let mut key = String::new();
+    writeln!(key, "rv={}", env!("CARGO_PKG_VERSION")).unwrap();
+    writeln!(key, "system={:?}", SystemInfo::from_os_info()).unwrap();
+    for var in [LIBRARY_DIR_ENV_VAR_NAME, RENV_PATHS_LIBRARY_ENV_VAR_NAME] {
+        writeln!(key, "library_env={var}={:?}", std::env::var(var).ok()).unwrap();
+    }
+    writeln!(key, "config={}", fs::read_to_string(config_file).ok()?).unwrap();
+    for repo in config.repositories() {
+        writeln!(key, "repo={}", repo.url()).unwrap();
+    }
+    for url in dependency_urls(config) {
+        writeln!(key, "dependency={url}").unwrap();
+    }
+    let lockfile = fs::read_to_string(project_dir.join(config.lockfile_name())).ok()?;
+    writeln!(key, "lockfile={lockfile}").unwrap();
+    writeln!(key, "library={}", list_library(library)?).unwrap();
+    for additional in config.additional_libraries() {
+        let path = project_dir.join(additional);
+        // A missing additional library is fine, it just provides nothing
+        let listing = list_library(&path).unwrap_or_default();
+        writeln!(key, "additional={} {listing}", path.display()).unwrap();
+    }
+
+    Some(hex::encode(Sha256::digest(key.as_bytes())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn library_listing_changes_with_content() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join(STAGING_DIR_NAME)).unwrap();
+        fs::create_dir_all(dir.path().join("rlang")).unwrap();
+        let before = list_library(dir.path()).unwrap();
+        assert!(before.starts_with("rlang "));
+        assert!(!before.contains(STAGING_DIR_NAME));
+
+        fs::create_dir_all(dir.path().join("cli")).unwrap();
+        assert_ne!(list_library(dir.path()).unwrap(), before);
+        assert!(list_library(&dir.path().join("missing")).is_none());
+    }
+}
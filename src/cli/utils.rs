@@ -28,7 +28,7 @@ macro_rules! timeit {
         let start = std::time::Instant::now();
         let res = $x;
         let duration = start.elapsed();
-        log::info!("{} in {}ms", $msg, duration.as_millis());
+        log::info!("{} in {}", $msg, $crate::format_duration(duration));
         res
     }};
 }
@@ -0,0 +1,205 @@
+use std::fmt::Write;
+
+use url::Url;
+
+use crate::consts::{CRAN_HOSTS, PPM_HOSTS};
+use crate::lockfile::{LockedPackage, Source};
+use crate::{Config, Lockfile};
+
+const CONDA_FORGE: &str = "conda-forge";
+const BIOCONDA: &str = "bioconda";
+
+/// Conda package versions can't contain `-` so conda-forge replaces them with `_`
+fn conda_version(version: &str) -> String {
+    version.replace('-', "_")
+}
+
+/// The channel republishing the packages of that repository, if it is CRAN or Bioconductor,
+/// either directly or through Posit Package Manager.
+/// Other repositories can have packages that aren't on CRAN or a different version of them.
+fn conda_channel(repository: &Url) -> Option<&'static str> {
+    let host = repository.host_str()?;
+    if CRAN_HOSTS.contains(&host) {
+        return Some(CONDA_FORGE);
+    }
+    if host == "bioconductor.org" || host == "www.bioconductor.org" {
+        return Some(BIOCONDA);
+    }
+    if PPM_HOSTS.contains(&host) {
+        return match repository.path_segments()?.next()? {
+            "cran" => Some(CONDA_FORGE),
+            "bioconductor" => Some(BIOCONDA),
+            _ => None,
+        };
+    }
+    None
+}
+
+/// Returns the conda package name and the channel it is published on.
+/// Only packages coming from CRAN or Bioconductor are published on conda.
+fn conda_package(pkg: &LockedPackage) -> Option<(String, &'static str)> {
+    let Source::Repository { repository } = &pkg.source else {
+        return None;
+    };
+    let name = pkg.name.to_lowercase();
+    match conda_channel(repository)? {
+        BIOCONDA => Some((format!("bioconductor-{name}"), BIOCONDA)),
+        channel => Some((format!("r-{name}"), channel)),
+    }
+}
+
+/// Convert an rv Lockfile + Config into a conda environment.yml.
+/// Returns the environment file content and a list of warnings for packages that can't be found
+/// on conda, which are listed as comments in the file.
+pub fn to_conda_environment(lockfile: &Lockfile, config: &Config) -> (String, Vec<String>) {
+    let mut warnings = Vec::new();
+    let mut dependencies = vec![format!("r-base={}", lockfile.r_version_string())];
+    let mut unmapped = Vec::new();
+    let mut channels = vec![CONDA_FORGE];
+
+    for pkg in lockfile.packages() {
+        // Builtin packages come with r-base
+        if pkg.source.is_builtin() {
+            continue;
+        }
+
+        if let Some((name, channel)) = conda_package(pkg) {
+            if !channels.contains(&channel) {
+                channels.push(channel);
+            }
+            dependencies.push(format!("{name}={}", conda_version(&pkg.version)));
+        } else {
+            warnings.push(format!(
+                "`{}`: not available on conda, it needs to be installed from {}",
+                pkg.name, pkg.source
+            ));
+            unmapped.push(format!("{}={} ({})", pkg.name, pkg.version, pkg.source));
+        }
+    }
+    dependencies[1..].sort();
+
+    let mut out = String::new();
+    writeln!(
+        out,
+        "# Generated by `rv export conda`. Do not edit by hand."
+    )
+    .unwrap();
+    writeln!(out, "name: {}", config.project_name()).unwrap();
+    writeln!(out, "channels:").unwrap();
+    for channel in channels {
+        writeln!(out, "  - {channel}").unwrap();
+    }
+    writeln!(out, "dependencies:").unwrap();
+    for dep in dependencies {
+        writeln!(out, "  - {dep}").unwrap();
+    }
+    if !unmapped.is_empty() {
+        writeln!(out, "# The following packages are not available on conda:").unwrap();
+        for pkg in unmapped {
+            writeln!(out, "#   - {pkg}").unwrap();
+        }
+    }
+
+    (out, warnings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BIOCONDA, CONDA_FORGE, conda_channel, to_conda_environment};
+    use crate::{Config, Lockfile};
+    use url::Url;
+
+    #[test]
+    fn only_maps_cran_and_bioconductor() {
+        for (url, expected) in [
+            ("https://cloud.r-project.org/", Some(CONDA_FORGE)),
+            (
+                "https://packagemanager.posit.co/cran/2024-12-16/",
+                Some(CONDA_FORGE),
+            ),
+            ("https://p3m.dev/bioconductor/latest", Some(BIOCONDA)),
+            (
+                "https://bioconductor.org/packages/3.20/bioc/",
+                Some(BIOCONDA),
+            ),
+            ("https://packagemanager.posit.co/internal/latest", None),
+            ("https://a2-ai.r-universe.dev", None),
+            ("https://bioconductor.mirror.internal/", None),
+        ] {
+            assert_eq!(conda_channel(&Url::parse(url).unwrap()), expected, "{url}");
+        }
+    }
+
+    #[test]
+    fn test_conda_export() {
+        let lockfile_toml = r#"
+version = 2
+r_version = "4.4"
+
+[[packages]]
+name = "rlang"
+version = "1.1.4"
+source = { repository = "https://cran.r-project.org/" }
+force_source = false
+dependencies = []
+
+[[packages]]
+name = "data.table"
+version = "1.16.2"
+source = { repository = "https://cran.r-project.org/" }
+force_source = false
+dependencies = []
+
+[[packages]]
+name = "MASS"
+version = "7.3-61"
+source = { repository = "https://cran.r-project.org/" }
+force_source = false
+dependencies = []
+
+[[packages]]
+name = "Biobase"
+version = "2.66.0"
+source = { repository = "https://bioconductor.org/packages/3.20/bioc/" }
+force_source = false
+dependencies = []
+
+[[packages]]
+name = "internalpkg"
+version = "0.1.0"
+source = { repository = "https://cran.internal.example.com/" }
+force_source = false
+dependencies = []
+
+[[packages]]
+name = "survival"
+version = "3.7-0"
+source = { builtin = true }
+force_source = false
+dependencies = []
+
+[[packages]]
+name = "ghqc"
+version = "0.3.2"
+source = { git = "https://github.com/a2-ai/ghqc", sha = "55c23eb6a444542dab742d3d37c7b65af7b12e38" }
+force_source = false
+dependencies = ["rlang"]
+"#;
+
+        let config_toml = r#"
+[project]
+name = "test"
+r_version = "4.4"
+repositories = [
+    { alias = "CRAN", url = "https://cran.r-project.org/" },
+]
+dependencies = ["rlang", "ghqc"]
+"#;
+
+        let lockfile: Lockfile = lockfile_toml.parse().unwrap();
+        let config: Config = config_toml.parse().unwrap();
+        let (out, warnings) = to_conda_environment(&lockfile, &config);
+        assert_eq!(warnings.len(), 2);
+        insta::assert_snapshot!("conda_export", out);
+    }
+}
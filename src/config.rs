@@ -1,17 +1,27 @@
-use std::collections::{HashMap, HashSet};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::ops::Deref;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
 use crate::SystemInfo;
-use crate::consts::LOCKFILE_NAME;
-use crate::dependency_edit::DEFAULT_GIT_SHORTHAND_BASE_URL;
+use crate::consts::{DEFAULT_GIT_SHORTHAND_BASE_URL, LOCKFILE_NAME};
 use crate::git::url::GitUrl;
+#[cfg(not(target_arch = "wasm32"))]
 use crate::lockfile::Source;
 use crate::package::{Version, deserialize_version, serialize_version};
 use serde::{Deserialize, Deserializer, Serialize};
+use toml_edit::TableLike;
 use url::Url;
 
+/// Keys starting with that prefix can be added to dependency entries to annotate them, eg
+/// `x-owner` or `x-reason`. They are not used by rv.
+const DEPENDENCY_METADATA_PREFIX: &str = "x-";
+pub(crate) const ENV_VAR_START: &str = "${";
+
+/// The `x-` keys of a dependency entry
+pub type DependencyMetadata = BTreeMap<String, toml::Value>;
+
 #[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct HttpUrl(Url);
 
@@ -91,6 +101,8 @@ pub enum ConfigDependency {
         install_suggestions: bool,
         #[serde(default)]
         dependencies_only: bool,
+        #[serde(default)]
+        no_dependencies: bool,
     },
     Local {
         path: PathBuf,
@@ -99,6 +111,8 @@ pub enum ConfigDependency {
         install_suggestions: bool,
         #[serde(default)]
         dependencies_only: bool,
+        #[serde(default)]
+        no_dependencies: bool,
     },
     Url {
         url: HttpUrl,
@@ -107,6 +121,8 @@ pub enum ConfigDependency {
         install_suggestions: bool,
         #[serde(default)]
         dependencies_only: bool,
+        #[serde(default)]
+        no_dependencies: bool,
     },
     Detailed {
         name: String,
@@ -117,6 +133,8 @@ pub enum ConfigDependency {
         force_source: Option<bool>,
         #[serde(default)]
         dependencies_only: bool,
+        #[serde(default)]
+        no_dependencies: bool,
     },
 }
 
@@ -170,6 +188,27 @@ impl ConfigDependency {
         }
     }
 
+    /// Install only the package itself and trust the environment (eg a site library) for its
+    /// dependencies
+    pub fn no_dependencies(&self) -> bool {
+        match self {
+            ConfigDependency::Git {
+                no_dependencies, ..
+            }
+            | ConfigDependency::Local {
+                no_dependencies, ..
+            }
+            | ConfigDependency::Url {
+                no_dependencies, ..
+            }
+            | ConfigDependency::Detailed {
+                no_dependencies, ..
+            } => *no_dependencies,
+            ConfigDependency::Simple(_) => false,
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
     pub(crate) fn as_git_source_with_sha(&self, sha: String) -> Source {
         match self.clone() {
             ConfigDependency::Git {
@@ -210,6 +249,38 @@ impl ConfigDependency {
             } => *install_suggestions,
         }
     }
+
+    /// Turns on `install_suggestions`, switching a simple dependency to the detailed form
+    pub fn enable_install_suggestions(&mut self) {
+        match self {
+            ConfigDependency::Simple(name) => {
+                *self = ConfigDependency::Detailed {
+                    name: std::mem::take(name),
+                    repository: None,
+                    install_suggestions: true,
+                    force_source: None,
+                    dependencies_only: false,
+                    no_dependencies: false,
+                }
+            }
+            ConfigDependency::Detailed {
+                install_suggestions,
+                ..
+            }
+            | ConfigDependency::Url {
+                install_suggestions,
+                ..
+            }
+            | ConfigDependency::Local {
+                install_suggestions,
+                ..
+            }
+            | ConfigDependency::Git {
+                install_suggestions,
+                ..
+            } => *install_suggestions = true,
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
@@ -277,6 +348,19 @@ pub enum ConfigureArgsRule {
     },
 }
 
+/// How a package is scheduled during a sync relative to the other packages being installed
+#[derive(Debug, Default, PartialEq, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct InstallConstraint {
+    /// Nothing else is installed at the same time, eg for packages using a lot of memory
+    #[serde(default)]
+    pub serial: bool,
+    /// Packages in the same group are never installed at the same time, eg if they use the
+    /// same temporary resources
+    #[serde(default)]
+    pub exclusive_group: Option<String>,
+}
+
 impl ConfigureArgsRule {
     pub fn matches(&self, system_info: &SystemInfo) -> Option<&[String]> {
         match self {
@@ -310,6 +394,10 @@ impl ConfigureArgsRule {
 #[serde(deny_unknown_fields)]
 pub(crate) struct Project {
     name: String,
+    /// A stable identifier for the project, used to key what rv keeps about it in the cache.
+    /// Without it, the project directory is used so moving the project loses that state.
+    #[serde(default)]
+    id: Option<String>,
     #[serde(
         deserialize_with = "deserialize_version",
         serialize_with = "serialize_version"
@@ -343,6 +431,12 @@ pub(crate) struct Project {
     /// install from the remote.
     #[serde(default)]
     prefer_repositories_for: Vec<String>,
+    /// Packages matching a name pattern (`*` and `?` wildcards) are only looked up in the
+    /// repository with the given alias, eg `{ "BioC*" = "bioc" }`, rather than in the first
+    /// repository having them. If several patterns match, the most specific one is used.
+    /// A `repository` set on a dependency takes precedence.
+    #[serde(default)]
+    package_repositories: HashMap<String, String>,
     /// This is where you add specific environment variables for each package compilation step,
     /// they will be passed to R.
     /// If a package is already available as binary and you don't mention you want to force source,
@@ -357,10 +451,26 @@ pub(crate) struct Project {
     /// Packages listed here will be installed without those flags.
     #[serde(default)]
     no_strip: Vec<String>,
+    /// Packages that can't be installed concurrently with others, see [`InstallConstraint`]
+    #[serde(default)]
+    install_constraints: HashMap<String, InstallConstraint>,
+    /// Licenses the packages of the project can have, checked by `rv check-policy` against their
+    /// `License` field, eg `["MIT", "GPL-2", "GPL-3"]`. Version constraints are ignored so
+    /// `GPL (>= 2)` is `GPL`. A package available under several licenses needs one of them allowed.
+    #[serde(default)]
+    allowed_licenses: Vec<String>,
+    /// Packages guaranteed to be available at runtime, eg installed in the image rv runs in.
+    /// They are considered satisfied without a source and will never be installed by rv.
+    #[serde(default)]
+    provided: Vec<String>,
     /// Base URL used by `rv add <owner>/<repo>` shorthand.
     /// Defaults to https://github.com when not specified.
     #[serde(default)]
     git_shorthand_base_url: Option<String>,
+    /// Environment variables set for the project R sessions, both in activated projects
+    /// and in `rv run`.
+    #[serde(default)]
+    env: BTreeMap<String, String>,
 }
 
 // That's the way to do it with serde :/
@@ -373,10 +483,193 @@ fn default_true() -> bool {
 #[serde(deny_unknown_fields)]
 pub struct Config {
     pub(crate) library: Option<String>,
+    /// Libraries not managed by rv, eg a read-only site library with huge packages.
+    /// Packages found in them are considered provided and will not be installed by rv.
+    /// They are added to `.libPaths()` after the rv library, in the order listed.
+    #[serde(default)]
+    additional_libraries: Vec<PathBuf>,
+    /// Reuse packages installed in the site libraries (`R_LIBS_SITE`) when they are not in the
+    /// cache and match the resolved version (and commit for git packages), by linking them into
+    /// the rv library instead of installing them.
+    #[serde(default)]
+    use_site_library: bool,
+    /// For deployments only changed through CI: commands that would modify the project
+    /// (sync, add, remove, upgrade) only report what would change instead.
+    #[serde(default)]
+    readonly: bool,
     #[serde(default = "default_true")]
     pub(crate) use_lockfile: bool,
     lockfile_name: Option<String>,
     pub(crate) project: Project,
+    /// Taken out of the dependency entries before parsing, keyed by dependency name
+    #[serde(skip)]
+    dependency_metadata: BTreeMap<String, DependencyMetadata>,
+    /// The URLs expanded from environment variables, with how they are written in the config
+    #[serde(skip)]
+    url_templates: BTreeMap<String, String>,
+}
+
+/// Takes the `x-` keys out of the dependency entries so the rest can still be parsed strictly.
+/// Returns `None` if there are none, in which case the config can be parsed as is.
+fn extract_dependency_metadata(
+    content: &str,
+) -> Option<(String, BTreeMap<String, DependencyMetadata>)> {
+    if !content.contains(DEPENDENCY_METADATA_PREFIX) {
+        return None;
+    }
+    let mut doc = content.parse::<toml_edit::DocumentMut>().ok()?;
+    let project = doc.get_mut("project")?.as_table_like_mut()?;
+    let mut metadata = BTreeMap::new();
+
+    for field in ["dependencies", "dev_dependencies", "suggests"] {
+        let Some(item) = project.get_mut(field) else {
+            continue;
+        };
+        let entries: Vec<&mut dyn TableLike> = if let Some(array) = item.as_array_mut() {
+            array
+                .iter_mut()
+                .filter_map(|v| v.as_inline_table_mut().map(|t| t as &mut dyn TableLike))
+                .collect()
+        } else if let Some(tables) = item.as_array_of_tables_mut() {
+            tables.iter_mut().map(|t| t as &mut dyn TableLike).collect()
+        } else {
+            continue;
+        };
+
+        for entry in entries {
+            let keys: Vec<_> = entry
+                .iter()
+                .map(|(k, _)| k.to_string())
+                .filter(|k| k.starts_with(DEPENDENCY_METADATA_PREFIX))
+                .collect();
+            let Some(name) = entry.get("name").and_then(|v| v.as_str()) else {
+                continue;
+            };
+            let name = name.to_string();
+            for key in keys {
+                let Some(value) = entry.remove(&key) else {
+                    continue;
+                };
+                // Going through a table is the easiest way to convert from toml_edit to toml
+                let value = value
+                    .as_value()
+                    .and_then(|v| toml::from_str::<toml::Table>(&format!("v = {v}")).ok())
+                    .and_then(|mut t| t.remove("v"));
+                if let Some(value) = value {
+                    metadata
+                        .entry(name.clone())
+                        .or_insert_with(DependencyMetadata::new)
+                        .insert(key, value);
+                }
+            }
+        }
+    }
+
+    if metadata.is_empty() {
+        None
+    } else {
+        Some((doc.to_string(), metadata))
+    }
+}
+
+/// The config content with its URLs expanded, and the expanded URLs with their template
+type InterpolatedConfig = (String, BTreeMap<String, String>);
+
+/// Expands `${VAR}` and `${VAR:-default}` in the URLs of the repositories and of the git/url
+/// dependencies, so a committed config can point to mirrors depending on the environment.
+/// The default is used if the variable is unset or empty.
+/// Returns the new content along with the expanded URLs and their templates, or `None` if there
+/// is nothing to expand, in which case the config can be parsed as is.
+fn interpolate_env_vars(
+    content: &str,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Result<Option<InterpolatedConfig>, String> {
+    if !content.contains(ENV_VAR_START) {
+        return Ok(None);
+    }
+    let Ok(mut doc) = content.parse::<toml_edit::DocumentMut>() else {
+        return Ok(None);
+    };
+    let Some(project) = doc.get_mut("project").and_then(|p| p.as_table_like_mut()) else {
+        return Ok(None);
+    };
+    let mut templates = BTreeMap::new();
+
+    for (field, keys) in [
+        ("repositories", &["url"][..]),
+        ("dependencies", &["git", "url"]),
+        ("dev_dependencies", &["git", "url"]),
+        ("suggests", &["git", "url"]),
+    ] {
+        let Some(item) = project.get_mut(field) else {
+            continue;
+        };
+        let entries: Vec<&mut dyn TableLike> = if let Some(array) = item.as_array_mut() {
+            array
+                .iter_mut()
+                .filter_map(|v| v.as_inline_table_mut().map(|t| t as &mut dyn TableLike))
+                .collect()
+        } else if let Some(tables) = item.as_array_of_tables_mut() {
+            tables.iter_mut().map(|t| t as &mut dyn TableLike).collect()
+        } else {
+            continue;
+        };
+
+        for entry in entries {
+            for key in keys {
+                let Some(value) = entry.get_mut(key).and_then(|v| v.as_value_mut()) else {
+                    continue;
+                };
+                let Some(s) = value.as_str().filter(|s| s.contains(ENV_VAR_START)) else {
+                    continue;
+                };
+                let expanded = expand_env_vars(s, &lookup)
+                    .map_err(|e| format!("Failed to expand `{s}` in {field}: {e}"))?;
+                templates.insert(expanded.clone(), s.to_string());
+                let decor = value.decor().clone();
+                *value = expanded.into();
+                *value.decor_mut() = decor;
+            }
+        }
+    }
+
+    if templates.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some((doc.to_string(), templates)))
+    }
+}
+
+pub(crate) fn expand_env_vars(
+    value: &str,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Result<String, String> {
+    let mut out = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find(ENV_VAR_START) {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + ENV_VAR_START.len()..];
+        let end = after
+            .find('}')
+            .ok_or_else(|| "missing closing `}`".to_string())?;
+        let (name, default) = match after[..end].split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (&after[..end], None),
+        };
+        if name.is_empty() {
+            return Err("empty variable name".to_string());
+        }
+        match (lookup(name).filter(|v| !v.is_empty()), default) {
+            (Some(v), _) => out.push_str(&v),
+            (None, Some(default)) => out.push_str(default),
+            (None, None) => {
+                return Err(format!("environment variable `{name}` is not set"));
+            }
+        }
+        rest = &after[end + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
 }
 
 impl Config {
@@ -390,11 +683,32 @@ impl Config {
                 });
             }
         };
+        Self::parse(&content, path.as_ref())
+    }
+
+    fn parse(content: &str, path: &Path) -> Result<Self, ConfigLoadError> {
+        let (content, dependency_metadata) = match extract_dependency_metadata(content) {
+            Some((content, metadata)) => (Cow::Owned(content), metadata),
+            None => (Cow::Borrowed(content), BTreeMap::new()),
+        };
+        let (content, url_templates) =
+            match interpolate_env_vars(&content, |name| std::env::var(name).ok()) {
+                Ok(Some((interpolated, templates))) => (Cow::Owned(interpolated), templates),
+                Ok(None) => (content, BTreeMap::new()),
+                Err(e) => {
+                    return Err(ConfigLoadError {
+                        path: path.into(),
+                        source: ConfigLoadErrorKind::InvalidConfig(e),
+                    });
+                }
+            };
         let mut config: Self = toml::from_str(&content).map_err(|e| ConfigLoadError {
-            path: path.as_ref().into(),
+            path: path.into(),
             source: ConfigLoadErrorKind::Parse(e),
         })?;
-        config.finalize(path.as_ref())?;
+        config.dependency_metadata = dependency_metadata;
+        config.url_templates = url_templates;
+        config.finalize(path)?;
         Ok(config)
     }
 
@@ -419,6 +733,12 @@ impl Config {
         }
 
         for d in self.project.dependencies.iter_mut() {
+            if d.dependencies_only() && d.no_dependencies() {
+                errors.push(format!(
+                    "Dependency {} cannot set both `dependencies_only` and `no_dependencies`.",
+                    d.name()
+                ));
+            }
             match d {
                 // If it has a repository set, we need to check the alias is found and replace it with the url
                 ConfigDependency::Detailed {
@@ -459,6 +779,36 @@ impl Config {
             }
         }
 
+        for (pattern, alias) in self.project.package_repositories.iter_mut() {
+            if let Some(repo) = repo_mapping.get(alias.as_str()) {
+                *alias = repo.url().to_string();
+            } else {
+                errors.push(format!(
+                    "Package pattern {pattern} is using alias {alias} which is unknown."
+                ));
+            }
+        }
+
+        if self
+            .project
+            .id
+            .as_deref()
+            .is_some_and(|id| id.trim().is_empty())
+        {
+            errors.push("`project.id` cannot be empty.".to_string());
+        }
+
+        for name in self.project.env.keys() {
+            if name.is_empty()
+                || name.contains(['=', '\0'])
+                || name.chars().any(char::is_whitespace)
+            {
+                errors.push(format!(
+                    "Invalid environment variable name `{name}` in `project.env`."
+                ));
+            }
+        }
+
         if let Some(base_url) = self.project.git_shorthand_base_url.as_deref() {
             let base_url = base_url.trim();
             if base_url.is_empty() {
@@ -503,10 +853,30 @@ impl Config {
         &mut self.project.dependencies
     }
 
+    /// The URLs expanded from environment variables, with how they are written in the config.
+    /// The lockfile keeps the latter so it doesn't contain secrets or machine specific values.
+    pub fn url_templates(&self) -> &BTreeMap<String, String> {
+        &self.url_templates
+    }
+
+    /// The `x-` keys set on the entry of that dependency, if any
+    pub fn dependency_metadata(&self, name: &str) -> Option<&DependencyMetadata> {
+        self.dependency_metadata.get(name)
+    }
+
     pub fn prefer_repositories_for(&self) -> &[String] {
         &self.project.prefer_repositories_for
     }
 
+    /// The URL of the repository the package is routed to by `package_repositories`, if any
+    pub fn package_repository(&self, package_name: &str) -> Option<&str> {
+        find_package_repository(&self.project.package_repositories, package_name)
+    }
+
+    pub fn package_repositories(&self) -> &HashMap<String, String> {
+        &self.project.package_repositories
+    }
+
     pub fn packages_env_vars(&self) -> &HashMap<String, HashMap<String, String>> {
         &self.project.packages_env_vars
     }
@@ -515,14 +885,31 @@ impl Config {
         &self.project.r_version
     }
 
+    pub fn project_id(&self) -> Option<&str> {
+        self.project.id.as_deref()
+    }
+
+    /// Used to replace the `devel` alias by the version of the R-devel we found
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn set_r_version(&mut self, version: Version) {
+        self.project.r_version = version;
+    }
+
+    /// `r_version = "devel"` implies `use_devel`
     pub fn use_devel(&self) -> bool {
-        self.project.use_devel.unwrap_or(false)
+        self.project.use_devel.unwrap_or(false) || self.project.r_version.is_r_devel()
     }
 
     pub fn use_lockfile(&self) -> bool {
         self.use_lockfile
     }
 
+    /// Used to override `use_lockfile` for a single command
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn set_use_lockfile(&mut self, use_lockfile: bool) {
+        self.use_lockfile = use_lockfile;
+    }
+
     pub fn library(&self) -> Option<PathBuf> {
         self.library.as_ref().map(|s| {
             let [maj, min] = self.project.r_version.major_minor();
@@ -533,6 +920,18 @@ impl Config {
         })
     }
 
+    pub fn additional_libraries(&self) -> &[PathBuf] {
+        &self.additional_libraries
+    }
+
+    pub fn use_site_library(&self) -> bool {
+        self.use_site_library
+    }
+
+    pub fn readonly(&self) -> bool {
+        self.readonly
+    }
+
     pub fn set_library(&mut self, library: &str) {
         self.library = Some(library.to_string());
     }
@@ -558,10 +957,31 @@ impl Config {
         &self.project.configure_args
     }
 
+    /// Environment variables to set in R sessions of the project, sorted by name
+    pub fn env_vars(&self) -> &BTreeMap<String, String> {
+        &self.project.env
+    }
+
     pub fn no_strip(&self) -> &[String] {
         &self.project.no_strip
     }
 
+    pub fn install_constraints(&self) -> &HashMap<String, InstallConstraint> {
+        &self.project.install_constraints
+    }
+
+    pub fn project_name(&self) -> &str {
+        &self.project.name
+    }
+
+    pub fn provided(&self) -> &[String] {
+        &self.project.provided
+    }
+
+    pub fn allowed_licenses(&self) -> &[String] {
+        &self.project.allowed_licenses
+    }
+
     pub fn git_shorthand_base_url(&self) -> &str {
         self.project
             .git_shorthand_base_url
@@ -574,12 +994,7 @@ impl FromStr for Config {
     type Err = ConfigLoadError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut config: Self = toml::from_str(s).map_err(|e| ConfigLoadError {
-            path: Path::new(".").into(),
-            source: ConfigLoadErrorKind::Parse(e),
-        })?;
-        config.finalize(Path::new("."))?;
-        Ok(config)
+        Self::parse(s, Path::new("."))
     }
 }
 
@@ -600,6 +1015,56 @@ pub enum ConfigLoadErrorKind {
     InvalidConfig(String),
 }
 
+/// The repository of the most specific pattern matching the package name: the one with
+/// the most characters that are not wildcards
+pub(crate) fn find_package_repository<'a>(
+    package_repositories: &'a HashMap<String, String>,
+    package_name: &str,
+) -> Option<&'a str> {
+    package_repositories
+        .iter()
+        .filter(|(pattern, _)| matches_pattern(pattern, package_name))
+        .max_by_key(|(pattern, _)| {
+            (
+                pattern.chars().filter(|c| *c != '*' && *c != '?').count(),
+                // Ties are broken by the pattern itself to stay deterministic
+                std::cmp::Reverse(pattern.as_str()),
+            )
+        })
+        .map(|(_, repo)| repo.as_str())
+}
+
+/// Glob matching with `*` matching any number of characters and `?` a single one
+fn matches_pattern(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<_> = pattern.chars().collect();
+    let name: Vec<_> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // Where to resume if the current attempt after the last `*` fails
+    let mut backtrack = None;
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star_p, star_n)) => {
+                    p = star_p + 1;
+                    n = star_n + 1;
+                    backtrack = Some((star_p, star_n + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -625,6 +1090,149 @@ mod tests {
         }
     }
 
+    #[test]
+    fn can_parse_project_id() {
+        let config = Config::from_file("src/tests/valid_config/all_fields.toml").unwrap();
+        assert_eq!(
+            config.project_id(),
+            Some("6f1c2e8a-3b4d-4c5e-9f60-7a8b9c0d1e2f")
+        );
+        let config = Config::from_file("src/tests/valid_config/dependency_metadata.toml").unwrap();
+        assert_eq!(config.project_id(), None);
+    }
+
+    #[test]
+    fn can_route_packages_to_repositories() {
+        let config = Config::from_file("src/tests/valid_config/all_fields.toml").unwrap();
+        assert_eq!(
+            config.package_repository("mrgsolve"),
+            Some("https://mpn.metworx.com/snapshots/stable/2020-09-20")
+        );
+        assert_eq!(config.package_repository("dplyr"), None);
+
+        let routes = HashMap::from([
+            ("BioC*".to_string(), "bioc".to_string()),
+            ("our.*".to_string(), "internal".to_string()),
+            ("our.special".to_string(), "special".to_string()),
+            ("pkg?".to_string(), "other".to_string()),
+        ]);
+        assert_eq!(
+            find_package_repository(&routes, "BioCgenerics"),
+            Some("bioc")
+        );
+        assert_eq!(
+            find_package_repository(&routes, "our.utils"),
+            Some("internal")
+        );
+        // The most specific pattern wins
+        assert_eq!(
+            find_package_repository(&routes, "our.special"),
+            Some("special")
+        );
+        assert_eq!(find_package_repository(&routes, "pkg1"), Some("other"));
+        assert_eq!(find_package_repository(&routes, "pkg12"), None);
+        assert_eq!(find_package_repository(&routes, "ours"), None);
+    }
+
+    #[test]
+    fn can_match_patterns() {
+        assert!(matches_pattern("*", "dplyr"));
+        assert!(matches_pattern("d*r", "dplyr"));
+        assert!(matches_pattern("*ply*", "dplyr"));
+        assert!(matches_pattern("dpl?r", "dplyr"));
+        assert!(matches_pattern("dplyr", "dplyr"));
+        assert!(!matches_pattern("dplyr", "dplyr2"));
+        assert!(!matches_pattern("d*x", "dplyr"));
+        assert!(!matches_pattern("", "dplyr"));
+    }
+
+    #[test]
+    fn can_expand_env_vars() {
+        let lookup = |name: &str| match name {
+            "HOST" => Some("mirror.example.com".to_string()),
+            "EMPTY" => Some(String::new()),
+            _ => None,
+        };
+        assert_eq!(
+            expand_env_vars("https://${HOST}/cran", lookup).unwrap(),
+            "https://mirror.example.com/cran"
+        );
+        assert_eq!(
+            expand_env_vars("https://${MISSING:-cran.r-project.org}/${HOST}", lookup).unwrap(),
+            "https://cran.r-project.org/mirror.example.com"
+        );
+        assert_eq!(
+            expand_env_vars("https://${EMPTY:-default}", lookup).unwrap(),
+            "https://default"
+        );
+        assert!(expand_env_vars("https://${MISSING}", lookup).is_err());
+        assert!(expand_env_vars("https://${HOST", lookup).is_err());
+        assert!(expand_env_vars("https://${}", lookup).is_err());
+    }
+
+    #[test]
+    fn can_interpolate_env_vars_in_urls() {
+        let content =
+            std::fs::read_to_string("src/tests/valid_config/env_interpolation.toml").unwrap();
+        let lookup = |name: &str| (name == "RV_TEST_PPM_HOST").then(|| "ppm.internal".to_string());
+        let (interpolated, templates) = interpolate_env_vars(&content, lookup).unwrap().unwrap();
+        assert_eq!(
+            templates["https://ppm.internal/cran/latest"],
+            "https://${RV_TEST_PPM_HOST:-packagemanager.posit.co}/cran/latest"
+        );
+        let config = Config::from_str(&interpolated).unwrap();
+        assert_eq!(
+            config.repositories()[0].url(),
+            "https://ppm.internal/cran/latest"
+        );
+        match &config.dependencies()[1] {
+            ConfigDependency::Git { git, .. } => {
+                assert_eq!(git.url(), "https://github.com/org/internal")
+            }
+            d => panic!("unexpected dependency {d:?}"),
+        }
+
+        // Other fields are left alone
+        let content = "[project]\nname = \"${NAME}\"\nr_version = \"4.4\"\nrepositories = []\n";
+        assert_eq!(interpolate_env_vars(content, lookup).unwrap(), None);
+        let content = content.replace("[]", "[{ alias = \"a\", url = \"https://${MISSING}\" }]");
+        let err = interpolate_env_vars(&content, lookup).unwrap_err();
+        assert!(err.contains("`MISSING` is not set"), "{err}");
+    }
+
+    #[test]
+    fn can_parse_dependency_metadata() {
+        let config = Config::from_file("src/tests/valid_config/dependency_metadata.toml").unwrap();
+        assert!(config.dependency_metadata("dplyr").is_none());
+        let purrr = config.dependency_metadata("purrr").unwrap();
+        assert_eq!(purrr["x-owner"].as_str(), Some("data-team"));
+        assert_eq!(purrr["x-ticket"].as_integer(), Some(1234));
+        let ggplot2 = config.dependency_metadata("ggplot2").unwrap();
+        assert_eq!(
+            ggplot2.keys().collect::<Vec<_>>(),
+            vec!["x-reason", "x-reviewed"]
+        );
+        // The entries are otherwise parsed as usual
+        assert_eq!(
+            config.dependencies()[1].r_repository(),
+            Some("https://cran.r-project.org/")
+        );
+    }
+
+    #[test]
+    fn can_parse_install_constraints() {
+        let config = Config::from_file("src/tests/valid_config/all_fields.toml").unwrap();
+        let constraints = config.install_constraints();
+        assert!(constraints["torch"].serial);
+        assert_eq!(constraints["torch"].exclusive_group, None);
+        assert!(!constraints["sf"].serial);
+        assert_eq!(constraints["sf"].exclusive_group.as_deref(), Some("gdal"));
+        assert_eq!(
+            constraints["terra"].exclusive_group.as_deref(),
+            Some("gdal")
+        );
+    }
+
     #[test]
     fn can_parse_no_strip() {
         let toml_str = r#"
@@ -650,6 +1258,67 @@ repositories = []
         assert!(config.no_strip().is_empty());
     }
 
+    #[test]
+    fn can_parse_project_env() {
+        let toml_str = r#"
+[project]
+name = "test"
+r_version = "4.4"
+repositories = []
+
+[project.env]
+OMP_NUM_THREADS = "1"
+API_URL = "https://api.example.com"
+"#;
+        let config: Config = toml::from_str(toml_str).unwrap();
+        assert_eq!(
+            config.env_vars().iter().collect::<Vec<_>>(),
+            vec![
+                (
+                    &"API_URL".to_string(),
+                    &"https://api.example.com".to_string()
+                ),
+                (&"OMP_NUM_THREADS".to_string(), &"1".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn can_enable_install_suggestions() {
+        let toml_str = r#"
+[project]
+name = "test"
+r_version = "4.4"
+repositories = []
+dependencies = [
+    "dplyr",
+    { name = "ggplot2", repository = "posit" },
+]
+"#;
+        let mut config: Config = toml::from_str(toml_str).unwrap();
+        for dep in config.dependencies_mut() {
+            assert!(!dep.install_suggestions());
+            dep.enable_install_suggestions();
+        }
+        let deps = config.dependencies();
+        assert!(deps.iter().all(|d| d.install_suggestions()));
+        assert_eq!(deps[0].name(), "dplyr");
+        assert_eq!(deps[1].r_repository(), Some("posit"));
+    }
+
+    #[test]
+    fn can_parse_provided() {
+        let toml_str = r#"
+[project]
+name = "test"
+r_version = "4.4"
+repositories = []
+provided = ["ROracle", "sf"]
+"#;
+        let config: Config = toml::from_str(toml_str).unwrap();
+        assert_eq!(config.provided(), &["ROracle", "sf"]);
+    }
+
     #[test]
     fn config_r_version_round_trips_as_string() {
         let toml_str = r#"
@@ -678,7 +1347,7 @@ repositories = []
         let default_config = Config::from_str(default_toml).unwrap();
         assert_eq!(
             default_config.git_shorthand_base_url(),
-            crate::dependency_edit::DEFAULT_GIT_SHORTHAND_BASE_URL
+            crate::consts::DEFAULT_GIT_SHORTHAND_BASE_URL
         );
 
         let custom_toml = r#"
@@ -721,6 +1390,38 @@ repositories = []
         assert_eq!(config.library(), Some(PathBuf::from("/abs/path/to/lib")));
     }
 
+    #[test]
+    fn use_site_library_defaults_to_false() {
+        let toml_str = r#"
+[project]
+name = "foo"
+r_version = "4.5"
+repositories = []
+"#;
+        assert!(!Config::from_str(toml_str).unwrap().use_site_library());
+        let config = Config::from_str(&format!("use_site_library = true\n{toml_str}")).unwrap();
+        assert!(config.use_site_library());
+    }
+
+    #[test]
+    fn additional_libraries_keep_config_order() {
+        let toml_str = r#"
+additional_libraries = ["/opt/R/site-library", "shared/lib"]
+[project]
+name = "foo"
+r_version = "4.5"
+repositories = []
+"#;
+        let config = Config::from_str(toml_str).unwrap();
+        assert_eq!(
+            config.additional_libraries(),
+            &[
+                PathBuf::from("/opt/R/site-library"),
+                PathBuf::from("shared/lib")
+            ]
+        );
+    }
+
     #[test]
     fn invalid_git_shorthand_base_url_errors() {
         let toml_str = r#"
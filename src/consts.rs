@@ -2,14 +2,60 @@ pub const PACKAGE_FILENAME: &str = "PACKAGES";
 pub const DESCRIPTION_FILENAME: &str = "DESCRIPTION";
 pub const SOURCE_PACKAGES_PATH: &str = "/src/contrib/PACKAGES";
 pub const RUNIVERSE_PACKAGES_API_PATH: &str = "api/packages";
+pub const CONFIG_FILENAME: &str = "rproject.toml";
 pub const LOCKFILE_NAME: &str = "rv.lock";
+/// Hosts of CRAN itself rather than of one of its mirrors
+pub const CRAN_HOSTS: [&str; 3] = [
+    "cran.r-project.org",
+    "cloud.r-project.org",
+    "cran.rstudio.com",
+];
+/// Hosts of Posit Package Manager, which serves CRAN under `/cran` and Bioconductor under
+/// `/bioconductor`
+pub const PPM_HOSTS: [&str; 3] = [
+    "packagemanager.posit.co",
+    "packagemanager.rstudio.com",
+    "p3m.dev",
+];
+/// Where `owner/repo` git dependencies are looked up, unless the config sets another URL
+pub const DEFAULT_GIT_SHORTHAND_BASE_URL: &str = "https://github.com";
 
 pub const RV_DIR_NAME: &str = "rv";
 pub const LIBRARY_ROOT_DIR_NAME: &str = "library";
 pub const STAGING_DIR_NAME: &str = "__rv__staging";
+/// Written in the rv folder of the project after each sync
+pub const LAST_SYNC_REPORT_FILENAME: &str = "last-sync.json";
+/// Written instead of the sync report by `rv sync --dry-run` so it doesn't replace the real one
+pub const SIMULATED_SYNC_REPORT_FILENAME: &str = "last-sync-simulated.json";
+/// Fingerprint of the project after the last successful sync, in the rv folder of the project
+pub const SYNC_STAMP_FILENAME: &str = ".sync-stamp.json";
+/// History of `rv bench startup`, in the rv folder of the project
+pub const STARTUP_BENCH_FILENAME: &str = "startup-bench.json";
+#[cfg(not(target_arch = "wasm32"))]
 pub(crate) const LIBRARY_METADATA_FILENAME: &str = ".rv.metadata";
+/// In the library root folder, records the content of each library written by sync
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) const LIBRARY_MANIFEST_FILENAME: &str = ".manifest.json";
 pub const BUILD_LOG_FILENAME: &str = "__rv_build.log";
 pub const BUILT_FROM_SOURCE_FILENAME: &str = ".__rv_source";
+pub const BUILD_INFO_FILENAME: &str = "__rv_build_info.json";
+/// HMAC of the files of a binary package in the cache, see `sync::signature`
+pub const SIGNATURE_FILENAME: &str = ".__rv_signature";
+/// Environment variables from the process that can change the output of a build
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) const BUILD_ENV_VARS: [&str; 11] = [
+    "CC",
+    "CXX",
+    "CFLAGS",
+    "CXXFLAGS",
+    "CPPFLAGS",
+    "LDFLAGS",
+    "FFLAGS",
+    "MAKEFLAGS",
+    "PKG_CONFIG_PATH",
+    "R_MAKEVARS_USER",
+    "R_MAKEVARS_SITE",
+];
 
 /// How long are the package databases cached for
 /// Same default value as PKGCACHE_TIMEOUT:
@@ -19,6 +65,12 @@ pub const PACKAGE_TIMEOUT_ENV_VAR_NAME: &str = "PKGCACHE_TIMEOUT";
 pub const PACKAGE_DB_FILENAME: &str = "packages.mp";
 
 pub const NUM_CPUS_ENV_VAR_NAME: &str = "RV_NUM_CPUS";
+pub const NUM_COMPILE_JOBS_ENV_VAR_NAME: &str = "RV_NUM_COMPILE_JOBS";
+pub const INSTALL_NICENESS_ENV_VAR_NAME: &str = "RV_INSTALL_NICENESS";
+pub const INSTALL_CPU_AFFINITY_ENV_VAR_NAME: &str = "RV_INSTALL_CPU_AFFINITY";
+pub const INSTALL_MEMORY_LIMIT_ENV_VAR_NAME: &str = "RV_INSTALL_MEMORY_LIMIT";
+pub const MIN_FREE_MEMORY_ENV_VAR_NAME: &str = "RV_MIN_FREE_MEMORY";
+pub const HARD_CANCEL_KILL_ENV_VAR_NAME: &str = "RV_HARD_CANCEL_KILL";
 pub const SYS_REQ_URL_ENV_VAR_NAME: &str = "RV_SYS_REQ_URL";
 pub const NO_CHECK_OPEN_FILE_ENV_VAR_NAME: &str = "RV_NO_CHECK_OPEN_FILE";
 pub const SYS_DEPS_CHECK_IN_PATH_ENV_VAR_NAME: &str = "RV_SYS_DEPS_CHECK_IN_PATH";
@@ -28,9 +80,23 @@ pub const COPY_THREADS_ENV_VAR_NAME: &str = "RV_COPY_THREADS";
 pub const GLOBAL_CACHE_DIR_ENV_VAR_NAME: &str = "RV_GLOBAL_CACHE_DIR";
 pub const INSECURE_TLS_ENV_VAR_NAME: &str = "RV_INSECURE";
 pub const LIBRARY_DIR_ENV_VAR_NAME: &str = "RV_LIBRARY_DIR";
+/// Experimental: where to compile source packages, see `sync::remote_build`
+pub const REMOTE_BUILDER_ENV_VAR_NAME: &str = "RV_REMOTE_BUILDER";
+/// renv's cache root, used as the rv cache (in a `rv` subfolder) if `RV_CACHE_DIR` is not set
+pub const RENV_PATHS_CACHE_ENV_VAR_NAME: &str = "RENV_PATHS_CACHE";
+/// renv's library root, used instead of `rv/library` if no other library override is set
+pub const RENV_PATHS_LIBRARY_ENV_VAR_NAME: &str = "RENV_PATHS_LIBRARY";
+/// Key used to sign the reports of `rv export validation-report` with a HMAC
+pub const VALIDATION_SIGNING_KEY_ENV_VAR_NAME: &str = "RV_VALIDATION_SIGNING_KEY";
+/// Key used to sign the binaries added to the cache and verify the ones linked from it
+pub const CACHE_SIGNING_KEY_ENV_VAR_NAME: &str = "RV_CACHE_SIGNING_KEY";
+/// Accept binaries from the cache without signature with a warning, to migrate a cache filled
+/// before the signing key was set
+pub const CACHE_ALLOW_UNSIGNED_ENV_VAR_NAME: &str = "RV_CACHE_ALLOW_UNSIGNED";
 
 // List obtained from the REPL: `rownames(installed.packages(priority="base"))`
 // Those will have the same version as R
+#[cfg(not(target_arch = "wasm32"))]
 pub(crate) const BASE_PACKAGES: [&str; 14] = [
     "base",
     "compiler",
@@ -50,6 +116,7 @@ pub(crate) const BASE_PACKAGES: [&str; 14] = [
 
 // List obtained from the REPL: `rownames(installed.packages(priority="recommended"))`
 // Those are versioned separately from R and some packages might have version requirements on them
+#[cfg(not(target_arch = "wasm32"))]
 pub(crate) const RECOMMENDED_PACKAGES: [&str; 15] = [
     "boot",
     "class",
@@ -68,6 +135,7 @@ pub(crate) const RECOMMENDED_PACKAGES: [&str; 15] = [
     "survival",
 ];
 
+#[cfg(not(target_arch = "wasm32"))]
 pub(crate) const ACTIVATE_FILE_TEMPLATE: &str = r#"local({%global wd content%
 	if (!nzchar(Sys.which("%rv command%"))) {
 		warning(
@@ -78,7 +146,7 @@ pub(crate) const ACTIVATE_FILE_TEMPLATE: &str = r#"local({%global wd content%
 	}
 	rv_info <- system2(
 		"%rv command%",
-		c("info", "--library", "--r-version", "--repositories"),
+		c("info", "--library", "--r-version", "--repositories", "--additional-libraries", "--env"),
 		stdout = TRUE
 	)
 	if (!is.null(attr(rv_info, "status"))) {
@@ -108,13 +176,31 @@ pub(crate) const ACTIVATE_FILE_TEMPLATE: &str = r#"local({%global wd content%
 	names(repo_urls) <- repo_names
 	options(repos = repo_urls)
 
+	# Project environment variables, as NAME=value
+	env_vars <- get_val("env")
+	if (length(env_vars)) {
+		env_names <- sub("=.*$", "", env_vars)
+		env_values <- substring(env_vars, nchar(env_names) + 2)
+		names(env_values) <- env_names
+		do.call(Sys.setenv, as.list(env_values))
+	}
+
+	# normalizePath would turn mapped network drives into UNC paths, which break R in some
+	# shells, so paths are only made absolute with forward slashes
+	as_r_path <- function(paths) {
+		paths <- path.expand(gsub("\\", "/", paths, fixed = TRUE))
+		relative <- !grepl("^([A-Za-z]:)?/", paths)
+		paths[relative] <- file.path(gsub("\\", "/", getwd(), fixed = TRUE), paths[relative])
+		paths
+	}
+
 	# Check R version and set library
 	rv_r_ver <- get_val("r-version")
 	sys_r <- sprintf("%s.%s", R.version$major, R.version$minor)
 	r_match <- grepl(paste0("^", rv_r_ver), sys_r)
 
 	rv_lib <- if (r_match) {
-		normalizePath(get_val("library"), mustWork = FALSE)
+		as_r_path(get_val("library"))
 	} else {
 		message(sprintf(
 			"WARNING: R version specified in config (%s) does not match session version (%s).
@@ -135,7 +221,15 @@ rv library will not be activated until the issue is resolved. Entering safe mode
 		dir.create(rv_lib, recursive = TRUE)
 	}
 
-	.libPaths(rv_lib, include.site = FALSE)
+	# Libraries not managed by rv go after the rv library, in the order of the config
+	extra_libs <- get_val("additional-libraries")
+	extra_libs <- if (r_match && length(extra_libs) && nzchar(extra_libs)) {
+		as_r_path(strsplit(extra_libs, .Platform$path.sep, fixed = TRUE)[[1]])
+	} else {
+		character()
+	}
+
+	.libPaths(c(rv_lib, extra_libs), include.site = FALSE)
 	Sys.setenv("R_LIBS_USER" = rv_lib)
 	Sys.setenv("R_LIBS_SITE" = rv_lib)
 
@@ -164,6 +258,7 @@ rv library will not be activated until the issue is resolved. Entering safe mode
 })
 "#;
 
+#[cfg(not(target_arch = "wasm32"))]
 pub(crate) const RVR_FILE_CONTENT: &str = r#".rv <- new.env()
 .rv$config_path <- file.path(normalizePath(getwd()), "rproject.toml")
 .rv$summary <- function(json = FALSE) {
@@ -8,24 +8,33 @@ use std::error::Error;
 use std::path::{Path, PathBuf};
 use url::Url;
 
-use crate::cache::Cache;
-use crate::consts::{RUNIVERSE_PACKAGES_API_PATH, STAGING_DIR_NAME};
+use crate::cache::{BuildKeys, Cache};
+use crate::consts::{
+    LAST_SYNC_REPORT_FILENAME, RUNIVERSE_PACKAGES_API_PATH, RV_DIR_NAME,
+    SIMULATED_SYNC_REPORT_FILENAME, STAGING_DIR_NAME, STARTUP_BENCH_FILENAME,
+};
 use crate::events;
+use crate::fs::is_writable;
+use crate::git::{GitReference, GitRemote};
+use crate::library::{find_provided_packages, find_site_libraries};
 use crate::lockfile::Lockfile;
 use crate::package::Package;
 use crate::r_finder::find_r_install;
 use crate::utils::create_spinner;
 use crate::{
-    Config, DiskCache, GitExecutor, Http, Library, RInstall, Repository, RepositoryDatabase,
-    Resolution, Resolver, SystemInfo, Version, get_package_file_urls, http, system_req,
+    Config, ConfigDependency, DiskCache, GitExecutor, Http, Library, OsType, RCmd, RInstall,
+    Repository, RepositoryDatabase, Resolution, Resolver, SystemInfo, Version,
+    get_package_file_urls, hash_string, http, system_req,
 };
 
 /// Method on how to find the R Version on the system
 #[derive(Debug, Clone, PartialEq)]
 pub enum RCommandLookup {
     /// Used for commands that require R to be on the system (installation commands)
-    /// Also used for planning commands when the `--r-version` flag is not in use
     Strict,
+    /// Used for planning commands when the `--r-version` flag is not in use: the R version of
+    /// the config is looked up but the commands still work if it is not installed
+    Lenient,
     /// Used when the `--r-version` flag is set for planning commands
     Soft(Version),
     /// Used when finding the RCommand is not required, primarily for information commands like
@@ -35,12 +44,12 @@ pub enum RCommandLookup {
 
 impl From<Option<Version>> for RCommandLookup {
     /// convert Option<Version> to RCommandLookup, where if the Version is specified, it is a soft lookup
-    /// If it is not specified, it is a strict lookup.
+    /// If it is not specified, it is a lenient lookup.
     fn from(ver: Option<Version>) -> Self {
         if let Some(v) = ver {
             Self::Soft(v)
         } else {
-            Self::Strict
+            Self::Lenient
         }
     }
 }
@@ -66,12 +75,25 @@ pub struct Context {
     pub databases: Vec<(RepositoryDatabase, bool)>,
     pub lockfile: Option<Lockfile>,
     pub r_cmd: RInstall,
+    /// Whether a R installation matching `r_version` was found. Planning commands can work
+    /// without it.
+    pub r_found: bool,
     pub builtin_packages: HashMap<String, Package>,
+    /// Libraries not managed by rv, resolved against the project directory
+    pub additional_libraries: Vec<PathBuf>,
+    /// Packages found in the additional libraries along with the library they are in, as written
+    /// in the config since it ends up in the lockfile
+    pub provided_packages: HashMap<String, (PathBuf, Package)>,
+    /// Packages found in the site libraries if `use_site_library` is enabled, along with the
+    /// library they are in
+    pub site_packages: HashMap<String, (PathBuf, Package)>,
     /// Taken from posit API. Only for some linux distrib, it will remain empty
     /// on mac/windows/arch etc
     pub system_dependencies: HashMap<String, Vec<String>>,
     /// Whether to show progress bars/spinners
     pub show_progress_bar: bool,
+    /// Whether to print every package as it is resolved, along with where it comes from
+    pub resolve_verbose: bool,
 }
 
 impl Context {
@@ -92,14 +114,26 @@ impl Context {
             config.set_library(&p);
         }
 
-        // This can only be set to false if the user passed a r_version to rv plan
+        // This can only be set to false for planning commands
         let mut r_version_found = true;
         let r_version = match r_command_lookup {
-            RCommandLookup::Strict | RCommandLookup::Skip => config.r_version().clone(),
+            RCommandLookup::Strict | RCommandLookup::Lenient | RCommandLookup::Skip => {
+                config.r_version().clone()
+            }
             RCommandLookup::Soft(ref v) => v.clone(),
         };
-        let r_cmd = match find_r_install(&r_version, config.use_devel()) {
+        let use_devel = config.use_devel() || r_version.is_r_devel();
+        let r_install = find_r_install(&r_version, use_devel);
+        let r_found = r_install.is_some();
+        let r_cmd = match r_install {
             Some(r_install) => r_install,
+            // We can't know which version `devel` refers to without an R-devel
+            None if r_version.is_r_devel() => {
+                return Err(
+                    "`r_version = \"devel\"` requires an R-devel installation but none was found"
+                        .into(),
+                );
+            }
             None => match r_command_lookup {
                 RCommandLookup::Strict => {
                     return Err(format!(
@@ -108,7 +142,7 @@ impl Context {
                     )
                     .into());
                 }
-                RCommandLookup::Soft(_) => {
+                RCommandLookup::Soft(_) | RCommandLookup::Lenient => {
                     r_version_found = false;
                     RInstall::default_from_path()
                 }
@@ -116,42 +150,104 @@ impl Context {
             },
         };
 
-        let cache = if let Some(dir) = cache_dir {
-            Cache::new_in_dir(&r_version, SystemInfo::from_os_info(), dir)?
+        // The library and cache are keyed by the major.minor of the R-devel found
+        let r_version = if r_version.is_r_devel() {
+            log::debug!("Using R-devel {} for `devel`", r_cmd.version.original);
+            config.set_r_version(r_cmd.version.clone());
+            r_cmd.version.clone()
         } else {
-            Cache::new(&r_version, SystemInfo::from_os_info())?
+            r_version
         };
 
+        let mut system_info = SystemInfo::from_os_info();
+        // Posit Package Manager only builds Linux binaries for released R versions
+        if r_cmd.is_devel && matches!(system_info.os_type, OsType::Linux(_)) {
+            log::debug!("R-devel detected, forcing source installs for all repositories");
+            for repo in config.repositories_mut() {
+                repo.force_source = true;
+            }
+        }
+
+        // A macOS machine can have both arm64 and x86_64 R installed so we key the library and
+        // the cache by the architecture of the R we found to keep them side by side
+        if r_version_found
+            && system_info.os_type == OsType::MacOs
+            && let Some(arch) = r_cmd.arch()
+        {
+            system_info.set_r_arch(&arch);
+        }
+
+        let mut cache = if let Some(dir) = cache_dir {
+            Cache::new_in_dir(&r_version, system_info, dir)?
+        } else {
+            Cache::new(&r_version, system_info)?
+        };
+        let configure_args = config
+            .configure_args()
+            .keys()
+            .map(|name| {
+                let args = config.get_configure_args(name, cache.system_info());
+                (name.clone(), args.to_vec())
+            })
+            .collect();
+        cache.set_build_keys(BuildKeys::new(&configure_args, config.packages_env_vars()));
+
         let project_dir = config_file.parent().unwrap().to_path_buf();
         let lockfile_path = project_dir.join(config.lockfile_name());
-        let lockfile = if lockfile_path.exists() && config.use_lockfile() {
-            if let Some(lockfile) = Lockfile::load(&lockfile_path)? {
-                if !lockfile.r_version().hazy_match(&r_version) {
-                    log::debug!(
-                        "R version in config file and lockfile are not compatible. Ignoring lockfile."
-                    );
-                    None
-                } else {
-                    Some(lockfile)
-                }
-            } else {
-                None
-            }
+        let lockfile = if config.use_lockfile() {
+            load_lockfile(&lockfile_path, &r_version)?
         } else {
             None
         };
 
         let mut library = if let Some(p) = config.library() {
             Library::new_custom(&project_dir, p)
+        } else if let Ok(root) = std::env::var(crate::consts::RENV_PATHS_LIBRARY_ENV_VAR_NAME) {
+            log::debug!("Using the library root from RENV_PATHS_LIBRARY: {root}");
+            Library::new_in_root(
+                project_dir.join(root),
+                cache.system_info(),
+                r_version.major_minor(),
+            )
         } else {
             Library::new(&project_dir, cache.system_info(), r_version.major_minor())
         };
         fs::create_dir_all(&library.path)?;
         library.find_content();
 
-        // We can only fetch the builtin packages if we have the right R
+        let additional_libraries: Vec<_> = config
+            .additional_libraries()
+            .iter()
+            .map(|p| {
+                if p.is_relative() {
+                    project_dir.join(p)
+                } else {
+                    p.clone()
+                }
+            })
+            .collect();
+        let mut provided_packages = find_provided_packages(&additional_libraries);
+        for (library, _) in provided_packages.values_mut() {
+            if let Some(i) = additional_libraries.iter().position(|l| l == library) {
+                *library = config.additional_libraries()[i].clone();
+            }
+        }
+        let site_packages = if config.use_site_library() {
+            let mut exclude = vec![library.path()];
+            exclude.extend(additional_libraries.iter().map(|p| p.as_path()));
+            find_provided_packages(&find_site_libraries(&exclude))
+        } else {
+            HashMap::new()
+        };
+
+        // We can only fetch the builtin packages if we have the right R, otherwise we use the
+        // ones saved in the cache by a previous run if there are some. The lockfile also records
+        // which packages were builtin.
         let builtin_packages = if r_version_found {
             cache.get_builtin_packages_versions(&r_cmd)?
+        } else if let Some(builtin) = cache.find_builtin_packages_versions(&r_version) {
+            log::debug!("R version not found: using the builtin packages found in the cache");
+            builtin
         } else {
             log::warn!(
                 "R version not found: there may be issues with resolution regarding recommended packages"
@@ -168,9 +264,14 @@ impl Context {
             lockfile,
             databases: Vec::new(),
             r_cmd,
+            r_found,
             builtin_packages,
+            additional_libraries,
+            provided_packages,
+            site_packages,
             system_dependencies: HashMap::new(),
             show_progress_bar: false,
+            resolve_verbose: false,
         })
     }
 
@@ -179,6 +280,10 @@ impl Context {
         self.show_progress_bar = true;
     }
 
+    pub fn resolve_verbose(&mut self) {
+        self.resolve_verbose = true;
+    }
+
     /// Load package databases from repositories
     pub fn load_databases(&mut self) -> Result<(), Box<dyn Error + Send + Sync>> {
         let pb = create_spinner(self.show_progress_bar, "Loading databases...");
@@ -187,6 +292,69 @@ impl Context {
         Ok(())
     }
 
+    /// Load the package databases already in the cache, even expired ones, without downloading
+    /// anything. Repositories without a cached database are skipped: only the packages of the
+    /// lockfile can be resolved from them.
+    pub fn load_cached_databases(&mut self) {
+        let cache = self.cache.local();
+        self.databases = self
+            .config
+            .repositories()
+            .iter()
+            .filter_map(|r| {
+                let (path, _) = cache.get_package_db_entry(r.url());
+                match RepositoryDatabase::load(&path) {
+                    Ok(db) => Some((db, r.force_source)),
+                    Err(e) => {
+                        log::warn!("No cached database for {}: {e}", r.url());
+                        None
+                    }
+                }
+            })
+            .collect();
+    }
+
+    /// Downloads the databases again even if the cached ones are still fresh
+    pub fn refresh_databases(&mut self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let pb = create_spinner(self.show_progress_bar, "Refreshing databases...");
+        self.databases = fetch_databases(self.config.repositories(), self.cache.local(), true)?;
+        pb.finish_and_clear();
+        Ok(())
+    }
+
+    /// Fetches the git dependencies following a branch in the cache so the next resolution only
+    /// has to fetch what changed in the meantime.
+    /// Returns the dependencies that could not be fetched along with the error.
+    pub fn refresh_git_dependencies(&self) -> Vec<(String, std::io::Error)> {
+        let pb = create_spinner(self.show_progress_bar, "Refreshing git dependencies...");
+        let mut errors = Vec::new();
+        for dep in self.config.dependencies() {
+            if let ConfigDependency::Git {
+                git,
+                branch: Some(branch),
+                directory,
+                name,
+                ..
+            } = dep
+            {
+                let mut remote = GitRemote::new(git.url());
+                if let Some(d) = directory {
+                    remote.set_directory(d);
+                }
+                let dest = self.cache.local().get_git_clone_path(git.url());
+                if let Err(e) = remote.sparse_checkout_for_description(
+                    dest,
+                    &GitReference::Branch(branch),
+                    GitExecutor,
+                ) {
+                    errors.push((name.clone(), e));
+                }
+            }
+        }
+        pb.finish_and_clear();
+        errors
+    }
+
     /// Load system requirements from posit API (only supported on some Linux distros)
     pub fn load_system_requirements(&mut self) {
         if !system_req::is_supported(self.cache.system_info()) {
@@ -216,6 +384,63 @@ impl Context {
         self.project_dir.join(self.config.lockfile_name())
     }
 
+    /// Overrides `use_lockfile` from the config, loading or dropping the lockfile accordingly
+    pub fn set_use_lockfile(
+        &mut self,
+        use_lockfile: bool,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.config.set_use_lockfile(use_lockfile);
+        self.lockfile = if use_lockfile {
+            load_lockfile(&self.lockfile_path(), &self.r_version)?
+        } else {
+            None
+        };
+        Ok(())
+    }
+
+    /// Identifies the project in the cache bookkeeping: the `project.id` of the config if set,
+    /// otherwise derived from the project directory
+    pub fn project_id(&self) -> String {
+        match self.config.project_id() {
+            Some(id) => hash_string(id.trim()),
+            None => {
+                let dir = fs::canonicalize(&self.project_dir)
+                    .unwrap_or_else(|_| self.project_dir.clone());
+                hash_string(&dir.to_string_lossy())
+            }
+        }
+    }
+
+    pub fn last_sync_report_path(&self) -> PathBuf {
+        self.project_dir
+            .join(RV_DIR_NAME)
+            .join(LAST_SYNC_REPORT_FILENAME)
+    }
+
+    pub fn simulated_sync_report_path(&self) -> PathBuf {
+        self.project_dir
+            .join(RV_DIR_NAME)
+            .join(SIMULATED_SYNC_REPORT_FILENAME)
+    }
+
+    pub fn startup_bench_path(&self) -> PathBuf {
+        self.project_dir
+            .join(RV_DIR_NAME)
+            .join(STARTUP_BENCH_FILENAME)
+    }
+
+    /// Why the project can't be modified, if it can't: either `readonly = true` in the config
+    /// or the library is not writable, eg mounted read-only
+    pub fn readonly_reason(&self) -> Option<&'static str> {
+        if self.config.readonly() {
+            Some("`readonly = true` is set in the config")
+        } else if !is_writable(self.library.path()) {
+            Some("the library is not writable")
+        } else {
+            None
+        }
+    }
+
     pub fn library_path(&self) -> &Path {
         self.library.path()
     }
@@ -230,21 +455,7 @@ impl Context {
             ResolveMode::FullUpgrade => &None,
         };
 
-        let mut resolver = Resolver::new(
-            &self.project_dir,
-            &self.databases,
-            self.config.repositories().iter().map(|x| x.url()).collect(),
-            &self.r_version,
-            &self.builtin_packages,
-            lockfile.as_ref(),
-            self.config.packages_env_vars(),
-        );
-
-        if self.show_progress_bar {
-            resolver.show_progress_bar();
-        }
-
-        let mut resolution = resolver.resolve(
+        let mut resolution = self.resolver(lockfile.as_ref()).resolve(
             self.config.dependencies(),
             self.config.prefer_repositories_for(),
             &self.cache,
@@ -252,6 +463,10 @@ impl Context {
             &Http {},
         );
 
+        if let Some(lockfile) = &self.lockfile {
+            resolution.explain_archived_failures(lockfile);
+        }
+
         // If upgrade mode and there is a lockfile, adjust from_lockfile flags
         // to indicate which resolved deps match what was in the lockfile
         if resolve_mode == ResolveMode::FullUpgrade && self.lockfile.is_some() {
@@ -267,6 +482,96 @@ impl Context {
 
         resolution
     }
+
+    /// Resolves only the dependencies in `added`, which were just added to the config, keeping
+    /// the rest of the project as it is in the lockfile.
+    /// Returns `None` if that is not possible and a full resolution is needed: there is no
+    /// lockfile, the other dependencies don't match it or the resolution of the new ones would
+    /// change packages already in it.
+    /// The resolution contains the new packages and the locked packages they depend on, which
+    /// are left as they are in the library.
+    pub fn resolve_added(&self, added: &[String]) -> Option<Resolution<'_>> {
+        let lockfile = self.lockfile.as_ref()?;
+        // `rv add` appends the new entries at the end of the dependencies
+        let dependencies = self.config.dependencies();
+        let (existing_deps, new_deps) =
+            dependencies.split_at(dependencies.len().checked_sub(added.len())?);
+        if new_deps.is_empty()
+            || new_deps
+                .iter()
+                .any(|d| !added.iter().any(|name| name == d.name()))
+            || !lockfile.can_resolve(existing_deps, self.config.repositories())
+        {
+            return None;
+        }
+
+        let resolution = self.resolver(Some(lockfile)).resolve(
+            new_deps,
+            self.config.prefer_repositories_for(),
+            &self.cache,
+            &GitExecutor {},
+            &Http {},
+        );
+
+        // Shared with the rest of the project, it needs to be exactly what is locked
+        let changes_lockfile = resolution.found.iter().any(|dep| {
+            lockfile.get_package(&dep.name, None).is_some_and(|locked| {
+                locked.version != dep.version.original || locked.source != dep.source
+            })
+        });
+        if changes_lockfile {
+            log::debug!("Added packages change the lockfile, doing a full resolution");
+            return None;
+        }
+
+        Some(resolution)
+    }
+
+    fn resolver<'a>(&'a self, lockfile: Option<&'a Lockfile>) -> Resolver<'a> {
+        let mut resolver = Resolver::new(
+            &self.project_dir,
+            &self.databases,
+            self.config.repositories().iter().map(|x| x.url()).collect(),
+            &self.r_version,
+            &self.builtin_packages,
+            lockfile,
+            self.config.packages_env_vars(),
+        );
+
+        if self.show_progress_bar {
+            resolver.show_progress_bar();
+        }
+        if self.resolve_verbose {
+            resolver.resolve_verbose();
+        }
+        resolver.set_provided_packages(&self.provided_packages);
+        resolver.set_declared_provided(self.config.provided());
+        resolver.set_package_repositories(self.config.package_repositories());
+        if let Some(lockfile) = lockfile {
+            resolver.set_stale_in_lockfile(
+                lockfile.packages_with_stale_repository(self.config.repositories()),
+            );
+        }
+        resolver
+    }
+}
+
+/// Loads the lockfile if it exists and is for a R version compatible with the one used
+fn load_lockfile(
+    path: &Path,
+    r_version: &Version,
+) -> Result<Option<Lockfile>, Box<dyn Error + Send + Sync>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let Some(lockfile) = Lockfile::load(path)? else {
+        return Ok(None);
+    };
+    if !lockfile.r_version().hazy_match(r_version) {
+        log::debug!("R version in config file and lockfile are not compatible. Ignoring lockfile.");
+        return Ok(None);
+    }
+    Ok(Some(lockfile))
 }
 
 /// Load package databases from repositories
@@ -274,6 +579,16 @@ impl Context {
 pub fn load_databases(
     repositories: &[Repository],
     cache: &DiskCache,
+) -> Result<Vec<(RepositoryDatabase, bool)>, Box<dyn Error + Send + Sync>> {
+    fetch_databases(repositories, cache, false)
+}
+
+/// Same as `load_databases` but `force_refresh` will download the databases even if the cached
+/// ones are still fresh
+fn fetch_databases(
+    repositories: &[Repository],
+    cache: &DiskCache,
+    force_refresh: bool,
 ) -> Result<Vec<(RepositoryDatabase, bool)>, Box<dyn Error + Send + Sync>> {
     #[cfg(feature = "cli")]
     let iter = repositories.par_iter();
@@ -283,7 +598,7 @@ pub fn load_databases(
     let results: Vec<Result<_, Box<dyn Error + Send + Sync>>> = iter
         .map(|r| {
             let task = events::Task::new(format!("db:{}", r.alias), r.alias.clone());
-            let db = events::with_task(task, || load_single_database(r, cache))?;
+            let db = events::with_task(task, || load_single_database(r, cache, force_refresh))?;
             Ok((db, r.force_source))
         })
         .collect();
@@ -299,12 +614,13 @@ pub fn load_databases(
 fn load_single_database(
     r: &Repository,
     cache: &DiskCache,
+    force_refresh: bool,
 ) -> Result<RepositoryDatabase, Box<dyn Error + Send + Sync>> {
     // 1. Generate path to add to URL to get the src PACKAGE and binary PACKAGE for current OS
     let (path, exists) = cache.get_package_db_entry(r.url());
 
     // 2. Check in cache whether we have the database and is not expired
-    if exists {
+    if exists && !force_refresh {
         // load the archive
         // We want to fallback on fetching it again if we somehow can't load it
         if let Ok(db) = RepositoryDatabase::load(&path) {
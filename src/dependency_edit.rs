@@ -10,7 +10,6 @@ use clap::Parser;
 use crate::git::{self, CommandExecutor, GitExecutor, GitReference};
 use crate::{Config, config::ConfigLoadError, git::url::GitUrl};
 
-pub const DEFAULT_GIT_SHORTHAND_BASE_URL: &str = "https://github.com";
 const DEFAULT_GIT_HEAD_REFERENCE: &str = "HEAD";
 
 #[derive(Debug, Clone, PartialEq, Default)]
@@ -28,6 +27,12 @@ pub struct AddOptions {
     /// Install only the dependencies, not the package itself
     #[cfg_attr(feature = "cli", clap(long))]
     pub dependencies_only: bool,
+    /// Install only the package itself, trusting the environment for its dependencies
+    #[cfg_attr(
+        feature = "cli",
+        clap(long = "no-deps", conflicts_with = "dependencies_only")
+    )]
+    pub no_dependencies: bool,
     /// Git repository URL (https or ssh)
     #[cfg_attr(feature = "cli", clap(long, conflicts_with_all = ["repository", "path", "url"]))]
     pub git: Option<String>,
@@ -60,6 +65,7 @@ impl AddOptions {
             || self.force_source
             || self.install_suggestions
             || self.dependencies_only
+            || self.no_dependencies
             || self.git.is_some()
             || self.path.is_some()
             || self.url.is_some()
@@ -371,6 +377,10 @@ fn add_common_options(table: &mut InlineTable, options: &AddOptions) {
     if options.dependencies_only {
         table.insert("dependencies_only", Value::from(true));
     }
+
+    if options.no_dependencies {
+        table.insert("no_dependencies", Value::from(true));
+    }
 }
 
 fn get_mut_array(doc: &mut DocumentMut) -> &mut Array {
@@ -550,7 +560,8 @@ pub enum DependencyEditErrorKind {
 
 #[cfg(test)]
 mod tests {
-    use super::{AddOptions, DEFAULT_GIT_SHORTHAND_BASE_URL, parse_add_package_spec};
+    use super::{AddOptions, parse_add_package_spec};
+    use crate::consts::DEFAULT_GIT_SHORTHAND_BASE_URL;
     use crate::{add_packages, read_and_verify_config, remove_packages};
 
     const BASELINE_ADD_CONFIG: &str = "src/tests/valid_config/baseline_for_add.toml";
@@ -632,6 +643,21 @@ mod tests {
         insta::assert_snapshot!(doc.to_string());
     }
 
+    #[test]
+    fn add_with_no_dependencies() {
+        let mut doc = read_and_verify_config(BASELINE_ADD_CONFIG).unwrap();
+        add_packages(
+            &mut doc,
+            vec!["dplyr".to_string()],
+            AddOptions {
+                no_dependencies: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        insta::assert_snapshot!(doc.to_string());
+    }
+
     #[test]
     fn add_git_with_commit() {
         let mut doc = read_and_verify_config(BASELINE_ADD_CONFIG).unwrap();
@@ -870,4 +896,13 @@ mod tests {
         .unwrap();
         insta::assert_snapshot!(doc.to_string());
     }
+
+    #[test]
+    fn add_and_remove_keep_dependency_metadata() {
+        let mut doc =
+            read_and_verify_config("src/tests/valid_config/dependency_metadata.toml").unwrap();
+        remove_packages(&mut doc, vec!["dplyr".to_string()]).unwrap();
+        add_packages(&mut doc, vec!["tidyr".to_string()], AddOptions::default()).unwrap();
+        insta::assert_snapshot!(doc.to_string());
+    }
 }
@@ -72,6 +72,13 @@ pub enum Event {
         result: TaskResult,
         time_ms: u64,
     },
+    /// For tasks going through a number of items not known in advance, eg the resolution
+    TaskProgress {
+        #[serde(flatten)]
+        task: Task,
+        completed: usize,
+        pending: usize,
+    },
 }
 
 /// Emit `TaskStarted`, run `f`, then emit `TaskFinished` with the result and elapsed time.
@@ -123,6 +123,24 @@ fn metadata(path: impl AsRef<Path>) -> Result<Metadata, std::io::Error> {
     fs::metadata(path)
 }
 
+/// Total size in bytes of the files in the given folder. Symlinks are not followed so packages
+/// linked from the cache are not counted twice
+pub(crate) fn dir_size(folder: impl AsRef<Path>) -> u64 {
+    WalkDir::new(folder)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .filter_map(|e| e.metadata().ok())
+        .map(|m| m.len())
+        .sum()
+}
+
+/// Whether we can create files in the given folder. Permissions alone don't tell us about
+/// read-only mounts so we actually try
+pub(crate) fn is_writable(folder: impl AsRef<Path>) -> bool {
+    tempfile::tempfile_in(folder).is_ok()
+}
+
 /// Returns the maximum mtime found in the given folder, looking at all subfolders and
 /// following symlinks
 /// Taken from cargo crates/cargo-util/src/paths.rs
@@ -331,3 +349,52 @@ pub fn is_network_fs(path: impl AsRef<Path>) -> Result<bool, std::io::Error> {
 pub fn is_network_fs(_path: impl AsRef<Path>) -> std::io::Result<bool> {
     Ok(false)
 }
+
+/// Formats a path to be used by R, eg in `.libPaths()` or `source()`.
+/// R accepts forward slashes on every platform while backslashes would need escaping in R strings.
+/// The verbatim prefix added by `canonicalize` on Windows isn't understood by R so it is removed,
+/// keeping network shares as `//server/share`.
+pub fn r_path(path: impl AsRef<Path>) -> String {
+    let path = path.as_ref().to_string_lossy();
+    let path = if let Some(rest) = path.strip_prefix(r"\\?\UNC\") {
+        format!(r"\\{rest}")
+    } else if let Some(rest) = path.strip_prefix(r"\\?\") {
+        rest.to_string()
+    } else {
+        path.into_owned()
+    };
+    path.replace('\\', "/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn can_detect_writable_folders() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(is_writable(dir.path()));
+        assert!(!is_writable(dir.path().join("missing")));
+        // Nothing is left behind
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn can_format_r_paths() {
+        let cases = [
+            (
+                r"C:\Users\me\project\rv\library",
+                "C:/Users/me/project/rv/library",
+            ),
+            (r"\\?\C:\Users\me\project", "C:/Users/me/project"),
+            (r"\\server\share\project\rv", "//server/share/project/rv"),
+            (r"\\?\UNC\server\share\project", "//server/share/project"),
+            (r"Z:\project\rv\library", "Z:/project/rv/library"),
+            ("rv/library/4.4/x86_64", "rv/library/4.4/x86_64"),
+            ("/home/me/project", "/home/me/project"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(r_path(input), expected, "{input}");
+        }
+    }
+}
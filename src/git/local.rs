@@ -15,6 +15,7 @@ pub struct GitRepository {
 }
 
 impl GitRepository {
+    #[cfg(not(target_arch = "wasm32"))]
     pub(crate) fn rm_folder(&self) -> Result<(), std::io::Error> {
         if self.path.is_dir() {
             fs::remove_dir_all(&self.path)?;
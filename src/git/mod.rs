@@ -3,6 +3,7 @@ use std::process::Command;
 
 mod local;
 mod reference;
+#[cfg(not(target_arch = "wasm32"))]
 mod remote;
 pub(crate) mod url;
 
@@ -11,12 +12,15 @@ pub trait CommandExecutor {
 }
 
 pub use local::GitRepository;
+#[cfg(not(target_arch = "wasm32"))]
 pub use reference::GitReference;
+#[cfg(not(target_arch = "wasm32"))]
 pub use remote::GitRemote;
 
 const SYMREF_PREFIX: &str = "ref: refs/heads/";
 
 /// Resolves the default branch of a remote git repository by URL.
+#[cfg(not(target_arch = "wasm32"))]
 pub fn resolve_default_branch_for_url(
     executor: &dyn CommandExecutor,
     url: &str,
@@ -32,6 +36,23 @@ pub fn resolve_default_branch_in_repo(
     ls_remote_symref_head(executor, "origin", Some(repo_path))
 }
 
+/// Reads the content of a file, relative to `repo_dir`, as it was at the given revision
+pub fn read_file_at_revision(
+    executor: &dyn CommandExecutor,
+    repo_dir: &Path,
+    revision: &str,
+    path: &str,
+) -> Result<String, std::io::Error> {
+    // `./` makes git look up the path relative to the current directory rather than the root
+    // of the repository, so it works for projects in a subfolder
+    executor.execute(
+        Command::new("git")
+            .arg("show")
+            .arg(format!("{revision}:./{path}"))
+            .current_dir(repo_dir),
+    )
+}
+
 fn ls_remote_symref_head(
     executor: &dyn CommandExecutor,
     target: &str,
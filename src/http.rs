@@ -31,13 +31,17 @@ fn retry_backoff(attempt: u32) -> Duration {
 }
 
 pub(crate) fn build_agent(insecure: bool) -> Agent {
+    build_agent_with_timeout(insecure, Duration::from_secs(200))
+}
+
+pub(crate) fn build_agent_with_timeout(insecure: bool, timeout: Duration) -> Agent {
     let mut tls_builder = TlsConfig::builder().root_certs(RootCerts::PlatformVerifier);
     if insecure {
         tls_builder = tls_builder.disable_verification(true);
     }
     Agent::config_builder()
         .tls_config(tls_builder.build())
-        .timeout_global(Some(Duration::from_secs(200)))
+        .timeout_global(Some(timeout))
         .build()
         .new_agent()
 }
@@ -1,66 +1,148 @@
+//! On wasm32, only the parts without IO are available: the config, the lockfile, versions and
+//! dependencies, eg to parse and diff lockfiles in a browser.
+#[cfg(not(target_arch = "wasm32"))]
 mod activate;
+#[cfg(feature = "tokio")]
+mod async_tasks;
+#[cfg(not(target_arch = "wasm32"))]
+mod bundle;
+#[cfg(not(target_arch = "wasm32"))]
 mod cache;
 mod cancellation;
 #[cfg(feature = "cli")]
 pub mod cli;
+mod conda;
 mod config;
+#[cfg(not(target_arch = "wasm32"))]
 mod configure;
 pub mod consts;
+#[cfg(not(target_arch = "wasm32"))]
 mod context;
+#[cfg(not(target_arch = "wasm32"))]
 mod dependency_edit;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod events;
+#[cfg(not(target_arch = "wasm32"))]
 mod format;
+#[cfg(not(target_arch = "wasm32"))]
 mod fs;
 mod git;
+#[cfg(not(target_arch = "wasm32"))]
 mod http;
+#[cfg(not(target_arch = "wasm32"))]
 mod library;
 mod lockfile;
+#[cfg(not(target_arch = "wasm32"))]
+mod nix;
 mod package;
+#[cfg(not(target_arch = "wasm32"))]
+mod process_limits;
+#[cfg(not(target_arch = "wasm32"))]
 mod project_summary;
+#[cfg(not(target_arch = "wasm32"))]
 mod r_cmd;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod r_finder;
+#[cfg(not(target_arch = "wasm32"))]
 mod renv;
+#[cfg(not(target_arch = "wasm32"))]
 mod repository;
+#[cfg(not(target_arch = "wasm32"))]
 mod repository_urls;
+#[cfg(not(target_arch = "wasm32"))]
 mod resolver;
+#[cfg(not(target_arch = "wasm32"))]
 mod run;
+#[cfg(not(target_arch = "wasm32"))]
+mod startup_bench;
+#[cfg(not(target_arch = "wasm32"))]
 mod sync;
 mod system_info;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod system_req;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod test_utils;
 mod utils;
+#[cfg(not(target_arch = "wasm32"))]
+mod validation;
 
+#[cfg(not(target_arch = "wasm32"))]
 pub use activate::{activate, deactivate};
+#[cfg(feature = "tokio")]
+pub use async_tasks::TaskError;
+#[cfg(not(target_arch = "wasm32"))]
+pub use bundle::{
+    BundleError, BundleManifest, RRuntimeReference, bundle_platform, create_bundle, install_bundle,
+    read_bundle_manifest,
+};
+#[cfg(not(target_arch = "wasm32"))]
 pub use cache::{Cache, CacheInfo, DiskCache, PackagePaths, utils::hash_string};
 pub use cancellation::Cancellation;
-pub use config::{Config, ConfigDependency, Repository};
+pub use conda::to_conda_environment;
+pub use config::{Config, ConfigDependency, DependencyMetadata, InstallConstraint, Repository};
+#[cfg(not(target_arch = "wasm32"))]
 pub use configure::{
     ConfigureRepositoryResponse, RepositoryAction, RepositoryMatcher, RepositoryOperation,
     RepositoryPositioning, RepositoryUpdates, execute_repository_action,
 };
+#[cfg(not(target_arch = "wasm32"))]
 pub use context::{Context, RCommandLookup, ResolveMode};
+#[cfg(not(target_arch = "wasm32"))]
 pub use dependency_edit::{
     AddOptions, ResolvedGitRef, add_packages, parse_add_package_spec, read_and_verify_config,
     remove_packages, resolve_add_options_reference_with_executor,
 };
+#[cfg(not(target_arch = "wasm32"))]
 pub use format::format_document;
-pub use fs::is_network_fs;
-pub use git::{CommandExecutor, GitExecutor, GitRepository};
-pub use http::{Http, HttpDownload};
+#[cfg(not(target_arch = "wasm32"))]
+pub use fs::{is_network_fs, r_path};
+pub use git::{CommandExecutor, GitExecutor, GitRepository, read_file_at_revision};
+#[cfg(not(target_arch = "wasm32"))]
+pub use http::{Http, HttpDownload, HttpError, HttpErrorKind};
+#[cfg(not(target_arch = "wasm32"))]
 pub use library::Library;
-pub use lockfile::{LockedPackage, Lockfile, Source};
-pub use package::{
-    Dependency, FetchPackage, Operator, Version, VersionRequirement, is_binary_package,
+pub use lockfile::{
+    DiffPackage, LockedPackage, Lockfile, LockfileDiff, PackageChange, RepositoryMove, Source,
 };
-pub use project_summary::ProjectSummary;
+#[cfg(not(target_arch = "wasm32"))]
+pub use nix::to_nix_expression;
+#[cfg(not(target_arch = "wasm32"))]
+pub use package::FetchPackage;
+#[cfg(not(target_arch = "wasm32"))]
+pub use package::is_binary_package;
+pub use package::{Dependency, DependencyType, Operator, Version, VersionRequirement};
+#[cfg(not(target_arch = "wasm32"))]
+pub use project_summary::{ProjectSummary, SummarySection};
+#[cfg(not(target_arch = "wasm32"))]
 pub use r_cmd::RCmd;
+#[cfg(not(target_arch = "wasm32"))]
 pub use r_finder::RInstall;
-pub use renv::RenvLock;
-pub use repository::RepositoryDatabase;
+#[cfg(not(target_arch = "wasm32"))]
+pub use renv::{RenvLock, update_renv_lock};
+#[cfg(not(target_arch = "wasm32"))]
+pub use repository::{RepositoryDatabase, RepositoryDiff, RepositoryPackage, VersionChange};
+#[cfg(not(target_arch = "wasm32"))]
 pub use repository_urls::{get_package_file_urls, get_tarball_urls};
+#[cfg(not(target_arch = "wasm32"))]
 pub use resolver::{Resolution, ResolvedDependency, Resolver, UnresolvedDependency};
-pub use run::{RunError, run};
-pub use sync::{BuildPlan, BuildStep, LinkMode, SyncChange, SyncHandler};
+#[cfg(not(target_arch = "wasm32"))]
+pub use run::{LoadFailure, RunError, run, smoke_test};
+#[cfg(not(target_arch = "wasm32"))]
+pub use startup_bench::{
+    PackageStartup, StartupBench, StartupBenchError, StartupHistory, StartupRegression,
+    bench_startup,
+};
+#[cfg(not(target_arch = "wasm32"))]
+pub use sync::{
+    BuildPlan, BuildStep, FailureAction, LinkMode, PackageOutcome, PackageReport, SyncChange,
+    SyncHandler, SyncReport,
+};
 pub use system_info::{OsType, SystemInfo};
+pub use utils::{format_bytes, format_duration};
+#[cfg(not(target_arch = "wasm32"))]
+pub use validation::{
+    ReportSeal, ValidatedPackage, ValidationPlatform, ValidationReport, hash_installed_package,
+};
 
 #[doc(hidden)]
 pub mod internal {
@@ -1,17 +1,18 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::io::Write;
 use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 
 use fs_err as fs;
 use serde::{Deserialize, Serialize};
 
 use crate::consts::{
-    DESCRIPTION_FILENAME, LIBRARY_METADATA_FILENAME, LIBRARY_ROOT_DIR_NAME, RV_DIR_NAME,
-    STAGING_DIR_NAME,
+    DESCRIPTION_FILENAME, LIBRARY_MANIFEST_FILENAME, LIBRARY_METADATA_FILENAME,
+    LIBRARY_ROOT_DIR_NAME, RV_DIR_NAME, STAGING_DIR_NAME,
 };
 use crate::fs::mtime_recursive;
 use crate::lockfile::Source;
-use crate::package::parse_version;
+use crate::package::{Package, parse_description_file_in_folder, parse_version};
 use crate::{ResolvedDependency, SystemInfo, Version};
 
 /// Builds the path for binary in the cache and the library based on system info and R version
@@ -74,6 +75,52 @@ impl LocalMetadata {
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct ManifestPackage {
+    version: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source: Option<Source>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    metadata: Option<LocalMetadata>,
+    /// Removing the target of a symlink doesn't touch the library folder so those are checked
+    /// every time
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    symlink: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct ManifestLibrary {
+    /// The mtime of the library folder when the manifest was written. Installing or removing
+    /// a package outside of rv changes it so we know the manifest can't be trusted anymore.
+    modified: SystemTime,
+    packages: BTreeMap<String, ManifestPackage>,
+}
+
+/// The content of every library of the project, keyed by their path relative to the library
+/// root (`{R_Version}/{arch}/{library_identifier}`).
+/// It is written by sync so the content of a library can be known without reading every
+/// DESCRIPTION file, which is slow on network filesystems.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+struct LibraryManifest {
+    libraries: BTreeMap<String, ManifestLibrary>,
+}
+
+impl LibraryManifest {
+    fn load(path: &Path) -> Option<Self> {
+        let content = fs::read_to_string(path).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    /// Written to a temporary file first so readers never see a partial manifest
+    fn save(&self, path: &Path) -> Result<(), std::io::Error> {
+        let parent = path.parent().expect("manifest to be in a folder");
+        let mut tmp = tempfile::NamedTempFile::new_in(parent)?;
+        tmp.write_all(serde_json::to_string_pretty(self).unwrap().as_bytes())?;
+        tmp.persist(path).map_err(|e| e.error)?;
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Library {
     /// This is the path where the packages are installed so
@@ -87,6 +134,8 @@ pub struct Library {
     /// It could also be something that is not a R package added by another tool
     pub broken: HashSet<String>,
     pub custom: bool,
+    /// Where the manifest is and the key of this library in it. Custom libraries don't have one.
+    manifest: Option<(PathBuf, String)>,
 }
 
 impl Library {
@@ -95,19 +144,30 @@ impl Library {
         system_info: &SystemInfo,
         r_version: [u32; 2],
     ) -> Library {
-        let system_path = get_current_system_path(system_info, r_version);
-        let path = project_dir
+        let root = project_dir
             .as_ref()
             .join(RV_DIR_NAME)
-            .join(LIBRARY_ROOT_DIR_NAME)
-            .join(system_path);
+            .join(LIBRARY_ROOT_DIR_NAME);
+        Self::new_in_root(root, system_info, r_version)
+    }
+
+    /// Like [`Library::new`] but with the platform specific folders in `root` rather than in
+    /// the `rv/library` folder of the project
+    pub fn new_in_root(root: PathBuf, system_info: &SystemInfo, r_version: [u32; 2]) -> Library {
+        let system_path = get_current_system_path(system_info, r_version);
+        let key = system_path
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
 
         Self {
-            path,
+            path: root.join(system_path),
             packages: HashMap::new(),
             non_repo_packages: HashMap::new(),
             broken: HashSet::new(),
             custom: false,
+            manifest: Some((root.join(LIBRARY_MANIFEST_FILENAME), key)),
         }
     }
 
@@ -122,6 +182,7 @@ impl Library {
             non_repo_packages: HashMap::new(),
             broken: HashSet::new(),
             custom: true,
+            manifest: None,
         }
     }
 
@@ -129,11 +190,8 @@ impl Library {
         &self.path
     }
 
-    /// Finds the content of the library: packages, their version and their metadata (sha/mtime)
-    /// if they are not installed via a package repository
-    /// Also figures out if we can access the DESCRIPTION file, if we can't
-    /// it's likely that some linking between the cache and the library broke
-    /// and we should not consider them installed.
+    /// Finds the content of the library, from the manifest written by the last sync if the
+    /// library wasn't modified since then or by reading every package otherwise.
     pub fn find_content(&mut self) {
         if !self.path.is_dir() {
             return;
@@ -144,6 +202,58 @@ impl Library {
             return;
         }
 
+        if self.load_manifest() {
+            log::debug!("Library content read from its manifest");
+            return;
+        }
+        self.scan_content();
+    }
+
+    fn load_manifest(&mut self) -> bool {
+        let Some((path, key)) = &self.manifest else {
+            return false;
+        };
+        let Some(mut manifest) = LibraryManifest::load(path) else {
+            return false;
+        };
+        let Some(library) = manifest.libraries.remove(key) else {
+            return false;
+        };
+        let modified = fs::metadata(&self.path).and_then(|m| m.modified()).ok();
+        if modified != Some(library.modified) {
+            return false;
+        }
+
+        let mut packages = HashMap::new();
+        let mut non_repo_packages = HashMap::new();
+        for (name, package) in library.packages {
+            let Ok(version) = package.version.parse() else {
+                return false;
+            };
+            if package.symlink && !self.path.join(&name).join(DESCRIPTION_FILENAME).exists() {
+                return false;
+            }
+            packages.insert(name.clone(), version);
+            if let Some(metadata) = package.metadata {
+                non_repo_packages.insert(name, metadata);
+            }
+        }
+        self.packages = packages;
+        self.non_repo_packages = non_repo_packages;
+        self.broken.clear();
+        true
+    }
+
+    /// Finds the content of the library: packages, their version and their metadata (sha/mtime)
+    /// if they are not installed via a package repository
+    /// Also figures out if we can access the DESCRIPTION file, if we can't
+    /// it's likely that some linking between the cache and the library broke
+    /// and we should not consider them installed.
+    pub fn scan_content(&mut self) {
+        if !self.path.is_dir() || self.custom {
+            return;
+        }
+
         self.packages.clear();
         self.non_repo_packages.clear();
         self.broken.clear();
@@ -222,6 +332,249 @@ impl Library {
             }
             Source::Repository { .. } => &self.packages[pkg.name.as_ref()] == pkg.version.as_ref(),
             Source::Builtin { .. } => true,
+            // Those live outside the rv library, a copy in there would shadow them
+            Source::Provided { .. } => false,
+        }
+    }
+
+    /// Records the current content of the library in the manifest, along with the source of
+    /// the packages in `deps`. Should be called after the library was modified.
+    pub(crate) fn write_manifest(&self, deps: &[ResolvedDependency]) -> std::io::Result<()> {
+        let Some((path, key)) = &self.manifest else {
+            return Ok(());
+        };
+        // Read before scanning so a change made while we scan makes the manifest stale
+        let modified = fs::metadata(&self.path)?.modified()?;
+        // The library in the context was read before the sync so we need to look at it again
+        let mut library = self.clone();
+        library.scan_content();
+        let mut manifest = LibraryManifest::load(path).unwrap_or_default();
+        if !library.broken.is_empty() {
+            // We don't want to record a library we can't fully describe
+            manifest.libraries.remove(key);
+        } else {
+            let sources: HashMap<_, _> =
+                deps.iter().map(|d| (d.name.as_ref(), &d.source)).collect();
+            let previous = manifest.libraries.remove(key);
+            let packages = library
+                .packages
+                .iter()
+                .map(|(name, version)| {
+                    // Packages not in the deps were left untouched, eg by an incremental add
+                    let previous_source = || {
+                        previous
+                            .as_ref()
+                            .and_then(|l| l.packages.get(name))
+                            .filter(|p| p.version == version.original)
+                            .and_then(|p| p.source.clone())
+                    };
+                    let package = ManifestPackage {
+                        version: version.original.clone(),
+                        source: sources
+                            .get(name.as_str())
+                            .map(|s| (*s).clone())
+                            .or_else(previous_source),
+                        metadata: library.non_repo_packages.get(name).cloned(),
+                        symlink: self.path.join(name).is_symlink(),
+                    };
+                    (name.clone(), package)
+                })
+                .collect();
+            manifest
+                .libraries
+                .insert(key.clone(), ManifestLibrary { modified, packages });
+        }
+        manifest.save(path)
+    }
+}
+
+/// The site libraries listed in `R_LIBS_SITE` that exist, minus the ones in `exclude`.
+/// rv sets `R_LIBS_SITE` to the project library in activated projects so we need to skip it.
+pub fn find_site_libraries(exclude: &[&Path]) -> Vec<PathBuf> {
+    let Some(value) = std::env::var_os("R_LIBS_SITE") else {
+        return Vec::new();
+    };
+    std::env::split_paths(&value)
+        .filter(|p| p.is_dir() && !exclude.contains(&p.as_path()))
+        .collect()
+}
+
+/// Whether a package installed in a site library can be used in place of a resolved dependency:
+/// same version, same commit for git sources and built for the same R minor version.
+/// We can't tell which repository a package came from so only the version is compared for those.
+pub(crate) fn site_package_matches(
+    package: &Package,
+    version: &Version,
+    source: &Source,
+    r_version: [u32; 2],
+) -> bool {
+    if package.version != *version {
+        return false;
+    }
+    let source_matches = match source {
+        Source::Repository { .. } => true,
+        Source::Git { sha, .. } | Source::RUniverse { sha, .. } => {
+            package.remote_sha.as_deref() == Some(sha.as_str())
         }
+        _ => false,
+    };
+    // Built looks like `R 4.4.1; x86_64-pc-linux-gnu; 2024-06-14 10:35:04 UTC; unix`
+    let built_for = package
+        .built
+        .as_deref()
+        .and_then(|b| b.strip_prefix("R "))
+        .and_then(|b| b.split(';').next())
+        .and_then(|v| v.trim().parse::<Version>().ok());
+    source_matches && built_for.is_some_and(|v| v.major_minor() == r_version)
+}
+
+/// Finds the packages available in libraries rv does not manage, eg a read-only site library.
+/// If a package is present in several of them, the first library listed wins like it would
+/// in `.libPaths()`.
+pub(crate) fn find_provided_packages(libraries: &[PathBuf]) -> HashMap<String, (PathBuf, Package)> {
+    let mut out = HashMap::new();
+
+    for library in libraries {
+        let entries = match fs::read_dir(library) {
+            Ok(e) => e,
+            Err(e) => {
+                log::warn!(
+                    "Could not read additional library {}: {e}",
+                    library.display()
+                );
+                continue;
+            }
+        };
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if !path.join(DESCRIPTION_FILENAME).exists() {
+                continue;
+            }
+            match parse_description_file_in_folder(&path) {
+                Ok(package) => {
+                    out.entry(package.name.clone())
+                        .or_insert_with(|| (library.clone(), package));
+                }
+                Err(e) => log::debug!("Skipping {} in additional library: {e}", path.display()),
+            }
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_description(library: &Path, name: &str, version: &str) {
+        let dir = library.join(name);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join(DESCRIPTION_FILENAME),
+            format!("Package: {name}\nVersion: {version}\n"),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn uses_manifest_until_library_changes() {
+        let project = tempfile::tempdir().unwrap();
+        let system_info = SystemInfo::new(crate::OsType::Linux("ubuntu"), None, None, "24.04");
+        let mut library = Library::new(project.path(), &system_info, [4, 4]);
+        write_description(library.path(), "rlang", "1.1.4");
+        library.write_manifest(&[]).unwrap();
+
+        // Editing a package doesn't touch the library folder so the manifest is still used
+        write_description(library.path(), "rlang", "1.1.5");
+        library.find_content();
+        assert_eq!(library.packages["rlang"].original, "1.1.4");
+
+        // Adding one does and we read everything again
+        write_description(library.path(), "cli", "3.6.3");
+        library.find_content();
+        assert_eq!(library.packages["rlang"].original, "1.1.5");
+        assert_eq!(library.packages["cli"].original, "3.6.3");
+    }
+
+    #[test]
+    fn site_package_needs_same_version_and_r() {
+        let package = Package {
+            name: "arrow".to_string(),
+            version: "17.0.0".parse().unwrap(),
+            built: Some("R 4.4.1; x86_64-pc-linux-gnu; 2024-07-01 10:00:00 UTC; unix".to_string()),
+            ..Default::default()
+        };
+        let repository = Source::Repository {
+            repository: "https://cran.r-project.org".parse().unwrap(),
+        };
+        let version = "17.0.0".parse().unwrap();
+
+        assert!(site_package_matches(
+            &package,
+            &version,
+            &repository,
+            [4, 4]
+        ));
+        assert!(!site_package_matches(
+            &package,
+            &version,
+            &repository,
+            [4, 5]
+        ));
+        assert!(!site_package_matches(
+            &package,
+            &"16.1.0".parse().unwrap(),
+            &repository,
+            [4, 4]
+        ));
+
+        let git = Source::Git {
+            git: "https://github.com/apache/arrow".try_into().unwrap(),
+            sha: "abc".to_string(),
+            directory: None,
+            tag: None,
+            branch: None,
+        };
+        assert!(!site_package_matches(&package, &version, &git, [4, 4]));
+        let from_git = Package {
+            remote_sha: Some("abc".to_string()),
+            ..package.clone()
+        };
+        assert!(site_package_matches(&from_git, &version, &git, [4, 4]));
+
+        // Not installed, eg a source package copied in the library
+        let not_built = Package {
+            built: None,
+            ..package
+        };
+        assert!(!site_package_matches(
+            &not_built,
+            &version,
+            &repository,
+            [4, 4]
+        ));
+    }
+
+    #[test]
+    fn first_additional_library_wins() {
+        let first = tempfile::tempdir().unwrap();
+        let second = tempfile::tempdir().unwrap();
+        write_description(first.path(), "torch", "0.14.0");
+        write_description(second.path(), "torch", "0.13.0");
+        write_description(second.path(), "sf", "1.0-19");
+
+        let found = find_provided_packages(&[
+            first.path().to_path_buf(),
+            second.path().to_path_buf(),
+            first.path().join("missing"),
+        ]);
+
+        assert_eq!(found.len(), 2);
+        let (library, torch) = &found["torch"];
+        assert_eq!(library, first.path());
+        assert_eq!(torch.version.original, "0.14.0");
+        assert_eq!(found["sf"].0, second.path());
     }
 }
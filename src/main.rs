@@ -1,6 +1,8 @@
-use clap::{CommandFactory, Parser, Subcommand};
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
 use std::collections::{HashMap, HashSet};
-use std::path::PathBuf;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 mod cli_docs;
 
@@ -10,17 +12,24 @@ use serde_json::json;
 
 use anyhow::anyhow;
 use rv::cli::{
-    Context, OutputFormat, RCommandLookup, ResolveMode, SyncHelper, export_renv,
-    find_r_repositories, init, init_structure, migrate_renv, resolve_dependencies, tree,
+    Context, FoundRepository, OutputFormat, PlanCache, RCommandLookup, RepositoryOrigin,
+    ResolveMode, SyncHelper, SyncStamp, check_policy, diff_lockfile, diff_repositories,
+    environment_fingerprint, explain_source, export_bundle, export_conda, export_nix, export_renv,
+    export_validation_report, find_binary_repository, find_nested_projects, find_project_dir,
+    find_r_repositories_with_origin, init, init_structure, migrate_renv, parse_repository_arg,
+    policy_sarif, preview_suggests, resolve_dependencies, sysdeps_tree, tree,
 };
-use rv::r_finder::get_r_from_path;
+use rv::consts::CONFIG_FILENAME;
+use rv::r_finder::{find_r_install, get_r_from_path};
 use rv::system_req::{SysDep, SysInstallationStatus};
 use rv::{AddOptions, FetchPackage, Http, RepositoryOperation as LibRepositoryOperation};
 use rv::{
-    CacheInfo, Config, GitExecutor, ProjectSummary, RepositoryAction, RepositoryMatcher,
-    RepositoryPositioning, RepositoryUpdates, Version, activate, add_packages, deactivate,
-    execute_repository_action, parse_add_package_spec, read_and_verify_config,
-    resolve_add_options_reference_with_executor, system_req,
+    CacheInfo, Config, DependencyType, GitExecutor, ProjectSummary, Repository, RepositoryAction,
+    RepositoryMatcher, RepositoryPositioning, RepositoryUpdates, StartupBench, StartupHistory,
+    SummarySection, SystemInfo, Version, activate, add_packages, bundle_platform, deactivate,
+    execute_repository_action, format_duration, install_bundle, parse_add_package_spec, r_path,
+    read_and_verify_config, read_bundle_manifest, resolve_add_options_reference_with_executor,
+    system_req,
 };
 
 /// rv, the R package manager
@@ -39,10 +48,21 @@ pub struct Cli {
     #[clap(long, global = true, conflicts_with = "json")]
     emit_events: bool,
 
-    /// Path to a config file other than rproject.toml in the current directory
-    #[clap(short = 'c', long, default_value = "rproject.toml", global = true)]
+    /// Path to a config file to use instead of the closest rproject.toml found in the
+    /// current directory or its parents
+    #[clap(short = 'c', long, default_value = CONFIG_FILENAME, global = true)]
     pub config_file: PathBuf,
 
+    /// Directory of the project to use, instead of the one found from the current directory.
+    /// Useful to pick a project explicitly when projects are nested.
+    #[clap(long, global = true, conflicts_with = "config_file")]
+    pub project_dir: Option<PathBuf>,
+
+    /// Print every package as it is resolved along with where it comes from, to debug slow or
+    /// unexpected resolutions
+    #[clap(long, global = true)]
+    resolve_verbose: bool,
+
     #[clap(subcommand)]
     pub command: Command,
 }
@@ -56,9 +76,16 @@ pub enum Command {
         #[clap(short = 'r', long)]
         /// Specify a non-default R version
         r_version: Option<Version>,
-        #[clap(long)]
+        #[clap(long, conflicts_with = "repositories")]
         /// Do no populated repositories
         no_repositories: bool,
+        #[clap(long = "repository", value_name = "ALIAS=URL", value_parser = parse_repository_arg, action = clap::ArgAction::Append)]
+        /// Repositories to use instead of the ones set in R (repeatable, e.g.
+        /// --repository CRAN=https://cloud.r-project.org)
+        repositories: Vec<Repository>,
+        #[clap(long)]
+        /// On Linux, use Posit Package Manager instead of a CRAN mirror to get binary packages
+        use_binaries: bool,
         #[clap(long, action = clap::ArgAction::Append)]
         /// Add simple package to the config (repeatable, e.g. --add pkg1 --add pkg2)
         add: Vec<String>,
@@ -74,6 +101,17 @@ pub enum Command {
         #[clap(subcommand)]
         subcommand: MigrateSubcommand,
     },
+    /// Compare the lockfile with another lockfile or with the lockfile at a git revision, listing
+    /// the packages added, removed or changed since then
+    Diff {
+        /// Path to a lockfile or git revision, eg `HEAD~5`, a tag or a branch
+        target: String,
+    },
+    /// Work with package repositories
+    Repo {
+        #[clap(subcommand)]
+        subcommand: RepoSubcommand,
+    },
     /// Export rv project to other formats
     Export {
         #[clap(subcommand)]
@@ -84,9 +122,28 @@ pub enum Command {
         #[clap(long)]
         save_install_logs_in: Option<PathBuf>,
         /// Fail if the lockfile is missing or out of sync with the config.
-        /// Intended for CI and reproducible installs.
+        /// Intended for CI and reproducible installs. Uses the lockfile even if `use_lockfile`
+        /// is disabled in the config.
         #[clap(long)]
         locked: bool,
+        /// Ignore the lockfile for this command, even if `use_lockfile` is enabled in the config.
+        /// The lockfile is neither read nor written.
+        #[clap(long, conflicts_with = "locked")]
+        no_lockfile: bool,
+        /// After installing, load every newly installed package in R to catch load failures,
+        /// eg missing system libraries, right away rather than at first use.
+        #[clap(long)]
+        smoke_test: bool,
+        /// Go through the whole sync (staging, progress, report) without installing anything,
+        /// needing R or the network, to test CI pipelines and wrappers. Only the cached package
+        /// databases are used. The report goes to `rv/last-sync-simulated.json`.
+        /// Use `rv plan` to only see the changes.
+        #[clap(long, conflicts_with = "smoke_test")]
+        dry_run: bool,
+        /// Packages that fail with a synthetic error during a `--dry-run` (comma separated or
+        /// repeated)
+        #[clap(long, value_delimiter = ',', requires = "dry_run")]
+        simulate_failures: Vec<String>,
     },
     /// Add packages to the project and sync
     Add {
@@ -128,9 +185,25 @@ pub enum Command {
         #[clap(long)]
         r_version: Option<Version>,
         /// Fail if the lockfile is missing or out of sync with the config.
-        /// Intended for CI and reproducible installs.
+        /// Intended for CI and reproducible installs. Uses the lockfile even if `use_lockfile`
+        /// is disabled in the config.
         #[clap(long)]
         locked: bool,
+        /// Ignore the lockfile for this command, even if `use_lockfile` is enabled in the config.
+        /// The lockfile is neither read nor written.
+        #[clap(long, conflicts_with = "locked")]
+        no_lockfile: bool,
+        /// Always resolve dependencies instead of reusing the previous plan when nothing changed
+        #[clap(long)]
+        no_cache: bool,
+        /// Preview what enabling install_suggestions on those dependencies (comma separated or
+        /// repeated) would add, without modifying the config
+        #[clap(long, value_delimiter = ',', conflicts_with = "locked")]
+        with_suggests: Vec<String>,
+        /// Only print a hash of the environment sync would create, changing whenever the
+        /// lockfile, R version or platform do. Meant to be used as a cache key in CI
+        #[clap(long, conflicts_with = "with_suggests")]
+        fingerprint: bool,
     },
     /// Provide a summary about the project status
     Summary {
@@ -138,6 +211,10 @@ pub enum Command {
         /// The command will not error even if this R version is not found
         #[clap(long)]
         r_version: Option<Version>,
+        /// Only compute and show those sections (comma separated or repeated).
+        /// Defaults to all of them
+        #[clap(long, value_enum, value_delimiter = ',')]
+        section: Vec<SummarySection>,
     },
     /// Configure project settings
     Configure {
@@ -162,11 +239,44 @@ pub enum Command {
         /// This only does anything on supported platforms (eg some Linux), it's already
         /// hidden otherwise
         hide_system_deps: bool,
+        #[clap(long, conflicts_with_all = ["hide_system_deps", "depth"])]
+        /// Show the system dependencies instead, each with the R packages requiring it and the
+        /// dependencies from the config pulling those packages in
+        sysdeps_only: bool,
+        #[clap(long, conflicts_with = "sysdeps_only")]
+        /// Show why each package depends on the next one: depends, imports, linking_to or
+        /// suggests
+        edge_types: bool,
+        #[clap(
+            long,
+            value_enum,
+            value_delimiter = ',',
+            conflicts_with = "sysdeps_only"
+        )]
+        /// Only follow the dependencies of those types (comma separated or repeated).
+        /// Implies --edge-types
+        only_edge_types: Vec<DependencyType>,
         #[clap(long)]
         /// Specify an R version different from the one in the config.
         /// The command will not error even if this R version is not found
         r_version: Option<Version>,
     },
+    /// Explain where an installed package comes from: its entry in the config and lockfile,
+    /// the resolved source, cache paths, install time, build log and which dependencies from
+    /// the config require it
+    ExplainSource {
+        /// Name of the package
+        package: String,
+    },
+    /// Check the packages installed in the library against the policy of the project: the
+    /// licenses they can have, set with `allowed_licenses`. Fails if any package doesn't follow
+    /// it, for use as a CI gate after `rv sync`
+    CheckPolicy {
+        /// Also write the violations in the SARIF format to that file, for code scanning
+        /// annotations on pull requests
+        #[clap(long)]
+        sarif: Option<PathBuf>,
+    },
     /// Returns the path for the library for the current project/system in UNIX format, even
     /// on Windows.
     Library,
@@ -184,6 +294,19 @@ pub enum Command {
         /// The repositories specified in the config
         #[clap(long)]
         repositories: bool,
+        #[clap(long)]
+        /// The additional libraries specified in the config, separated by the platform
+        /// path separator, in the order they should be added after the rv library
+        additional_libraries: bool,
+        #[clap(long)]
+        /// The environment variables of the project, one `NAME=value` per line
+        env: bool,
+        #[clap(long)]
+        /// The absolute path of the project root directory
+        project_root: bool,
+        #[clap(long)]
+        /// The dependencies specified in the config with their `x-` metadata keys, one per line
+        dependencies: bool,
     },
     /// List the system dependencies needed by the dependency tree.
     /// This is currently only supported on Ubuntu/Debian, it will return an empty result
@@ -192,6 +315,9 @@ pub enum Command {
     /// The present/absent status may be wrong if a dependency was installed in
     /// a way that we couldn't detect (eg not via the main package manager of the OS).
     /// If a dependency that you know is installed but is showing up as
+    ///
+    /// With --json, each dependency lists its status, how it was detected and the R packages
+    /// requiring it.
     Sysdeps {
         /// Only show the dependencies not detected on the system.
         #[clap(long)]
@@ -203,6 +329,15 @@ pub enum Command {
         #[clap(long)]
         ignore: Vec<String>,
     },
+    /// Work with bundles created by `rv export bundle`
+    Bundle {
+        #[clap(subcommand)]
+        subcommand: BundleSubcommand,
+    },
+    /// Refresh the cached repository databases and git dependencies following a branch,
+    /// even if they are still considered fresh.
+    /// Meant to be run periodically, eg from cron, so other commands find warm metadata.
+    Refresh,
     /// Activate a previously initialized rv project
     Activate {
         #[clap(long)]
@@ -225,6 +360,32 @@ pub enum Command {
         #[clap(subcommand)]
         subcommand: DocsSubcommand,
     },
+    /// Measure the performance of the project
+    Bench {
+        #[clap(subcommand)]
+        subcommand: BenchSubcommand,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum BenchSubcommand {
+    /// Time launching R and attaching the dependencies of the project with `library()`.
+    /// Results are kept in rv/startup-bench.json and compared to the last run made before the
+    /// library changed, to notice when a sync made startup slower
+    Startup {
+        /// How many times to launch R, the median is kept
+        #[clap(long, default_value_t = 3)]
+        runs: usize,
+        /// How much slower, in percent, startup needs to be to be reported as a regression
+        #[clap(long, default_value_t = 20.0)]
+        threshold: f64,
+        /// Exit with an error if startup regressed, eg for CI
+        #[clap(long)]
+        fail_on_regression: bool,
+        /// Do not record this run in the history
+        #[clap(long)]
+        no_save: bool,
+    },
 }
 
 #[derive(Debug, Subcommand)]
@@ -243,6 +404,21 @@ pub enum DocsSubcommand {
     },
 }
 
+#[derive(Debug, Subcommand)]
+pub enum RepoSubcommand {
+    /// Compare the packages of two repositories, eg two snapshot dates, to see what would change
+    /// for the project when moving from one to the other
+    Diff {
+        /// URL or alias of the repository currently used
+        old: String,
+        /// URL or alias of the repository to compare with
+        new: String,
+        /// Show all the packages of the repositories, not only the ones used by the project
+        #[clap(long)]
+        all: bool,
+    },
+}
+
 #[derive(Debug, Subcommand)]
 pub enum ConfigureSubcommand {
     /// Configure project repositories
@@ -331,6 +507,17 @@ pub enum MigrateSubcommand {
         #[clap(long)]
         /// Turn off rv access through .rv R environment
         no_r_environment: bool,
+        #[clap(long)]
+        /// Print the config that would be written and the unresolved packages, without
+        /// writing anything
+        dry_run: bool,
+        #[clap(long, value_delimiter = ',')]
+        /// Only migrate those packages (comma separated or repeated)
+        only: Vec<String>,
+        #[clap(long)]
+        /// Do not comment out renv's activation in .Rprofile, to use renv and rv side by side
+        /// during a transition. renv is activated last so R sessions keep using its library.
+        keep_renv: bool,
     },
 }
 
@@ -341,6 +528,56 @@ pub enum ExportSubcommand {
         /// Output file path
         #[clap(long, short, default_value = "renv.lock")]
         output: PathBuf,
+        /// Update an existing renv.lock from rv.lock, keeping renv specific sections, to
+        /// use renv and rv side by side
+        #[clap(long)]
+        sync: bool,
+    },
+    /// Export to a conda environment.yml, using conda-forge and bioconda packages
+    Conda {
+        /// Output file path
+        #[clap(long, short, default_value = "environment.yml")]
+        output: PathBuf,
+    },
+    /// Export to a Nix expression building the locked packages
+    Nix {
+        /// Output file path
+        #[clap(long, short, default_value = "default.nix")]
+        output: PathBuf,
+    },
+    /// Export the project to a tarball that `rv bundle install` can unpack on another machine,
+    /// eg an offline compute cluster
+    Bundle {
+        /// Output file path
+        #[clap(long, short, default_value = "rv-bundle.tar.gz")]
+        output: PathBuf,
+        /// Include the installed library. It can only be installed on an identical platform.
+        #[clap(long)]
+        with_library: bool,
+        /// Record the R installation used so `rv bundle install` can check it is available
+        #[clap(long)]
+        with_r_reference: bool,
+    },
+    /// Export a report of the environment (R, platform, packages with their source and hash,
+    /// system dependencies) for validation, eg GxP. Written as JSON and as markdown next to it.
+    /// It is signed with a HMAC if RV_VALIDATION_SIGNING_KEY is set.
+    ValidationReport {
+        /// Output file path. The markdown is written with the same name and a `.md` extension
+        #[clap(long, short, default_value = "validation-report.json")]
+        output: PathBuf,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum BundleSubcommand {
+    /// Unpack a bundle created by `rv export bundle` in the project directory
+    Install {
+        /// Path to the bundle
+        bundle: PathBuf,
+        /// Install the library even if the bundle was created on a different platform and
+        /// overwrite the existing config and lockfile
+        #[clap(long)]
+        force: bool,
     },
 }
 
@@ -384,8 +621,33 @@ fn print_remove_summary(output_format: &OutputFormat, removed: &[String], dry_ru
     }
 }
 
+/// Planning commands work without R but the builtin packages can only come from the cache
+/// or the lockfile
+fn warn_if_r_missing(context: &Context, lookup: &RCommandLookup, output_format: &OutputFormat) {
+    // With `--r-version`, not having that R installed is expected
+    if *lookup == RCommandLookup::Lenient && !context.r_found && !output_format.is_json() {
+        eprintln!(
+            "WARNING: R {} was not found, using the lockfile and the builtin packages cached by previous runs",
+            context.r_version.original
+        );
+    }
+}
+
+/// Commands editing the config are refused in read-only projects
+fn ensure_config_writable(config_file: &Path) -> Result<()> {
+    if Config::from_file(config_file)?.readonly() {
+        return Err(anyhow!(
+            "The project is read-only (`readonly = true` is set in the config), it can't be modified"
+        ));
+    }
+    Ok(())
+}
+
 fn try_main() -> Result<()> {
-    let cli = Cli::parse();
+    let matches = Cli::command().get_matches();
+    let config_file_is_default =
+        matches.value_source("config_file") == Some(clap::parser::ValueSource::DefaultValue);
+    let mut cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
     let output_format = if cli.json {
         OutputFormat::Json
     } else {
@@ -416,11 +678,53 @@ fn try_main() -> Result<()> {
 
     system_req::validate_sysreq_url().map_err(|e| anyhow!("{e}"))?;
 
+    if let Some(project_dir) = &cli.project_dir {
+        cli.config_file = project_dir.join(CONFIG_FILENAME);
+    } else if config_file_is_default
+        // Those commands create the project in the current directory
+        && !matches!(cli.command, Command::Init { .. } | Command::Migrate { .. })
+        && let Ok(current_dir) = std::env::current_dir()
+        && let Some(project_dir) = find_project_dir(&current_dir)
+    {
+        log::info!("Using project at {}", project_dir.display());
+        // Keep the path relative when the project is in the current directory
+        if project_dir != current_dir {
+            cli.config_file = project_dir.join(CONFIG_FILENAME);
+        }
+        // The project was picked from the current directory, which might not be the one
+        // the user expects if projects are nested. Only checked for the commands changing the
+        // project since the others, eg `rv info` at each R startup, shouldn't walk the tree.
+        if !output_format.is_json()
+            && !cli.emit_events
+            && matches!(
+                cli.command,
+                Command::Sync { .. }
+                    | Command::Plan { .. }
+                    | Command::Add { .. }
+                    | Command::Remove { .. }
+                    | Command::Upgrade { .. }
+            )
+        {
+            warn_nested_projects(&project_dir);
+        }
+    }
+
+    // For the commands resolving the project, so they honour the global resolver flags
+    let load_context = |r_lookup: RCommandLookup| -> Result<Context> {
+        let mut context = Context::new(&cli.config_file, r_lookup).map_err(|e| anyhow!("{e}"))?;
+        if cli.resolve_verbose {
+            context.resolve_verbose();
+        }
+        Ok(context)
+    };
+
     match cli.command {
         Command::Init {
             project_directory,
             r_version,
             no_repositories,
+            repositories,
+            use_binaries,
             add,
             no_r_environment,
             force,
@@ -441,10 +745,18 @@ fn try_main() -> Result<()> {
                 }
             };
 
-            let repositories = if no_repositories {
+            let mut found = if no_repositories {
                 Vec::new()
+            } else if !repositories.is_empty() {
+                repositories
+                    .into_iter()
+                    .map(|repository| FoundRepository {
+                        repository,
+                        origin: RepositoryOrigin::CommandLine,
+                    })
+                    .collect()
             } else {
-                match find_r_repositories() {
+                match find_r_repositories_with_origin() {
                     Ok(repos) if !repos.is_empty() => repos,
                     _ => {
                         eprintln!(
@@ -454,6 +766,30 @@ fn try_main() -> Result<()> {
                     }
                 }
             };
+            let binary_repository = find_binary_repository(
+                &found
+                    .iter()
+                    .map(|r| r.repository.clone())
+                    .collect::<Vec<_>>(),
+                &SystemInfo::from_os_info(),
+            );
+            if let Some(binary) = &binary_repository {
+                if use_binaries {
+                    for repo in found
+                        .iter_mut()
+                        .filter(|r| r.repository.alias == binary.alias)
+                    {
+                        repo.repository = binary.repository();
+                        repo.origin = RepositoryOrigin::UseBinaries;
+                    }
+                } else if !output_format.is_json() {
+                    eprintln!(
+                        "TIP: {} ({}) only has source packages on Linux. Posit Package Manager ({}) has binaries for {}, which makes syncing much faster. Use `--use-binaries` to use it instead.\n",
+                        binary.alias, binary.source_url, binary.binary_url, binary.distribution
+                    );
+                }
+            }
+            let repositories: Vec<_> = found.iter().map(|r| r.repository.clone()).collect();
 
             init(
                 &project_directory,
@@ -468,9 +804,30 @@ fn try_main() -> Result<()> {
             if output_format.is_json() {
                 println!(
                     "{}",
-                    json!({"directory": format!("{}", project_directory.display())})
+                    json!({
+                        "directory": format!("{}", project_directory.display()),
+                        "repositories": found,
+                        "binary_repository": binary_repository.map(|binary| json!({
+                            "alias": binary.alias,
+                            "source_url": binary.source_url,
+                            "binary_url": binary.binary_url,
+                            "distribution": binary.distribution,
+                            "applied": use_binaries,
+                        })),
+                    })
                 );
             } else {
+                if !found.is_empty() {
+                    println!("Repositories:");
+                    for repo in &found {
+                        println!(
+                            "  {} ({}) from {}",
+                            repo.repository.alias,
+                            repo.repository.url(),
+                            repo.origin
+                        );
+                    }
+                }
                 println!(
                     "rv project successfully initialized at {}",
                     project_directory.display()
@@ -483,10 +840,36 @@ fn try_main() -> Result<()> {
                     renv_file,
                     strict_r_version,
                     no_r_environment,
+                    dry_run,
+                    only,
+                    keep_renv,
                 },
         } => {
-            let unresolved = migrate_renv(&renv_file, &cli.config_file, strict_r_version)?;
-            // migrate renv will create the config file, so parent directory is confirmed to exist
+            let (config, unresolved) = migrate_renv(&renv_file, strict_r_version, &only)?;
+            if dry_run {
+                if output_format.is_json() {
+                    println!(
+                        "{}",
+                        json!({
+                            "config": config,
+                            "unresolved": unresolved.iter().map(ToString::to_string).collect::<Vec<_>>(),
+                        })
+                    );
+                } else {
+                    println!("{config}");
+                    if !unresolved.is_empty() {
+                        eprintln!("{} unresolved packages:", unresolved.len());
+                        for u in &unresolved {
+                            eprintln!("    {u}");
+                        }
+                    }
+                }
+                return Ok(());
+            }
+
+            // Write config out to the config file specified in the cli, even if config file is outside of the renv.lock project
+            write(&cli.config_file, config)?;
+            // the config file was just written, so parent directory is confirmed to exist
             let project_dir = &cli
                 .config_file
                 .canonicalize()?
@@ -495,11 +878,13 @@ fn try_main() -> Result<()> {
                 .to_path_buf();
             init_structure(project_dir)?;
             activate(project_dir, no_r_environment)?;
-            let content = read_to_string(project_dir.join(".Rprofile"))?.replace(
-                "source(\"renv/activate.R\")",
-                "# source(\"renv/activate.R\")",
-            );
-            write(project_dir.join(".Rprofile"), content)?;
+            if !keep_renv {
+                let content = read_to_string(project_dir.join(".Rprofile"))?.replace(
+                    "source(\"renv/activate.R\")",
+                    "# source(\"renv/activate.R\")",
+                );
+                write(project_dir.join(".Rprofile"), content)?;
+            }
 
             if unresolved.is_empty() {
                 if output_format.is_json() {
@@ -537,10 +922,52 @@ fn try_main() -> Result<()> {
                 }
             }
         }
-        Command::Export {
-            subcommand: ExportSubcommand::Renv { output },
+        Command::Diff { target } => {
+            let diff = diff_lockfile(&cli.config_file, &target)?;
+            if output_format.is_json() {
+                println!("{}", serde_json::to_string_pretty(&diff)?);
+            } else {
+                print!("{diff}");
+            }
+        }
+        Command::Repo {
+            subcommand: RepoSubcommand::Diff { old, new, all },
         } => {
-            let warnings = export_renv(&cli.config_file, &output)?;
+            let diff = diff_repositories(&cli.config_file, &old, &new, all)?;
+            if output_format.is_json() {
+                println!("{}", serde_json::to_string_pretty(&diff)?);
+            } else {
+                print!("{diff}");
+            }
+        }
+        Command::Export { subcommand } => {
+            let (output, warnings) = match subcommand {
+                ExportSubcommand::Renv { output, sync } => {
+                    let warnings = export_renv(&cli.config_file, &output, sync)?;
+                    (output, warnings)
+                }
+                ExportSubcommand::Conda { output } => {
+                    let warnings = export_conda(&cli.config_file, &output)?;
+                    (output, warnings)
+                }
+                ExportSubcommand::Nix { output } => {
+                    let warnings = export_nix(&cli.config_file, &output)?;
+                    (output, warnings)
+                }
+                ExportSubcommand::Bundle {
+                    output,
+                    with_library,
+                    with_r_reference,
+                } => {
+                    let warnings =
+                        export_bundle(&cli.config_file, &output, with_library, with_r_reference)?;
+                    (output, warnings)
+                }
+                ExportSubcommand::ValidationReport { output } => {
+                    let warnings = export_validation_report(&cli.config_file, &output)?;
+                    (output, warnings)
+                }
+            };
             if output_format.is_json() {
                 println!(
                     "{}",
@@ -562,18 +989,59 @@ fn try_main() -> Result<()> {
         Command::Sync {
             save_install_logs_in,
             locked,
+            no_lockfile,
+            smoke_test,
+            dry_run,
+            simulate_failures,
         } => {
-            let mut context = Context::new(&cli.config_file, RCommandLookup::Strict)
-                .map_err(|e| anyhow!("{e}"))?;
+            // The stamp is keyed on the config so it can't know about the lockfile overrides
+            let use_stamp =
+                !smoke_test && !locked && !no_lockfile && !dry_run && !cli.resolve_verbose;
+            if use_stamp && SyncStamp::is_fresh(&cli.config_file) {
+                log::debug!("Nothing changed since the last sync, skipping it");
+                if !cli.emit_events {
+                    if output_format.is_json() {
+                        println!(
+                            "{}",
+                            serde_json::to_string_pretty(&json!({"installed": [], "removed": []}))
+                                .expect("valid json")
+                        );
+                    } else {
+                        println!("Nothing to do");
+                    }
+                }
+                return Ok(());
+            }
+            let r_lookup = if dry_run {
+                RCommandLookup::Lenient
+            } else {
+                RCommandLookup::Strict
+            };
+            let mut context = load_context(r_lookup)?;
+            if locked || no_lockfile {
+                context
+                    .set_use_lockfile(locked)
+                    .map_err(|e| anyhow!("{e}"))?;
+            }
 
             if !log_enabled && !cli.emit_events {
                 context.show_progress_bar();
             }
             let resolve_mode = ResolveMode::Default;
-            context
-                .load_for_resolve_mode(resolve_mode)
-                .map_err(|e| anyhow!("{e}"))?;
-            SyncHelper {
+            // A simulation doesn't touch the network
+            if dry_run {
+                context.load_cached_databases();
+            } else {
+                context
+                    .load_for_resolve_mode(resolve_mode)
+                    .map_err(|e| anyhow!("{e}"))?;
+            }
+            // Failures can only be handled one by one with someone to ask
+            let interactive = !cli.emit_events
+                && !output_format.is_json()
+                && std::io::stdin().is_terminal()
+                && std::io::stderr().is_terminal();
+            let resolution = SyncHelper {
                 dry_run: false,
                 output_format: if cli.emit_events {
                     None
@@ -582,9 +1050,15 @@ fn try_main() -> Result<()> {
                 },
                 save_install_logs_in,
                 locked,
+                smoke_test,
+                simulate_failures: dry_run.then_some(simulate_failures),
+                interactive,
                 ..Default::default()
             }
             .run(&context, resolve_mode)?;
+            if use_stamp && resolution.is_success() && context.readonly_reason().is_none() {
+                SyncStamp::save(&context, &cli.config_file);
+            }
         }
         Command::Add {
             packages,
@@ -619,9 +1093,11 @@ fn try_main() -> Result<()> {
 
             // Load config to verify structure is valid
             let mut doc = read_and_verify_config(&cli.config_file)?;
+            if !dry_run {
+                ensure_config_writable(&cli.config_file)?;
+            }
 
-            let mut context = Context::new(&cli.config_file, RCommandLookup::Strict)
-                .map_err(|e| anyhow!("{e}"))?;
+            let mut context = load_context(RCommandLookup::Strict)?;
             if !log_enabled {
                 context.show_progress_bar();
             }
@@ -667,6 +1143,7 @@ fn try_main() -> Result<()> {
                     let mut options = parsed.options;
                     options.install_suggestions = add_options.install_suggestions;
                     options.dependencies_only = add_options.dependencies_only;
+                    options.no_dependencies = add_options.no_dependencies;
                     options.force_source = add_options.force_source;
                     let resolved_ref =
                         resolve_add_options_reference_with_executor(&mut options, &GitExecutor {})
@@ -732,6 +1209,7 @@ fn try_main() -> Result<()> {
                 dry_run,
                 output_format: Some(output_format.clone()),
                 exit_on_failure: false,
+                added: added.clone(),
                 ..Default::default()
             };
 
@@ -768,6 +1246,9 @@ fn try_main() -> Result<()> {
 
             // Load config to verify structure is valid
             let mut doc = read_and_verify_config(&cli.config_file)?;
+            if !dry_run {
+                ensure_config_writable(&cli.config_file)?;
+            }
 
             let removed = remove_packages(&mut doc, packages)?;
 
@@ -785,8 +1266,7 @@ fn try_main() -> Result<()> {
                 return Ok(());
             }
 
-            let mut context = Context::new(&cli.config_file, RCommandLookup::Strict)
-                .map_err(|e| anyhow!("{e}"))?;
+            let mut context = load_context(RCommandLookup::Strict)?;
 
             if !log_enabled {
                 context.show_progress_bar();
@@ -812,8 +1292,7 @@ fn try_main() -> Result<()> {
             .run(&context, resolve_mode)?;
         }
         Command::Upgrade { dry_run } => {
-            let mut context = Context::new(&cli.config_file, RCommandLookup::Strict)
-                .map_err(|e| anyhow!("{e}"))?;
+            let mut context = load_context(RCommandLookup::Strict)?;
 
             if !log_enabled {
                 context.show_progress_bar();
@@ -833,6 +1312,10 @@ fn try_main() -> Result<()> {
             upgrade,
             r_version,
             locked,
+            no_lockfile,
+            no_cache,
+            with_suggests,
+            fingerprint,
         } => {
             if locked && upgrade {
                 return Err(anyhow!("--locked and --upgrade are mutually exclusive"));
@@ -842,8 +1325,64 @@ fn try_main() -> Result<()> {
             } else {
                 ResolveMode::Default
             };
-            let mut context =
-                Context::new(&cli.config_file, r_version.into()).map_err(|e| anyhow!("{e}"))?;
+            let r_lookup = RCommandLookup::from(r_version);
+            let mut context = load_context(r_lookup.clone())?;
+            warn_if_r_missing(&context, &r_lookup, &output_format);
+            if locked || no_lockfile {
+                context
+                    .set_use_lockfile(locked)
+                    .map_err(|e| anyhow!("{e}"))?;
+            }
+
+            if fingerprint {
+                if !log_enabled {
+                    context.show_progress_bar();
+                }
+                let fingerprint = environment_fingerprint(&mut context, upgrade, locked)?;
+                if output_format.is_json() {
+                    println!(
+                        "{}",
+                        serde_json::to_string_pretty(&json!({ "fingerprint": fingerprint }))
+                            .expect("valid json")
+                    );
+                } else {
+                    println!("{fingerprint}");
+                }
+                return Ok(());
+            }
+
+            if !with_suggests.is_empty() {
+                if !log_enabled {
+                    context.show_progress_bar();
+                }
+                context.load_databases().map_err(|e| anyhow!("{e}"))?;
+                context.load_system_requirements();
+                let preview = preview_suggests(&mut context, &with_suggests, upgrade)?;
+                if output_format.is_json() {
+                    println!(
+                        "{}",
+                        serde_json::to_string_pretty(&preview).expect("valid json")
+                    );
+                } else {
+                    preview.print();
+                }
+                return Ok(());
+            }
+
+            // The cached output doesn't have what --resolve-verbose prints while resolving
+            let plan_cache = if no_cache || cli.resolve_verbose {
+                None
+            } else {
+                PlanCache::new(
+                    &context,
+                    &cli.config_file,
+                    &format!("{upgrade:?} {locked} {no_lockfile} {output_format:?}"),
+                )
+            };
+            if let Some(output) = plan_cache.as_ref().and_then(|c| c.get()) {
+                print!("{output}");
+                return Ok(());
+            }
 
             if !log_enabled {
                 context.show_progress_bar();
@@ -856,42 +1395,88 @@ fn try_main() -> Result<()> {
                 dry_run: true,
                 output_format: Some(output_format),
                 locked,
+                plan_cache,
                 ..Default::default()
             }
             .run(&context, upgrade)?;
         }
-        Command::Summary { r_version } => {
-            let mut context =
-                Context::new(&cli.config_file, r_version.into()).map_err(|e| anyhow!("{e}"))?;
-            context.load_databases().map_err(|e| anyhow!("{e}"))?;
-            context.load_system_requirements();
+        Command::Summary { r_version, section } => {
+            let sections = if section.is_empty() {
+                SummarySection::ALL.to_vec()
+            } else {
+                section
+            };
+            let wants = |s| sections.contains(&s);
+            // Sections are printed as soon as they are computed, only JSON needs everything
+            let stream = !output_format.is_json();
+
+            let r_lookup = RCommandLookup::from(r_version);
+            let mut context = load_context(r_lookup.clone())?;
+            warn_if_r_missing(&context, &r_lookup, &output_format);
+            if stream && wants(SummarySection::System) {
+                print!(
+                    "{}",
+                    ProjectSummary::with_sections(&context, &[SummarySection::System], &[])
+                );
+            }
+
+            if sections.iter().any(SummarySection::needs_databases) {
+                context.load_databases().map_err(|e| anyhow!("{e}"))?;
+            }
+            if wants(SummarySection::Sysdeps) {
+                context.load_system_requirements();
+            }
             if !log_enabled {
                 context.show_progress_bar();
             }
-            let resolved = resolve_dependencies(&context, ResolveMode::Default, true).found;
-            let project_sys_deps: HashSet<_> = resolved
+            let resolved = if sections.iter().any(SummarySection::needs_resolution) {
+                let resolution = resolve_dependencies(&context, ResolveMode::Default, true);
+                for message in resolution.archived_warning_messages() {
+                    eprintln!("WARNING: {message}");
+                }
+                resolution.found
+            } else {
+                Vec::new()
+            };
+
+            let remaining: Vec<_> = sections
                 .iter()
-                .flat_map(|x| context.system_dependencies.get(x.name.as_ref()))
-                .flatten()
-                .map(|x| x.as_str())
+                .copied()
+                .filter(|s| !stream || *s != SummarySection::System)
                 .collect();
+            let mut summary = ProjectSummary::with_sections(&context, &remaining, &resolved);
+            if stream {
+                print!("{}", summary.section(SummarySection::Dependencies));
+            }
 
-            let sys_deps: Vec<_> = system_req::check_installation_status(
-                context.cache.system_info(),
-                &project_sys_deps,
-            )
-            .into_iter()
-            .map(|(name, status)| SysDep { name, status })
-            .collect();
+            if wants(SummarySection::Sysdeps) {
+                let project_sys_deps: HashSet<_> = resolved
+                    .iter()
+                    .flat_map(|x| context.system_dependencies.get(x.name.as_ref()))
+                    .flatten()
+                    .map(|x| x.as_str())
+                    .collect();
+
+                let sys_deps: Vec<_> = system_req::check_installation_status(
+                    context.cache.system_info(),
+                    &project_sys_deps,
+                )
+                .into_iter()
+                .map(|(name, status)| SysDep { name, status })
+                .collect();
+                summary.set_sys_deps(sys_deps);
+            }
 
-            let summary = ProjectSummary::new(&context, &resolved, sys_deps);
-            if output_format.is_json() {
+            if stream {
+                print!("{}", summary.section(SummarySection::Sysdeps));
+                if wants(SummarySection::Remote) {
+                    println!("{}", summary.section(SummarySection::Remote));
+                }
+            } else {
                 println!(
                     "{}",
                     serde_json::to_string_pretty(&summary).expect("valid json")
                 );
-            } else {
-                println!("{summary}");
             }
         }
         // configure left at bottom due to its size
@@ -923,10 +1508,14 @@ fn try_main() -> Result<()> {
         Command::Tree {
             depth,
             hide_system_deps,
+            sysdeps_only,
+            edge_types,
+            only_edge_types,
             r_version,
         } => {
-            let mut context =
-                Context::new(&cli.config_file, r_version.into()).map_err(|e| anyhow!("{e}"))?;
+            let r_lookup = RCommandLookup::from(r_version);
+            let mut context = load_context(r_lookup.clone())?;
+            warn_if_r_missing(&context, &r_lookup, &output_format);
             context.load_databases().map_err(|e| anyhow!("{e}"))?;
             if !hide_system_deps {
                 context.load_system_requirements();
@@ -935,7 +1524,24 @@ fn try_main() -> Result<()> {
                 context.show_progress_bar();
             }
             let resolution = resolve_dependencies(&context, ResolveMode::Default, false);
-            let tree = tree(&context, &resolution.found, &resolution.failed);
+            if sysdeps_only {
+                let tree = sysdeps_tree(&context, &resolution.found);
+                if output_format.is_json() {
+                    println!(
+                        "{}",
+                        serde_json::to_string_pretty(&tree).expect("valid json")
+                    );
+                } else {
+                    tree.print();
+                }
+                return Ok(());
+            }
+            let tree = tree(
+                &context,
+                &resolution.found,
+                &resolution.failed,
+                &only_edge_types,
+            );
 
             if output_format.is_json() {
                 println!(
@@ -943,7 +1549,55 @@ fn try_main() -> Result<()> {
                     serde_json::to_string_pretty(&tree).expect("valid json")
                 );
             } else {
-                tree.print(depth, !hide_system_deps);
+                tree.print(
+                    depth,
+                    !hide_system_deps,
+                    edge_types || !only_edge_types.is_empty(),
+                );
+            }
+        }
+        Command::ExplainSource { package } => {
+            let mut context = load_context(RCommandLookup::Skip)?;
+            context.load_databases().map_err(|e| anyhow!("{e}"))?;
+            if !log_enabled {
+                context.show_progress_bar();
+            }
+            let resolution = resolve_dependencies(&context, ResolveMode::Default, false);
+            let explanation =
+                explain_source(&context, &cli.config_file, &resolution.found, &package)?;
+            if output_format.is_json() {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&explanation).expect("valid json")
+                );
+            } else {
+                print!("{explanation}");
+            }
+        }
+        Command::CheckPolicy { sarif } => {
+            let context = load_context(RCommandLookup::Skip)?;
+            let violations = check_policy(&context)?;
+            if let Some(path) = sarif {
+                let sarif = policy_sarif(&violations, &context.lockfile_path());
+                write(
+                    path,
+                    serde_json::to_string_pretty(&sarif).expect("valid json"),
+                )?;
+            }
+            if output_format.is_json() {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&violations).expect("valid json")
+                );
+            } else if violations.is_empty() {
+                println!("All packages follow the policy");
+            } else {
+                for violation in &violations {
+                    println!("{violation}");
+                }
+            }
+            if !violations.is_empty() {
+                ::std::process::exit(1);
             }
         }
         Command::Library => {
@@ -963,8 +1617,7 @@ fn try_main() -> Result<()> {
             }
         }
         Command::Cache => {
-            let mut context =
-                Context::new(&cli.config_file, RCommandLookup::Skip).map_err(|e| anyhow!("{e}"))?;
+            let mut context = load_context(RCommandLookup::Skip)?;
             context.load_databases().map_err(|e| anyhow!("{e}"))?;
             if !log_enabled {
                 context.show_progress_bar();
@@ -994,17 +1647,30 @@ fn try_main() -> Result<()> {
             library,
             r_version,
             repositories,
+            additional_libraries,
+            env,
+            project_root,
+            dependencies,
         } => {
             // TODO: handle info, eg need to accumulate fields
             let mut output = Vec::new();
             let context =
                 Context::new(&cli.config_file, RCommandLookup::Skip).map_err(|e| anyhow!("{e}"))?;
+            if project_root {
+                // The project dir is empty if the config file is in the current directory
+                let root = std::path::absolute(context.project_dir.join("."))?;
+                let root_out = if cfg!(windows) {
+                    r_path(&root)
+                } else {
+                    root.to_string_lossy().to_string()
+                };
+                output.push(("project-root", root_out));
+            }
             if library {
-                let path_str = context.library_path().to_string_lossy();
                 let path_out = if cfg!(windows) {
-                    path_str.replace('\\', "/")
+                    r_path(context.library_path())
                 } else {
-                    path_str.to_string()
+                    context.library_path().to_string_lossy().to_string()
                 };
                 output.push(("library", path_out));
             }
@@ -1021,22 +1687,84 @@ fn try_main() -> Result<()> {
                     .join(", ");
                 output.push(("repositories", repos));
             }
+            if additional_libraries {
+                let (sep, libs) = if cfg!(windows) {
+                    (
+                        ";",
+                        context
+                            .additional_libraries
+                            .iter()
+                            .map(r_path)
+                            .collect::<Vec<_>>(),
+                    )
+                } else {
+                    (
+                        ":",
+                        context
+                            .additional_libraries
+                            .iter()
+                            .map(|p| p.to_string_lossy().to_string())
+                            .collect::<Vec<_>>(),
+                    )
+                };
+                output.push(("additional-libraries", libs.join(sep)));
+            }
+            let env_vars = context.config.env_vars();
 
             if output_format.is_json() {
-                let output: HashMap<_, _> = output.into_iter().collect();
+                let mut output: serde_json::Map<_, _> = output
+                    .into_iter()
+                    .map(|(key, val)| (key.to_string(), val.into()))
+                    .collect();
+                if env {
+                    output.insert("env".to_string(), json!(env_vars));
+                }
+                if dependencies {
+                    let deps: Vec<_> = context
+                        .config
+                        .dependencies()
+                        .iter()
+                        .map(|d| {
+                            json!({
+                                "name": d.name(),
+                                "metadata": context.config.dependency_metadata(d.name()).cloned().unwrap_or_default(),
+                            })
+                        })
+                        .collect();
+                    output.insert("dependencies".to_string(), json!(deps));
+                }
                 println!("{}", serde_json::to_string_pretty(&output)?);
             } else {
                 for (key, val) in output {
                     println!("{key}: {val}");
                 }
+                if env {
+                    for (name, val) in env_vars {
+                        println!("env: {name}={val}");
+                    }
+                }
+                if dependencies {
+                    for dep in context.config.dependencies() {
+                        match context.config.dependency_metadata(dep.name()) {
+                            Some(metadata) => {
+                                let metadata = metadata
+                                    .iter()
+                                    .map(|(k, v)| format!("{k}={v}"))
+                                    .collect::<Vec<_>>()
+                                    .join(", ");
+                                println!("dependencies: {} ({metadata})", dep.name());
+                            }
+                            None => println!("dependencies: {}", dep.name()),
+                        }
+                    }
+                }
             }
         }
         Command::Sysdeps {
             only_absent,
             ignore,
         } => {
-            let mut context =
-                Context::new(&cli.config_file, RCommandLookup::Skip).map_err(|e| anyhow!("{e}"))?;
+            let mut context = load_context(RCommandLookup::Skip)?;
             if !log_enabled {
                 context.show_progress_bar();
             }
@@ -1044,43 +1772,159 @@ fn try_main() -> Result<()> {
             context.load_system_requirements();
 
             let resolved = resolve_dependencies(&context, ResolveMode::Default, false).found;
-            let project_sys_deps: HashSet<_> = resolved
-                .iter()
-                .flat_map(|x| context.system_dependencies.get(x.name.as_ref()))
-                .flatten()
-                .map(|x| x.as_str())
-                .collect();
+            // Which R packages need each system dependency
+            let mut required_by: HashMap<&str, Vec<&str>> = HashMap::new();
+            for pkg in &resolved {
+                for sys_dep in context
+                    .system_dependencies
+                    .get(pkg.name.as_ref())
+                    .into_iter()
+                    .flatten()
+                {
+                    required_by
+                        .entry(sys_dep.as_str())
+                        .or_default()
+                        .push(pkg.name.as_ref());
+                }
+            }
+            let project_sys_deps: HashSet<_> = required_by.keys().copied().collect();
 
-            let sys_deps_status = system_req::check_installation_status(
-                context.cache.system_info(),
-                &project_sys_deps,
-            );
+            let sys_deps_checks =
+                system_req::check_installation(context.cache.system_info(), &project_sys_deps);
 
-            let mut sys_deps_names: Vec<_> = sys_deps_status
+            let mut sys_deps: Vec<_> = sys_deps_checks
                 .into_iter()
-                .filter(|(name, status)| {
+                .filter(|(name, check)| {
                     // Filter by only_absent flag
-                    if only_absent && *status != SysInstallationStatus::Absent {
+                    if only_absent && check.status != SysInstallationStatus::Absent {
                         return false;
                     }
 
                     // Filter by ignore list
                     !ignore.contains(name)
                 })
-                .map(|(name, _)| name)
                 .collect();
 
             // Sort by name for consistent output
-            sys_deps_names.sort();
+            sys_deps.sort_by(|a, b| a.0.cmp(&b.0));
 
             if output_format.is_json() {
-                println!("{}", json!(sys_deps_names));
+                let out: Vec<_> = sys_deps
+                    .iter()
+                    .map(|(name, check)| {
+                        let mut packages = required_by[name.as_str()].clone();
+                        packages.sort_unstable();
+                        packages.dedup();
+                        json!({
+                            "name": name,
+                            "status": check.status,
+                            "detection_method": check.method,
+                            "required_by": packages,
+                        })
+                    })
+                    .collect();
+                println!("{}", json!(out));
             } else {
-                for name in &sys_deps_names {
+                for (name, _) in &sys_deps {
                     println!("{name}");
                 }
             }
         }
+        Command::Bundle {
+            subcommand: BundleSubcommand::Install { bundle, force },
+        } => {
+            let project_dir = match cli.config_file.parent() {
+                Some(p) if !p.as_os_str().is_empty() => p.to_path_buf(),
+                _ => PathBuf::from("."),
+            };
+            let manifest = read_bundle_manifest(&bundle)?;
+            let r_version = manifest
+                .r_version
+                .parse::<Version>()
+                .map_err(|e| anyhow!("{e}"))?;
+            let platform = bundle_platform(&SystemInfo::from_os_info(), r_version.major_minor());
+            let (manifest, library_path) = install_bundle(&bundle, &project_dir, &platform, force)?;
+
+            let mut warnings = Vec::new();
+            if let Some(runtime) = &manifest.r_runtime {
+                let version = runtime
+                    .version
+                    .parse::<Version>()
+                    .map_err(|e| anyhow!("{e}"))?;
+                if find_r_install(&version, false).is_none() {
+                    warnings.push(format!(
+                        "the bundle was created with R {} ({}) but it was not found on this machine",
+                        runtime.version,
+                        runtime.r_home.display()
+                    ));
+                }
+            }
+
+            if output_format.is_json() {
+                println!(
+                    "{}",
+                    json!({
+                        "success": true,
+                        "library": library_path.as_ref().map(|p| p.display().to_string()),
+                        "packages": manifest.packages.len(),
+                        "warnings": warnings,
+                    })
+                );
+            } else {
+                for w in &warnings {
+                    eprintln!("WARNING: {w}");
+                }
+                if let Some(library_path) = library_path {
+                    println!(
+                        "Installed {} packages in {}",
+                        manifest.packages.len(),
+                        library_path.display()
+                    );
+                } else {
+                    println!("Unpacked project files in {}", project_dir.display());
+                }
+            }
+        }
+        Command::Refresh => {
+            let mut context =
+                Context::new(&cli.config_file, RCommandLookup::Skip).map_err(|e| anyhow!("{e}"))?;
+            if !log_enabled {
+                context.show_progress_bar();
+            }
+            context.refresh_databases().map_err(|e| anyhow!("{e}"))?;
+            let git_errors = context.refresh_git_dependencies();
+            let repositories: Vec<_> = context
+                .config
+                .repositories()
+                .iter()
+                .map(|r| r.alias.as_str())
+                .collect();
+
+            if output_format.is_json() {
+                println!(
+                    "{}",
+                    json!({
+                        "repositories": repositories,
+                        "failed_git_dependencies": git_errors
+                            .iter()
+                            .map(|(name, e)| json!({"name": name, "error": e.to_string()}))
+                            .collect::<Vec<_>>(),
+                    })
+                );
+            } else {
+                println!("Refreshed {} repositories", repositories.len());
+                for (name, e) in &git_errors {
+                    eprintln!("Failed to refresh git dependency {name}: {e}");
+                }
+            }
+
+            if !git_errors.is_empty() {
+                return Err(anyhow!(
+                    "Failed to refresh {} git dependencies",
+                    git_errors.len()
+                ));
+            }
+        }
         Command::Activate { no_r_environment } => {
             let config_file = cli.config_file.canonicalize()?;
             let project_dir = config_file.parent().expect("parent to exist");
@@ -1125,11 +1969,97 @@ fn try_main() -> Result<()> {
             println!("{}", output);
         }
 
-        Command::Run { no_sync, args } => {
-            let mut context = Context::new(&cli.config_file, RCommandLookup::Strict)
+        Command::Bench {
+            subcommand:
+                BenchSubcommand::Startup {
+                    runs,
+                    threshold,
+                    fail_on_regression,
+                    no_save,
+                },
+        } => {
+            let context = Context::new(&cli.config_file, RCommandLookup::Strict)
                 .map_err(|e| anyhow!("{e}"))?;
+            let (packages, missing): (Vec<_>, Vec<_>) = context
+                .config
+                .dependencies()
+                .iter()
+                .map(|d| d.name())
+                .partition(|name| context.library.packages.contains_key(*name));
+            if !missing.is_empty() {
+                eprintln!(
+                    "WARNING: not installed, run `rv sync` first to include them: {}",
+                    missing.join(", ")
+                );
+            }
+
+            let (total, timings) = rv::bench_startup(
+                &context.r_cmd.bin_path,
+                context.library_path(),
+                &context.additional_libraries,
+                context.config.env_vars(),
+                &packages,
+                runs,
+            )?;
+            let bench = StartupBench::new(
+                &context,
+                jiff::Timestamp::now().to_string(),
+                runs,
+                total,
+                timings,
+            );
 
-            if !no_sync {
+            let path = context.startup_bench_path();
+            let mut history = StartupHistory::load(&path);
+            let baseline = history.baseline(&bench).cloned();
+            let regression = baseline.as_ref().and_then(|b| bench.compare(b, threshold));
+
+            if output_format.is_json() {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&json!({
+                        "bench": bench,
+                        "baseline": baseline,
+                        "regression": regression.as_ref().map(|r| r.to_string()),
+                    }))
+                    .expect("valid json")
+                );
+            } else {
+                println!(
+                    "R startup: {} (median of {} run(s))",
+                    format_duration(bench.total()),
+                    bench.runs
+                );
+                for package in &bench.packages {
+                    println!(
+                        "  {}: {}",
+                        package.name,
+                        format_duration(Duration::from_millis(package.load_ms))
+                    );
+                }
+                if let Some(regression) = &regression {
+                    eprintln!("WARNING: {regression}");
+                }
+            }
+
+            if !no_save {
+                history.add(bench);
+                if let Err(e) = history.save(&path) {
+                    log::warn!("Failed to write {}: {e}", path.display());
+                }
+            }
+            if fail_on_regression && regression.is_some() {
+                return Err(anyhow!("startup time regressed"));
+            }
+        }
+
+        Command::Run { no_sync, args } => {
+            let mut context = load_context(RCommandLookup::Strict)?;
+
+            // A read-only project runs with whatever is in the library
+            if let Some(reason) = context.readonly_reason() {
+                log::debug!("Not syncing before running: {reason}");
+            } else if !no_sync {
                 if !log_enabled {
                     context.show_progress_bar();
                 }
@@ -1144,7 +2074,13 @@ fn try_main() -> Result<()> {
                 .run(&context, resolve_mode)?;
             }
 
-            let code = rv::run(&context.r_cmd.bin_path, context.library_path(), &args)?;
+            let code = rv::run(
+                &context.r_cmd.bin_path,
+                context.library_path(),
+                &context.additional_libraries,
+                context.config.env_vars(),
+                &args,
+            )?;
             std::process::exit(code);
         }
 
@@ -1303,6 +2239,18 @@ fn try_main() -> Result<()> {
     Ok(())
 }
 
+fn warn_nested_projects(project_dir: &Path) {
+    let home_dir = etcetera::home_dir().ok();
+    let nested = find_nested_projects(project_dir, home_dir.as_deref());
+    if nested.is_empty() {
+        return;
+    }
+    for warning in nested.warnings(project_dir) {
+        eprintln!("WARNING: {warning}");
+    }
+    eprintln!("Use --project-dir to choose the project explicitly.");
+}
+
 fn main() {
     if let Err(e) = try_main() {
         eprintln!("{e:?}");
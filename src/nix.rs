@@ -0,0 +1,321 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Write;
+
+use sha2::{Digest, Sha256};
+use url::Url;
+
+use crate::http::HttpDownload;
+use crate::lockfile::{LockedPackage, Lockfile, Source};
+
+/// Placeholder used when we could not compute the hash of a tarball.
+/// Nix will report the actual hash when building.
+const FAKE_HASH: &str = "lib.fakeHash";
+
+/// Escapes a string to be used in a double quoted Nix string
+fn nix_string(s: &str) -> String {
+    let escaped = s
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace("${", "\\${");
+    format!("\"{escaped}\"")
+}
+
+/// CRAN-like repositories move the tarball to the archive when a new version is published so
+/// we list both urls
+fn repository_tarball_urls(repository: &Url, pkg: &LockedPackage) -> Vec<Url> {
+    let file_name = format!("{}_{}.tar.gz", pkg.name, pkg.version);
+    let mut source = repository.clone();
+    let mut archive = repository.clone();
+    {
+        let mut segments = source.path_segments_mut().expect("Valid absolute url");
+        segments.pop_if_empty().extend(["src", "contrib"]);
+        if let Some(p) = &pkg.path {
+            segments.extend(p.split('/'));
+        }
+        segments.push(&file_name);
+    }
+    {
+        let mut segments = archive.path_segments_mut().expect("Valid absolute url");
+        segments
+            .pop_if_empty()
+            .extend(["src", "contrib", "Archive", &pkg.name, &file_name]);
+    }
+    vec![source, archive]
+}
+
+/// Downloads the first url that works and returns the sha256 of its content
+fn hash_tarball(http: &impl HttpDownload, urls: &[Url]) -> Option<String> {
+    for url in urls {
+        let mut buffer = Vec::new();
+        match http.download(url, &mut buffer, Vec::new()) {
+            Ok(_) => {
+                let mut hasher = Sha256::new();
+                hasher.update(&buffer);
+                return Some(hex::encode(hasher.finalize()));
+            }
+            Err(e) => log::debug!("Failed to download {url}: {e}"),
+        }
+    }
+    None
+}
+
+fn nix_list(items: &[String]) -> String {
+    if items.is_empty() {
+        "[ ]".to_string()
+    } else {
+        format!("[ {} ]", items.join(" "))
+    }
+}
+
+fn fetch_git(url: &str, sha: &str, directory: Option<&str>) -> String {
+    let fetch = format!(
+        "builtins.fetchGit {{ url = {}; rev = {}; allRefs = true; }}",
+        nix_string(url),
+        nix_string(sha)
+    );
+    if let Some(d) = directory {
+        format!("\"${{{fetch}}}/{}\"", d.trim_matches('/'))
+    } else {
+        fetch
+    }
+}
+
+fn fetch_url(urls: &[Url], sha256: Option<&str>) -> String {
+    let urls = urls
+        .iter()
+        .map(|u| nix_string(u.as_str()))
+        .collect::<Vec<_>>();
+    let hash = sha256
+        .map(nix_string)
+        .unwrap_or_else(|| FAKE_HASH.to_string());
+    format!(
+        "pkgs.fetchurl {{ urls = {}; sha256 = {hash}; }}",
+        nix_list(&urls)
+    )
+}
+
+/// Convert an rv Lockfile into a Nix expression building every package with `buildRPackage`.
+/// Repository tarballs are downloaded to compute their hashes since we don't store them in the
+/// lockfile.
+/// Returns the expression and a list of warnings for packages that couldn't be perfectly mapped.
+pub fn to_nix_expression(
+    lockfile: &Lockfile,
+    system_dependencies: &HashMap<String, Vec<String>>,
+    http: &impl HttpDownload,
+) -> (String, Vec<String>) {
+    let mut warnings = Vec::new();
+    let mut packages: Vec<_> = lockfile
+        .packages()
+        .iter()
+        .filter(|p| !p.source.is_builtin())
+        .collect();
+    packages.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+
+    let exported: BTreeSet<_> = packages
+        .iter()
+        .filter(|p| !p.source.is_provided())
+        .map(|p| p.name.as_str())
+        .collect();
+
+    let mut out = String::new();
+    writeln!(out, "# Generated by `rv export nix`. Do not edit by hand.").unwrap();
+    writeln!(
+        out,
+        "{{ pkgs ? import <nixpkgs> {{ }}, systemDeps ? {{ }} }}:\n"
+    )
+    .unwrap();
+    writeln!(out, "let").unwrap();
+    writeln!(out, "  inherit (pkgs) lib;").unwrap();
+    writeln!(
+        out,
+        "  # System dependencies are named after the Linux distribution packages, pass `systemDeps`"
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "  # to map them to nixpkgs derivations. Unknown names are looked up in nixpkgs without `-dev`."
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "  sysDep = name: systemDeps.${{name}} or (pkgs.${{lib.removeSuffix \"-dev\" name}} or null);"
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "  sysDeps = names: builtins.filter (x: x != null) (map sysDep names);"
+    )
+    .unwrap();
+    writeln!(out, "  rPackages = {{").unwrap();
+
+    for pkg in packages {
+        let src = match &pkg.source {
+            Source::Repository { repository } => {
+                let urls = repository_tarball_urls(repository, pkg);
+                let hash = hash_tarball(http, &urls);
+                if hash.is_none() {
+                    warnings.push(format!(
+                        "`{}`: could not download the tarball to compute its hash",
+                        pkg.name
+                    ));
+                }
+                fetch_url(&urls, hash.as_deref())
+            }
+            Source::Url { url, sha } => fetch_url(std::slice::from_ref(url), Some(sha)),
+            Source::Git {
+                git,
+                sha,
+                directory,
+                ..
+            }
+            | Source::RUniverse {
+                git,
+                sha,
+                directory,
+                ..
+            } => fetch_git(git.url(), sha, directory.as_deref()),
+            Source::Local { path, .. } => {
+                let path = path.to_string_lossy().replace('\\', "/");
+                if path.starts_with('/') {
+                    path
+                } else {
+                    format!("./{}", path.trim_start_matches("./"))
+                }
+            }
+            Source::Provided { .. } => {
+                warnings.push(format!(
+                    "`{}`: provided packages are expected to be available in the Nix environment",
+                    pkg.name
+                ));
+                continue;
+            }
+            Source::Builtin { .. } => unreachable!("filtered above"),
+        };
+
+        // Suggests are left out: they aren't needed to build or load the package and can form
+        // cycles, which Nix can't evaluate
+        let inputs = pkg
+            .dependencies
+            .iter()
+            .map(|d| d.name())
+            // Builtin packages come with R itself
+            .filter(|name| exported.contains(name))
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .map(|name| format!("rPackages.{}", nix_string(name)))
+            .collect::<Vec<_>>();
+
+        let mut sys_deps = system_dependencies
+            .get(&pkg.name)
+            .map(|d| d.iter().map(|x| nix_string(x)).collect::<Vec<_>>())
+            .unwrap_or_default();
+        sys_deps.sort();
+
+        writeln!(
+            out,
+            "    {} = pkgs.rPackages.buildRPackage {{",
+            nix_string(&pkg.name)
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "      name = {};",
+            nix_string(&format!("{}-{}", pkg.name, pkg.version))
+        )
+        .unwrap();
+        writeln!(out, "      src = {src};").unwrap();
+        writeln!(out, "      propagatedBuildInputs = {};", nix_list(&inputs)).unwrap();
+        writeln!(out, "      buildInputs = sysDeps {};", nix_list(&sys_deps)).unwrap();
+        writeln!(out, "    }};").unwrap();
+    }
+
+    writeln!(out, "  }};").unwrap();
+    writeln!(out, "in").unwrap();
+    writeln!(
+        out,
+        "lib.warnIf (lib.versions.majorMinor pkgs.R.version != {r}) \"rv resolved this project for R {r_raw}\"",
+        r = nix_string(lockfile.r_version_string()),
+        r_raw = lockfile.r_version_string(),
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "  (pkgs.rWrapper.override {{ packages = builtins.attrValues rPackages; }})"
+    )
+    .unwrap();
+
+    (out, warnings)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::to_nix_expression;
+    use crate::Lockfile;
+    use crate::test_utils::FakeHttp;
+
+    #[test]
+    fn test_nix_export() {
+        let lockfile_toml = r#"
+version = 2
+r_version = "4.4"
+
+[[packages]]
+name = "rlang"
+version = "1.1.4"
+source = { repository = "https://cran.r-project.org/" }
+force_source = false
+dependencies = []
+suggests = ["ghqc"]
+
+[[packages]]
+name = "xml2"
+version = "1.3.6"
+source = { repository = "https://cran.r-project.org/" }
+force_source = false
+dependencies = ["rlang"]
+
+[[packages]]
+name = "ghqc"
+version = "0.3.2"
+source = { git = "https://github.com/a2-ai/ghqc", sha = "55c23eb6a444542dab742d3d37c7b65af7b12e38", directory = "pkg" }
+force_source = false
+dependencies = ["MASS", "rlang", "xml2"]
+
+[[packages]]
+name = "dplyr"
+version = "1.1.3"
+source = { url = "https://cran.r-project.org/src/contrib/Archive/dplyr/dplyr_1.1.3.tar.gz", sha = "7a0a4e5d0f6bcb4a4cfe2e1f5f2cc8b5e0c1c2d3e4f5a6b7c8d9e0f1a2b3c4d5" }
+force_source = false
+dependencies = []
+
+[[packages]]
+name = "localpkg"
+version = "0.1.0"
+source = { path = "pkgs/localpkg" }
+force_source = false
+dependencies = []
+
+[[packages]]
+name = "MASS"
+version = "7.3-61"
+source = { builtin = true }
+force_source = false
+dependencies = []
+"#;
+        let lockfile: Lockfile = lockfile_toml.parse().unwrap();
+        let system_dependencies =
+            HashMap::from([("xml2".to_string(), vec!["libxml2-dev".to_string()])]);
+        // The tarballs contain their own url so each gets a different hash
+        let http = [
+            "https://cran.r-project.org/src/contrib/rlang_1.1.4.tar.gz",
+            "https://cran.r-project.org/src/contrib/xml2_1.3.6.tar.gz",
+        ]
+        .into_iter()
+        .fold(FakeHttp::new(), |http, url| http.with_response(url, url));
+        let (out, warnings) = to_nix_expression(&lockfile, &system_dependencies, &http);
+        assert!(warnings.is_empty());
+        insta::assert_snapshot!("nix_export", out);
+    }
+}
@@ -5,7 +5,7 @@ use url::Url;
 use crate::{
     Cache, CommandExecutor, HttpDownload, ResolvedGitRef, Source,
     git::GitRemote,
-    package::{Package, parse_description_file, parse_description_file_in_folder},
+    package::{Package, PackageType, parse_description_file, parse_description_file_in_folder},
 };
 
 pub enum FetchPackage<'a, H: HttpDownload, E: CommandExecutor + Clone + 'static> {
@@ -35,9 +35,13 @@ impl<'a, H: HttpDownload, E: CommandExecutor + Clone + 'static> FetchPackage<'a,
                 let source = Source::Repository {
                     repository: repository.clone(),
                 };
-                let pkg_paths = cache
-                    .local()
-                    .get_package_paths(&source, Some(name), Some(version));
+                // Any binary has the DESCRIPTION we need, we don't build anything here
+                let pkg_paths = cache.local().get_package_paths(
+                    &source,
+                    Some(name),
+                    Some(version),
+                    PackageType::Binary,
+                );
                 if let Ok(pkg) = parse_description_file_in_folder(&pkg_paths.binary)
                     .or(parse_description_file_in_folder(&pkg_paths.source))
                 {
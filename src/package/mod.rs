@@ -1,28 +1,46 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+#[cfg(not(target_arch = "wasm32"))]
+use std::collections::{BTreeMap, HashMap};
 use std::fmt;
+#[cfg(not(target_arch = "wasm32"))]
 use std::path::Path;
 use toml_edit::{InlineTable, Value};
 
+#[cfg(not(target_arch = "wasm32"))]
 mod builtin;
+#[cfg(not(target_arch = "wasm32"))]
 mod description;
+#[cfg(not(target_arch = "wasm32"))]
 mod fetch;
 mod parser;
+#[cfg(not(target_arch = "wasm32"))]
 mod remotes;
 mod version;
 
-use crate::{consts::BASE_PACKAGES, git::url::GitUrl};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::consts::BASE_PACKAGES;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::git::url::GitUrl;
+#[cfg(not(target_arch = "wasm32"))]
 pub use builtin::{BuiltinPackages, get_builtin_versions_from_library};
+#[cfg(not(target_arch = "wasm32"))]
 pub use description::{parse_description_file, parse_description_file_in_folder, parse_version};
+#[cfg(not(target_arch = "wasm32"))]
 pub use fetch::FetchPackage;
-pub use parser::{parse_dependencies, parse_needs_entries, parse_package_file};
+pub use parser::parse_dependencies;
+#[cfg(not(target_arch = "wasm32"))]
+pub use parser::{parse_needs_entries, parse_package_file};
+#[cfg(not(target_arch = "wasm32"))]
 pub use remotes::PackageRemote;
 pub use version::{Operator, Version, VersionRequirement, deserialize_version, serialize_version};
 
+#[cfg(not(target_arch = "wasm32"))]
 pub(crate) use remotes::parse_remote;
 
+#[cfg(not(target_arch = "wasm32"))]
 const COMPILED_R_SUBDIRS: [&str; 2] = ["R", "data"];
 
+#[cfg(not(target_arch = "wasm32"))]
 #[derive(Debug, PartialEq, Eq, Copy, Clone, Hash, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum PackageType {
@@ -30,6 +48,7 @@ pub enum PackageType {
     Binary,
 }
 
+#[cfg(not(target_arch = "wasm32"))]
 impl fmt::Display for PackageType {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -76,15 +95,42 @@ impl Dependency {
     }
 }
 
+/// Why a package depends on another one, ie the DESCRIPTION field listing it.
+/// `Enhances` is not there since those packages are never installed.
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[cfg_attr(feature = "cli", value(rename_all = "snake_case"))]
+pub enum DependencyType {
+    Depends,
+    Imports,
+    /// Only needed to compile the package, for its C/C++ headers
+    LinkingTo,
+    Suggests,
+}
+
+impl fmt::Display for DependencyType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Depends => write!(f, "depends"),
+            Self::Imports => write!(f, "imports"),
+            Self::LinkingTo => write!(f, "linking_to"),
+            Self::Suggests => write!(f, "suggests"),
+        }
+    }
+}
+
 /// Represents a single entry in a `Config/Needs/*` field.
 /// Entries are either plain package names (possibly with a version requirement)
 /// or remote shorthands like `tidyverse/tidytemplate`.
+#[cfg(not(target_arch = "wasm32"))]
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub enum NeedsEntry {
     Package(Dependency),
     Remote(String, PackageRemote),
 }
 
+#[cfg(not(target_arch = "wasm32"))]
 #[derive(Debug, Default, PartialEq, Clone, Serialize, Deserialize)]
 pub struct Package {
     pub name: String,
@@ -116,12 +162,14 @@ pub struct Package {
     pub(crate) needs: HashMap<String, Vec<NeedsEntry>>,
 }
 
+#[cfg(not(target_arch = "wasm32"))]
 #[derive(Debug, Default, PartialEq, Clone)]
 pub struct InstallationDependencies<'a> {
     pub(crate) direct: Vec<&'a Dependency>,
     pub(crate) suggests: Vec<&'a Dependency>,
 }
 
+#[cfg(not(target_arch = "wasm32"))]
 impl Package {
     #[inline]
     pub fn works_with_r_version(&self, r_version: &Version) -> bool {
@@ -132,6 +180,26 @@ impl Package {
         }
     }
 
+    /// The type of the dependencies to install that are not `Imports`, the most common one.
+    /// A dependency listed in several fields gets the first of `Depends`, `Imports` and
+    /// `LinkingTo`, the same order as `dependencies_to_install`.
+    pub(crate) fn dependency_types(&self) -> BTreeMap<String, DependencyType> {
+        let mut out = BTreeMap::new();
+        for (deps, dep_type) in [
+            (&self.depends, DependencyType::Depends),
+            (&self.imports, DependencyType::Imports),
+            (&self.linking_to, DependencyType::LinkingTo),
+        ] {
+            for dep in deps {
+                out.entry(dep.name().to_string()).or_insert(dep_type);
+            }
+        }
+        out.retain(|name, dep_type| {
+            *dep_type != DependencyType::Imports && !BASE_PACKAGES.contains(&name.as_str())
+        });
+        out
+    }
+
     pub fn dependencies_to_install(
         &self,
         install_suggestions: bool,
@@ -169,6 +237,7 @@ impl Package {
 
 /// Returns whether this folder contains compiled R files
 /// Error only occurs if the DESCRIPTION file cannot be parsed
+#[cfg(not(target_arch = "wasm32"))]
 pub fn is_binary_package(
     path: impl AsRef<Path>,
     name: &str,
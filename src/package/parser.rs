@@ -1,17 +1,28 @@
 //! Parses the PACKAGES files
 
+#[cfg(not(target_arch = "wasm32"))]
+use crate::Version;
+use crate::VersionRequirement;
+use crate::package::Dependency;
+#[cfg(not(target_arch = "wasm32"))]
 use crate::package::remotes::parse_remote;
-use crate::package::{Dependency, NeedsEntry, Package};
-use crate::{Version, VersionRequirement};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::package::{NeedsEntry, Package};
+#[cfg(not(target_arch = "wasm32"))]
 use regex::Regex;
+#[cfg(not(target_arch = "wasm32"))]
 use std::collections::HashMap;
 use std::str::FromStr;
+#[cfg(not(target_arch = "wasm32"))]
 use std::sync::LazyLock;
 
+#[cfg(not(target_arch = "wasm32"))]
 static PACKAGE_KEY_VAL_RE: LazyLock<Regex> =
     LazyLock::new(|| Regex::new(r"(?m)^(?P<key>[\w/]+):(?P<value>.*(?:\n\s+.*)*)").unwrap());
+#[cfg(not(target_arch = "wasm32"))]
 static ANY_SPACE_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\s+").unwrap());
 
+#[cfg(not(target_arch = "wasm32"))]
 pub fn parse_needs_entries(value: &str) -> Vec<NeedsEntry> {
     value
         .split(',')
@@ -68,6 +79,7 @@ pub fn parse_dependencies(content: &str) -> Vec<Dependency> {
 /// 2. Get the first that match in the vector (the vector is in reversed order of appearance in PACKAGE file)
 ///
 /// This assumes the content is valid and does not contain errors. It will panic otherwise.
+#[cfg(not(target_arch = "wasm32"))]
 pub fn parse_package_file(content: &str) -> HashMap<String, Vec<Package>> {
     let mut packages: HashMap<String, Vec<Package>> = HashMap::new();
 
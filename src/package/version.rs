@@ -64,7 +64,35 @@ impl TryFrom<String> for Version {
     }
 }
 
+/// Alias accepted for `r_version` to use whichever R-devel is installed
+const R_DEVEL: &str = "devel";
+
+/// Status suffixes R appends to its version, eg `4.5.0 Under development (unstable)` or
+/// `4.4.2 Patched`
+const R_VERSION_STATUSES: [&str; 5] = ["under development", "patched", "rc", "alpha", "beta"];
+
+/// Returns the numeric part of a version, ignoring any R status suffix
+fn numeric_part(s: &str) -> Option<&str> {
+    let s = s.trim();
+    match s.split_once(char::is_whitespace) {
+        None => Some(s),
+        Some((version, status)) => {
+            let status = status.trim().to_lowercase();
+            R_VERSION_STATUSES
+                .iter()
+                .any(|x| status.starts_with(x))
+                .then_some(version)
+        }
+    }
+}
+
 impl Version {
+    /// Whether this is the `devel` alias rather than an actual version number.
+    /// It needs to be replaced by the version of the R-devel found before being used.
+    pub fn is_r_devel(&self) -> bool {
+        self.original.trim() == R_DEVEL
+    }
+
     /// Returns the major/minor part of a version.
     /// Only meant to be used for R itself.
     // unlikely to be a problem but if hashing on the list is too slow but we can return a u64 instead
@@ -76,8 +104,17 @@ impl Version {
 
     /// Determines if the called version matches in the input version based on the number of specified elements in the called version
     /// i.e. 4.4 = 4.4.1, but 4.4.2 != 4.4.1
+    /// The `devel` alias matches any version.
+    #[cfg(not(target_arch = "wasm32"))]
     pub(crate) fn hazy_match(&self, version: &Version) -> bool {
-        let num_specified = self.original.replace("-", ".").split('.').count();
+        if self.is_r_devel() {
+            return true;
+        }
+        let num_specified = numeric_part(&self.original)
+            .unwrap_or(&self.original)
+            .replace("-", ".")
+            .split('.')
+            .count();
         self.parts[..num_specified] == version.parts[..num_specified]
     }
 }
@@ -86,8 +123,15 @@ impl FromStr for Version {
     type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut parts: Vec<_> = s
-            .trim()
+        if s.trim() == R_DEVEL {
+            return Ok(Self {
+                parts: [0; 10],
+                original: s.to_string(),
+            });
+        }
+
+        let mut parts: Vec<_> = numeric_part(s)
+            .ok_or_else(|| format!("{s} cannot be parsed as a version"))?
             .replace('-', ".")
             .split('.')
             .map(|x| x.parse::<u32>())
@@ -309,4 +353,24 @@ mod tests {
         assert_eq!(Version::from_str("1.0.0").unwrap().major_minor(), [1, 0]);
         assert_eq!(Version::from_str("4.5").unwrap().major_minor(), [4, 5]);
     }
+
+    #[test]
+    fn can_parse_r_prerelease_versions() {
+        let devel = Version::from_str("4.5.0 Under development (unstable)").unwrap();
+        assert_eq!(devel, Version::from_str("4.5.0").unwrap());
+        assert!(Version::from_str("4.5").unwrap().hazy_match(&devel));
+        assert_eq!(
+            Version::from_str("4.4.2 Patched").unwrap().major_minor(),
+            [4, 4]
+        );
+        assert!(Version::from_str("4.4.2 something").is_err());
+    }
+
+    #[test]
+    fn devel_alias_matches_any_version() {
+        let devel = Version::from_str("devel").unwrap();
+        assert!(devel.is_r_devel());
+        assert!(devel.hazy_match(&Version::from_str("4.6.0").unwrap()));
+        assert!(!Version::from_str("4.6").unwrap().is_r_devel());
+    }
 }
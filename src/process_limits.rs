@@ -0,0 +1,221 @@
+//! Limits applied to the R processes compiling/installing packages during a sync, so a big sync
+//! on a shared server doesn't starve interactive users. Set with environment variables:
+//! - `RV_INSTALL_NICENESS`: niceness of the processes, from 0 to 19
+//! - `RV_INSTALL_CPU_AFFINITY`: CPUs the processes can run on, eg `0-3,8` (Linux only)
+//! - `RV_INSTALL_MEMORY_LIMIT`: maximum virtual memory of each process, like `ulimit -v`, in
+//!   bytes or with a K/M/G/T suffix, eg `4G`
+//!
+//! They only apply on Unix and invalid values are ignored with a warning.
+use std::process::Command;
+use std::sync::LazyLock;
+
+use crate::consts::{
+    INSTALL_CPU_AFFINITY_ENV_VAR_NAME, INSTALL_MEMORY_LIMIT_ENV_VAR_NAME,
+    INSTALL_NICENESS_ENV_VAR_NAME,
+};
+
+const MAX_NICENESS: i32 = 19;
+
+pub(crate) static PROCESS_LIMITS: LazyLock<ProcessLimits> = LazyLock::new(ProcessLimits::from_env);
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct ProcessLimits {
+    niceness: Option<i32>,
+    cpus: Option<Vec<usize>>,
+    memory_bytes: Option<u64>,
+}
+
+impl ProcessLimits {
+    fn from_env() -> Self {
+        fn read<T>(name: &str, parse: impl Fn(&str) -> Option<T>) -> Option<T> {
+            let value = std::env::var(name).ok()?;
+            let parsed = parse(value.trim());
+            if parsed.is_none() {
+                log::warn!("Ignoring invalid value `{value}` for {name}");
+            }
+            parsed
+        }
+
+        let limits = Self {
+            niceness: read(INSTALL_NICENESS_ENV_VAR_NAME, parse_niceness),
+            cpus: read(INSTALL_CPU_AFFINITY_ENV_VAR_NAME, parse_cpu_list),
+            memory_bytes: read(INSTALL_MEMORY_LIMIT_ENV_VAR_NAME, parse_memory_size),
+        };
+        if limits != Self::default() {
+            log::debug!("Limits for R processes: {limits:?}");
+        }
+        if limits.cpus.is_some() && !cfg!(target_os = "linux") {
+            log::warn!("{INSTALL_CPU_AFFINITY_ENV_VAR_NAME} is only supported on Linux");
+        }
+        limits
+    }
+
+    /// Sets the limits on the process when it is spawned
+    pub(crate) fn apply(&self, command: &mut Command) {
+        if *self == Self::default() {
+            return;
+        }
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::CommandExt;
+
+            // Everything is computed beforehand: only syscalls are allowed after the fork
+            let niceness = self.niceness;
+            let memory_bytes = self.memory_bytes.map(|b| b as libc::rlim_t);
+            #[cfg(target_os = "linux")]
+            let cpu_set = self.cpus.as_ref().map(|cpus| {
+                // SAFETY: cpu_set_t is a plain bitmask, all zeroes is an empty set
+                let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+                for cpu in cpus {
+                    unsafe { libc::CPU_SET(*cpu, &mut set) };
+                }
+                set
+            });
+
+            // SAFETY: the closure only makes syscalls on the child process
+            unsafe {
+                command.pre_exec(move || {
+                    // This fails if rv already runs with a higher niceness, which is fine
+                    if let Some(niceness) = niceness {
+                        libc::setpriority(libc::PRIO_PROCESS, 0, niceness);
+                    }
+                    if let Some(bytes) = memory_bytes {
+                        let mut limit = libc::rlimit {
+                            rlim_cur: 0,
+                            rlim_max: 0,
+                        };
+                        if libc::getrlimit(libc::RLIMIT_AS, &mut limit) != 0 {
+                            return Err(std::io::Error::last_os_error());
+                        }
+                        // We can't go above the hard limit
+                        limit.rlim_cur = bytes.min(limit.rlim_max);
+                        if libc::setrlimit(libc::RLIMIT_AS, &limit) != 0 {
+                            return Err(std::io::Error::last_os_error());
+                        }
+                    }
+                    #[cfg(target_os = "linux")]
+                    if let Some(set) = &cpu_set
+                        && libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), set)
+                            != 0
+                    {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                    Ok(())
+                });
+            }
+        }
+
+        #[cfg(not(unix))]
+        let _ = command;
+    }
+}
+
+fn parse_niceness(value: &str) -> Option<i32> {
+    value
+        .parse()
+        .ok()
+        .filter(|n| (0..=MAX_NICENESS).contains(n))
+}
+
+/// A comma separated list of CPU numbers or ranges, eg `0-3,8`
+fn parse_cpu_list(value: &str) -> Option<Vec<usize>> {
+    let mut cpus = Vec::new();
+    for part in value.split(',') {
+        let part = part.trim();
+        match part.split_once('-') {
+            Some((start, end)) => {
+                let start: usize = start.trim().parse().ok()?;
+                let end: usize = end.trim().parse().ok()?;
+                if start > end {
+                    return None;
+                }
+                cpus.extend(start..=end);
+            }
+            None => cpus.push(part.parse().ok()?),
+        }
+    }
+    // cpu_set_t can't hold more
+    if cpus.is_empty() || cpus.iter().any(|c| *c >= 1024) {
+        return None;
+    }
+    cpus.sort_unstable();
+    cpus.dedup();
+    Some(cpus)
+}
+
+/// A number of bytes with an optional K/M/G/T (powers of 1024) suffix
+pub(crate) fn parse_memory_size(value: &str) -> Option<u64> {
+    let value = value.to_ascii_uppercase();
+    let value = value.strip_suffix('B').unwrap_or(&value);
+    let (number, multiplier) = match value.chars().last()? {
+        'K' => (&value[..value.len() - 1], 1u64 << 10),
+        'M' => (&value[..value.len() - 1], 1 << 20),
+        'G' => (&value[..value.len() - 1], 1 << 30),
+        'T' => (&value[..value.len() - 1], 1 << 40),
+        _ => (value, 1),
+    };
+    number
+        .trim()
+        .parse::<u64>()
+        .ok()?
+        .checked_mul(multiplier)
+        .filter(|b| *b > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn can_parse_niceness() {
+        assert_eq!(parse_niceness("10"), Some(10));
+        assert_eq!(parse_niceness("0"), Some(0));
+        assert_eq!(parse_niceness("20"), None);
+        assert_eq!(parse_niceness("-5"), None);
+        assert_eq!(parse_niceness("low"), None);
+    }
+
+    #[test]
+    fn can_parse_cpu_list() {
+        assert_eq!(parse_cpu_list("0-3,8"), Some(vec![0, 1, 2, 3, 8]));
+        assert_eq!(parse_cpu_list("2, 1,2"), Some(vec![1, 2]));
+        assert_eq!(parse_cpu_list("3-1"), None);
+        assert_eq!(parse_cpu_list("a"), None);
+        assert_eq!(parse_cpu_list(""), None);
+        assert_eq!(parse_cpu_list("2000"), None);
+    }
+
+    #[test]
+    fn can_parse_memory_size() {
+        assert_eq!(parse_memory_size("1024"), Some(1024));
+        assert_eq!(parse_memory_size("512k"), Some(512 * 1024));
+        assert_eq!(parse_memory_size("4G"), Some(4 << 30));
+        assert_eq!(parse_memory_size("4GB"), Some(4 << 30));
+        assert_eq!(parse_memory_size("1T"), Some(1 << 40));
+        assert_eq!(parse_memory_size("0"), None);
+        assert_eq!(parse_memory_size("G"), None);
+        assert_eq!(parse_memory_size("lots"), None);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn limits_are_applied_to_child_processes() {
+        let limits = ProcessLimits {
+            niceness: Some(MAX_NICENESS),
+            cpus: None,
+            memory_bytes: Some(1 << 40),
+        };
+        let mut command = Command::new("sh");
+        command.args(["-c", "ulimit -v; nice"]);
+        limits.apply(&mut command);
+        let output = command.output().unwrap();
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let lines: Vec<_> = stdout.lines().collect();
+        assert_eq!(
+            lines,
+            vec![((1u64 << 40) / 1024).to_string(), "19".to_string()]
+        );
+    }
+}
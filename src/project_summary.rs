@@ -20,65 +20,81 @@ use crate::{
     OsType,
     system_req::{SysDep, SysInstallationStatus},
 };
-use crate::{repository_urls::get_distro_name, utils::get_max_workers};
+use crate::{
+    repository_urls::get_distro_name,
+    utils::{get_max_compile_jobs, get_max_workers},
+};
+
+/// The parts of the summary, in the order they are displayed.
+/// Each section is computed independently so only what is requested is computed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum SummarySection {
+    /// OS, R version, cache and sync settings
+    System,
+    /// Status of the project dependencies in the library and cache
+    Dependencies,
+    /// Status of the system dependencies
+    Sysdeps,
+    /// Number of packages available in each repository
+    Remote,
+}
+
+impl SummarySection {
+    pub const ALL: [SummarySection; 4] = [
+        SummarySection::System,
+        SummarySection::Dependencies,
+        SummarySection::Sysdeps,
+        SummarySection::Remote,
+    ];
+
+    /// Whether the repositories databases need to be loaded to compute that section
+    pub fn needs_databases(&self) -> bool {
+        !matches!(self, SummarySection::System)
+    }
+
+    /// Whether the dependencies need to be resolved to compute that section
+    pub fn needs_resolution(&self) -> bool {
+        matches!(self, SummarySection::Dependencies | SummarySection::Sysdeps)
+    }
+}
 
 #[derive(Debug, Clone, Serialize)]
-pub struct ProjectSummary<'a> {
+pub struct SystemSummary<'a> {
     r_version: &'a Version,
     system_info: &'a SystemInfo,
-    dependency_info: DependencyInfo<'a>,
     global_cache_root: Option<PathBuf>,
     local_cache_root: PathBuf,
     network_fs: bool,
     link_mode: &'static str,
-    remote_info: RemoteInfo<'a>,
-    sys_deps: Vec<SysDep>,
     max_workers: usize,
+    max_compile_jobs: usize,
 }
 
-impl<'a> ProjectSummary<'a> {
-    pub fn new(
-        context: &'a Context,
-        resolved_deps: &'a [ResolvedDependency],
-        sys_deps: Vec<SysDep>,
-    ) -> Self {
+impl<'a> SystemSummary<'a> {
+    pub fn new(context: &'a Context) -> Self {
         let lib_path = context.library.path();
         let network_fs = is_network_fs(lib_path).unwrap_or(false);
         let link_mode = LinkMode::effective_mode(lib_path).name();
 
         Self {
             r_version: &context.r_version,
-            sys_deps,
             system_info: context.cache.system_info(),
-            dependency_info: DependencyInfo::new(
-                &context.library,
-                resolved_deps,
-                context.config.repositories(),
-                &context.databases,
-                &context.r_version,
-                &context.cache,
-                context.lockfile.as_ref(),
-            ),
             local_cache_root: context.cache.local().root.clone(),
             global_cache_root: context.cache.global().map(|x| x.root.clone()),
             network_fs,
             link_mode,
-            remote_info: RemoteInfo::new(
-                context.config.repositories(),
-                &context.databases,
-                &context.r_version.major_minor(),
-                context.cache.system_info(),
-            ),
             max_workers: get_max_workers(),
+            max_compile_jobs: get_max_compile_jobs().min(get_max_workers()),
         }
     }
 }
 
-impl fmt::Display for ProjectSummary<'_> {
+impl fmt::Display for SystemSummary<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
-            "== System Information == \nOS: {}{}{}\nR Version: {}\n\nNum Workers for Sync: {} ({} cpus available)\nCache Location: {}{}\nNetwork Filesystem: {}\nLink Mode: {}\n\n",
+            "== System Information == \nOS: {}{}{}\nR Version: {}\n\nNum Workers for Sync: {} ({} compiling, {} cpus available)\nCache Location: {}{}\nNetwork Filesystem: {}\nLink Mode: {}\n\n",
             self.system_info.os_family(),
             if let OsType::Linux(distro) = self.system_info.os_type {
                 format!(" {distro} {}", self.system_info.version)
@@ -92,6 +108,7 @@ impl fmt::Display for ProjectSummary<'_> {
             },
             self.r_version,
             self.max_workers,
+            self.max_compile_jobs,
             num_cpus::get(),
             self.local_cache_root.to_string_lossy(),
             if let Some(g) = self.global_cache_root.as_ref() {
@@ -106,42 +123,144 @@ impl fmt::Display for ProjectSummary<'_> {
             },
             self.network_fs,
             self.link_mode,
-        )?;
+        )
+    }
+}
 
-        write!(f, "== Dependencies == \n{}\n", self.dependency_info)?;
-        if !self.sys_deps.is_empty() {
-            let mut present = 0;
-            let mut absent = Vec::new();
-            let mut unknown = Vec::new();
-            for d in self.sys_deps.iter() {
-                match d.status {
-                    SysInstallationStatus::Present => present += 1,
-                    SysInstallationStatus::Absent => absent.push(d.name.as_str()),
-                    SysInstallationStatus::Unknown => unknown.push(d.name.as_str()),
-                }
+#[derive(Debug, Clone, Serialize)]
+#[serde(transparent)]
+pub struct SysDepsSummary(Vec<SysDep>);
+
+impl SysDepsSummary {
+    pub fn new(sys_deps: Vec<SysDep>) -> Self {
+        Self(sys_deps)
+    }
+}
+
+impl fmt::Display for SysDepsSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.is_empty() {
+            return Ok(());
+        }
+        let mut present = 0;
+        let mut absent = Vec::new();
+        let mut unknown = Vec::new();
+        for d in self.0.iter() {
+            match d.status {
+                SysInstallationStatus::Present => present += 1,
+                SysInstallationStatus::Absent => absent.push(d.name.as_str()),
+                SysInstallationStatus::Unknown => unknown.push(d.name.as_str()),
             }
+        }
 
-            write!(
-                f,
-                "== System Dependencies == \n{}{}{}\n",
-                if present != 0 {
-                    format!("Present: {present}/{}\n", self.sys_deps.len())
-                } else {
-                    String::new()
-                },
-                if !absent.is_empty() {
-                    format!("Absent:\n  {}\n", absent.join("\n  "))
-                } else {
-                    String::new()
-                },
-                if !unknown.is_empty() {
-                    format!("Unknown:\n  {}\n", unknown.join("\n  "))
-                } else {
-                    String::new()
-                },
-            )?;
+        write!(
+            f,
+            "== System Dependencies == \n{}{}{}\n",
+            if present != 0 {
+                format!("Present: {present}/{}\n", self.0.len())
+            } else {
+                String::new()
+            },
+            if !absent.is_empty() {
+                format!("Absent:\n  {}\n", absent.join("\n  "))
+            } else {
+                String::new()
+            },
+            if !unknown.is_empty() {
+                format!("Unknown:\n  {}\n", unknown.join("\n  "))
+            } else {
+                String::new()
+            },
+        )
+    }
+}
+
+/// Every section of the summary, the ones not computed being skipped in the output
+#[derive(Debug, Clone, Serialize)]
+pub struct ProjectSummary<'a> {
+    #[serde(flatten)]
+    system: Option<SystemSummary<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dependency_info: Option<DependencyInfo<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    remote_info: Option<RemoteInfo<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sys_deps: Option<SysDepsSummary>,
+}
+
+impl<'a> ProjectSummary<'a> {
+    pub fn new(
+        context: &'a Context,
+        resolved_deps: &'a [ResolvedDependency],
+        sys_deps: Vec<SysDep>,
+    ) -> Self {
+        let mut summary = Self::with_sections(context, &SummarySection::ALL, resolved_deps);
+        summary.sys_deps = Some(SysDepsSummary::new(sys_deps));
+        summary
+    }
+
+    /// Only computes the given sections.
+    /// The system dependencies status is not computed here as it can be slow, see
+    /// [`ProjectSummary::set_sys_deps`].
+    pub fn with_sections(
+        context: &'a Context,
+        sections: &[SummarySection],
+        resolved_deps: &'a [ResolvedDependency],
+    ) -> Self {
+        Self {
+            system: sections
+                .contains(&SummarySection::System)
+                .then(|| SystemSummary::new(context)),
+            dependency_info: sections.contains(&SummarySection::Dependencies).then(|| {
+                DependencyInfo::new(
+                    &context.library,
+                    resolved_deps,
+                    context.config.repositories(),
+                    &context.databases,
+                    &context.r_version,
+                    &context.cache,
+                    context.lockfile.as_ref(),
+                )
+            }),
+            remote_info: sections.contains(&SummarySection::Remote).then(|| {
+                RemoteInfo::new(
+                    context.config.repositories(),
+                    &context.databases,
+                    &context.r_version.major_minor(),
+                    context.cache.system_info(),
+                )
+            }),
+            sys_deps: None,
+        }
+    }
+
+    pub fn set_sys_deps(&mut self, sys_deps: Vec<SysDep>) {
+        self.sys_deps = Some(SysDepsSummary::new(sys_deps));
+    }
+
+    /// The text output of a single section, empty if it was not computed
+    pub fn section(&self, section: SummarySection) -> String {
+        match section {
+            SummarySection::System => self.system.as_ref().map(ToString::to_string),
+            SummarySection::Dependencies => self
+                .dependency_info
+                .as_ref()
+                .map(|d| format!("== Dependencies == \n{d}\n")),
+            SummarySection::Sysdeps => self.sys_deps.as_ref().map(ToString::to_string),
+            SummarySection::Remote => self
+                .remote_info
+                .as_ref()
+                .map(|r| format!("== Remote == \n{r}")),
+        }
+        .unwrap_or_default()
+    }
+}
+
+impl fmt::Display for ProjectSummary<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for section in SummarySection::ALL {
+            write!(f, "{}", self.section(section))?;
         }
-        write!(f, "== Remote == \n{}", self.remote_info)?;
         Ok(())
     }
 }
@@ -463,6 +582,7 @@ impl<'a> DependencySummary<'a> {
             &resolved_dep.name,
             &resolved_dep.version.original,
             &resolved_dep.source,
+            resolved_dep.kind,
         );
 
         let binary_in_global = cache_status
@@ -591,7 +711,7 @@ fn is_binary_package(
     // We only will say a package is a binary if its from a repository or its built in
     let repository = match &resolved_dep.source {
         Source::Repository { repository } => repository,
-        Source::Builtin { .. } => return true,
+        Source::Builtin { .. } | Source::Provided { .. } => return true,
         _ => return false,
     };
     let ver_req = Some(VersionRequirement::new(
@@ -627,6 +747,7 @@ fn get_dep_id(dep: &ResolvedDependency, repos: &[Repository]) -> String {
         Source::Local { path, .. } => path.to_string_lossy().to_string(),
         Source::Url { url, .. } => url.to_string(),
         Source::Builtin { .. } => "builtin".to_string(),
+        Source::Provided { .. } => "provided".to_string(),
     }
 }
 
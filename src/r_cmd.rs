@@ -8,7 +8,10 @@ use std::sync::{Arc, LazyLock, Mutex};
 use std::time::Duration;
 use std::{fs, thread};
 
+#[cfg(feature = "cli")]
+use crate::consts::HARD_CANCEL_KILL_ENV_VAR_NAME;
 use crate::fs::copy_folder;
+use crate::process_limits::PROCESS_LIMITS;
 use crate::r_finder::RInstall;
 use crate::sync::{LinkError, LinkMode};
 use crate::{Cancellation, Version};
@@ -21,6 +24,22 @@ fn is_r_devel(output: &str) -> bool {
     output.contains("R Under development")
 }
 
+/// Extracts the CPU architecture R was built for from the `Platform: aarch64-apple-darwin20` line
+/// of `R --version`. It can differ from the OS one, eg an x86_64 R running through Rosetta.
+fn find_r_arch(output: &str) -> Option<String> {
+    output
+        .lines()
+        .find_map(|l| l.trim().strip_prefix("Platform:"))
+        .and_then(|p| p.trim().split('-').next())
+        .filter(|a| !a.is_empty())
+        .map(|a| a.to_string())
+}
+
+/// The architecture of each R binary, filled from the `R --version` output of
+/// [`RCmd::version`] so looking it up doesn't spawn R again
+static R_ARCHS: LazyLock<Mutex<HashMap<PathBuf, Option<String>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
 fn find_r_version(output: &str) -> Option<Version> {
     R_VERSION_RE
         .find(output)
@@ -61,6 +80,14 @@ pub trait RCmd: Send + Sync {
     fn get_r_library(&self) -> Result<PathBuf, LibraryError>;
 
     fn version(&self) -> Result<Option<Version>, VersionError>;
+
+    /// The CPU architecture the R binary was built for, if it can be detected
+    fn arch(&self) -> Option<String> {
+        None
+    }
+
+    /// The value of a variable from `R CMD config`, eg the `CC` compiler used to build packages
+    fn config_value(&self, name: &str) -> Option<String>;
 }
 
 /// Canonicalize library paths and join them into R's expected format
@@ -87,6 +114,7 @@ fn r_library_paths(libraries: &[impl AsRef<Path>]) -> Result<String, std::io::Er
 /// To allow graceful shutdown, we create a process group in Unix and the equivalent on Windows
 /// so we can control _how_ they get killed, and allow for a soft cancellation (eg we let
 /// ongoing tasks finish but stop enqueuing/processing new ones.
+/// The limits set by the user for those processes are applied there as well.
 fn spawn_isolated_r_command(r_cmd: &RInstall) -> Command {
     let mut command = Command::new(&r_cmd.bin_path);
 
@@ -108,6 +136,7 @@ fn spawn_isolated_r_command(r_cmd: &RInstall) -> Command {
         command.creation_flags(CREATE_NEW_PROCESS_GROUP);
     }
 
+    PROCESS_LIMITS.apply(&mut command);
     command
 }
 
@@ -203,25 +232,94 @@ fn run_r_command(
     }
 }
 
+/// What gets killed on hard cancellation, set with `RV_HARD_CANCEL_KILL`.
+/// Only the R processes spawned by rv are ever considered, never other R sessions running on
+/// the machine.
+#[cfg(feature = "cli")]
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub(crate) enum KillScope {
+    /// The R processes and everything they started, eg compilers or `make`
+    #[default]
+    ProcessGroup,
+    /// Only the R processes, their children are left running
+    Process,
+    /// Nothing, the R processes are left running after rv exits
+    None,
+}
+
 #[cfg(feature = "cli")]
-pub fn kill_all_r_processes() {
+impl FromStr for KillScope {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "group" => Ok(Self::ProcessGroup),
+            "process" => Ok(Self::Process),
+            "none" => Ok(Self::None),
+            _ => Err(()),
+        }
+    }
+}
+
+#[cfg(feature = "cli")]
+impl KillScope {
+    fn from_env() -> Self {
+        let Ok(value) = std::env::var(HARD_CANCEL_KILL_ENV_VAR_NAME) else {
+            return Self::default();
+        };
+        value.parse().unwrap_or_else(|_| {
+            log::warn!("Ignoring invalid value `{value}` for {HARD_CANCEL_KILL_ENV_VAR_NAME}");
+            Self::default()
+        })
+    }
+}
+
+/// Kills the R processes spawned by rv that are still running, according to
+/// `RV_HARD_CANCEL_KILL`.
+#[cfg(feature = "cli")]
+pub fn kill_spawned_r_processes() {
+    let scope = KillScope::from_env();
     let process_ids = ACTIVE_R_PROCESS_IDS.lock().unwrap();
+    if scope == KillScope::None {
+        if !process_ids.is_empty() {
+            log::warn!(
+                "Leaving {} R process(es) running: {}",
+                process_ids.len(),
+                process_ids
+                    .iter()
+                    .map(|pid| pid.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+        return;
+    }
 
     for pid in process_ids.iter() {
         #[cfg(unix)]
         {
+            let pid = (*pid) as libc::pid_t;
+            // The processes are spawned in their own group, whose id is their pid.
+            // We check it is still the case so we never signal a group we didn't create.
+            let own_group = unsafe { libc::getpgid(pid) } == pid;
+            let target = if scope == KillScope::ProcessGroup && own_group {
+                -pid
+            } else {
+                pid
+            };
             unsafe {
-                libc::kill((*pid) as i32, libc::SIGTERM);
+                libc::kill(target, libc::SIGTERM);
             }
         }
 
         #[cfg(windows)]
         {
-            let _ = Command::new("taskkill")
-                .arg("/PID")
-                .arg(pid.to_string())
-                .arg("/F")
-                .output();
+            let mut command = Command::new("taskkill");
+            command.arg("/PID").arg(pid.to_string()).arg("/F");
+            if scope == KillScope::ProcessGroup {
+                command.arg("/T");
+            }
+            let _ = command.output();
         }
     }
 }
@@ -428,6 +526,10 @@ impl RCmd for RInstall {
         let stdout = r_output_str(&output).map_err(|e| VersionError {
             source: VersionErrorKind::Utf8(e),
         })?;
+        R_ARCHS
+            .lock()
+            .unwrap()
+            .insert(self.bin_path.clone(), find_r_arch(stdout));
 
         if is_r_devel(stdout) {
             return Ok(None);
@@ -437,6 +539,35 @@ impl RCmd for RInstall {
             source: VersionErrorKind::NotFound,
         })
     }
+
+    fn arch(&self) -> Option<String> {
+        if let Some(arch) = R_ARCHS.lock().unwrap().get(&self.bin_path) {
+            return arch.clone();
+        }
+        // Installs found from their header never ran `R --version`
+        let output = Command::new(&self.bin_path)
+            .arg("--version")
+            .output()
+            .ok()?;
+        let arch = find_r_arch(r_output_str(&output).ok()?);
+        R_ARCHS
+            .lock()
+            .unwrap()
+            .insert(self.bin_path.clone(), arch.clone());
+        arch
+    }
+
+    fn config_value(&self, name: &str) -> Option<String> {
+        let output = Command::new(&self.bin_path)
+            .args(["CMD", "config", name])
+            .output()
+            .ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        let value = r_output_str(&output).ok()?.trim();
+        (!value.is_empty()).then(|| value.to_string())
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -547,7 +678,7 @@ pub(crate) fn get_r_home(r_bin_path: &Path) -> Result<PathBuf, std::io::Error> {
 
 #[cfg(test)]
 mod tests {
-    use super::find_r_version;
+    use super::{find_r_arch, find_r_version};
     use crate::Version;
 
     #[test]
@@ -591,4 +722,26 @@ The command could not be located because '/usr/local/bin' is not included in the
 R: command not found"#;
         assert!(find_r_version(r_response).is_none());
     }
+
+    #[test]
+    fn can_read_r_arch() {
+        let r_response = r#"/
+R version 4.4.1 (2024-06-14) -- "Race for Your Life"
+Copyright (C) 2024 The R Foundation for Statistical Computing
+Platform: x86_64-apple-darwin20 (64-bit)
+"#;
+        assert_eq!(find_r_arch(r_response).as_deref(), Some("x86_64"));
+        assert!(find_r_arch("R: command not found").is_none());
+    }
+
+    #[cfg(feature = "cli")]
+    #[test]
+    fn can_parse_kill_scope() {
+        use super::KillScope;
+
+        assert_eq!("group".parse(), Ok(KillScope::ProcessGroup));
+        assert_eq!("Process".parse(), Ok(KillScope::Process));
+        assert_eq!(" none ".parse(), Ok(KillScope::None));
+        assert_eq!("all".parse::<KillScope>(), Err(()));
+    }
 }
@@ -186,6 +186,17 @@ impl RenvLock {
         &self.r.version
     }
 
+    /// Only keep the given packages, eg to migrate a renv.lock partially.
+    /// Returns the names that are not in the renv.lock.
+    pub fn retain_packages<'a>(&mut self, names: &'a [String]) -> Vec<&'a str> {
+        self.packages.retain(|name, _| names.contains(name));
+        names
+            .iter()
+            .filter(|n| !self.packages.contains_key(n.as_str()))
+            .map(String::as_str)
+            .collect()
+    }
+
     pub fn config_repositories(&self) -> Vec<Repository> {
         self.r
             .repositories
@@ -579,7 +590,7 @@ fn locked_package_to_renv(
                 "URL source `{url}` has no direct renv equivalent, mapped as Repository with RemoteUrl"
             ));
         }
-        LockSource::Builtin { .. } => return None,
+        LockSource::Builtin { .. } | LockSource::Provided { .. } => return None,
     }
 
     Some((info, warning))
@@ -595,6 +606,7 @@ fn normalize_renv_r_version(r_version: &str) -> String {
 
 /// Convert an rv Lockfile + Config into an RenvLock.
 /// Returns the RenvLock and a list of warnings for packages that couldn't be perfectly mapped.
+#[cfg_attr(not(feature = "cli"), allow(dead_code))]
 pub fn to_renv_lock(lockfile: &Lockfile, config: &Config) -> (RenvLock, Vec<String>) {
     let mut warnings = Vec::new();
 
@@ -637,11 +649,25 @@ pub fn to_renv_lock(lockfile: &Lockfile, config: &Config) -> (RenvLock, Vec<Stri
     (renv_lock, warnings)
 }
 
+/// Updates the content of an existing renv.lock with the R info and packages of `renv_lock`.
+/// Everything else, eg the renv or Python sections, is left as is so renv can keep using it.
+pub fn update_renv_lock(existing: &str, renv_lock: &RenvLock) -> Result<String, serde_json::Error> {
+    let mut existing: serde_json::Value = serde_json::from_str(existing)?;
+    let serde_json::Value::Object(exported) = serde_json::to_value(renv_lock)? else {
+        unreachable!("RenvLock is serialized as an object")
+    };
+    match existing.as_object_mut() {
+        Some(obj) => obj.extend(exported),
+        None => existing = serde_json::Value::Object(exported),
+    }
+    serde_json::to_string_pretty(&existing)
+}
+
 #[cfg(test)]
 mod tests {
     use crate::{Config, Lockfile, Repository, RepositoryDatabase, Version};
 
-    use super::{RenvLock, to_renv_lock};
+    use super::{RenvLock, to_renv_lock, update_renv_lock};
 
     fn repository_databases(
         r_version: &Version,
@@ -684,6 +710,36 @@ mod tests {
         insta::assert_snapshot!("renv_resolver".to_string(), out);
     }
 
+    #[test]
+    fn can_retain_renv_packages() {
+        let mut renv_lock = RenvLock::parse_renv_lock("src/tests/renv/renv.lock").unwrap();
+        let names = vec!["R6".to_string(), "ghqc".to_string(), "dplyr".to_string()];
+        assert_eq!(renv_lock.retain_packages(&names), vec!["dplyr"]);
+        assert_eq!(
+            renv_lock.packages.keys().collect::<Vec<_>>(),
+            vec!["R6", "ghqc"]
+        );
+    }
+
+    #[test]
+    fn update_renv_lock_keeps_renv_sections() {
+        let renv_lock = RenvLock::parse_renv_lock("src/tests/renv/renv.lock").unwrap();
+        let existing = r#"{
+  "R": {"Version": "4.2.0", "Repositories": []},
+  "renv": {"Version": "1.0.7"},
+  "Packages": {"old": {"Package": "old"}}
+}"#;
+        let updated = update_renv_lock(existing, &renv_lock).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&updated).unwrap();
+        assert_eq!(value["renv"]["Version"], "1.0.7");
+        assert!(value["Packages"].get("old").is_none());
+        assert!(value["Packages"].get("R6").is_some());
+        assert_eq!(
+            serde_json::from_str::<RenvLock>(&updated).unwrap(),
+            renv_lock
+        );
+    }
+
     #[test]
     fn test_renv_export() {
         let lockfile_toml = r#"
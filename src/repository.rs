@@ -1,5 +1,6 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::path::Path;
 
 use crate::consts::RECOMMENDED_PACKAGES;
@@ -131,6 +132,138 @@ impl RepositoryDatabase {
     pub(crate) fn get_source_count(&self) -> usize {
         self.source_packages.len()
     }
+
+    /// The most recent version of each source package
+    fn latest_versions(&self) -> HashMap<&str, &Version> {
+        self.source_packages
+            .iter()
+            .filter_map(|(name, pkgs)| {
+                pkgs.iter()
+                    .map(|p| &p.version)
+                    .max()
+                    .map(|v| (name.as_str(), v))
+            })
+            .collect()
+    }
+
+    /// Compares the most recent source version of each package with the ones in `new`, eg to
+    /// see what would change when moving to another snapshot of a repository
+    pub fn diff(&self, new: &RepositoryDatabase) -> RepositoryDiff {
+        let old_versions = self.latest_versions();
+        let new_versions = new.latest_versions();
+        let mut diff = RepositoryDiff {
+            old_url: self.url.clone(),
+            new_url: new.url.clone(),
+            ..Default::default()
+        };
+
+        for (name, old_version) in &old_versions {
+            match new_versions.get(name) {
+                Some(new_version) if new_version != old_version => {
+                    let change = VersionChange {
+                        name: name.to_string(),
+                        old_version: old_version.original.clone(),
+                        new_version: new_version.original.clone(),
+                    };
+                    if new_version > old_version {
+                        diff.upgraded.push(change);
+                    } else {
+                        diff.downgraded.push(change);
+                    }
+                }
+                Some(_) => (),
+                None => diff.removed.push(RepositoryPackage {
+                    name: name.to_string(),
+                    version: old_version.original.clone(),
+                }),
+            }
+        }
+        diff.added.extend(
+            new_versions
+                .iter()
+                .filter(|(name, _)| !old_versions.contains_key(*name))
+                .map(|(name, version)| RepositoryPackage {
+                    name: name.to_string(),
+                    version: version.original.clone(),
+                }),
+        );
+
+        diff.added.sort_by(|a, b| a.name.cmp(&b.name));
+        diff.removed.sort_by(|a, b| a.name.cmp(&b.name));
+        diff.upgraded.sort_by(|a, b| a.name.cmp(&b.name));
+        diff.downgraded.sort_by(|a, b| a.name.cmp(&b.name));
+        diff
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RepositoryPackage {
+    pub name: String,
+    pub version: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct VersionChange {
+    pub name: String,
+    pub old_version: String,
+    pub new_version: String,
+}
+
+/// The packages added, removed, upgraded or downgraded between two repositories, sorted by name
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct RepositoryDiff {
+    pub old_url: String,
+    pub new_url: String,
+    pub added: Vec<RepositoryPackage>,
+    pub removed: Vec<RepositoryPackage>,
+    pub upgraded: Vec<VersionChange>,
+    pub downgraded: Vec<VersionChange>,
+}
+
+impl RepositoryDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.upgraded.is_empty()
+            && self.downgraded.is_empty()
+    }
+
+    /// Only keeps the packages in `names`, eg the ones of a project
+    pub fn retain_packages(&mut self, names: &HashSet<&str>) {
+        self.added.retain(|p| names.contains(p.name.as_str()));
+        self.removed.retain(|p| names.contains(p.name.as_str()));
+        self.upgraded.retain(|p| names.contains(p.name.as_str()));
+        self.downgraded.retain(|p| names.contains(p.name.as_str()));
+    }
+}
+
+impl fmt::Display for RepositoryDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "No changes");
+        }
+        for pkg in &self.added {
+            writeln!(f, "+ {} {}", pkg.name, pkg.version)?;
+        }
+        for pkg in &self.removed {
+            writeln!(f, "- {} {}", pkg.name, pkg.version)?;
+        }
+        for change in self.upgraded.iter().chain(&self.downgraded) {
+            writeln!(
+                f,
+                "~ {} {} -> {}",
+                change.name, change.old_version, change.new_version
+            )?;
+        }
+        writeln!(
+            f,
+            "\n{} added, {} removed, {} upgraded, {} downgraded",
+            self.added.len(),
+            self.removed.len(),
+            self.upgraded.len(),
+            self.downgraded.len()
+        )
+    }
 }
 
 fn yes_no_to_bool<'de, D>(deserializer: D) -> Result<bool, D::Error>
@@ -326,6 +459,7 @@ pub enum RepositoryDatabaseErrorKind {
 
 #[cfg(test)]
 mod test {
+    use std::collections::HashSet;
     use std::fs;
 
     use crate::{RepositoryDatabase, repository::parse_runiverse_api_file};
@@ -437,4 +571,26 @@ mod test {
     fn errors_when_runiverse_response_is_not_an_array() {
         assert!(parse_runiverse_api_file("{\"not\": \"an array\"}").is_err());
     }
+
+    #[test]
+    fn can_diff_repositories() {
+        let mut old = RepositoryDatabase::new("https://ppm/cran/2024-01-01");
+        old.parse_source(
+            "Package: dplyr\nVersion: 1.1.3\n\nPackage: rlang\nVersion: 1.1.2\n\nPackage: gone\nVersion: 0.1.0\n\nPackage: same\nVersion: 2.0.0\n\nPackage: pinned\nVersion: 3.0.0\n",
+        );
+        let mut new = RepositoryDatabase::new("https://ppm/cran/2024-06-01");
+        new.parse_source(
+            "Package: dplyr\nVersion: 1.1.4\n\nPackage: rlang\nVersion: 1.1.2\n\nPackage: rlang\nVersion: 1.1.4\n\nPackage: new\nVersion: 0.2.0\n\nPackage: same\nVersion: 2.0.0\n\nPackage: pinned\nVersion: 2.9.0\n",
+        );
+
+        let mut diff = old.diff(&new);
+        insta::assert_snapshot!(diff.to_string());
+
+        diff.retain_packages(&HashSet::from(["dplyr", "gone"]));
+        assert_eq!(
+            diff.to_string(),
+            "- gone 0.1.0\n~ dplyr 1.1.3 -> 1.1.4\n\n0 added, 1 removed, 1 upgraded, 0 downgraded\n"
+        );
+        assert!(old.diff(&old).is_empty());
+    }
 }
@@ -72,6 +72,13 @@ fn get_source_path(url: &Url, file_path: &[&str]) -> Url {
     new_url
 }
 
+/// Where CRAN-like repositories move the source tarball of a version once it is not the latest
+/// anymore, or once the package is archived
+pub(crate) fn get_source_archive_url(url: &Url, name: &str, version: &str) -> Url {
+    let file_name = format!("{name}_{version}.tar.gz");
+    get_source_path(url, &["Archive", name, &file_name])
+}
+
 // Archived packages under the binary end point at Archive/<pkg name>/<pkg name>_<pkg version>.<ext>
 // returns source and binary archive paths
 fn get_archive_tarball_paths(
@@ -81,8 +88,7 @@ fn get_archive_tarball_paths(
     r_version: &[u32; 2],
     sysinfo: &SystemInfo,
 ) -> (Url, Option<Url>) {
-    let src_file_name = format!("{name}_{version}.tar.gz");
-    let src_url = get_source_path(url, &["Archive", name, &src_file_name]);
+    let src_url = get_source_archive_url(url, name, version);
 
     let bin_file_name = format!("{name}_{version}.{}", sysinfo.os_type.tarball_extension());
     let bin_url = get_binary_path(url, &["Archive", name, &bin_file_name], r_version, sysinfo);
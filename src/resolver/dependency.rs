@@ -1,5 +1,5 @@
 use std::borrow::Cow;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt;
 use std::path::PathBuf;
 use std::str::FromStr;
@@ -8,7 +8,9 @@ use url::Url;
 
 use crate::cache::CacheStatus;
 use crate::lockfile::{LockedPackage, Source};
-use crate::package::{Dependency, InstallationDependencies, Package, PackageRemote, PackageType};
+use crate::package::{
+    Dependency, DependencyType, InstallationDependencies, Package, PackageRemote, PackageType,
+};
 use crate::resolver::QueueItem;
 use crate::{Version, VersionRequirement};
 
@@ -22,6 +24,8 @@ pub struct ResolvedDependency<'d> {
     pub source: Source,
     pub(crate) dependencies: Vec<Cow<'d, Dependency>>,
     pub(crate) suggests: Vec<Cow<'d, Dependency>>,
+    /// The type of the dependencies that are not `Imports`, see `dependency_type`
+    pub(crate) dependency_types: BTreeMap<String, DependencyType>,
     pub(crate) force_source: bool,
     pub(crate) install_suggests: bool,
     pub(crate) kind: PackageType,
@@ -53,6 +57,17 @@ impl<'d> ResolvedDependency<'d> {
         matches!(self.source, Source::Local { .. })
     }
 
+    /// Why this package depends on `name`
+    pub fn dependency_type(&self, name: &str) -> DependencyType {
+        if let Some(dep_type) = self.dependency_types.get(name) {
+            *dep_type
+        } else if self.dependencies.iter().any(|d| d.name() == name) {
+            DependencyType::Imports
+        } else {
+            DependencyType::Suggests
+        }
+    }
+
     pub fn all_dependencies_names(&'d self) -> Vec<&'d str> {
         let mut deps: HashSet<_> = self.dependencies.iter().map(|x| x.name()).collect();
         if self.install_suggests {
@@ -76,6 +91,7 @@ impl<'d> ResolvedDependency<'d> {
             source: package.source.clone(),
             dependencies: package.dependencies.iter().map(Cow::Borrowed).collect(),
             suggests: package.suggests.iter().map(Cow::Borrowed).collect(),
+            dependency_types: package.dependency_types.clone(),
             kind,
             force_source: package.force_source,
             install_suggests: package.install_suggests(),
@@ -120,6 +136,7 @@ impl<'d> ResolvedDependency<'d> {
             source,
             dependencies: deps.direct.iter().map(|d| Cow::Borrowed(*d)).collect(),
             suggests: deps.suggests.iter().map(|d| Cow::Borrowed(*d)).collect(),
+            dependency_types: package.dependency_types(),
             kind: package_type,
             force_source,
             install_suggests,
@@ -153,6 +170,7 @@ impl<'d> ResolvedDependency<'d> {
                 .iter()
                 .map(|&d| Cow::Owned(d.clone()))
                 .collect(),
+            dependency_types: package.dependency_types(),
             kind: PackageType::Source,
             force_source: true,
             path: None,
@@ -186,6 +204,7 @@ impl<'d> ResolvedDependency<'d> {
                 .iter()
                 .map(|&d| Cow::Owned(d.clone()))
                 .collect(),
+            dependency_types: package.dependency_types(),
             kind: PackageType::Source,
             force_source: true,
             path: None,
@@ -220,6 +239,7 @@ impl<'d> ResolvedDependency<'d> {
                 .iter()
                 .map(|&d| Cow::Owned(d.clone()))
                 .collect(),
+            dependency_types: package.dependency_types(),
             kind,
             force_source: false,
             path: None,
@@ -251,6 +271,7 @@ impl<'d> ResolvedDependency<'d> {
             source: Source::Builtin { builtin: true },
             dependencies: deps.direct.iter().map(|d| Cow::Borrowed(*d)).collect(),
             suggests: deps.suggests.iter().map(|d| Cow::Borrowed(*d)).collect(),
+            dependency_types: package.dependency_types(),
             kind: PackageType::Binary,
             force_source: false,
             install_suggests,
@@ -266,6 +287,67 @@ impl<'d> ResolvedDependency<'d> {
 
         (res, deps)
     }
+
+    /// A package satisfied by something outside of rv's control, eg an additional library
+    pub fn from_provided_package(
+        package: &'d Package,
+        library: Option<PathBuf>,
+        install_suggests: bool,
+    ) -> (Self, InstallationDependencies<'d>) {
+        let deps = package.dependencies_to_install(install_suggests);
+
+        let res = Self {
+            name: Cow::Borrowed(&package.name),
+            version: Cow::Borrowed(&package.version),
+            source: Source::Provided {
+                provided: true,
+                library,
+            },
+            dependencies: deps.direct.iter().map(|d| Cow::Borrowed(*d)).collect(),
+            suggests: deps.suggests.iter().map(|d| Cow::Borrowed(*d)).collect(),
+            dependency_types: package.dependency_types(),
+            kind: PackageType::Binary,
+            force_source: false,
+            install_suggests,
+            path: None,
+            from_lockfile: false,
+            cache_status: CacheStatus::new_local_builtin_binary(),
+            remotes: HashMap::new(),
+            from_remote: false,
+            local_resolved_path: None,
+            env_vars: HashMap::new(),
+            ignored: false,
+        };
+
+        (res, deps)
+    }
+
+    /// A package declared as provided in the config that we can't find anywhere.
+    /// We don't know its version or its dependencies so we trust the runtime to have them.
+    pub fn from_declared_provided(name: Cow<'d, str>) -> Self {
+        Self {
+            name,
+            version: Cow::Owned(Version::default()),
+            source: Source::Provided {
+                provided: true,
+                library: None,
+            },
+            dependencies: Vec::new(),
+            suggests: Vec::new(),
+            dependency_types: BTreeMap::new(),
+            kind: PackageType::Binary,
+            force_source: false,
+            install_suggests: false,
+            path: None,
+            from_lockfile: false,
+            cache_status: CacheStatus::new_local_builtin_binary(),
+            remotes: HashMap::new(),
+            from_remote: false,
+            local_resolved_path: None,
+            env_vars: HashMap::new(),
+            ignored: false,
+        }
+    }
 }
 
 impl fmt::Debug for ResolvedDependency<'_> {
@@ -1,4 +1,5 @@
 use crate::VersionRequirement;
+use crate::config::find_package_repository;
 use crate::{CommandExecutor, ConfigDependency, Lockfile, RepositoryDatabase, Version};
 
 use fs_err as fs;
@@ -9,6 +10,7 @@ use std::str::FromStr;
 use url::Url;
 
 mod dependency;
+mod progress;
 mod result;
 mod sat;
 
@@ -22,9 +24,10 @@ use crate::package::{
     Package, PackageRemote, PackageType, is_binary_package, parse_description_file,
     parse_description_file_in_folder,
 };
-use crate::utils::create_spinner;
+use crate::repository_urls::get_source_archive_url;
 pub use dependency::{ResolvedDependency, UnresolvedDependency};
-pub use result::Resolution;
+use progress::ResolutionProgress;
+pub use result::{ArchivedPackage, Resolution};
 
 #[derive(Debug, Clone, PartialEq, Default)]
 pub(crate) struct QueueItem<'d> {
@@ -107,8 +110,17 @@ pub struct Resolver<'d> {
     /// If we have a lockfile for the resolver, we will skip looking at the database for any package
     /// listed in it
     lockfile: Option<&'d Lockfile>,
-    /// Progress bar is only shown for git dependencies
+    /// Packages of the lockfile that need to be resolved again since the repositories changed
+    stale_in_lockfile: HashSet<&'d str>,
+    /// Package name patterns -> URL of the only repository they can come from
+    package_repositories: Option<&'d HashMap<String, String>>,
+    /// Packages found in libraries not managed by rv, with the library they were found in
+    provided_packages: Option<&'d HashMap<String, (PathBuf, Package)>>,
+    /// Packages declared as provided in the config: they are never looked up anywhere else
+    declared_provided: HashSet<&'d str>,
     show_progress_bar: bool,
+    /// Print every package as it is resolved, along with where it comes from
+    resolve_verbose: bool,
 }
 
 impl<'d> Resolver<'d> {
@@ -127,9 +139,14 @@ impl<'d> Resolver<'d> {
             repo_urls,
             r_version,
             lockfile,
+            stale_in_lockfile: HashSet::new(),
+            package_repositories: None,
             builtin_packages,
             packages_env_vars,
+            provided_packages: None,
+            declared_provided: HashSet::new(),
             show_progress_bar: false,
+            resolve_verbose: false,
         }
     }
 
@@ -137,6 +154,39 @@ impl<'d> Resolver<'d> {
         self.show_progress_bar = true;
     }
 
+    pub fn resolve_verbose(&mut self) {
+        self.resolve_verbose = true;
+    }
+
+    pub fn set_provided_packages(
+        &mut self,
+        provided_packages: &'d HashMap<String, (PathBuf, Package)>,
+    ) {
+        self.provided_packages = Some(provided_packages);
+    }
+
+    pub fn set_declared_provided(&mut self, names: &'d [String]) {
+        self.declared_provided = names.iter().map(|x| x.as_str()).collect();
+    }
+
+    pub fn set_package_repositories(&mut self, package_repositories: &'d HashMap<String, String>) {
+        self.package_repositories = Some(package_repositories);
+    }
+
+    /// The repository the item has to come from: the one set on the dependency in the config
+    /// or the one its name is routed to
+    fn required_repository(&self, item: &QueueItem<'d>) -> Option<&'d str> {
+        item.dep
+            .and_then(|c| c.r_repository())
+            .or_else(|| find_package_repository(self.package_repositories?, &item.name))
+    }
+
+    /// The lockfile entries for those packages are ignored, see
+    /// [`Lockfile::packages_with_stale_repository`]
+    pub fn set_stale_in_lockfile(&mut self, names: HashSet<&'d str>) {
+        self.stale_in_lockfile = names;
+    }
+
     fn local_lookup(
         &self,
         item: &QueueItem<'d>,
@@ -197,6 +247,9 @@ impl<'d> Resolver<'d> {
         {
             return None;
         }
+        if self.stale_in_lockfile.contains(item.name.as_ref()) {
+            return None;
+        }
 
         if let Some(package) = self
             .lockfile
@@ -208,15 +261,26 @@ impl<'d> Resolver<'d> {
                 return None;
             }
 
+            // Provided packages are always looked up from their library, which may have changed
+            if package.source.is_provided() {
+                return None;
+            }
+
+            // The package is now routed to another repository
+            if let Source::Repository { repository } = &package.source
+                && self
+                    .required_repository(item)
+                    .is_some_and(|r| r != repository.as_str())
+            {
+                return None;
+            }
+
             if let Some(req) = &item.version_requirement
                 && !req.is_satisfied(&Version::from_str(&package.version).unwrap())
             {
                 return None;
             }
 
-            let installation_status =
-                cache.get_installation_status(&item.name, &package.version, &package.source);
-
             // We search first in the repo for whether we have source or binary since
             // we don't record that info in the lockfile
             let kind = if package.force_source {
@@ -263,6 +327,8 @@ impl<'d> Resolver<'d> {
                 // url/git/local are probably source packages
                 PackageType::Source
             };
+            let installation_status =
+                cache.get_installation_status(&item.name, &package.version, &package.source, kind);
             let resolved_dep =
                 ResolvedDependency::from_locked_package(package, installation_status, kind);
 
@@ -284,12 +350,42 @@ impl<'d> Resolver<'d> {
         }
     }
 
+    /// Whether that package from the lockfile is only available in the archive of its repository
+    fn archived_lookup(&self, dep: &ResolvedDependency<'d>) -> Option<ArchivedPackage> {
+        let Source::Repository { repository } = &dep.source else {
+            return None;
+        };
+        let (repo, _) = self
+            .repositories
+            .iter()
+            .find(|(repo, _)| repo.url == repository.as_str())?;
+        let version_req =
+            VersionRequirement::from_str(&format!("(== {})", dep.version.original)).unwrap();
+        if repo
+            .find_package(&dep.name, Some(&version_req), self.r_version, false)
+            .is_some()
+        {
+            return None;
+        }
+
+        Some(ArchivedPackage {
+            name: dep.name.to_string(),
+            version: dep.version.original.clone(),
+            repository: repository.to_string(),
+            latest_version: repo
+                .find_package(&dep.name, None, self.r_version, false)
+                .map(|(p, _)| p.version.original.clone()),
+            archive_url: get_source_archive_url(repository, &dep.name, &dep.version.original)
+                .to_string(),
+        })
+    }
+
     fn repositories_lookup(
         &self,
         item: &QueueItem<'d>,
         cache: &'d Cache,
     ) -> Option<(ResolvedDependency<'d>, Vec<QueueItem<'d>>)> {
-        let repository = item.dep.as_ref().and_then(|c| c.r_repository());
+        let repository = self.required_repository(item);
 
         for (repo, repo_source_only) in self.repositories {
             if let Some(r) = repository
@@ -315,6 +411,7 @@ impl<'d> Resolver<'d> {
                     &Source::Repository {
                         repository: Url::parse(&repo.url).unwrap(),
                     },
+                    package_type,
                 );
 
                 // If we have the binary but not built from source and the user asked from_source
@@ -338,6 +435,7 @@ impl<'d> Resolver<'d> {
         None
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn git_lookup(
         &self,
         item: &QueueItem<'d>,
@@ -346,6 +444,7 @@ impl<'d> Resolver<'d> {
         git_ref: GitReference,
         git_executor: &'d (impl CommandExecutor + Clone + 'static),
         cache: &'d Cache,
+        progress: &ResolutionProgress,
     ) -> Result<(ResolvedDependency<'d>, Vec<QueueItem<'d>>), Box<dyn std::error::Error>> {
         let clone_path = cache.local().get_git_clone_path(repo_url.url());
 
@@ -354,14 +453,12 @@ impl<'d> Resolver<'d> {
             remote.set_directory(d);
         }
 
-        let spinner = create_spinner(
-            self.show_progress_bar,
-            format!("Fetching DESCRIPTION file from {repo_url}#{git_ref}"),
-        );
+        progress.set_message(format!(
+            "Fetching DESCRIPTION file from {repo_url}#{git_ref}"
+        ));
 
         match remote.sparse_checkout_for_description(clone_path, &git_ref, git_executor.clone()) {
             Ok((sha, description_content)) => {
-                spinner.finish_and_clear();
                 let package = match parse_description_file(&description_content) {
                     Some(p) => p,
                     None => {
@@ -398,6 +495,7 @@ impl<'d> Resolver<'d> {
                     &package.name,
                     &package.version.original,
                     &source,
+                    PackageType::Source,
                 );
                 let (resolved_dep, deps) = ResolvedDependency::from_git_package(
                     &package,
@@ -408,7 +506,6 @@ impl<'d> Resolver<'d> {
                 Ok(prepare_deps!(resolved_dep, deps, item.matching_in_lockfile))
             }
             Err(e) => {
-                spinner.finish_and_clear();
                 Err(format!("Could not fetch repository {repo_url} (ref: {git_ref:?}) {e}").into())
             }
         }
@@ -474,6 +571,74 @@ impl<'d> Resolver<'d> {
         }
     }
 
+    /// Installing a package at the exact version shipped with R would only shadow the builtin
+    /// copy so we use the builtin one instead.
+    fn prefer_builtin(
+        &self,
+        item: &QueueItem<'d>,
+        resolved_dep: ResolvedDependency<'d>,
+        items: Vec<QueueItem<'d>>,
+    ) -> (ResolvedDependency<'d>, Vec<QueueItem<'d>>) {
+        match self.builtin_packages.get(item.name.as_ref()) {
+            Some(package)
+                if !resolved_dep.source.is_builtin()
+                    && package.version == *resolved_dep.version =>
+            {
+                let (builtin_dep, deps) =
+                    ResolvedDependency::from_builtin_package(package, item.install_suggestions);
+                prepare_deps!(builtin_dep, deps, item.matching_in_lockfile)
+            }
+            _ => (resolved_dep, items),
+        }
+    }
+
+    fn provided_lookup(
+        &self,
+        item: &QueueItem<'d>,
+    ) -> Option<(ResolvedDependency<'d>, Vec<QueueItem<'d>>)> {
+        let (library, package) = self.provided_packages?.get(item.name.as_ref())?;
+        if let Some(ref req) = item.version_requirement
+            && !req.is_satisfied(&package.version)
+        {
+            return None;
+        }
+
+        let (resolved_dep, deps) = ResolvedDependency::from_provided_package(
+            package,
+            Some(library.clone()),
+            item.install_suggestions,
+        );
+        Some(prepare_deps!(resolved_dep, deps, item.matching_in_lockfile))
+    }
+
+    /// Packages declared as provided are always satisfied. If they are found in an additional
+    /// library we use that version and its dependencies, otherwise we know nothing about them.
+    fn declared_provided_lookup(
+        &self,
+        item: &QueueItem<'d>,
+    ) -> Option<(ResolvedDependency<'d>, Vec<QueueItem<'d>>)> {
+        if !self.declared_provided.contains(item.name.as_ref()) {
+            return None;
+        }
+
+        if let Some((library, package)) = self
+            .provided_packages
+            .and_then(|p| p.get(item.name.as_ref()))
+        {
+            let (resolved_dep, deps) = ResolvedDependency::from_provided_package(
+                package,
+                Some(library.clone()),
+                item.install_suggestions,
+            );
+            return Some(prepare_deps!(resolved_dep, deps, item.matching_in_lockfile));
+        }
+
+        Some((
+            ResolvedDependency::from_declared_provided(item.name.clone()),
+            Vec::new(),
+        ))
+    }
+
     /// Tries to find all dependencies from the repos, as well as their installation status
     pub fn resolve(
         &self,
@@ -499,6 +664,13 @@ impl<'d> Resolver<'d> {
             .filter(|d| d.dependencies_only())
             .map(|d| d.name())
             .collect();
+        // Those packages are installed on their own: their dependencies are expected to be
+        // available in the environment already
+        let no_dependencies: HashSet<_> = dependencies
+            .iter()
+            .filter(|d| d.no_dependencies())
+            .map(|d| d.name())
+            .collect();
 
         // The `install_suggestions` flag needs to apply to the package coming from any sources.
         // If we don't do that, we might miss some suggested packages because a version constraint
@@ -527,7 +699,17 @@ impl<'d> Resolver<'d> {
             })
             .collect();
 
+        let mut progress = ResolutionProgress::new(self.show_progress_bar, self.resolve_verbose);
         while let Some(mut item) = queue.pop_front() {
+            progress.update(&result, queue.len());
+            if item
+                .parent
+                .as_ref()
+                .is_some_and(|p| no_dependencies.contains(p.as_ref()))
+            {
+                continue;
+            }
+
             if let Some(ver_reqs) = processed.get(item.name.as_ref()) {
                 // If we have already found that dependency and it has a forced repo, skip it
                 if repo_required.contains(item.name.as_ref()) {
@@ -544,6 +726,18 @@ impl<'d> Resolver<'d> {
                 item.install_suggestions = true;
             }
 
+            // Packages declared as provided are satisfied by the runtime, whatever the source
+            // or version requirement
+            if let Some((resolved_dep, items)) = self.declared_provided_lookup(&item) {
+                processed
+                    .entry(resolved_dep.name.to_string())
+                    .or_default()
+                    .insert(item.version_requirement.clone());
+                result.add_found(resolved_dep);
+                queue.extend(items);
+                continue;
+            }
+
             // If we have a local path, we don't need to check anything at all, just the actual path
             if item.local_path.is_some() {
                 match self.local_lookup(&item) {
@@ -563,8 +757,27 @@ impl<'d> Resolver<'d> {
                 continue;
             }
 
+            // Packages available in a library not managed by rv take precedence over anything
+            // else, unless the package is explicitly required from a repository
+            if !item.has_required_repo()
+                && let Some((resolved_dep, items)) = self.provided_lookup(&item)
+            {
+                processed
+                    .entry(resolved_dep.name.to_string())
+                    .or_default()
+                    .insert(item.version_requirement.clone());
+                result.add_found(resolved_dep);
+                queue.extend(items);
+                continue;
+            }
+
             // First we look at the lockfile and trust what is inside
             if let Some((resolved_dep, items)) = self.lockfile_lookup(&item, cache) {
+                let (resolved_dep, items) = self.prefer_builtin(&item, resolved_dep, items);
+                if let Some(archived) = self.archived_lookup(&resolved_dep) {
+                    log::debug!("{} {} is archived", archived.name, archived.version);
+                    result.archived.push(archived);
+                }
                 processed
                     .entry(resolved_dep.name.to_string())
                     .or_default()
@@ -626,6 +839,7 @@ impl<'d> Resolver<'d> {
                                 .unwrap_or(GitReference::Unknown("HEAD")),
                             git_exec,
                             cache,
+                            &progress,
                         ) {
                             Ok((mut resolved_dep, items)) => {
                                 // TODO: do we want to keep track of the remote string?
@@ -669,6 +883,7 @@ impl<'d> Resolver<'d> {
                         continue;
                     }
                     if let Some((resolved_dep, items)) = self.repositories_lookup(&item, cache) {
+                        let (resolved_dep, items) = self.prefer_builtin(&item, resolved_dep, items);
                         result.add_found(resolved_dep);
                         queue.extend(items);
                     } else {
@@ -723,6 +938,7 @@ impl<'d> Resolver<'d> {
                         git_ref,
                         git_exec,
                         cache,
+                        &progress,
                     ) {
                         Ok((resolved_dep, items)) => {
                             result.add_found(resolved_dep);
@@ -738,10 +954,23 @@ impl<'d> Resolver<'d> {
             }
         }
 
+        progress.update(&result, 0);
+
         for name in dependencies_only {
             result.ignore(name);
         }
 
+        // Only keep the dependencies that something else brought in so they are installed first
+        let found_names: HashSet<_> = result.found.iter().map(|d| d.name.to_string()).collect();
+        for dep in result
+            .found
+            .iter_mut()
+            .filter(|d| no_dependencies.contains(d.name.as_ref()))
+        {
+            dep.dependencies.retain(|d| found_names.contains(d.name()));
+            dep.suggests.retain(|d| found_names.contains(d.name()));
+        }
+
         for dep in result.found.iter_mut() {
             if let Some(args) = self.packages_env_vars.get(dep.name.as_ref()) {
                 dep.env_vars = args.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect();
@@ -751,7 +980,8 @@ impl<'d> Resolver<'d> {
         // We might get in a situation where something has been resolved but is not actually needed anymore
         // because the package it was coming from has been replaced by a different version in the resolution.
         let roots: HashSet<_> = dependencies.iter().map(|d| d.name()).collect();
-        result.finalize(&roots);
+        result.finalize(&roots, &self.declared_provided);
+        progress.finish(&result);
         result
     }
 }
@@ -760,9 +990,7 @@ impl<'d> Resolver<'d> {
 mod tests {
     use super::*;
     use std::collections::HashMap;
-    use std::io::Write;
     use std::path::{Path, PathBuf};
-    use std::process::Command;
     use std::str::FromStr;
 
     use serde::Deserialize;
@@ -771,52 +999,9 @@ mod tests {
     use crate::SystemInfo;
     use crate::config::Config;
     use crate::consts::{BASE_PACKAGES, DESCRIPTION_FILENAME};
-    use crate::http::HttpError;
     use crate::package::{Package, parse_package_file};
     use crate::repository::RepositoryDatabase;
-
-    #[derive(Clone)]
-    struct FakeGit;
-
-    impl CommandExecutor for FakeGit {
-        fn execute(&self, _: &mut Command) -> Result<String, std::io::Error> {
-            Ok("somethinglikeasha".to_string())
-        }
-    }
-
-    struct FakeHttp;
-
-    impl HttpDownload for FakeHttp {
-        fn download<W: Write>(
-            &self,
-            url: &Url,
-            w: &mut W,
-            _: Vec<(&str, String)>,
-        ) -> Result<u64, HttpError> {
-            // if its an api query, we return the api string
-            if url.as_str().contains("r-universe.dev/api") {
-                let path = format!(
-                    "src/tests/r_universe/{}.api",
-                    url.as_str().split('/').next_back().unwrap_or("")
-                );
-                let content = fs::read_to_string(path).unwrap();
-
-                w.write_all(content.as_bytes())
-                    .map_err(|e| HttpError::from_io(url.as_str(), e))?;
-            }
-            Ok(0)
-        }
-
-        fn download_and_untar(
-            &self,
-            _: &Url,
-            _: impl AsRef<Path>,
-            _: bool,
-            _: Option<&Path>,
-        ) -> Result<(Option<PathBuf>, String), HttpError> {
-            Ok((None, "SOME_SHA".to_string()))
-        }
-    }
+    use crate::test_utils::{FakeGit, FakeHttp};
 
     #[derive(Debug, Deserialize)]
     struct TestRepo {
@@ -910,6 +1095,7 @@ mod tests {
             },
             Some("test.force_source"),
             Some("1.0.0"),
+            PackageType::Binary,
         );
         let binary_path = paths.binary.join("test.force_source");
         fs::create_dir_all(&binary_path).unwrap();
@@ -956,7 +1142,8 @@ mod tests {
             };
             builtin_packages.insert("MASS".to_string(), mass);
 
-            let resolver = Resolver::new(
+            let http = FakeHttp::new();
+            let mut resolver = Resolver::new(
                 Path::new("."),
                 &repositories,
                 repositories.iter().map(|(x, _)| x.url.as_str()).collect(),
@@ -965,13 +1152,15 @@ mod tests {
                 Some(&lockfile),
                 config.packages_env_vars(),
             );
+            resolver.set_declared_provided(config.provided());
+            resolver.set_package_repositories(config.package_repositories());
 
             let resolution = resolver.resolve(
                 config.dependencies(),
                 config.prefer_repositories_for(),
                 &cache,
                 &FakeGit {},
-                &FakeHttp {},
+                &http,
             );
             // let new_lockfile = Lockfile::from_resolved(&r_version.major_minor(), resolution.found.clone());
             // println!("{}", new_lockfile.as_toml_string());
@@ -1013,4 +1202,50 @@ mod tests {
             insta::assert_snapshot!(p.file_name().unwrap().to_string_lossy().to_string(), out);
         }
     }
+
+    #[test]
+    fn provided_version_mismatch_is_a_warning() {
+        let dbs = HashMap::from([(
+            "cran-binary".to_string(),
+            parse_package_file(
+                &std::fs::read_to_string("src/tests/package_files/cran-binary.PACKAGE").unwrap(),
+            ),
+        )]);
+        let (config, r_version, repositories, lockfile) =
+            extract_test_elements(Path::new("src/tests/resolution/provided.txt"), &dbs);
+        let (_cache_dir, cache) = setup_cache(&r_version);
+        let builtin_packages = HashMap::new();
+        let rlang = Package {
+            name: "rlang".to_string(),
+            version: Version::from_str("1.0.0").unwrap(),
+            ..Default::default()
+        };
+        let provided_packages =
+            HashMap::from([("rlang".to_string(), (PathBuf::from("/site-library"), rlang))]);
+
+        let http = FakeHttp::new();
+        let mut resolver = Resolver::new(
+            Path::new("."),
+            &repositories,
+            repositories.iter().map(|(x, _)| x.url.as_str()).collect(),
+            &r_version,
+            &builtin_packages,
+            Some(&lockfile),
+            config.packages_env_vars(),
+        );
+        resolver.set_provided_packages(&provided_packages);
+        resolver.set_declared_provided(config.provided());
+
+        let resolution = resolver.resolve(
+            config.dependencies(),
+            config.prefer_repositories_for(),
+            &cache,
+            &FakeGit {},
+            &http,
+        );
+        assert!(resolution.is_success());
+        let messages = resolution.provided_warning_messages();
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].starts_with("rlang is provided at version 1.0.0 but "));
+    }
 }
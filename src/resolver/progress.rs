@@ -0,0 +1,141 @@
+use std::time::Instant;
+
+use indicatif::ProgressBar;
+
+use crate::events::{self, Event, Task, TaskResult};
+use crate::resolver::Resolution;
+use crate::utils::create_spinner;
+
+/// Reports how far along the resolution is: on the spinner, as events, and by printing every
+/// package as it is resolved if `verbose` is set.
+/// The number of packages to resolve is not known in advance so we report what has been resolved
+/// and how many items are still waiting in the queue.
+pub(crate) struct ResolutionProgress {
+    spinner: ProgressBar,
+    task: Task,
+    start: Instant,
+    verbose: bool,
+    reported_found: usize,
+    reported_failed: usize,
+}
+
+impl ResolutionProgress {
+    pub(crate) fn new(show_progress_bar: bool, verbose: bool) -> Self {
+        let task = Task::new("resolve", "Resolving dependencies");
+        events::emit(&Event::TaskStarted { task: task.clone() });
+        Self {
+            spinner: create_spinner(show_progress_bar, "Resolving dependencies..."),
+            task,
+            start: Instant::now(),
+            verbose,
+            reported_found: 0,
+            reported_failed: 0,
+        }
+    }
+
+    /// Lookups taking a while, eg fetching a git repository, can tell what they are doing
+    pub(crate) fn set_message(&self, message: String) {
+        self.spinner.set_message(message);
+    }
+
+    /// To call before processing an item of the queue, `pending` being the number of items left
+    /// in it. Nothing is reported if nothing was resolved since the last call.
+    pub(crate) fn update(&mut self, result: &Resolution, pending: usize) {
+        let lines = self.new_lines(result);
+        if lines.is_empty() {
+            return;
+        }
+        if self.verbose {
+            self.spinner.suspend(|| {
+                for line in &lines {
+                    eprintln!("{line}");
+                }
+            });
+        }
+
+        self.spinner.set_message(format!(
+            "Resolving dependencies ({} resolved, {pending} queued)",
+            result.found.len()
+        ));
+        events::emit(&Event::TaskProgress {
+            task: self.task.clone(),
+            completed: self.reported_found + self.reported_failed,
+            pending,
+        });
+    }
+
+    /// `result` is expected to be finalized, after the last call to `update`
+    pub(crate) fn finish(self, result: &Resolution) {
+        self.spinner.finish_and_clear();
+        events::emit(&Event::TaskFinished {
+            task: self.task,
+            result: if result.is_success() {
+                TaskResult::Ok
+            } else {
+                TaskResult::Failed
+            },
+            time_ms: self.start.elapsed().as_millis() as u64,
+        });
+    }
+
+    /// A line for each package found or failed since the last call, with where it came from
+    fn new_lines(&mut self, result: &Resolution) -> Vec<String> {
+        let mut lines = Vec::new();
+        for dep in &result.found[self.reported_found..] {
+            // Packages declared as provided don't have a version
+            let version = if dep.version.original.is_empty() {
+                String::new()
+            } else {
+                format!(" {}", dep.version.original)
+            };
+            lines.push(format!(
+                "Resolved {}{version} from {}{}",
+                dep.name,
+                dep.source.to_string().trim(),
+                if dep.from_lockfile { " (lockfile)" } else { "" },
+            ));
+        }
+        for dep in &result.failed[self.reported_failed..] {
+            lines.push(format!("Failed to resolve {dep}"));
+        }
+        self.reported_found = result.found.len();
+        self.reported_failed = result.failed.len();
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resolver::QueueItem;
+    use crate::{ResolvedDependency, UnresolvedDependency};
+    use std::borrow::Cow;
+
+    #[test]
+    fn only_reports_new_packages() {
+        let mut progress = ResolutionProgress::new(false, true);
+        let mut result = Resolution::default();
+        result.add_found(ResolvedDependency::from_declared_provided(Cow::Borrowed(
+            "a",
+        )));
+        assert_eq!(
+            progress.new_lines(&result),
+            vec!["Resolved a from provided"]
+        );
+        assert!(progress.new_lines(&result).is_empty());
+
+        let item = QueueItem {
+            name: Cow::Borrowed("missing"),
+            parent: Some(Cow::Borrowed("a")),
+            ..Default::default()
+        };
+        result
+            .failed
+            .push(UnresolvedDependency::from_item(&item).with_error("not found".to_string()));
+        assert_eq!(
+            progress.new_lines(&result),
+            vec!["Failed to resolve missing [required by: a]: not found"]
+        );
+        progress.finish(&result);
+    }
+}
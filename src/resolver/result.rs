@@ -1,3 +1,6 @@
+use crate::Lockfile;
+use crate::lockfile::Source;
+use crate::repository_urls::get_source_archive_url;
 use crate::resolver::sat::DependencySolver;
 use crate::{ResolvedDependency, UnresolvedDependency};
 use std::collections::{HashMap, HashSet};
@@ -15,11 +18,51 @@ impl fmt::Display for RequirementFailure {
     }
 }
 
+/// A package of the lockfile whose version is not in its repository anymore: a newer version
+/// replaced it or the package itself was archived, eg orphaned on CRAN.
+/// It can still be installed from the archive of the repository.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArchivedPackage {
+    pub name: String,
+    pub version: String,
+    pub repository: String,
+    /// The version currently available in the repository, if the package is still in it
+    pub latest_version: Option<String>,
+    pub archive_url: String,
+}
+
+impl fmt::Display for ArchivedPackage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} is archived on {}, it will be installed from {}. ",
+            self.name, self.version, self.repository, self.archive_url
+        )?;
+        match &self.latest_version {
+            Some(latest) => write!(
+                f,
+                "Version {latest} is available, run `rv upgrade` to use it"
+            ),
+            None => write!(
+                f,
+                "The package is not available there anymore: consider replacing it or \
+                 pinning it with {{ name = \"{}\", url = \"{}\" }}",
+                self.name, self.archive_url
+            ),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Default)]
 pub struct Resolution<'d> {
     pub found: Vec<ResolvedDependency<'d>>,
     pub failed: Vec<UnresolvedDependency<'d>>,
     pub req_failures: HashMap<String, Vec<RequirementFailure>>,
+    /// Requirements on packages declared as provided that the version we detected doesn't satisfy.
+    /// Those are not errors since we can't install them anyway.
+    pub provided_mismatches: HashMap<String, Vec<RequirementFailure>>,
+    /// Packages coming from the lockfile that are only available in the archive of their repository
+    pub archived: Vec<ArchivedPackage>,
 }
 
 impl<'d> Resolution<'d> {
@@ -46,7 +89,7 @@ impl<'d> Resolution<'d> {
         }
     }
 
-    pub fn finalize(&mut self, roots: &HashSet<&str>) {
+    pub fn finalize(&mut self, roots: &HashSet<&str>, provided: &HashSet<&str>) {
         // First we go through the failed dependencies to see if something that would match was found
         // (for example it can happen if someone puts a dep in a git package and specify that dep
         // directly in rproject.toml instead of remotes)
@@ -70,9 +113,28 @@ impl<'d> Resolution<'d> {
             self.failed.remove(i);
         }
 
+        // Packages declared as provided are outside the solver: we can't pick another version
+        // so we only warn if we know their version and it doesn't match
+        let provided_versions: HashMap<_, _> = self
+            .found
+            .iter()
+            .filter(|p| provided.contains(p.name.as_ref()))
+            .map(|p| {
+                let detected = matches!(
+                    p.source,
+                    Source::Provided {
+                        library: Some(_),
+                        ..
+                    }
+                );
+                (p.name.as_ref(), detected.then_some(p.version.as_ref()))
+            })
+            .collect();
+        let mut provided_mismatches: HashMap<String, Vec<RequirementFailure>> = HashMap::new();
+
         let mut solver = DependencySolver::default();
         for package in &self.found {
-            if !package.ignored {
+            if !package.ignored && !provided_versions.contains_key(package.name.as_ref()) {
                 solver.add_package(&package.name, &package.version);
             }
 
@@ -86,10 +148,25 @@ impl<'d> Resolution<'d> {
 
             for dep in deps {
                 if let Some(req) = dep.version_requirement() {
+                    if let Some(version) = provided_versions.get(dep.name()) {
+                        if let Some(version) = version
+                            && !req.is_satisfied(version)
+                        {
+                            provided_mismatches
+                                .entry(dep.name().to_string())
+                                .or_default()
+                                .push(RequirementFailure {
+                                    required_by: package.name.to_string(),
+                                    version_req: req.to_string(),
+                                });
+                        }
+                        continue;
+                    }
                     solver.add_requirement(dep.name(), req, &package.name);
                 }
             }
         }
+        self.provided_mismatches = provided_mismatches;
 
         // If we have a different number of packages that means we have
         match solver.solve() {
@@ -105,8 +182,8 @@ impl<'d> Resolution<'d> {
                             names.insert(&pkg.name);
                             indices.insert(i);
                         }
-                    } else if pkg.ignored {
-                        // We still insert ignored packages
+                    } else if pkg.ignored || provided_versions.contains_key(pkg.name.as_ref()) {
+                        // We still insert ignored and provided packages
                         names.insert(&pkg.name);
                         indices.insert(i);
                     }
@@ -139,6 +216,11 @@ impl<'d> Resolution<'d> {
                     }
                 }
                 self.found.retain(|p| reachable.contains(p.name.as_ref()));
+                self.archived.retain(|a| {
+                    self.found
+                        .iter()
+                        .any(|p| p.name == a.name.as_str() && p.version.original == a.version)
+                });
             }
             Err(req_errors) => {
                 let mut out = HashMap::new();
@@ -155,6 +237,22 @@ impl<'d> Resolution<'d> {
         }
     }
 
+    /// Packages of the lockfile that can't be found at all were most likely archived, which is
+    /// otherwise a confusing failure. The locked version can still be installed from the archive.
+    pub(crate) fn explain_archived_failures(&mut self, lockfile: &Lockfile) {
+        for failed in self.failed.iter_mut().filter(|f| f.error.is_none()) {
+            if let Some(package) = lockfile.get_package(&failed.name, None)
+                && let Source::Repository { repository } = &package.source
+            {
+                failed.error = Some(format!(
+                    "not found in the repositories, it was probably archived. The locked version {} is available at {}",
+                    package.version,
+                    get_source_archive_url(repository, &package.name, &package.version)
+                ));
+            }
+        }
+    }
+
     pub fn is_success(&self) -> bool {
         self.failed.is_empty() && self.req_failures.is_empty()
     }
@@ -173,6 +271,37 @@ impl<'d> Resolution<'d> {
         }
     }
 
+    /// Warnings for provided packages whose detected version doesn't satisfy their dependents
+    pub fn provided_warning_messages(&self) -> Vec<String> {
+        let mut messages: Vec<_> = self
+            .provided_mismatches
+            .iter()
+            .map(|(name, reqs)| {
+                let version = self
+                    .found
+                    .iter()
+                    .find(|f| f.name == name.as_str())
+                    .map(|x| x.version.original.as_str())
+                    .unwrap_or_default();
+                let reqs_msg = reqs
+                    .iter()
+                    .map(|x| x.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                format!("{name} is provided at version {version} but {reqs_msg}")
+            })
+            .collect();
+        messages.sort();
+        messages
+    }
+
+    /// Warnings for locked packages that are only available in the archive of their repository
+    pub fn archived_warning_messages(&self) -> Vec<String> {
+        let mut messages: Vec<_> = self.archived.iter().map(|a| a.to_string()).collect();
+        messages.sort();
+        messages
+    }
+
     pub fn req_error_messages(&self) -> Vec<String> {
         self.req_failures
             .iter()
@@ -1,30 +1,68 @@
+use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
 
 /// R environment variables to remove before spawning Rscript.
 /// R_LIBS is cleared so only the project library is used.
 const R_ENV_VARS_TO_REMOVE: &[&str] = &["R_LIBS", "R_INCLUDE_DIR", "R_SHARE_DIR", "R_DOC_DIR"];
 
-/// Run `Rscript` with the given arguments and the project library paths configured.
-pub fn run(r_bin_path: &Path, library_path: &Path, args: &[String]) -> Result<i32, RunError> {
+/// Marker printed by the smoke test script for each package that fails to load
+const LOAD_FAILED_MARKER: &str = "RV_LOAD_FAILED";
+
+/// Loads each package given as argument in order and reports the ones failing, without stopping
+const SMOKE_TEST_SCRIPT: &str = r#"for (pkg in commandArgs(trailingOnly = TRUE)) {
+  err <- tryCatch({ loadNamespace(pkg); NULL }, error = function(e) conditionMessage(e))
+  if (!is.null(err)) cat("RV_LOAD_FAILED", pkg, paste0(gsub("[\r\n]+", " ", err), "\n"), sep = "\t")
+}"#;
+
+/// Build an `Rscript` command with the project library paths and environment variables configured.
+/// The additional libraries are added after the project library, in order.
+/// The project environment variables cannot override the library paths.
+pub(crate) fn rscript_command(
+    r_bin_path: &Path,
+    library_path: &Path,
+    additional_libraries: &[PathBuf],
+    env_vars: &BTreeMap<String, String>,
+) -> Result<(PathBuf, std::process::Command), RunError> {
     let r_home = crate::r_cmd::get_r_home(r_bin_path).map_err(|source| RunError::RHome {
         path: r_bin_path.to_path_buf(),
         source,
     })?;
     let rscript = resolve_rscript_path(&r_home, r_bin_path);
+    let user_libs = std::env::join_paths(
+        std::iter::once(library_path).chain(additional_libraries.iter().map(|p| p.as_path())),
+    )
+    .map_err(|source| RunError::LibraryPaths { source })?;
 
     let mut cmd = std::process::Command::new(&rscript);
-    cmd.args(args)
+    cmd.envs(env_vars)
         .env("R_HOME", &r_home)
-        .env("R_LIBS_USER", library_path)
-        .env("R_LIBS_SITE", library_path)
-        .stdin(std::process::Stdio::inherit())
-        .stdout(std::process::Stdio::inherit())
-        .stderr(std::process::Stdio::inherit());
+        .env("R_LIBS_USER", &user_libs)
+        .env("R_LIBS_SITE", library_path);
 
     for var in R_ENV_VARS_TO_REMOVE {
         cmd.env_remove(var);
     }
 
+    Ok((rscript, cmd))
+}
+
+/// Run `Rscript` with the given arguments and the project library paths and environment variables
+/// configured.
+/// The additional libraries are added after the project library, in order.
+pub fn run(
+    r_bin_path: &Path,
+    library_path: &Path,
+    additional_libraries: &[PathBuf],
+    env_vars: &BTreeMap<String, String>,
+    args: &[String],
+) -> Result<i32, RunError> {
+    let (rscript, mut cmd) =
+        rscript_command(r_bin_path, library_path, additional_libraries, env_vars)?;
+    cmd.args(args)
+        .stdin(std::process::Stdio::inherit())
+        .stdout(std::process::Stdio::inherit())
+        .stderr(std::process::Stdio::inherit());
+
     let status = cmd.status().map_err(|source| RunError::Spawn {
         path: rscript,
         source,
@@ -33,6 +71,65 @@ pub fn run(r_bin_path: &Path, library_path: &Path, args: &[String]) -> Result<i3
     Ok(status.code().unwrap_or(1))
 }
 
+/// A package that could not be loaded after being installed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoadFailure {
+    pub package: String,
+    pub error: String,
+}
+
+fn parse_load_failures(output: &str) -> Vec<LoadFailure> {
+    output
+        .lines()
+        .filter_map(|l| l.strip_prefix(LOAD_FAILED_MARKER)?.strip_prefix('\t'))
+        .filter_map(|l| {
+            let (package, error) = l.split_once('\t')?;
+            Some(LoadFailure {
+                package: package.to_string(),
+                error: error.trim().to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Launch R once and `loadNamespace()` each package in the given order, which should be the
+/// dependency order so the root cause of a failure is reported first.
+/// This catches issues that only show up at load time, eg missing system libraries.
+pub fn smoke_test(
+    r_bin_path: &Path,
+    library_path: &Path,
+    additional_libraries: &[PathBuf],
+    env_vars: &BTreeMap<String, String>,
+    packages: &[&str],
+) -> Result<Vec<LoadFailure>, RunError> {
+    if packages.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let (rscript, mut cmd) =
+        rscript_command(r_bin_path, library_path, additional_libraries, env_vars)?;
+    cmd.args(["--vanilla", "-e", SMOKE_TEST_SCRIPT])
+        .args(packages)
+        .stdin(std::process::Stdio::null());
+
+    let output = cmd.output().map_err(|source| RunError::Spawn {
+        path: rscript.clone(),
+        source,
+    })?;
+
+    // The script catches load errors itself so a failure means R didn't even get to run it
+    if !output.status.success() {
+        return Err(RunError::Failed {
+            path: rscript,
+            stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        });
+    }
+
+    Ok(parse_load_failures(&String::from_utf8_lossy(
+        &output.stdout,
+    )))
+}
+
 fn resolve_rscript_path(r_home: &Path, r_bin_path: &Path) -> PathBuf {
     let mut rscript = r_home.join("bin").join("Rscript");
 
@@ -54,6 +151,10 @@ pub enum RunError {
         path: PathBuf,
         source: std::io::Error,
     },
+    #[error("Rscript at {path} failed: {stderr}")]
+    Failed { path: PathBuf, stderr: String },
+    #[error("Invalid library path: {source}")]
+    LibraryPaths { source: std::env::JoinPathsError },
     #[error("Failed to determine R_HOME from {path}: {source}")]
     RHome {
         path: PathBuf,
@@ -63,9 +164,21 @@ pub enum RunError {
 
 #[cfg(test)]
 mod tests {
-    use super::resolve_rscript_path;
+    use super::{LoadFailure, parse_load_failures, resolve_rscript_path};
     use std::path::PathBuf;
 
+    #[test]
+    fn can_parse_load_failures() {
+        let output = "Loading required package: stats\nRV_LOAD_FAILED\tsf\tunable to load shared object 'sf.so': libgdal.so.30: cannot open shared object file \n";
+        assert_eq!(
+            parse_load_failures(output),
+            vec![LoadFailure {
+                package: "sf".to_string(),
+                error: "unable to load shared object 'sf.so': libgdal.so.30: cannot open shared object file".to_string(),
+            }]
+        );
+    }
+
     #[test]
     fn resolve_rscript_from_r_home_when_r_has_no_parent() {
         let r_home = PathBuf::from("/opt/R/4.5.0/lib/R");
@@ -0,0 +1,363 @@
+//! Measures how long R takes to start and load the dependencies of the project, keeping a
+//! history in `rv/startup-bench.json` to notice when a sync made it slower, eg for Shiny apps
+//! where cold starts matter.
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use fs_err as fs;
+use serde::{Deserialize, Serialize};
+
+use crate::Context;
+use crate::cache::utils::hash_string;
+use crate::run::{RunError, rscript_command};
+use crate::utils::format_duration;
+
+/// Marker printed by the script for each package with the time it took to load, or NA
+const TIMING_MARKER: &str = "RV_STARTUP";
+
+/// Attaches each package given as argument in order, printing how long each took.
+/// Dependencies already loaded by a previous package are not counted again.
+const TIMING_SCRIPT: &str = r#"for (pkg in commandArgs(trailingOnly = TRUE)) {
+  ok <- TRUE
+  t <- system.time(tryCatch(library(pkg, character.only = TRUE), error = function(e) ok <<- FALSE))
+  cat("RV_STARTUP", pkg, if (ok) t[["elapsed"]] else "NA", "\n", sep = "\t")
+}"#;
+
+/// How many runs to keep in the history
+const MAX_HISTORY: usize = 50;
+/// Differences smaller than that are noise
+const MIN_REGRESSION: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PackageStartup {
+    pub name: String,
+    pub load_ms: u64,
+}
+
+/// The median of several runs of R loading the root dependencies of the project
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StartupBench {
+    pub timestamp: String,
+    pub r_version: String,
+    /// Identifies the content of the library, to find the runs made before the last sync
+    pub library_key: String,
+    pub runs: usize,
+    /// From launching R to having loaded every package
+    pub total_ms: u64,
+    pub packages: Vec<PackageStartup>,
+}
+
+impl StartupBench {
+    pub fn new(
+        context: &Context,
+        timestamp: String,
+        runs: usize,
+        total: Duration,
+        packages: Vec<(String, Duration)>,
+    ) -> Self {
+        let mut installed: Vec<_> = context
+            .library
+            .packages
+            .iter()
+            .map(|(name, version)| format!("{name}={}", version.original))
+            .collect();
+        installed.sort_unstable();
+        Self {
+            timestamp,
+            r_version: context.r_version.original.clone(),
+            library_key: hash_string(&installed.join("\n")),
+            runs,
+            total_ms: total.as_millis() as u64,
+            packages: packages
+                .into_iter()
+                .map(|(name, time)| PackageStartup {
+                    name,
+                    load_ms: time.as_millis() as u64,
+                })
+                .collect(),
+        }
+    }
+
+    pub fn total(&self) -> Duration {
+        Duration::from_millis(self.total_ms)
+    }
+
+    fn package(&self, name: &str) -> Option<Duration> {
+        self.packages
+            .iter()
+            .find(|p| p.name == name)
+            .map(|p| Duration::from_millis(p.load_ms))
+    }
+
+    /// How much slower than `baseline` this run is, if it is slower by more than `threshold`
+    /// percent
+    pub fn compare(&self, baseline: &StartupBench, threshold: f64) -> Option<StartupRegression> {
+        let is_regression = |before: Duration, after: Duration| {
+            after > before + MIN_REGRESSION
+                && after.as_secs_f64() > before.as_secs_f64() * (1.0 + threshold / 100.0)
+        };
+        if !is_regression(baseline.total(), self.total()) {
+            return None;
+        }
+
+        let packages = self
+            .packages
+            .iter()
+            .filter_map(|p| {
+                let after = Duration::from_millis(p.load_ms);
+                let before = baseline.package(&p.name).unwrap_or_default();
+                is_regression(before, after).then(|| (p.name.clone(), before, after))
+            })
+            .collect();
+        Some(StartupRegression {
+            baseline_timestamp: baseline.timestamp.clone(),
+            before: baseline.total(),
+            after: self.total(),
+            packages,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StartupRegression {
+    pub baseline_timestamp: String,
+    pub before: Duration,
+    pub after: Duration,
+    /// The packages that got slower to load, with their time before and after
+    pub packages: Vec<(String, Duration, Duration)>,
+}
+
+impl std::fmt::Display for StartupRegression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let change = |before: Duration, after: Duration| {
+            if before.is_zero() {
+                format!("{} (new)", format_duration(after))
+            } else {
+                format!(
+                    "{} -> {} (+{:.0}%)",
+                    format_duration(before),
+                    format_duration(after),
+                    (after.as_secs_f64() / before.as_secs_f64() - 1.0) * 100.0
+                )
+            }
+        };
+        write!(
+            f,
+            "Startup time regressed since {}: {}",
+            self.baseline_timestamp,
+            change(self.before, self.after)
+        )?;
+        for (name, before, after) in &self.packages {
+            write!(f, "\n  {name}: {}", change(*before, *after))?;
+        }
+        Ok(())
+    }
+}
+
+/// The previous runs of `rv bench startup`, oldest first
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct StartupHistory {
+    pub runs: Vec<StartupBench>,
+}
+
+impl StartupHistory {
+    /// A missing or unreadable history is considered empty
+    pub fn load(path: impl AsRef<Path>) -> Self {
+        fs::read_to_string(path.as_ref())
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), std::io::Error> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(
+            path,
+            serde_json::to_string_pretty(self).expect("valid json"),
+        )
+    }
+
+    pub fn add(&mut self, bench: StartupBench) {
+        self.runs.push(bench);
+        if self.runs.len() > MAX_HISTORY {
+            self.runs.drain(..self.runs.len() - MAX_HISTORY);
+        }
+    }
+
+    /// The last run with the same R version but a different library, ie before the last sync
+    /// that changed it
+    pub fn baseline(&self, bench: &StartupBench) -> Option<&StartupBench> {
+        self.runs
+            .iter()
+            .rev()
+            .find(|b| b.r_version == bench.r_version && b.library_key != bench.library_key)
+    }
+}
+
+/// The time to load each package, `None` if it failed
+type PackageTimings = Vec<(String, Option<Duration>)>;
+
+/// A single launch of R: the total time and the time to load each package
+fn run_once(
+    r_bin_path: &Path,
+    library_path: &Path,
+    additional_libraries: &[PathBuf],
+    env_vars: &BTreeMap<String, String>,
+    packages: &[&str],
+) -> Result<(Duration, PackageTimings), RunError> {
+    let (rscript, mut cmd) =
+        rscript_command(r_bin_path, library_path, additional_libraries, env_vars)?;
+    cmd.args(["--vanilla", "-e", TIMING_SCRIPT])
+        .args(packages)
+        .stdin(std::process::Stdio::null());
+
+    let start = Instant::now();
+    let output = cmd.output().map_err(|source| RunError::Spawn {
+        path: rscript,
+        source,
+    })?;
+    Ok((
+        start.elapsed(),
+        parse_timings(&String::from_utf8_lossy(&output.stdout)),
+    ))
+}
+
+fn parse_timings(output: &str) -> PackageTimings {
+    output
+        .lines()
+        .filter_map(|l| l.strip_prefix(TIMING_MARKER)?.strip_prefix('\t'))
+        .filter_map(|l| {
+            let mut parts = l.split('\t');
+            let name = parts.next()?;
+            let secs = parts.next()?.trim().parse::<f64>().ok();
+            Some((name.to_string(), secs.map(Duration::from_secs_f64)))
+        })
+        .collect()
+}
+
+fn median(mut values: Vec<Duration>) -> Duration {
+    values.sort_unstable();
+    values.get(values.len() / 2).copied().unwrap_or_default()
+}
+
+/// Launches R `runs` times, each time attaching the packages in order, and keeps the median of
+/// the timings. Returns the packages that could not be loaded as an error.
+pub fn bench_startup(
+    r_bin_path: &Path,
+    library_path: &Path,
+    additional_libraries: &[PathBuf],
+    env_vars: &BTreeMap<String, String>,
+    packages: &[&str],
+    runs: usize,
+) -> Result<(Duration, Vec<(String, Duration)>), StartupBenchError> {
+    let mut totals = Vec::with_capacity(runs);
+    let mut by_package: BTreeMap<String, Vec<Duration>> = BTreeMap::new();
+    for _ in 0..runs.max(1) {
+        let (total, timings) = run_once(
+            r_bin_path,
+            library_path,
+            additional_libraries,
+            env_vars,
+            packages,
+        )?;
+        let failed: Vec<_> = packages
+            .iter()
+            .filter(|name| !timings.iter().any(|(n, t)| n == *name && t.is_some()))
+            .map(|name| name.to_string())
+            .collect();
+        if !failed.is_empty() {
+            return Err(StartupBenchError::LoadFailed(failed));
+        }
+        totals.push(total);
+        for (name, time) in timings {
+            by_package
+                .entry(name)
+                .or_default()
+                .push(time.unwrap_or_default());
+        }
+    }
+
+    let packages = packages
+        .iter()
+        .map(|name| {
+            let times = by_package.remove(*name).unwrap_or_default();
+            (name.to_string(), median(times))
+        })
+        .collect();
+    Ok((median(totals), packages))
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum StartupBenchError {
+    #[error(transparent)]
+    Run(#[from] RunError),
+    #[error("Failed to load {}", .0.join(", "))]
+    LoadFailed(Vec<String>),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bench(library_key: &str, total_ms: u64, packages: &[(&str, u64)]) -> StartupBench {
+        StartupBench {
+            timestamp: format!("run-{library_key}"),
+            r_version: "4.5.1".to_string(),
+            library_key: library_key.to_string(),
+            runs: 3,
+            total_ms,
+            packages: packages
+                .iter()
+                .map(|(name, load_ms)| PackageStartup {
+                    name: name.to_string(),
+                    load_ms: *load_ms,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn can_parse_timings() {
+        let output = "Loading required package: stats\nRV_STARTUP\tshiny\t0.412\t\nRV_STARTUP\tbroken\tNA\t\n";
+        assert_eq!(
+            parse_timings(output),
+            vec![
+                ("shiny".to_string(), Some(Duration::from_millis(412))),
+                ("broken".to_string(), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn compares_with_the_run_before_the_last_sync() {
+        let mut history = StartupHistory::default();
+        history.add(bench("a", 1000, &[("shiny", 600), ("dplyr", 200)]));
+        history.add(bench("b", 1050, &[("shiny", 620), ("dplyr", 210)]));
+
+        // Same library as the last run: compared to the one before
+        let current = bench(
+            "b",
+            1500,
+            &[("shiny", 1000), ("dplyr", 210), ("arrow", 200)],
+        );
+        let baseline = history.baseline(&current).unwrap();
+        assert_eq!(baseline.library_key, "a");
+        let regression = current.compare(baseline, 20.0).unwrap();
+        assert_eq!(
+            regression.to_string(),
+            "Startup time regressed since run-a: 1.0s -> 1.5s (+50%)\n  shiny: 600ms -> 1.0s (+67%)\n  arrow: 200ms (new)"
+        );
+
+        // Within the threshold
+        let current = bench("c", 1100, &[("shiny", 650), ("dplyr", 210)]);
+        assert!(
+            current
+                .compare(history.baseline(&current).unwrap(), 20.0)
+                .is_none()
+        );
+    }
+}
@@ -0,0 +1,168 @@
+//! Provenance of the packages compiled from source: which compilers, flags and platform were
+//! used. It is saved next to the build log so "works on machine A, segfaults on machine B"
+//! issues can be investigated.
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::process::Command;
+use std::sync::OnceLock;
+
+use fs_err as fs;
+use serde::{Deserialize, Serialize};
+
+use crate::consts::{BUILD_ENV_VARS, BUILD_INFO_FILENAME};
+use crate::{RCmd, SystemInfo};
+
+/// Compilers as configured by R to build packages
+const COMPILER_VARS: [&str; 3] = ["CC", "CXX", "FC"];
+
+#[derive(Debug, Default, Clone, Serialize)]
+struct Toolchain {
+    r_version: Option<String>,
+    /// Compiler command (eg `gcc -std=gnu2x`) to the first line of its `--version`
+    compilers: BTreeMap<String, String>,
+}
+
+/// The toolchain is the same for every package built in a given rv invocation so we only
+/// look it up once
+static TOOLCHAIN: OnceLock<Toolchain> = OnceLock::new();
+
+fn compiler_version(command: &str) -> Option<String> {
+    let program = command.split_whitespace().next()?;
+    let output = Command::new(program).arg("--version").output().ok()?;
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .next()
+        .map(|l| l.trim().to_string())
+}
+
+fn toolchain(r_cmd: &impl RCmd) -> &'static Toolchain {
+    TOOLCHAIN.get_or_init(|| {
+        let compilers = COMPILER_VARS
+            .iter()
+            .filter_map(|var| {
+                let command = r_cmd.config_value(var)?;
+                let version = compiler_version(&command).unwrap_or_default();
+                Some((format!("{var}: {command}"), version))
+            })
+            .collect();
+        Toolchain {
+            r_version: r_cmd.version().ok().flatten().map(|v| v.original),
+            compilers,
+        }
+    })
+}
+
+#[derive(Debug, Serialize)]
+struct BuildInfo<'a> {
+    rv_version: &'static str,
+    r_version: Option<&'a str>,
+    platform: &'a SystemInfo,
+    compilers: &'a BTreeMap<String, String>,
+    /// Both the package `env_vars` from the config and the relevant ones from the environment
+    env_vars: BTreeMap<String, String>,
+    configure_args: &'a [String],
+}
+
+/// The options a package was built with according to its build info file
+#[derive(Debug, Default, Deserialize)]
+pub(crate) struct RecordedBuildOptions {
+    #[serde(default)]
+    env_vars: BTreeMap<String, String>,
+    #[serde(default)]
+    configure_args: Vec<String>,
+}
+
+impl RecordedBuildOptions {
+    /// Reads the build info file next to the given build log, if there is one
+    pub(crate) fn load(log_path: &Path) -> Option<Self> {
+        let content = fs::read_to_string(log_path.with_file_name(BUILD_INFO_FILENAME)).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    /// Whether those options are the ones a project requires for that package.
+    /// The env vars picked up from the environment are only compared if the project sets them.
+    pub(crate) fn matches(
+        &self,
+        env_vars: &HashMap<&str, &str>,
+        configure_args: &[String],
+    ) -> bool {
+        if self.configure_args != configure_args {
+            return false;
+        }
+        let recorded: BTreeMap<_, _> = self
+            .env_vars
+            .iter()
+            .filter(|(k, _)| {
+                !BUILD_ENV_VARS.contains(&k.as_str()) || env_vars.contains_key(k.as_str())
+            })
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect();
+        let required: BTreeMap<_, _> = env_vars.iter().map(|(k, v)| (*k, *v)).collect();
+        recorded == required
+    }
+}
+
+/// Writes the build info file in the same folder as the build log
+pub(crate) fn save_build_info(
+    log_path: &Path,
+    r_cmd: &impl RCmd,
+    system_info: &SystemInfo,
+    env_vars: &HashMap<&str, &str>,
+    configure_args: &[String],
+) -> Result<(), std::io::Error> {
+    let toolchain = toolchain(r_cmd);
+    let mut all_env_vars: BTreeMap<_, _> = BUILD_ENV_VARS
+        .iter()
+        .filter_map(|k| std::env::var(k).ok().map(|v| (k.to_string(), v)))
+        .collect();
+    all_env_vars.extend(env_vars.iter().map(|(k, v)| (k.to_string(), v.to_string())));
+
+    let info = BuildInfo {
+        rv_version: env!("CARGO_PKG_VERSION"),
+        r_version: toolchain.r_version.as_deref(),
+        platform: system_info,
+        compilers: &toolchain.compilers,
+        env_vars: all_env_vars,
+        configure_args,
+    };
+
+    fs::write(
+        log_path.with_file_name(BUILD_INFO_FILENAME),
+        serde_json::to_string_pretty(&info).expect("valid json"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recorded(env_vars: &[(&str, &str)], configure_args: &[&str]) -> RecordedBuildOptions {
+        RecordedBuildOptions {
+            env_vars: env_vars
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            configure_args: configure_args.iter().map(|x| x.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn can_compare_build_options() {
+        let args = vec!["--with-proj-lib=/usr/lib".to_string()];
+        let env_vars = HashMap::from([("PROJ_LIB", "/usr/share/proj")]);
+
+        let same = recorded(
+            &[("PROJ_LIB", "/usr/share/proj"), ("CFLAGS", "-O2")],
+            &["--with-proj-lib=/usr/lib"],
+        );
+        assert!(same.matches(&env_vars, &args));
+        // The environment CFLAGS matter once the project sets them
+        let with_cflags = HashMap::from([("PROJ_LIB", "/usr/share/proj"), ("CFLAGS", "-O3")]);
+        assert!(!same.matches(&with_cflags, &args));
+
+        assert!(!same.matches(&env_vars, &[]));
+        assert!(!same.matches(&HashMap::new(), &args));
+        assert!(recorded(&[("CFLAGS", "-O2")], &[]).matches(&HashMap::new(), &[]));
+        assert!(!recorded(&[("PROJ_LIB", "/opt")], &[]).matches(&HashMap::new(), &[]));
+    }
+}
@@ -1,7 +1,7 @@
 use std::collections::{HashMap, HashSet, VecDeque};
 
 use crate::lockfile::Source;
-use crate::{ResolvedDependency, Version};
+use crate::{InstallConstraint, ResolvedDependency, Version};
 
 #[derive(Debug, PartialEq)]
 pub enum BuildStep<'a> {
@@ -18,6 +18,8 @@ pub struct BuildPlan<'a> {
     /// Full list of dependencies for each dependencies.
     /// The value will be updated as packages are installed to remove them from that list
     pub(crate) full_deps: HashMap<&'a str, HashSet<&'a str>>,
+    /// Packages that can't be installed at the same time as some others
+    constraints: Option<&'a HashMap<String, InstallConstraint>>,
 }
 
 impl<'a> BuildPlan<'a> {
@@ -49,9 +51,36 @@ impl<'a> BuildPlan<'a> {
             full_deps,
             installed: HashSet::new(),
             installing: HashSet::new(),
+            constraints: None,
         }
     }
 
+    pub fn set_install_constraints(&mut self, constraints: &'a HashMap<String, InstallConstraint>) {
+        self.constraints = Some(constraints);
+    }
+
+    fn constraint(&self, name: &str) -> Option<&'a InstallConstraint> {
+        self.constraints?.get(name)
+    }
+
+    fn is_serial(&self, name: &str) -> bool {
+        self.constraint(name).is_some_and(|c| c.serial)
+    }
+
+    /// Whether a package of the same exclusive group is being installed
+    fn conflicts_with_installing(&self, name: &str) -> bool {
+        let Some(group) = self
+            .constraint(name)
+            .and_then(|c| c.exclusive_group.as_deref())
+        else {
+            return false;
+        };
+        self.installing.iter().any(|other| {
+            self.constraint(other)
+                .is_some_and(|c| c.exclusive_group.as_deref() == Some(group))
+        })
+    }
+
     pub fn mark_installed(&mut self, name: &str) {
         // The lifetime for the name might be different from that struct
         let pkg = self
@@ -67,6 +96,25 @@ impl<'a> BuildPlan<'a> {
         }
     }
 
+    /// The package failed to install and won't be retried, nor will anything depending on it.
+    /// They are all considered done so the rest of the plan can go on.
+    /// Returns the packages depending on it, sorted by name.
+    pub fn mark_skipped(&mut self, name: &str) -> Vec<&'a str> {
+        let mut dependents: Vec<_> = self
+            .full_deps
+            .iter()
+            .filter(|(dep, deps)| deps.contains(name) && !self.installed.contains(*dep))
+            .map(|(dep, _)| *dep)
+            .collect();
+        dependents.sort_unstable();
+
+        self.mark_installed(name);
+        for dep in &dependents {
+            self.mark_installed(dep);
+        }
+        dependents
+    }
+
     fn is_skippable(&self, name: &str) -> bool {
         self.installed.contains(name) || self.installing.contains(name)
     }
@@ -96,21 +144,40 @@ impl<'a> BuildPlan<'a> {
             return BuildStep::Done;
         }
 
-        for (dep, _) in self.full_deps.iter().filter(|(_, v)| v.is_empty()) {
-            // Skip the ones being installed or already installed
-            if self.is_skippable(dep) {
-                continue;
-            }
-            self.installing.insert(dep);
-            return BuildStep::Install(
-                self.deps
-                    .iter()
-                    .find(|d| d.name == *dep)
-                    .expect("it should have a dep with that name"),
-            );
+        // Nothing else starts while a serial package is being installed
+        if self.installing.iter().any(|d| self.is_serial(d)) {
+            return BuildStep::Wait;
         }
 
-        BuildStep::Wait
+        // Skip the ones being installed or already installed
+        let ready: Vec<&'a str> = self
+            .full_deps
+            .iter()
+            .filter(|(dep, deps)| deps.is_empty() && !self.is_skippable(dep))
+            .map(|(dep, _)| *dep)
+            .collect();
+        // A serial package waits for the installations in progress to finish, without new
+        // ones being started in the meantime
+        let next = match ready.iter().find(|d| self.is_serial(d)) {
+            Some(serial) if self.installing.is_empty() => Some(*serial),
+            Some(_) => None,
+            None => ready
+                .into_iter()
+                .find(|d| !self.conflicts_with_installing(d)),
+        };
+
+        match next {
+            Some(dep) => {
+                self.installing.insert(dep);
+                BuildStep::Install(
+                    self.deps
+                        .iter()
+                        .find(|d| d.name == dep)
+                        .expect("it should have a dep with that name"),
+                )
+            }
+            None => BuildStep::Wait,
+        }
     }
 }
 
@@ -121,6 +188,7 @@ mod tests {
     use crate::lockfile::Source;
     use crate::package::{Dependency, PackageType};
     use std::borrow::Cow;
+    use std::collections::BTreeMap;
     use std::str::FromStr;
     use url::Url;
 
@@ -132,6 +200,7 @@ mod tests {
                 .map(|x| Cow::Owned(Dependency::Simple(x.to_string())))
                 .collect(),
             suggests: Vec::new(),
+            dependency_types: BTreeMap::new(),
             version: Cow::Owned(Version::from_str("0.1.0").unwrap()),
             source: Source::Repository {
                 repository: Url::parse("https://something.com").unwrap(),
@@ -202,4 +271,90 @@ mod tests {
         // Calling it again doesn't change anything
         assert_eq!(plan.get(), BuildStep::Done);
     }
+
+    #[test]
+    fn respects_install_constraints() {
+        let deps = vec![
+            get_resolved_dep("torch", vec![]),
+            get_resolved_dep("sf", vec![]),
+            get_resolved_dep("terra", vec![]),
+        ];
+        let constraints = HashMap::from([
+            (
+                "torch".to_string(),
+                InstallConstraint {
+                    serial: true,
+                    ..Default::default()
+                },
+            ),
+            (
+                "sf".to_string(),
+                InstallConstraint {
+                    exclusive_group: Some("gdal".to_string()),
+                    ..Default::default()
+                },
+            ),
+            (
+                "terra".to_string(),
+                InstallConstraint {
+                    exclusive_group: Some("gdal".to_string()),
+                    ..Default::default()
+                },
+            ),
+        ]);
+
+        let mut plan = BuildPlan::new(&deps);
+        plan.set_install_constraints(&constraints);
+        // torch is installed alone
+        assert_eq!(plan.get(), BuildStep::Install(&deps[0]));
+        assert_eq!(plan.get(), BuildStep::Wait);
+        plan.mark_installed("torch");
+        // sf and terra are in the same group so only one of them can be installed at a time
+        let first = match plan.get() {
+            BuildStep::Install(d) => d.name.to_string(),
+            step => panic!("Expected a package to install, got {step:?}"),
+        };
+        assert!(["sf", "terra"].contains(&first.as_str()));
+        assert_eq!(plan.get(), BuildStep::Wait);
+        plan.mark_installed(&first);
+        assert!(matches!(plan.get(), BuildStep::Install(_)));
+
+        // A serial package waits for the others being installed and nothing starts meanwhile
+        let deps = vec![
+            get_resolved_dep("torch", vec![]),
+            get_resolved_dep("E", vec![]),
+            get_resolved_dep("F", vec![]),
+        ];
+        let mut plan = BuildPlan::new(&deps);
+        plan.set_install_constraints(&constraints);
+        plan.installing.insert("E");
+        assert_eq!(plan.get(), BuildStep::Wait);
+        plan.mark_installed("E");
+        assert_eq!(plan.get(), BuildStep::Install(&deps[0]));
+        assert_eq!(plan.get(), BuildStep::Wait);
+        plan.mark_installed("torch");
+        assert_eq!(plan.get(), BuildStep::Install(&deps[2]));
+    }
+
+    #[test]
+    fn skipping_a_package_skips_its_dependents() {
+        let deps = vec![
+            get_resolved_dep("C", vec!["E"]),
+            get_resolved_dep("E", vec![]),
+            get_resolved_dep("A", vec!["C"]),
+            get_resolved_dep("J", vec![]),
+        ];
+
+        let mut plan = BuildPlan::new(&deps);
+        let step = plan.get();
+        assert!([BuildStep::Install(&deps[1]), BuildStep::Install(&deps[3])].contains(&step));
+        let step = plan.get();
+        assert!([BuildStep::Install(&deps[1]), BuildStep::Install(&deps[3])].contains(&step));
+
+        // E failing means C and A can't be installed either
+        assert_eq!(plan.mark_skipped("E"), vec!["A", "C"]);
+        assert_eq!(plan.get(), BuildStep::Wait);
+        plan.mark_installed("J");
+        assert_eq!(plan.get(), BuildStep::Done);
+    }
 }
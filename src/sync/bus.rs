@@ -0,0 +1,328 @@
+//! The sync handler only schedules installations and reports what happens to each package
+//! as a [`SyncEvent`]. Everything else reacting to it (progress bar, JSON events, logs) is a
+//! [`SyncObserver`] registered on the [`SyncBus`].
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use fs_err as fs;
+use indicatif::{ProgressBar, ProgressStyle};
+
+use crate::package::PackageType;
+use crate::r_cmd::{RCmdError, RCmdErrorKind};
+use crate::sync::changes::SyncChange;
+use crate::sync::errors::{SyncError, SyncErrorKind};
+use crate::sync::memory::MemoryPressure;
+use crate::sync::tasks::{install_task, memory_wait_task};
+use crate::{DiskCache, ResolvedDependency, events, format_bytes, format_duration};
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum SyncEvent<'a> {
+    /// All the dependencies of the package are installed so it was queued
+    NodeReady { dep: &'a ResolvedDependency<'a> },
+    /// A worker picked up the package
+    Started {
+        dep: &'a ResolvedDependency<'a>,
+        worker: usize,
+    },
+    /// The package was not in any cache and had to be downloaded
+    Downloaded { dep: &'a ResolvedDependency<'a> },
+    /// The package is installed in the staging library
+    Built {
+        change: &'a SyncChange,
+        elapsed: Duration,
+    },
+    /// The package was moved from the staging library into the project library
+    Linked { name: &'a str },
+    Failed {
+        dep: &'a ResolvedDependency<'a>,
+        error: &'a SyncError,
+        elapsed: Duration,
+    },
+    /// New compilations are paused or resumed depending on the memory available
+    Memory(MemoryPressure),
+}
+
+pub(crate) trait SyncObserver: Send + Sync {
+    fn on_event(&self, event: &SyncEvent);
+
+    /// Called once all the packages are installed or the sync failed
+    fn finish(&self) {}
+
+    /// Runs `f` without the observer writing to the terminal in the meantime, eg to ask the user
+    /// something
+    fn suspend(&self, f: &mut dyn FnMut()) {
+        f();
+    }
+}
+
+/// Dispatches every event to all the observers, in the order they were added
+#[derive(Default)]
+pub(crate) struct SyncBus<'a> {
+    observers: Vec<Box<dyn SyncObserver + 'a>>,
+}
+
+impl<'a> SyncBus<'a> {
+    pub fn add(&mut self, observer: impl SyncObserver + 'a) {
+        self.observers.push(Box::new(observer));
+    }
+
+    pub fn emit(&self, event: SyncEvent) {
+        for observer in &self.observers {
+            observer.on_event(&event);
+        }
+    }
+
+    pub fn finish(&self) {
+        for observer in &self.observers {
+            observer.finish();
+        }
+    }
+
+    /// Runs `f` with all the observers suspended
+    pub fn suspend<T>(&self, f: impl FnOnce() -> T) -> T {
+        fn suspend_all(observers: &[Box<dyn SyncObserver + '_>], f: &mut dyn FnMut()) {
+            match observers.split_first() {
+                Some((first, rest)) => first.suspend(&mut || suspend_all(rest, f)),
+                None => f(),
+            }
+        }
+
+        let mut f = Some(f);
+        let mut out = None;
+        suspend_all(&self.observers, &mut || {
+            if let Some(f) = f.take() {
+                out = Some(f());
+            }
+        });
+        out.expect("suspend to run the function")
+    }
+}
+
+/// Shows how many packages are installed and which ones are currently being installed
+pub(crate) struct ProgressObserver {
+    pb: ProgressBar,
+    installing: Mutex<HashSet<String>>,
+    /// Set while compilations are paused for lack of memory
+    memory_note: Mutex<Option<String>>,
+}
+
+impl ProgressObserver {
+    pub fn new(num_to_install: usize) -> Self {
+        let pb = ProgressBar::new(num_to_install as u64);
+        pb.set_style(
+            ProgressStyle::with_template("[{elapsed_precise}] {bar:60} {pos:>7}/{len:7}\n{msg}")
+                .unwrap(),
+        );
+        pb.enable_steady_tick(Duration::from_secs(1));
+        Self {
+            pb,
+            installing: Mutex::new(HashSet::new()),
+            memory_note: Mutex::new(None),
+        }
+    }
+}
+
+impl SyncObserver for ProgressObserver {
+    fn on_event(&self, event: &SyncEvent) {
+        let mut installing = self.installing.lock().unwrap();
+        match event {
+            SyncEvent::Started { dep, .. } => {
+                installing.insert(dep.name.to_string());
+            }
+            SyncEvent::Built { change, .. } => {
+                installing.remove(&change.name);
+                self.pb.inc(1);
+            }
+            SyncEvent::Memory(pressure) => {
+                *self.memory_note.lock().unwrap() = match pressure {
+                    MemoryPressure::Paused {
+                        available,
+                        threshold,
+                    } => Some(format!(
+                        "Low memory ({} available, below {}): waiting for compilations in progress before starting new ones",
+                        format_bytes(*available),
+                        format_bytes(*threshold)
+                    )),
+                    MemoryPressure::Resumed { .. } => None,
+                };
+            }
+            _ => return,
+        }
+        match &*self.memory_note.lock().unwrap() {
+            Some(note) => self
+                .pb
+                .set_message(format!("Installing {installing:?}\n{note}")),
+            None => self.pb.set_message(format!("Installing {installing:?}")),
+        }
+    }
+
+    fn finish(&self) {
+        self.pb.finish_and_clear();
+    }
+
+    fn suspend(&self, f: &mut dyn FnMut()) {
+        self.pb.suspend(f);
+    }
+}
+
+/// Forwards the start and end of each installation to the [`events`] handler
+pub(crate) struct EventsObserver;
+
+impl SyncObserver for EventsObserver {
+    fn on_event(&self, event: &SyncEvent) {
+        match event {
+            SyncEvent::Started { dep, .. } => events::emit(&events::Event::TaskStarted {
+                task: install_task(&dep.name),
+            }),
+            SyncEvent::Built { change, elapsed } => events::emit(&events::Event::TaskFinished {
+                task: install_task(&change.name),
+                result: events::TaskResult::Ok,
+                time_ms: elapsed.as_millis() as u64,
+            }),
+            SyncEvent::Failed { dep, elapsed, .. } => events::emit(&events::Event::TaskFinished {
+                task: install_task(&dep.name),
+                result: events::TaskResult::Failed,
+                time_ms: elapsed.as_millis() as u64,
+            }),
+            SyncEvent::Memory(MemoryPressure::Paused { .. }) => {
+                events::emit(&events::Event::TaskStarted {
+                    task: memory_wait_task(),
+                })
+            }
+            SyncEvent::Memory(MemoryPressure::Resumed { waited }) => {
+                events::emit(&events::Event::TaskFinished {
+                    task: memory_wait_task(),
+                    result: events::TaskResult::Ok,
+                    time_ms: waited.as_millis() as u64,
+                })
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Writes the debug logs and, if asked, copies the build log of each package in a folder
+pub(crate) struct LogObserver<'a> {
+    cache: &'a DiskCache,
+    save_install_logs_in: Option<PathBuf>,
+    num_to_install: usize,
+    completed: AtomicUsize,
+}
+
+impl<'a> LogObserver<'a> {
+    pub fn new(
+        cache: &'a DiskCache,
+        save_install_logs_in: Option<PathBuf>,
+        num_to_install: usize,
+    ) -> Self {
+        Self {
+            cache,
+            save_install_logs_in,
+            num_to_install,
+            completed: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl SyncObserver for LogObserver<'_> {
+    fn on_event(&self, event: &SyncEvent) {
+        match event {
+            SyncEvent::NodeReady { dep } => log::trace!("{} is ready to be installed", dep.name),
+            SyncEvent::Started { dep, worker } => {
+                let kind = match dep.kind {
+                    PackageType::Source => "source",
+                    PackageType::Binary => "binary",
+                };
+                log::debug!("Installing {} ({kind}) on worker {worker}", dep.name);
+            }
+            SyncEvent::Downloaded { dep } => log::debug!("Downloaded {}", dep.name),
+            SyncEvent::Built { change, .. } => {
+                let completed = self.completed.fetch_add(1, Ordering::Relaxed) + 1;
+                log::debug!(
+                    "Completed installing {} ({completed}/{})",
+                    change.name,
+                    self.num_to_install
+                );
+                if let Some(log_folder) = &self.save_install_logs_in
+                    && !change.is_builtin()
+                    && !change.is_provided()
+                {
+                    let log_path = change.log_path(self.cache);
+                    if log_path.exists() {
+                        fs::copy(log_path, log_folder.join(format!("{}.log", change.name)))
+                            .expect("no error");
+                    }
+                }
+            }
+            SyncEvent::Failed { dep, error, .. } => {
+                if let SyncErrorKind::RCmdError(RCmdError {
+                    source: RCmdErrorKind::InstallationFailed(msg) | RCmdErrorKind::BuildFailed(msg),
+                    ..
+                }) = &error.source
+                    && let Some(log_folder) = &self.save_install_logs_in
+                {
+                    fs::write(log_folder.join(format!("{}.log", dep.name)), msg.as_bytes())
+                        .expect("to write files");
+                }
+            }
+            SyncEvent::Linked { name } => log::debug!("Moved {name} into the library"),
+            SyncEvent::Memory(MemoryPressure::Paused {
+                available,
+                threshold,
+            }) => log::info!(
+                "Only {} of memory available (below {}), not starting new compilations",
+                format_bytes(*available),
+                format_bytes(*threshold)
+            ),
+            SyncEvent::Memory(MemoryPressure::Resumed { waited }) => log::info!(
+                "Resuming compilations after waiting {} for memory",
+                format_duration(*waited)
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    struct Recorder(Arc<Mutex<Vec<String>>>, &'static str);
+
+    impl SyncObserver for Recorder {
+        fn on_event(&self, event: &SyncEvent) {
+            let name = match event {
+                SyncEvent::Linked { name } => name,
+                _ => unreachable!(),
+            };
+            self.0.lock().unwrap().push(format!("{}:{name}", self.1));
+        }
+
+        fn finish(&self) {
+            self.0.lock().unwrap().push(format!("{}:finish", self.1));
+        }
+    }
+
+    #[test]
+    fn bus_dispatches_to_observers_in_order() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let mut bus = SyncBus::default();
+        bus.add(Recorder(Arc::clone(&seen), "a"));
+        bus.add(Recorder(Arc::clone(&seen), "b"));
+
+        bus.emit(SyncEvent::Linked { name: "rlang" });
+        bus.emit(SyncEvent::Linked { name: "cli" });
+        bus.finish();
+
+        assert_eq!(
+            *seen.lock().unwrap(),
+            vec![
+                "a:rlang", "b:rlang", "a:cli", "b:cli", "a:finish", "b:finish"
+            ]
+        );
+    }
+}
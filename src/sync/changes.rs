@@ -10,12 +10,15 @@ use crate::DiskCache;
 use crate::lockfile::Source;
 use crate::package::PackageType;
 use crate::system_req::{SysDep, SysInstallationStatus};
+use crate::utils::format_duration;
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
 pub enum CacheSource {
     Global,
     Local,
+    /// Linked from a site library (`R_LIBS_SITE`)
+    Site,
 }
 
 /// Sections for grouping sync output
@@ -23,6 +26,7 @@ pub enum CacheSource {
 pub enum OutputSection {
     GlobalCache,
     LocalCache,
+    SiteLibrary,
     Downloaded,
     LocalPath,
     Removed,
@@ -33,6 +37,7 @@ impl OutputSection {
         match self {
             Self::GlobalCache => "From global cache",
             Self::LocalCache => "From local cache",
+            Self::SiteLibrary => "From site library",
             Self::Downloaded => {
                 if dry_run {
                     "To Download"
@@ -79,6 +84,9 @@ pub struct SyncChange {
     pub sys_deps: Vec<SysDep>,
     /// Whether we already have the compiled binary somewhere in the cache
     pub binary_cached: bool,
+    /// The version shipped with R when this package shadows a builtin one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub overrides_builtin: Option<String>,
 }
 
 impl SyncChange {
@@ -103,6 +111,7 @@ impl SyncChange {
             version: Some(version.to_string()),
             sys_deps: sys_deps.into_iter().map(SysDep::new).collect(),
             binary_cached,
+            overrides_builtin: None,
         }
     }
 
@@ -117,9 +126,20 @@ impl SyncChange {
             version: None,
             sys_deps: Vec::new(),
             binary_cached: false,
+            overrides_builtin: None,
         }
     }
 
+    /// Note shown in the plan when a package is installed over the version shipped with R
+    pub fn builtin_override_note(&self) -> Option<String> {
+        let builtin = self.overrides_builtin.as_ref()?;
+        Some(format!(
+            "overriding builtin {} ({builtin} → {})",
+            self.name,
+            self.version.as_deref().unwrap_or_default()
+        ))
+    }
+
     /// Display string for the package kind, distinguishing cached binaries from source builds
     pub fn kind_display(&self) -> &'static str {
         match self.kind {
@@ -165,6 +185,7 @@ impl SyncChange {
             let cache_desc = match self.cache_source {
                 Some(CacheSource::Global) => "found in global cache",
                 Some(CacheSource::Local) => "found in cache",
+                Some(CacheSource::Site) => "found in site library",
                 None => "downloaded",
             };
             let sys_deps_string = if sys_deps.is_empty() {
@@ -183,7 +204,7 @@ impl SyncChange {
             );
 
             if include_timings {
-                base += &format!(" ({}ms)", self.timing.unwrap().as_millis());
+                base += &format!(" ({})", format_duration(self.timing.unwrap()));
                 base
             } else {
                 base
@@ -200,6 +221,13 @@ impl SyncChange {
             .unwrap_or_default()
     }
 
+    pub fn is_provided(&self) -> bool {
+        self.source
+            .as_ref()
+            .map(|x| x.is_provided())
+            .unwrap_or_default()
+    }
+
     /// Determine which output section this change belongs to
     pub fn section(&self) -> OutputSection {
         if !self.installed {
@@ -211,6 +239,7 @@ impl SyncChange {
         match self.cache_source {
             Some(CacheSource::Global) => OutputSection::GlobalCache,
             Some(CacheSource::Local) => OutputSection::LocalCache,
+            Some(CacheSource::Site) => OutputSection::SiteLibrary,
             None => OutputSection::Downloaded,
         }
     }
@@ -224,7 +253,11 @@ impl SyncChange {
             }
             Some(Source::Url { url, .. }) => url.to_string(),
             Some(Source::Local { path, .. }) => path.display().to_string(),
-            Some(Source::Builtin { .. }) | None => String::new(),
+            Some(Source::Provided {
+                library: Some(library),
+                ..
+            }) => library.display().to_string(),
+            Some(Source::Builtin { .. }) | Some(Source::Provided { .. }) | None => String::new(),
         }
     }
 
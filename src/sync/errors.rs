@@ -1,6 +1,7 @@
 use crate::http::HttpError;
 use crate::r_cmd::RCmdError;
 use crate::sync::LinkError;
+use crate::sync::signature::SignatureError;
 use std::fmt;
 use std::fmt::Formatter;
 use std::io;
@@ -29,8 +30,13 @@ pub enum SyncErrorKind {
         "Unable to sync - one or more packages ({0}) we want to remove is in use, please restart or terminate the process and then re-run the rv command."
     )]
     PackagesLoadedError(String),
+    #[error(transparent)]
+    Signature(SignatureError),
     #[error("Invalid package found at `{path}`: {error}")]
     InvalidPackage { path: PathBuf, error: String },
+    /// Requested with `rv sync --dry-run --simulate-failures`
+    #[error("Simulated failure")]
+    SimulatedFailure,
 }
 
 impl From<RCmdError> for SyncError {
@@ -57,6 +63,14 @@ impl From<HttpError> for SyncError {
     }
 }
 
+impl From<SignatureError> for SyncError {
+    fn from(error: SignatureError) -> Self {
+        Self {
+            source: SyncErrorKind::Signature(error),
+        }
+    }
+}
+
 impl From<io::Error> for SyncError {
     fn from(error: io::Error) -> Self {
         Self {
@@ -1,21 +1,28 @@
+use std::borrow::Cow;
 use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
-use crate::consts::{BASE_PACKAGES, NO_CHECK_OPEN_FILE_ENV_VAR_NAME, RECOMMENDED_PACKAGES};
+use crate::consts::{BASE_PACKAGES, RECOMMENDED_PACKAGES};
 use crate::events;
+use crate::library::site_package_matches;
 use crate::lockfile::Source;
 use crate::package::PackageType;
 #[cfg(feature = "cli")]
-use crate::r_cmd::kill_all_r_processes;
-use crate::r_cmd::{RCmdError, RCmdErrorKind};
+use crate::r_cmd::kill_spawned_r_processes;
+use crate::sync::build_info::RecordedBuildOptions;
+use crate::sync::bus::{EventsObserver, LogObserver, ProgressObserver, SyncBus, SyncEvent};
 use crate::sync::changes::{CacheSource, SyncChange};
 use crate::sync::errors::{SyncError, SyncErrorKind, SyncErrors};
-use crate::sync::tasks::{install_task, sync_task};
+use crate::sync::in_use::get_packages_in_use;
+use crate::sync::link::create_symlink;
+use crate::sync::memory::MemoryGuard;
+use crate::sync::signature::ArtifactSigning;
+use crate::sync::tasks::sync_task;
 use crate::sync::{LinkMode, sources};
-use crate::utils::{get_max_workers, is_env_var_truthy};
+use crate::utils::{Semaphore, get_max_compile_jobs, get_max_workers};
 use crate::{
     BuildPlan, BuildStep, Cancellation, Context, GitExecutor, RCmd, ResolvedDependency,
     get_tarball_urls,
@@ -27,59 +34,6 @@ use indicatif::{ProgressBar, ProgressStyle};
 #[cfg(not(feature = "cli"))]
 use std::fs;
 
-fn get_all_packages_in_use(path: &Path) -> HashMap<(String, u32), HashSet<String>> {
-    if !cfg!(unix) {
-        return HashMap::new();
-    }
-
-    if is_env_var_truthy(NO_CHECK_OPEN_FILE_ENV_VAR_NAME) {
-        return HashMap::new();
-    }
-
-    // lsof +D rv/ | awk 'NR>1 {print $2, $NF}' (to get PID and filename)
-    let output = match std::process::Command::new("lsof")
-        .arg("+D")
-        .arg(path)
-        .output()
-    {
-        Ok(output) => output,
-        Err(e) => {
-            log::error!("lsof error: {e}. The +D option might not be available");
-            return HashMap::new();
-        }
-    };
-
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    let mut out: HashMap<(String, u32), HashSet<String>> = HashMap::new();
-    for (i, line) in stdout.lines().enumerate() {
-        // Skip header
-        if i == 0 {
-            continue;
-        }
-
-        let fields: Vec<&str> = line.split_whitespace().collect();
-        if fields.len() >= 3 {
-            // Process name is the first field (index 0), PID is the second field (index 1), filename is the last field
-            if let (Ok(pid), Some(filename)) = (fields[1].parse::<u32>(), fields.last()) {
-                let process_name = fields[0].to_string();
-                // that should be a .so file in libs subfolder so we need to find grandparent
-                let p = Path::new(filename);
-                if let Some(parent) = p.parent().and_then(|p| p.parent())
-                    && let Some(package_name) = parent.file_name().and_then(|n| n.to_str())
-                {
-                    out.entry((process_name, pid))
-                        .or_default()
-                        .insert(package_name.to_string());
-                }
-            }
-        }
-    }
-
-    log::debug!("Packages with files loaded (via lsof): {out:?}");
-
-    out
-}
-
 fn remove_package_path(p: &Path) -> std::io::Result<()> {
     if fs::symlink_metadata(p)?.file_type().is_symlink() {
         fs::remove_file(p)
@@ -104,6 +58,28 @@ fn move_package_into_library(staged: &Path, dest: &Path, backup: &Path) -> std::
     Ok(())
 }
 
+/// What to do when a package fails to install
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureAction {
+    /// Try to install it again
+    Retry,
+    /// Leave it and the packages depending on it out of the library and go on with the sync
+    Skip,
+    /// Stop the sync, the default
+    Abort,
+}
+
+type OnFailure<'a> = Box<dyn Fn(&str, &SyncError) -> FailureAction + Send + Sync + 'a>;
+
+/// Decides what to do for each package failing to install, see [`SyncHandler::set_on_failure`]
+struct FailureHandler<'a>(OnFailure<'a>);
+
+impl std::fmt::Debug for FailureHandler<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("FailureHandler")
+    }
+}
+
 #[derive(Debug)]
 pub struct SyncHandler<'a> {
     context: &'a Context,
@@ -111,7 +87,19 @@ pub struct SyncHandler<'a> {
     dry_run: bool,
     show_progress_bar: bool,
     max_workers: usize,
+    /// How many of the workers can compile packages at the same time
+    max_compile_jobs: usize,
     uses_lockfile: bool,
+    /// Only install the deps given, leaving the rest of the library as is
+    additive: bool,
+    /// Set for simulations: the packages that fail instead of being installed
+    simulated_failures: Option<HashSet<String>>,
+    /// Set when the caller handles cancellation, otherwise Ctrl+C cancels the sync
+    cancellation: Option<Arc<Cancellation>>,
+    /// Without it, the sync is aborted as soon as a package fails
+    on_failure: Option<FailureHandler<'a>>,
+    /// Packages left out of the library with the reason why, see [`FailureAction::Skip`]
+    skipped: Mutex<Vec<(String, String)>>,
 }
 
 impl<'a> SyncHandler<'a> {
@@ -122,7 +110,13 @@ impl<'a> SyncHandler<'a> {
             dry_run: false,
             show_progress_bar: false,
             uses_lockfile: false,
+            additive: false,
             max_workers: get_max_workers(),
+            max_compile_jobs: get_max_compile_jobs(),
+            simulated_failures: None,
+            cancellation: None,
+            on_failure: None,
+            skipped: Mutex::new(Vec::new()),
         }
     }
 
@@ -141,10 +135,60 @@ impl<'a> SyncHandler<'a> {
         }
     }
 
+    /// Assigning a value <= 0 is a no-op
+    pub fn set_max_compile_jobs(&mut self, max_compile_jobs: usize) {
+        if max_compile_jobs > 0 {
+            self.max_compile_jobs = max_compile_jobs;
+        }
+    }
+
+    /// Cancels the sync with the given value instead of Ctrl+C, eg when embedding rv
+    pub fn set_cancellation(&mut self, cancellation: Arc<Cancellation>) {
+        self.cancellation = Some(cancellation);
+    }
+
+    /// Called when a package fails to install to decide what to do with it, eg by asking the
+    /// user. Calls are made one at a time from the worker that failed, with the progress bar
+    /// hidden.
+    pub fn set_on_failure(
+        &mut self,
+        on_failure: impl Fn(&str, &SyncError) -> FailureAction + Send + Sync + 'a,
+    ) {
+        self.on_failure = Some(FailureHandler(Box::new(on_failure)));
+    }
+
+    /// The packages skipped during the last sync along with the reason, sorted by name
+    pub fn skipped(&self) -> Vec<(String, String)> {
+        let mut skipped = self.skipped.lock().unwrap().clone();
+        skipped.sort();
+        skipped
+    }
+
     pub fn set_uses_lockfile(&mut self, uses_lockfile: bool) {
         self.uses_lockfile = uses_lockfile;
     }
 
+    /// Packages in the library that are not in the deps given to `handle` are kept rather than
+    /// removed. Used when the deps are only the packages added to a project.
+    pub fn additive(&mut self) {
+        self.additive = true;
+    }
+
+    /// A dry run going through the same steps as a sync (staging, progress, report) where the
+    /// packages given fail with a synthetic error rather than being installed.
+    /// A single worker is used so the outcome doesn't depend on timing.
+    pub fn simulate(&mut self, failures: impl IntoIterator<Item = String>) {
+        self.dry_run = true;
+        self.max_workers = 1;
+        self.simulated_failures = Some(failures.into_iter().collect());
+    }
+
+    fn simulates_failure(&self, package_name: &str) -> bool {
+        self.simulated_failures
+            .as_ref()
+            .is_some_and(|f| f.contains(package_name))
+    }
+
     /// Download source tarballs for all Repository dependencies without installing.
     /// Useful for archival/backup purposes.
     /// Returns paths to downloaded tarballs.
@@ -324,6 +368,56 @@ impl<'a> SyncHandler<'a> {
         Ok(())
     }
 
+    /// The site library containing a package matching `dep`, if it isn't in the cache already
+    fn find_in_site_library(&self, dep: &ResolvedDependency) -> Option<&Path> {
+        if dep.cache_status.binary_available() {
+            return None;
+        }
+        let (library, package) = self.context.site_packages.get(dep.name.as_ref())?;
+        site_package_matches(
+            package,
+            &dep.version,
+            &dep.source,
+            self.context.r_version.major_minor(),
+        )
+        .then_some(library.as_path())
+    }
+
+    /// Site libraries are usually read-only and managed by admins so we only reference the
+    /// package there
+    fn link_from_site_library(
+        &self,
+        dep: &ResolvedDependency,
+        library: &Path,
+    ) -> Result<(), SyncError> {
+        if self.dry_run {
+            return Ok(());
+        }
+
+        log::debug!(
+            "Linking package {} from site library {}",
+            dep.name,
+            library.display()
+        );
+        create_symlink(
+            library.join(dep.name.as_ref()),
+            self.context.staging_path().join(dep.name.as_ref()),
+        )?;
+
+        Ok(())
+    }
+
+    /// Whether installing the package compiles it rather than only downloading/extracting a binary
+    fn will_compile(dep: &ResolvedDependency) -> bool {
+        if dep.source.is_builtin()
+            || dep.source.is_provided()
+            || dep.cache_status.binary_available()
+        {
+            return false;
+        }
+        !dep.source.is_repo() || dep.kind == PackageType::Source
+    }
+
     fn install_package(
         &self,
         dep: &ResolvedDependency,
@@ -334,12 +428,26 @@ impl<'a> SyncHandler<'a> {
             return Ok(());
         }
         // we want the staging to take precedence over the library, but still have
-        // the library in the paths for lookup
+        // the library and the additional ones in the paths for lookup
         let staging_path = self.context.staging_path();
-        let library_dirs = vec![&staging_path, self.context.library.path()];
+        let mut library_dirs = vec![staging_path.as_path(), self.context.library.path()];
+        library_dirs.extend(
+            self.context
+                .additional_libraries
+                .iter()
+                .map(|p| p.as_path()),
+        );
         let configure_args = self.get_configure_args(&dep.name);
         let strip = self.should_strip(&dep.name);
 
+        let signing = ArtifactSigning::from_env()
+            .and_then(|signing| Some((signing, self.cached_binary_dir(dep)?)));
+        if let Some((signing, binary_dir)) = &signing
+            && dep.cache_status.binary_available()
+        {
+            signing.verify(binary_dir)?;
+        }
+
         match dep.source {
             Source::Repository { .. } => sources::repositories::install_package(
                 dep,
@@ -379,8 +487,90 @@ impl<'a> SyncHandler<'a> {
                 strip,
                 cancellation,
             ),
-            Source::Builtin { .. } => Ok(()),
+            Source::Builtin { .. } | Source::Provided { .. } => Ok(()),
+        }?;
+
+        // Sign what we just added to the cache
+        if let Some((signing, binary_dir)) = &signing
+            && !dep.cache_status.binary_available()
+            && binary_dir.is_dir()
+        {
+            signing.sign(binary_dir)?;
+        }
+        Ok(())
+    }
+
+    /// The folder of the binary of the package in the cache it is linked from. Local packages
+    /// are not shared between projects and are left out.
+    fn cached_binary_dir(&self, dep: &ResolvedDependency) -> Option<PathBuf> {
+        match dep.source {
+            Source::Repository { .. }
+            | Source::Git { .. }
+            | Source::RUniverse { .. }
+            | Source::Url { .. } => (),
+            Source::Local { .. } | Source::Builtin { .. } | Source::Provided { .. } => {
+                return None;
+            }
         }
+        let (local, global) = self.context.cache.get_package_paths(
+            &dep.source,
+            Some(&dep.name),
+            Some(&dep.version.original),
+            dep.kind,
+        );
+        let paths = if dep.cache_status.global_binary_available() {
+            global?
+        } else {
+            local
+        };
+        Some(paths.binary.join(dep.name.as_ref()))
+    }
+
+    fn failure_action(
+        &self,
+        package_name: &str,
+        error: &SyncError,
+        bus: &SyncBus,
+        failure_lock: &Mutex<()>,
+        has_errors: &AtomicBool,
+    ) -> FailureAction {
+        let Some(on_failure) = &self.on_failure else {
+            return FailureAction::Abort;
+        };
+        let _guard = failure_lock.lock().unwrap();
+        // No need to ask if the sync was aborted while we were waiting
+        if has_errors.load(Ordering::Relaxed) {
+            return FailureAction::Abort;
+        }
+        bus.suspend(|| (on_failure.0)(package_name, error))
+    }
+
+    /// Leaves the package and everything depending on it out of the sync.
+    /// Returns how many packages were skipped.
+    fn skip_package(
+        &self,
+        dep: &ResolvedDependency,
+        error: &SyncError,
+        plan: &Mutex<BuildPlan>,
+    ) -> usize {
+        let dependents = plan.lock().unwrap().mark_skipped(&dep.name);
+        // A failed installation can leave a partial package that would end up in the library
+        let staged = self.context.staging_path().join(dep.name.as_ref());
+        if fs::symlink_metadata(&staged).is_ok()
+            && let Err(e) = remove_package_path(&staged)
+        {
+            log::warn!("Failed to remove {}: {e}", staged.display());
+        }
+
+        let mut skipped = self.skipped.lock().unwrap();
+        skipped.push((dep.name.to_string(), error.to_string()));
+        skipped.extend(dependents.iter().map(|d| {
+            (
+                d.to_string(),
+                format!("depends on {} which was skipped", dep.name),
+            )
+        }));
+        dependents.len() + 1
     }
 
     /// We want to figure out:
@@ -406,10 +596,8 @@ impl<'a> SyncHandler<'a> {
                 // Additionally, any package in the library that is ignored, needs to be removed
                 if self.context.library.contains_package(dep) && !dep.ignored {
                     match &dep.source {
-                        Source::Repository { .. } => {
-                            if !self.uses_lockfile || dep.from_lockfile {
-                                deps_seen.insert(name.as_str());
-                            }
+                        Source::Repository { .. } if !self.uses_lockfile || dep.from_lockfile => {
+                            deps_seen.insert(name.as_str());
                         }
                         Source::Git { .. } | Source::RUniverse { .. } | Source::Url { .. } => {
                             deps_seen.insert(name.as_str());
@@ -422,6 +610,8 @@ impl<'a> SyncHandler<'a> {
                     }
                     continue;
                 }
+            } else if self.additive {
+                continue;
             }
             deps_to_remove.insert((name.as_str(), true));
         }
@@ -437,6 +627,20 @@ impl<'a> SyncHandler<'a> {
             }
         }
 
+        // Same for packages provided by a library we don't manage or by the runtime
+        for name in self
+            .context
+            .provided_packages
+            .keys()
+            .chain(self.context.config.provided())
+        {
+            if let Some(dep) = deps_by_name.get(name.as_str())
+                && dep.source.is_provided()
+            {
+                deps_seen.insert(name.as_str());
+            }
+        }
+
         // Lastly, remove any package that we can't really access
         for name in &self.context.library.broken {
             log::warn!("Package {name} in library is broken");
@@ -446,12 +650,70 @@ impl<'a> SyncHandler<'a> {
         (deps_seen, deps_to_copy, deps_to_remove)
     }
 
+    /// Whether the binary of the package in the global cache was built with other configure args
+    /// or env vars than the ones the project requires. Binaries without build info, eg
+    /// downloaded from a repository or built by an older rv, are assumed to be fine.
+    fn global_binary_diverges(&self, dep: &ResolvedDependency) -> bool {
+        let Some(global) = self.context.cache.global() else {
+            return false;
+        };
+        if !dep.cache_status.global_binary_available()
+            || dep.source.is_builtin()
+            || dep.source.is_provided()
+        {
+            return false;
+        }
+        RecordedBuildOptions::load(&global.build_log_path_for(dep))
+            .is_some_and(|r| !r.matches(&dep.env_vars, &self.get_configure_args(&dep.name)))
+    }
+
+    /// The global cache is read-only so packages whose binary there doesn't match the project
+    /// build options are used/built from the local cache instead
+    fn skip_divergent_global_binaries<'d>(
+        &self,
+        deps: &'d [ResolvedDependency<'d>],
+    ) -> Cow<'d, [ResolvedDependency<'d>]> {
+        let divergent: HashSet<_> = deps
+            .iter()
+            .filter(|d| self.global_binary_diverges(d))
+            .map(|d| d.name.as_ref())
+            .collect();
+        if divergent.is_empty() {
+            return Cow::Borrowed(deps);
+        }
+
+        let deps = deps
+            .iter()
+            .map(|dep| {
+                let mut dep = dep.clone();
+                if divergent.contains(dep.name.as_ref()) {
+                    if !dep.cache_status.local_binary_available() {
+                        log::warn!(
+                            "{} in the global cache was built with different configure args or env vars, building it in the local cache",
+                            dep.name
+                        );
+                    }
+                    dep.cache_status = dep.cache_status.ignore_global_binary();
+                }
+                dep
+            })
+            .collect();
+        Cow::Owned(deps)
+    }
+
     pub fn handle(
         &self,
         deps: &[ResolvedDependency],
         r_cmd: &impl RCmd,
     ) -> Result<Vec<SyncChange>, SyncError> {
-        events::with_task(sync_task(), || self.handle_impl(deps, r_cmd))
+        let deps = &self.skip_divergent_global_binaries(deps);
+        let changes = events::with_task(sync_task(), || self.handle_impl(deps, r_cmd))?;
+        if !self.dry_run
+            && let Err(e) = self.context.library.write_manifest(deps)
+        {
+            log::warn!("Failed to write the library manifest: {e}");
+        }
+        Ok(changes)
     }
 
     fn handle_impl(
@@ -460,11 +722,11 @@ impl<'a> SyncHandler<'a> {
         r_cmd: &impl RCmd,
     ) -> Result<Vec<SyncChange>, SyncError> {
         // Clean up at all times, even with a dry run
-        let cancellation = Arc::new(Cancellation::default());
+        let cancellation = self.cancellation.clone().unwrap_or_default();
 
         let staging_path = self.context.staging_path();
         #[cfg(feature = "cli")]
-        {
+        if self.cancellation.is_none() {
             let cancellation_clone = Arc::clone(&cancellation);
             let staging_path = staging_path.clone();
             ctrlc::set_handler(move || {
@@ -474,7 +736,7 @@ impl<'a> SyncHandler<'a> {
                         "Finishing current operations... Press Ctrl+C again to exit immediately."
                     );
                 } else if cancellation_clone.is_hard_cancellation() {
-                    kill_all_r_processes();
+                    kill_spawned_r_processes();
                     if staging_path.is_dir() {
                         fs::remove_dir_all(&staging_path).expect("Failed to remove staging path");
                     }
@@ -496,14 +758,17 @@ impl<'a> SyncHandler<'a> {
         let mut sync_changes = Vec::new();
 
         let mut plan = BuildPlan::new(deps);
+        plan.set_install_constraints(self.context.config.install_constraints());
         let num_deps_to_install = plan.num_to_install();
         let (deps_seen, deps_to_copy, deps_to_remove) = self.compare_with_local_library(deps);
         let needs_sync = deps_seen.len() != num_deps_to_install;
-        let packages_loaded = if !deps_to_remove.is_empty() {
-            get_all_packages_in_use(self.context.library.path())
-        } else {
-            HashMap::new()
-        };
+        let packages_loaded = get_packages_in_use(
+            self.context.library.path(),
+            &deps_to_remove
+                .iter()
+                .map(|(name, _)| *name)
+                .collect::<Vec<_>>(),
+        );
 
         for (dir_name, notify) in &deps_to_remove {
             if packages_loaded
@@ -575,50 +840,59 @@ impl<'a> SyncHandler<'a> {
         // create a lookup table for resolved deps by name and use those references across channels.
         let dep_by_name: HashMap<_, _> = deps.iter().map(|d| (&d.name, d)).collect();
 
-        let pb = if self.show_progress_bar {
-            let pb = ProgressBar::new(plan.num_to_install() as u64);
-            pb.set_style(
-                ProgressStyle::with_template(
-                    "[{elapsed_precise}] {bar:60} {pos:>7}/{len:7}\n{msg}",
-                )
-                .unwrap(),
-            );
-            pb.enable_steady_tick(Duration::from_secs(1));
-            Arc::new(pb)
-        } else {
-            Arc::new(ProgressBar::hidden())
-        };
+        let mut bus = SyncBus::default();
+        if self.show_progress_bar && (!self.dry_run || self.simulated_failures.is_some()) {
+            bus.add(ProgressObserver::new(num_deps_to_install));
+        }
+        bus.add(EventsObserver);
+        if !self.dry_run {
+            bus.add(LogObserver::new(
+                self.context.cache.local(),
+                self.save_install_logs_in.clone(),
+                num_deps_to_install,
+            ));
+        }
 
         let (ready_sender, ready_receiver) = channel::unbounded();
         let (done_sender, done_receiver) = channel::unbounded();
 
-        let plan = Arc::new(Mutex::new(plan));
+        let plan = Mutex::new(plan);
         // Initial deps we can install immediately
         {
             let mut plan = plan.lock().unwrap();
             while let BuildStep::Install(d) = plan.get() {
-                ready_sender.send(dep_by_name[&d.name]).unwrap();
+                let dep = dep_by_name[&d.name];
+                bus.emit(SyncEvent::NodeReady { dep });
+                ready_sender.send(dep).unwrap();
             }
         }
 
-        let installed_count = Arc::new(AtomicUsize::new(0));
-        let has_errors = Arc::new(AtomicBool::new(false));
-        let errors = Arc::new(Mutex::new(Vec::new()));
-        let deps_to_copy = Arc::new(deps_to_copy);
+        let installed_count = AtomicUsize::new(0);
+        let has_errors = AtomicBool::new(false);
+        let errors = Mutex::new(Vec::new());
+        let compile_jobs = self.max_compile_jobs.min(self.max_workers);
+        log::debug!(
+            "Syncing with {} workers, {compile_jobs} of which can compile packages",
+            self.max_workers
+        );
+        let compile_slots = Semaphore::new(compile_jobs);
+        let memory_guard = MemoryGuard::from_env();
+        // Only one failure is handled at a time
+        let failure_lock = Mutex::new(());
+        self.skipped.lock().unwrap().clear();
 
         thread::scope(|s| {
-            let plan_clone = Arc::clone(&plan);
             let ready_sender_clone = ready_sender.clone();
-            let installed_count_clone = Arc::clone(&installed_count);
-            let has_errors_clone = Arc::clone(&has_errors);
+            let (plan, bus, installed_count, has_errors) =
+                (&plan, &bus, &installed_count, &has_errors);
 
             // Different thread to monitor what needs to be installed next
             s.spawn(move |_| {
                 let mut seen = HashSet::new();
-                while !has_errors_clone.load(Ordering::Relaxed)
-                    && installed_count_clone.load(Ordering::Relaxed) < num_deps_to_install
+                while !has_errors.load(Ordering::Relaxed)
+                    && installed_count.load(Ordering::Relaxed) < num_deps_to_install
                 {
-                    let mut plan = plan_clone.lock().unwrap();
+                    let mut plan = plan.lock().unwrap();
                     let mut ready = Vec::new();
                     while let BuildStep::Install(d) = plan.get() {
                         ready.push(dep_by_name[&d.name]);
@@ -628,6 +902,7 @@ impl<'a> SyncHandler<'a> {
                     for p in ready {
                         if !seen.contains(&p.name) {
                             seen.insert(&p.name);
+                            bus.emit(SyncEvent::NodeReady { dep: p });
                             ready_sender_clone.send(p).unwrap();
                         }
                     }
@@ -635,64 +910,69 @@ impl<'a> SyncHandler<'a> {
                 }
                 drop(ready_sender_clone);
             });
-            let installing = Arc::new(Mutex::new(HashSet::new()));
 
             // Our worker threads that will actually perform the installation
             for worker_num in 0..self.max_workers {
                 let ready_receiver = ready_receiver.clone();
                 let done_sender = done_sender.clone();
-                let plan = Arc::clone(&plan);
-                let has_errors_clone = Arc::clone(&has_errors);
-                let errors_clone = Arc::clone(&errors);
-                let deps_to_copy_clone = Arc::clone(&deps_to_copy);
-                let pb_clone = Arc::clone(&pb);
-                let installing_clone = Arc::clone(&installing);
+                let (errors, deps_to_copy, compile_slots, memory_guard, failure_lock) = (
+                    &errors,
+                    &deps_to_copy,
+                    &compile_slots,
+                    &memory_guard,
+                    &failure_lock,
+                );
                 let cancellation_clone = cancellation.clone();
-                let save_install_logs_in_clone = self.save_install_logs_in.clone();
 
                 s.spawn(move |_| {
                     let local_worker_id = worker_num + 1;
                     while let Ok(dep) = ready_receiver.recv() {
-                        if has_errors_clone.load(Ordering::Relaxed)
-                            || cancellation_clone.is_cancelled()
-                        {
+                        if has_errors.load(Ordering::Relaxed) || cancellation_clone.is_cancelled() {
                             break;
                         }
 
-                        installing_clone.lock().unwrap().insert(dep.name.clone());
                         let start = std::time::Instant::now();
-                        events::emit(&events::Event::TaskStarted {
-                            task: install_task(&dep.name),
+                        bus.emit(SyncEvent::Started {
+                            dep,
+                            worker: local_worker_id,
                         });
-                        if !self.dry_run {
-                            if self.show_progress_bar {
-                                pb_clone.set_message(format!(
-                                    "Installing {:?}",
-                                    installing_clone.lock().unwrap()
-                                ));
+                        let copied = deps_to_copy.contains(dep.name.as_ref());
+                        let site_library =
+                            (!copied).then(|| self.find_in_site_library(dep)).flatten();
+                        let install = || {
+                            if self.simulates_failure(&dep.name) {
+                                Err(SyncError {
+                                    source: SyncErrorKind::SimulatedFailure,
+                                })
+                            } else if copied {
+                                self.copy_package(dep)
+                            } else if let Some(library) = site_library {
+                                self.link_from_site_library(dep, library)
+                            } else {
+                                // Binaries are mostly IO so only compilations wait for a slot
+                                // and for memory to be available
+                                let compiles = !self.dry_run && Self::will_compile(dep);
+                                let _slot = compiles.then(|| compile_slots.acquire());
+                                let _memory = compiles.then(|| {
+                                    memory_guard.start_compilation(|pressure| {
+                                        bus.emit(SyncEvent::Memory(pressure))
+                                    })
+                                });
+                                self.install_package(dep, r_cmd, cancellation_clone.clone())
                             }
-                            match dep.kind {
-                                PackageType::Source => {
-                                    log::debug!(
-                                        "Installing {} (source) on worker {}",
-                                        dep.name,
-                                        local_worker_id
-                                    )
-                                }
-                                PackageType::Binary => {
-                                    log::debug!(
-                                        "Installing {} (binary) on worker {}",
-                                        dep.name,
-                                        local_worker_id
-                                    )
-                                }
+                        };
+                        let mut install_result = install();
+                        let mut action = FailureAction::Abort;
+                        while let Err(e) = &install_result
+                            && !cancellation_clone.is_cancelled()
+                        {
+                            action =
+                                self.failure_action(&dep.name, e, bus, failure_lock, has_errors);
+                            if action != FailureAction::Retry {
+                                break;
                             }
+                            install_result = install();
                         }
-                        let install_result = if deps_to_copy_clone.contains(dep.name.as_ref()) {
-                            self.copy_package(dep)
-                        } else {
-                            self.install_package(dep, r_cmd, cancellation_clone.clone())
-                        };
 
                         match install_result {
                             Ok(_) => {
@@ -700,7 +980,9 @@ impl<'a> SyncHandler<'a> {
                                 let binary_cached =
                                     !is_binary && dep.cache_status.binary_available();
                                 let cache_source = {
-                                    if is_binary || binary_cached {
+                                    if site_library.is_some() {
+                                        Some(CacheSource::Site)
+                                    } else if is_binary || binary_cached {
                                         if dep.cache_status.global_binary_available() {
                                             Some(CacheSource::Global)
                                         } else if dep.cache_status.local_binary_available() {
@@ -724,7 +1006,14 @@ impl<'a> SyncHandler<'a> {
                                         }
                                     }
                                 };
-                                let sync_change = SyncChange::installed(
+                                if cache_source.is_none()
+                                    && !copied
+                                    && !dep.source.is_builtin()
+                                    && !dep.source.is_provided()
+                                {
+                                    bus.emit(SyncEvent::Downloaded { dep });
+                                }
+                                let mut sync_change = SyncChange::installed(
                                     &dep.name,
                                     &dep.version.original,
                                     dep.source.clone(),
@@ -738,54 +1027,40 @@ impl<'a> SyncHandler<'a> {
                                     cache_source,
                                     binary_cached,
                                 );
-                                let mut plan = plan.lock().unwrap();
-                                plan.mark_installed(&dep.name);
-                                drop(plan);
-                                if let Some(log_folder) = &save_install_logs_in_clone
-                                    && !sync_change.is_builtin()
+                                if !dep.source.is_builtin()
+                                    && let Some(builtin) =
+                                        self.context.builtin_packages.get(dep.name.as_ref())
+                                    && builtin.version != *dep.version
                                 {
-                                    let log_path = sync_change.log_path(self.context.cache.local());
-                                    if log_path.exists() {
-                                        fs::copy(
-                                            log_path,
-                                            log_folder.join(format!("{}.log", sync_change.name)),
-                                        )
-                                        .expect("no error");
-                                    }
+                                    sync_change.overrides_builtin =
+                                        Some(builtin.version.original.clone());
                                 }
-                                events::emit(&events::Event::TaskFinished {
-                                    task: install_task(&dep.name),
-                                    result: events::TaskResult::Ok,
-                                    time_ms: start.elapsed().as_millis() as u64,
+                                plan.lock().unwrap().mark_installed(&dep.name);
+                                bus.emit(SyncEvent::Built {
+                                    change: &sync_change,
+                                    elapsed: start.elapsed(),
                                 });
                                 if done_sender.send(sync_change).is_err() {
                                     break; // Channel closed
                                 }
                             }
+                            Err(e) if action == FailureAction::Skip => {
+                                bus.emit(SyncEvent::Failed {
+                                    dep,
+                                    error: &e,
+                                    elapsed: start.elapsed(),
+                                });
+                                let num_skipped = self.skip_package(dep, &e, plan);
+                                installed_count.fetch_add(num_skipped, Ordering::Relaxed);
+                            }
                             Err(e) => {
-                                events::emit(&events::Event::TaskFinished {
-                                    task: install_task(&dep.name),
-                                    result: events::TaskResult::Failed,
-                                    time_ms: start.elapsed().as_millis() as u64,
+                                bus.emit(SyncEvent::Failed {
+                                    dep,
+                                    error: &e,
+                                    elapsed: start.elapsed(),
                                 });
-                                has_errors_clone.store(true, Ordering::Relaxed);
-
-                                if let SyncErrorKind::RCmdError(RCmdError {
-                                    source:
-                                        RCmdErrorKind::InstallationFailed(msg)
-                                        | RCmdErrorKind::BuildFailed(msg),
-                                    ..
-                                }) = &e.source
-                                    && let Some(log_folder) = &save_install_logs_in_clone
-                                {
-                                    fs::write(
-                                        log_folder.join(format!("{}.log", dep.name)),
-                                        msg.as_bytes(),
-                                    )
-                                    .expect("to write files");
-                                }
-
-                                errors_clone.lock().unwrap().push((dep, e));
+                                has_errors.store(true, Ordering::Relaxed);
+                                errors.lock().unwrap().push((dep, e));
                                 break;
                             }
                         }
@@ -794,35 +1069,20 @@ impl<'a> SyncHandler<'a> {
                 });
             }
 
-            // Monitor progress in the main thread
+            // Collect the changes in the main thread.
+            // Skipped packages are counted by the workers since they don't have a change
             loop {
-                if has_errors.load(Ordering::Relaxed) {
+                if has_errors.load(Ordering::Relaxed)
+                    || installed_count.load(Ordering::Relaxed) == num_deps_to_install
+                {
                     break;
                 }
                 // timeout is necessary to avoid deadlock
                 if let Ok(change) = done_receiver.recv_timeout(Duration::from_millis(1)) {
                     installed_count.fetch_add(1, Ordering::Relaxed);
-                    installing.lock().unwrap().remove(change.name.as_str());
-                    if !self.dry_run {
-                        log::debug!(
-                            "Completed installing {} ({}/{})",
-                            change.name,
-                            installed_count.load(Ordering::Relaxed),
-                            num_deps_to_install
-                        );
-                        if self.show_progress_bar {
-                            pb.inc(1);
-                            pb.set_message(format!("Installing {:?}", installing.lock().unwrap()));
-                        }
-                    }
                     if !deps_seen.contains(change.name.as_str()) {
                         sync_changes.push(change);
                     }
-                    if installed_count.load(Ordering::Relaxed) == num_deps_to_install
-                        || has_errors.load(Ordering::Relaxed)
-                    {
-                        break;
-                    }
                 }
             }
 
@@ -831,7 +1091,7 @@ impl<'a> SyncHandler<'a> {
         })
         .expect("threads to not panic");
 
-        pb.finish_and_clear();
+        bus.finish();
 
         if has_errors.load(Ordering::Relaxed) {
             let mut err = errors.lock().unwrap();
@@ -883,6 +1143,7 @@ impl<'a> SyncHandler<'a> {
                 let out = self.context.library.path().join(&name);
                 let backup = staging_path.join(format!(".rvbak-{name}"));
                 move_package_into_library(&path, &out, &backup)?;
+                bus.emit(SyncEvent::Linked { name: &name });
             }
 
             // Then delete staging
@@ -903,15 +1164,29 @@ impl<'a> SyncHandler<'a> {
 
 #[cfg(test)]
 mod tests {
-    use super::move_package_into_library;
+    use super::{FailureAction, SyncHandler, move_package_into_library};
+    use crate::test_utils::TempCache;
+    use crate::{Context, RCommandLookup, RepositoryDatabase, ResolveMode, SystemInfo};
     use std::fs;
     use std::path::Path;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
 
     fn write_pkg(dir: &Path, marker: &str) {
         fs::create_dir_all(dir).unwrap();
         fs::write(dir.join("DESCRIPTION"), marker).unwrap();
     }
 
+    /// A cache with the database of a repository made of `packages`
+    fn cache_with_repository(url: &str, packages: &str) -> TempCache {
+        let cache = TempCache::new(&"4.5".parse().unwrap(), SystemInfo::from_os_info()).unwrap();
+        let (db_path, _) = cache.local().get_package_db_entry(url);
+        RepositoryDatabase::from_packages_str(url, packages)
+            .persist(&db_path)
+            .unwrap();
+        cache
+    }
+
     #[cfg(unix)]
     fn symlink_pkg(link: &Path, target: &Path) {
         std::os::unix::fs::symlink(target, link).unwrap();
@@ -973,4 +1248,115 @@ mod tests {
             "old"
         );
     }
+
+    #[test]
+    fn can_retry_and_skip_failed_packages() {
+        const REPO_URL: &str = "https://sync-skip.test/repo";
+        let project_dir = tempfile::tempdir().unwrap();
+        let cache = cache_with_repository(
+            REPO_URL,
+            "Package: a\nVersion: 1.0.0\nDepends: b\n\nPackage: b\nVersion: 1.0.0\n\nPackage: c\nVersion: 1.0.0\n",
+        );
+        let config_path = project_dir.path().join("rproject.toml");
+        fs::write(
+            &config_path,
+            format!(
+                r#"[project]
+name = "skip"
+r_version = "4.5"
+repositories = [{{ alias = "test", url = "{REPO_URL}" }}]
+dependencies = ["a", "c"]
+"#
+            ),
+        )
+        .unwrap();
+        let mut context =
+            Context::new_with_cache_dir(&config_path, RCommandLookup::Skip, Some(cache.path()))
+                .unwrap();
+        context.load_for_resolve_mode(ResolveMode::Default).unwrap();
+        let resolution = context.resolve(ResolveMode::Default);
+
+        let calls = AtomicUsize::new(0);
+        let mut handler = SyncHandler::new(&context, None);
+        handler.simulate(["b".to_string()]);
+        handler.set_on_failure(|name, _| {
+            assert_eq!(name, "b");
+            if calls.fetch_add(1, Ordering::Relaxed) == 0 {
+                FailureAction::Retry
+            } else {
+                FailureAction::Skip
+            }
+        });
+        let changes = handler.handle(&resolution.found, &context.r_cmd).unwrap();
+
+        assert_eq!(calls.load(Ordering::Relaxed), 2);
+        let installed: Vec<_> = changes.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(installed, vec!["c"]);
+        assert_eq!(
+            handler.skipped(),
+            vec![
+                (
+                    "a".to_string(),
+                    "depends on b which was skipped".to_string()
+                ),
+                ("b".to_string(), "Simulated failure".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn can_sync_added_package_depending_on_locked_one() {
+        const REPO_URL: &str = "https://sync-added.test/repo";
+        let project_dir = tempfile::tempdir().unwrap();
+        let cache = cache_with_repository(
+            REPO_URL,
+            "Package: cli\nVersion: 3.6.3\n\nPackage: rlang\nVersion: 1.1.4\nImports: cli\n",
+        );
+        let config_path = project_dir.path().join("rproject.toml");
+        fs::write(
+            &config_path,
+            format!(
+                r#"[project]
+name = "added"
+r_version = "4.5"
+repositories = [{{ alias = "test", url = "{REPO_URL}" }}]
+dependencies = ["cli", "rlang"]
+"#
+            ),
+        )
+        .unwrap();
+        fs::write(
+            project_dir.path().join("rv.lock"),
+            format!(
+                r#"version = 2
+r_version = "4.5"
+repositories = ["{REPO_URL}"]
+
+[[packages]]
+name = "cli"
+version = "3.6.3"
+source = {{ repository = "{REPO_URL}" }}
+force_source = false
+dependencies = []
+"#
+            ),
+        )
+        .unwrap();
+        let mut context =
+            Context::new_with_cache_dir(&config_path, RCommandLookup::Skip, Some(cache.path()))
+                .unwrap();
+        context.load_for_resolve_mode(ResolveMode::Default).unwrap();
+        let resolution = context.resolve_added(&["rlang".to_string()]).unwrap();
+
+        let mut handler = SyncHandler::new(&context, None);
+        handler.simulate([]);
+        handler.additive();
+        // The Ctrl+C handler can only be set once per process
+        handler.set_cancellation(Arc::default());
+        let changes = handler.handle(&resolution.found, &context.r_cmd).unwrap();
+
+        let mut installed: Vec<_> = changes.iter().map(|c| c.name.as_str()).collect();
+        installed.sort();
+        assert_eq!(installed, vec!["cli", "rlang"]);
+    }
 }
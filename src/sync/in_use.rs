@@ -0,0 +1,248 @@
+//! Finds whether packages we are about to remove or overwrite have files opened by a running
+//! process, typically the shared library of a package loaded in a R session.
+//! Only the folders of those packages are looked at, rather than the whole library, and the
+//! check is abandoned after a timeout so it can't slow down a sync on a slow filesystem.
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crossbeam::{channel, thread};
+
+use crate::consts::NO_CHECK_OPEN_FILE_ENV_VAR_NAME;
+use crate::utils::{get_max_workers, is_env_var_truthy};
+
+/// How long we wait for the check before going ahead without it
+const CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// (process name, pid) -> packages it has files opened in
+pub(crate) type PackagesInUse = HashMap<(String, u32), HashSet<String>>;
+
+/// The folders a package can be accessed through: its folder in the library and where it
+/// points to if it's a symlink to the cache
+fn package_dirs(library: &Path, name: &str) -> Vec<PathBuf> {
+    let dir = library.join(name);
+    let mut dirs = vec![dir.clone()];
+    if let Ok(canonical) = std::fs::canonicalize(&dir)
+        && canonical != dir
+    {
+        dirs.push(canonical);
+    }
+    dirs
+}
+
+/// Returns the packages among `packages` that are in use. Any error or a timeout results in
+/// assuming nothing is in use, like it was before that check existed.
+pub(crate) fn get_packages_in_use(library: &Path, packages: &[&str]) -> PackagesInUse {
+    if !cfg!(unix) || packages.is_empty() || is_env_var_truthy(NO_CHECK_OPEN_FILE_ENV_VAR_NAME) {
+        return HashMap::new();
+    }
+
+    let targets: Vec<(String, Vec<PathBuf>)> = packages
+        .iter()
+        .map(|name| (name.to_string(), package_dirs(library, name)))
+        .collect();
+
+    let (sender, receiver) = channel::bounded(1);
+    // Not joined: if it times out, the thread finishes on its own and its result is dropped
+    std::thread::spawn(move || {
+        let _ = sender.send(find_packages_in_use(&targets));
+    });
+
+    match receiver.recv_timeout(CHECK_TIMEOUT) {
+        Ok(out) => {
+            log::debug!("Packages with files opened: {out:?}");
+            out
+        }
+        Err(_) => {
+            log::warn!(
+                "Checking whether the packages to remove are in use took more than {}s, skipping it",
+                CHECK_TIMEOUT.as_secs()
+            );
+            HashMap::new()
+        }
+    }
+}
+
+/// Every process is looked at once, in parallel, and its memory mapped files compared with the
+/// package folders. That's where the shared libraries of loaded packages show up.
+#[cfg(target_os = "linux")]
+fn find_packages_in_use(targets: &[(String, Vec<PathBuf>)]) -> PackagesInUse {
+    let Ok(entries) = std::fs::read_dir("/proc") else {
+        return HashMap::new();
+    };
+    let pids: Vec<u32> = entries
+        .filter_map(|e| e.ok()?.file_name().to_str()?.parse().ok())
+        .filter(|pid| *pid != std::process::id())
+        .collect();
+    let chunk_size = pids.len().div_ceil(get_max_workers()).max(1);
+
+    let mut out = HashMap::new();
+    thread::scope(|s| {
+        let handles: Vec<_> = pids
+            .chunks(chunk_size)
+            .map(|chunk| {
+                s.spawn(move |_| {
+                    chunk
+                        .iter()
+                        .filter_map(|pid| {
+                            // Processes of other users can't be read, which is also the case for lsof
+                            let maps = std::fs::read_to_string(format!("/proc/{pid}/maps")).ok()?;
+                            let packages = match_packages(parse_maps(&maps), targets);
+                            if packages.is_empty() {
+                                return None;
+                            }
+                            let name = std::fs::read_to_string(format!("/proc/{pid}/comm"))
+                                .map(|s| s.trim().to_string())
+                                .unwrap_or_default();
+                            Some(((name, *pid), packages))
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        for handle in handles {
+            if let Ok(found) = handle.join() {
+                out.extend(found);
+            }
+        }
+    })
+    .expect("threads don't panic");
+    out
+}
+
+/// Without /proc, we run `lsof +D` on the folder of each package, in parallel
+#[cfg(not(target_os = "linux"))]
+fn find_packages_in_use(targets: &[(String, Vec<PathBuf>)]) -> PackagesInUse {
+    let chunk_size = targets.len().div_ceil(get_max_workers()).max(1);
+    let mut out: PackagesInUse = HashMap::new();
+    thread::scope(|s| {
+        let handles: Vec<_> = targets
+            .chunks(chunk_size)
+            .map(|chunk| {
+                s.spawn(move |_| {
+                    let mut found = Vec::new();
+                    for (name, dirs) in chunk {
+                        for dir in dirs {
+                            let output = match std::process::Command::new("lsof")
+                                .arg("+D")
+                                .arg(dir)
+                                .output()
+                            {
+                                Ok(output) => output,
+                                Err(e) => {
+                                    log::error!(
+                                        "lsof error: {e}. The +D option might not be available"
+                                    );
+                                    return found;
+                                }
+                            };
+                            for process in parse_lsof(&String::from_utf8_lossy(&output.stdout)) {
+                                found.push((process, name.clone()));
+                            }
+                        }
+                    }
+                    found
+                })
+            })
+            .collect();
+        for handle in handles {
+            if let Ok(found) = handle.join() {
+                for (process, name) in found {
+                    out.entry(process).or_default().insert(name);
+                }
+            }
+        }
+    })
+    .expect("threads don't panic");
+    out
+}
+
+/// The files mapped in a `/proc/<pid>/maps` content
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_maps(content: &str) -> impl Iterator<Item = &Path> {
+    // Lines look like `7f1c2a000000-7f1c2a021000 r-xp 00000000 08:01 1234   /path/to/file.so`
+    // and only the path can contain a `/`
+    content.lines().filter_map(|line| {
+        let path = &line[line.find('/')?..];
+        Some(Path::new(path.strip_suffix(" (deleted)").unwrap_or(path)))
+    })
+}
+
+/// The (process name, pid) listed in a `lsof` output
+#[cfg_attr(target_os = "linux", allow(dead_code))]
+fn parse_lsof(content: &str) -> HashSet<(String, u32)> {
+    content
+        .lines()
+        // Skip header
+        .skip(1)
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let name = fields.next()?;
+            let pid = fields.next()?.parse().ok()?;
+            Some((name.to_string(), pid))
+        })
+        .collect()
+}
+
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn match_packages<'a>(
+    files: impl Iterator<Item = &'a Path>,
+    targets: &[(String, Vec<PathBuf>)],
+) -> HashSet<String> {
+    let mut out = HashSet::new();
+    for file in files {
+        for (name, dirs) in targets {
+            if dirs.iter().any(|d| file.starts_with(d)) {
+                out.insert(name.clone());
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_packages_in_maps() {
+        let maps = r#"55d0c8a00000-55d0c8a2b000 r--p 00000000 08:01 1234  /usr/lib/R/bin/exec/R
+7f1c2a000000-7f1c2a021000 r-xp 00000000 08:01 5678  /project/rv/library/4.4/cli/libs/cli.so
+7f1c2b000000-7f1c2b021000 r-xp 00000000 08:01 5679  /cache/rlang/1.1.4/rlang/libs/rlang.so (deleted)
+7f1c2c000000-7f1c2c021000 rw-p 00000000 00:00 0
+7ffd4a000000-7ffd4a021000 rw-p 00000000 00:00 0  [stack]"#;
+        let targets = vec![
+            (
+                "cli".to_string(),
+                vec![PathBuf::from("/project/rv/library/4.4/cli")],
+            ),
+            (
+                "rlang".to_string(),
+                vec![
+                    PathBuf::from("/project/rv/library/4.4/rlang"),
+                    PathBuf::from("/cache/rlang/1.1.4/rlang"),
+                ],
+            ),
+            (
+                "R".to_string(),
+                vec![PathBuf::from("/project/rv/library/4.4/R")],
+            ),
+        ];
+        let found = match_packages(parse_maps(maps), &targets);
+        assert_eq!(
+            found,
+            HashSet::from(["cli".to_string(), "rlang".to_string()])
+        );
+    }
+
+    #[test]
+    fn can_parse_lsof_output() {
+        let output = r#"COMMAND   PID USER  FD   TYPE DEVICE SIZE/OFF    NODE NAME
+R       12345 user mem    REG    8,1   123456 1234567 /project/rv/library/4.4/cli/libs/cli.so
+rsession 678 user mem    REG    8,1   123456 1234567 /project/rv/library/4.4/cli/libs/cli.so"#;
+        assert_eq!(
+            parse_lsof(output),
+            HashSet::from([("R".to_string(), 12345), ("rsession".to_string(), 678)])
+        );
+    }
+}
@@ -0,0 +1,236 @@
+//! Keeps parallel compilations from running the machine out of memory: while the available
+//! memory is below `RV_MIN_FREE_MEMORY` (`1G` by default, `0` to disable), new compilations wait
+//! for the ones in progress to finish. A compilation always starts if nothing else is compiling
+//! so a sync can't be stuck.
+//!
+//! The available memory is only known on Linux, taking cgroup limits into account, so it does
+//! nothing elsewhere.
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::consts::MIN_FREE_MEMORY_ENV_VAR_NAME;
+use crate::process_limits::parse_memory_size;
+
+const DEFAULT_MIN_FREE_MEMORY: u64 = 1 << 30;
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Whether new compilations are paused because of the memory
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum MemoryPressure {
+    Paused { available: u64, threshold: u64 },
+    Resumed { waited: Duration },
+}
+
+#[derive(Debug, Default)]
+struct State {
+    compiling: usize,
+    waiting: usize,
+    paused_since: Option<Instant>,
+}
+
+#[derive(Debug)]
+pub(crate) struct MemoryGuard {
+    /// 0 if disabled
+    min_available: u64,
+    available_memory: fn() -> Option<u64>,
+    state: Mutex<State>,
+}
+
+impl MemoryGuard {
+    pub(crate) fn from_env() -> Self {
+        let min_available = match std::env::var(MIN_FREE_MEMORY_ENV_VAR_NAME) {
+            Ok(v) if v.trim() == "0" => 0,
+            Ok(v) => parse_memory_size(v.trim()).unwrap_or_else(|| {
+                log::warn!("Ignoring invalid value `{v}` for {MIN_FREE_MEMORY_ENV_VAR_NAME}");
+                DEFAULT_MIN_FREE_MEMORY
+            }),
+            Err(_) => DEFAULT_MIN_FREE_MEMORY,
+        };
+        Self::new(min_available, available_memory)
+    }
+
+    fn new(min_available: u64, available_memory: fn() -> Option<u64>) -> Self {
+        Self {
+            min_available,
+            available_memory,
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    /// Blocks until a compilation can start, calling `on_change` when compilations get paused and
+    /// resumed. The compilation is considered finished when the guard is dropped.
+    pub(crate) fn start_compilation(
+        &self,
+        on_change: impl Fn(MemoryPressure),
+    ) -> CompilationGuard<'_> {
+        let mut is_waiting = false;
+        loop {
+            let mut state = self.state.lock().unwrap();
+            let low_memory = if self.min_available == 0 || state.compiling == 0 {
+                None
+            } else {
+                (self.available_memory)().filter(|a| *a < self.min_available)
+            };
+
+            match low_memory {
+                Some(available) => {
+                    if !is_waiting {
+                        is_waiting = true;
+                        state.waiting += 1;
+                        if state.paused_since.is_none() {
+                            state.paused_since = Some(Instant::now());
+                            on_change(MemoryPressure::Paused {
+                                available,
+                                threshold: self.min_available,
+                            });
+                        }
+                    }
+                }
+                None => {
+                    if is_waiting {
+                        state.waiting -= 1;
+                        if state.waiting == 0
+                            && let Some(since) = state.paused_since.take()
+                        {
+                            on_change(MemoryPressure::Resumed {
+                                waited: since.elapsed(),
+                            });
+                        }
+                    }
+                    state.compiling += 1;
+                    return CompilationGuard { guard: self };
+                }
+            }
+            drop(state);
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct CompilationGuard<'a> {
+    guard: &'a MemoryGuard,
+}
+
+impl Drop for CompilationGuard<'_> {
+    fn drop(&mut self) {
+        self.guard.state.lock().unwrap().compiling -= 1;
+    }
+}
+
+/// The memory that can be used without swapping, in bytes
+#[cfg(target_os = "linux")]
+fn available_memory() -> Option<u64> {
+    let meminfo = std::fs::read_to_string("/proc/meminfo").ok()?;
+    let system = parse_meminfo_available(&meminfo)?;
+    match cgroup_available_memory() {
+        Some(cgroup) => Some(system.min(cgroup)),
+        None => Some(system),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn available_memory() -> Option<u64> {
+    None
+}
+
+/// Containers are killed when reaching their cgroup limit, whatever the memory of the host
+#[cfg(target_os = "linux")]
+fn cgroup_available_memory() -> Option<u64> {
+    let read = |name: &str| std::fs::read_to_string(format!("/sys/fs/cgroup/{name}")).ok();
+    parse_cgroup_available(
+        &read("memory.max")?,
+        &read("memory.current")?,
+        &read("memory.stat").unwrap_or_default(),
+    )
+}
+
+/// The usage of a cgroup includes its page cache, which is reclaimed before reaching the limit.
+/// Like `docker stats`, the inactive part of it isn't counted as used.
+#[cfg(any(target_os = "linux", test))]
+fn parse_cgroup_available(max: &str, current: &str, stat: &str) -> Option<u64> {
+    // `max` if there is no limit
+    let max = max.trim().parse::<u64>().ok()?;
+    let current = current.trim().parse::<u64>().ok()?;
+    let inactive_file = stat
+        .lines()
+        .find_map(|l| l.strip_prefix("inactive_file "))
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .unwrap_or(0);
+    Some(max.saturating_sub(current.saturating_sub(inactive_file)))
+}
+
+#[cfg(any(target_os = "linux", test))]
+fn parse_meminfo_available(meminfo: &str) -> Option<u64> {
+    let line = meminfo.lines().find(|l| l.starts_with("MemAvailable:"))?;
+    let kb = line
+        .trim_start_matches("MemAvailable:")
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse::<u64>()
+        .ok()?;
+    Some(kb * 1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    static AVAILABLE: AtomicU64 = AtomicU64::new(0);
+
+    fn fake_available_memory() -> Option<u64> {
+        Some(AVAILABLE.load(Ordering::Relaxed))
+    }
+
+    #[test]
+    fn can_parse_meminfo() {
+        let meminfo = "MemTotal:       16310588 kB\nMemFree:         1021880 kB\nMemAvailable:    8123456 kB\n";
+        assert_eq!(parse_meminfo_available(meminfo), Some(8123456 * 1024));
+        assert_eq!(parse_meminfo_available("MemTotal: 1 kB\n"), None);
+    }
+
+    #[test]
+    fn can_parse_cgroup_available_memory() {
+        let stat = "anon 1000\nfile 3000\nactive_file 1000\ninactive_file 2000\n";
+        assert_eq!(parse_cgroup_available("8000\n", "5000\n", stat), Some(5000));
+        assert_eq!(parse_cgroup_available("8000\n", "5000\n", ""), Some(3000));
+        assert_eq!(parse_cgroup_available("max\n", "5000\n", stat), None);
+    }
+
+    #[test]
+    fn waits_for_memory_only_if_something_is_compiling() {
+        AVAILABLE.store(100, Ordering::Relaxed);
+        let guard = MemoryGuard::new(1000, fake_available_memory);
+        let changes = Mutex::new(Vec::new());
+        let on_change = |c| changes.lock().unwrap().push(c);
+
+        // Nothing is compiling so it starts even with low memory
+        let first = guard.start_compilation(on_change);
+        assert!(changes.lock().unwrap().is_empty());
+
+        std::thread::scope(|s| {
+            let second = s.spawn(|| {
+                let _second = guard.start_compilation(on_change);
+            });
+            // The second compilation waits until the first is done
+            while changes.lock().unwrap().is_empty() {
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            drop(first);
+            second.join().unwrap();
+        });
+
+        let changes = changes.into_inner().unwrap();
+        assert_eq!(
+            changes[0],
+            MemoryPressure::Paused {
+                available: 100,
+                threshold: 1000
+            }
+        );
+        assert!(matches!(changes[1], MemoryPressure::Resumed { .. }));
+        assert_eq!(guard.state.lock().unwrap().compiling, 0);
+    }
+}
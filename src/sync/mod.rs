@@ -1,8 +1,15 @@
+mod build_info;
 mod build_plan;
+mod bus;
 mod changes;
 mod errors;
 mod handler;
+mod in_use;
 mod link;
+mod memory;
+mod remote_build;
+mod report;
+mod signature;
 mod sources;
 mod tasks;
 
@@ -10,5 +17,8 @@ pub use build_plan::{BuildPlan, BuildStep};
 #[cfg(feature = "cli")]
 pub use changes::OutputSection;
 pub use changes::SyncChange;
-pub use handler::SyncHandler;
+#[cfg(feature = "tokio")]
+pub(crate) use errors::SyncError;
+pub use handler::{FailureAction, SyncHandler};
 pub use link::{LinkError, LinkMode};
+pub use report::{PackageOutcome, PackageReport, SyncReport};
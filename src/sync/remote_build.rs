@@ -0,0 +1,307 @@
+//! Experimental: offloads the compilation of source packages to a remote builder, eg a CI farm
+//! machine, so that heavy packages like arrow never get compiled on laptops.
+//!
+//! The builder is set with `RV_REMOTE_BUILDER` and is either:
+//! - `http(s)://host/prefix`: rv sends `GET <prefix>/build?<query>`
+//! - `ssh://[user@]host[:port]`: rv runs `rv-remote-build '<query>'` on the host
+//!
+//! The query contains `package`, `version`, `repository` and `platform`, the latter being the
+//! `<R major.minor>/<arch>/<distribution>` folder the cache uses for binaries, and the answer
+//! (HTTP body or stdout) must be a tar.gz of the built package folder. It ends up in the local
+//! cache exactly like a binary downloaded from a repository.
+//! If anything goes wrong, the package is compiled locally as usual.
+use std::io::{self, Cursor, Read};
+use std::path::Path;
+use std::process::Command;
+use std::time::Duration;
+
+use fs_err as fs;
+use url::Url;
+
+use crate::consts::{INSECURE_TLS_ENV_VAR_NAME, REMOTE_BUILDER_ENV_VAR_NAME};
+use crate::fs::untar_archive;
+use crate::http::build_agent_with_timeout;
+use crate::is_binary_package;
+use crate::utils::is_env_var_truthy;
+
+/// Compiling a big package can take a while, we don't want the default download timeout
+const REMOTE_BUILD_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+const SSH_COMMAND: &str = "rv-remote-build";
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum RemoteBuildError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Http(#[from] Box<ureq::Error>),
+    #[error("`ssh` exited with {status}: {stderr}")]
+    Ssh { status: String, stderr: String },
+    #[error("the builder did not return a built package")]
+    NotBinary,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum RemoteBuilder {
+    Http(Url),
+    Ssh {
+        destination: String,
+        port: Option<u16>,
+    },
+}
+
+/// What we ask the builder to build
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct BuildRequest<'a> {
+    pub package: &'a str,
+    pub version: &'a str,
+    pub repository: &'a str,
+    pub platform: String,
+}
+
+impl BuildRequest<'_> {
+    fn query(&self) -> String {
+        url::form_urlencoded::Serializer::new(String::new())
+            .append_pair("package", self.package)
+            .append_pair("version", self.version)
+            .append_pair("repository", self.repository)
+            .append_pair("platform", &self.platform)
+            .finish()
+    }
+}
+
+impl RemoteBuilder {
+    /// Returns the builder set in the environment, if any. An invalid value is ignored with
+    /// a warning.
+    pub(crate) fn from_env() -> Option<Self> {
+        let value = std::env::var(REMOTE_BUILDER_ENV_VAR_NAME).ok()?;
+        match Self::parse(&value) {
+            Some(builder) => Some(builder),
+            None => {
+                log::warn!(
+                    "Ignoring {REMOTE_BUILDER_ENV_VAR_NAME}={value}: expected a http(s):// or ssh:// URL"
+                );
+                None
+            }
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        let url = Url::parse(value.trim()).ok()?;
+        match url.scheme() {
+            "http" | "https" => Some(Self::Http(url)),
+            "ssh" => {
+                let host = url.host_str()?;
+                let destination = if url.username().is_empty() {
+                    host.to_string()
+                } else {
+                    format!("{}@{host}", url.username())
+                };
+                Some(Self::Ssh {
+                    destination,
+                    port: url.port(),
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Builds the package remotely and extracts it in `destination`, which will then contain
+    /// a folder named after the package
+    pub(crate) fn build(
+        &self,
+        request: &BuildRequest,
+        destination: &Path,
+    ) -> Result<(), RemoteBuildError> {
+        let tarball = match self {
+            Self::Http(base) => self.build_http(base, request)?,
+            Self::Ssh { destination, port } => self.build_ssh(destination, *port, request)?,
+        };
+
+        untar_archive(Cursor::new(tarball), destination, false)?;
+        let pkg_path = destination.join(request.package);
+        if !is_binary_package(&pkg_path, request.package).unwrap_or(false) {
+            if pkg_path.is_dir() {
+                fs::remove_dir_all(&pkg_path)?;
+            }
+            return Err(RemoteBuildError::NotBinary);
+        }
+        Ok(())
+    }
+
+    fn build_http(&self, base: &Url, request: &BuildRequest) -> Result<Vec<u8>, RemoteBuildError> {
+        let mut url = base.clone();
+        if !url.path().ends_with('/') {
+            url.set_path(&format!("{}/", url.path()));
+        }
+        let mut url = url.join("build").expect("valid url");
+        url.set_query(Some(&request.query()));
+
+        let agent = build_agent_with_timeout(
+            is_env_var_truthy(INSECURE_TLS_ENV_VAR_NAME),
+            REMOTE_BUILD_TIMEOUT,
+        );
+        let mut res = agent.get(url.as_str()).call().map_err(Box::new)?;
+        let mut out = Vec::new();
+        res.body_mut()
+            .with_config()
+            .reader()
+            .read_to_end(&mut out)?;
+        Ok(out)
+    }
+
+    fn build_ssh(
+        &self,
+        destination: &str,
+        port: Option<u16>,
+        request: &BuildRequest,
+    ) -> Result<Vec<u8>, RemoteBuildError> {
+        let mut command = Command::new("ssh");
+        // Never prompt, we are not in an interactive context
+        command.args(["-o", "BatchMode=yes"]);
+        if let Some(port) = port {
+            command.arg("-p").arg(port.to_string());
+        }
+        // The query only contains url-encoded characters so it is safe to quote that way
+        command
+            .arg(destination)
+            .arg(SSH_COMMAND)
+            .arg(format!("'{}'", request.query()));
+
+        let output = command.output()?;
+        if !output.status.success() {
+            return Err(RemoteBuildError::Ssh {
+                status: output.status.to_string(),
+                stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            });
+        }
+        Ok(output.stdout)
+    }
+}
+
+impl std::fmt::Display for RemoteBuilder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Http(url) => write!(f, "{url}"),
+            Self::Ssh { destination, .. } => write!(f, "ssh://{destination}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+
+    fn request() -> BuildRequest<'static> {
+        BuildRequest {
+            package: "testpkg",
+            version: "1.0.0",
+            repository: "https://cran.r-project.org/",
+            platform: "4.4/x86_64/noble".to_string(),
+        }
+    }
+
+    fn package_tarball(binary: bool) -> Vec<u8> {
+        let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+        let mut files = vec![(
+            "testpkg/DESCRIPTION",
+            if binary {
+                "Package: testpkg\nVersion: 1.0.0\nBuilt: R 4.4.1; x86_64-pc-linux-gnu; 2025-01-01 00:00:00 UTC; unix\n"
+            } else {
+                "Package: testpkg\nVersion: 1.0.0\n"
+            },
+        )];
+        if binary {
+            files.push(("testpkg/Meta/package.rds", "rds"));
+        }
+        for (path, content) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, path, content.as_bytes())
+                .unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    #[test]
+    fn can_parse_builders() {
+        assert_eq!(
+            RemoteBuilder::parse("https://builder.example.com/rv"),
+            Some(RemoteBuilder::Http(
+                Url::parse("https://builder.example.com/rv").unwrap()
+            ))
+        );
+        assert_eq!(
+            RemoteBuilder::parse("ssh://ci@farm.example.com:2222"),
+            Some(RemoteBuilder::Ssh {
+                destination: "ci@farm.example.com".to_string(),
+                port: Some(2222)
+            })
+        );
+        assert_eq!(
+            RemoteBuilder::parse("ssh://farm"),
+            Some(RemoteBuilder::Ssh {
+                destination: "farm".to_string(),
+                port: None
+            })
+        );
+        assert_eq!(RemoteBuilder::parse("ftp://farm"), None);
+        assert_eq!(RemoteBuilder::parse("farm"), None);
+    }
+
+    #[test]
+    fn query_is_url_encoded() {
+        assert_eq!(
+            request().query(),
+            "package=testpkg&version=1.0.0&repository=https%3A%2F%2Fcran.r-project.org%2F&platform=4.4%2Fx86_64%2Fnoble"
+        );
+    }
+
+    #[test]
+    fn can_build_over_http() {
+        let mut server = mockito::Server::new();
+        let mock = server
+            .mock("GET", "/rv/build")
+            .match_query(mockito::Matcher::UrlEncoded(
+                "package".into(),
+                "testpkg".into(),
+            ))
+            .with_status(200)
+            .with_body(package_tarball(true))
+            .create();
+        let builder = RemoteBuilder::parse(&format!("{}/rv", server.url())).unwrap();
+        let destination = tempfile::tempdir().unwrap();
+
+        builder.build(&request(), destination.path()).unwrap();
+        mock.assert();
+        assert!(
+            destination
+                .path()
+                .join("testpkg")
+                .join("Meta")
+                .join("package.rds")
+                .exists()
+        );
+    }
+
+    #[test]
+    fn rejects_packages_that_are_not_built() {
+        let mut server = mockito::Server::new();
+        server
+            .mock("GET", "/build")
+            .match_query(mockito::Matcher::Any)
+            .with_status(200)
+            .with_body(package_tarball(false))
+            .create();
+        let builder = RemoteBuilder::parse(&server.url()).unwrap();
+        let destination = tempfile::tempdir().unwrap();
+
+        let err = builder.build(&request(), destination.path()).unwrap_err();
+        assert!(matches!(err, RemoteBuildError::NotBinary));
+        assert!(!destination.path().join("testpkg").exists());
+    }
+}
@@ -0,0 +1,276 @@
+//! A record of what a sync did, written to `rv/last-sync.json` so CI can archive it and other
+//! tools can read it.
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use fs_err as fs;
+use serde::{Deserialize, Serialize};
+
+use crate::DiskCache;
+use crate::lockfile::Source;
+use crate::package::PackageType;
+use crate::sync::changes::SyncChange;
+use crate::sync::errors::{SyncError, SyncErrorKind};
+use crate::system_req::SysDep;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PackageOutcome {
+    Installed,
+    Removed,
+    Failed,
+    /// Failed during an interactive sync and left out of the library on request
+    Skipped,
+}
+
+impl std::fmt::Display for PackageOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Installed => write!(f, "installed"),
+            Self::Removed => write!(f, "removed"),
+            Self::Failed => write!(f, "failed"),
+            Self::Skipped => write!(f, "skipped"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PackageReport {
+    pub name: String,
+    pub outcome: PackageOutcome,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<Source>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kind: Option<PackageType>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u64>,
+    /// Whether the package was found in the local or global cache rather than downloaded
+    pub cache_hit: bool,
+    /// The build log in the cache, if there is one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub log_path: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sys_deps: Vec<SysDep>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl PackageReport {
+    fn from_change(change: &SyncChange, cache: &DiskCache) -> Self {
+        let log_path = if change.installed && !change.is_builtin() && !change.is_provided() {
+            Some(change.log_path(cache)).filter(|p| p.exists())
+        } else {
+            None
+        };
+
+        Self {
+            name: change.name.clone(),
+            outcome: if change.installed {
+                PackageOutcome::Installed
+            } else {
+                PackageOutcome::Removed
+            },
+            version: change.version.clone(),
+            source: change.source.clone(),
+            kind: change.kind,
+            duration_ms: change.timing.map(|t| t.as_millis() as u64),
+            cache_hit: change.cache_source.is_some(),
+            log_path,
+            sys_deps: change.sys_deps.clone(),
+            error: None,
+        }
+    }
+
+    fn failed(name: &str, error: &SyncError) -> Self {
+        Self::not_installed(name, PackageOutcome::Failed, error.to_string())
+    }
+
+    fn not_installed(name: &str, outcome: PackageOutcome, error: String) -> Self {
+        Self {
+            name: name.to_string(),
+            outcome,
+            version: None,
+            source: None,
+            kind: None,
+            duration_ms: None,
+            cache_hit: false,
+            log_path: None,
+            sys_deps: Vec::new(),
+            error: Some(error),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SyncReport {
+    pub rv_version: String,
+    pub r_version: String,
+    /// When the sync finished, as a RFC 3339 timestamp
+    pub finished_at: String,
+    /// Time taken by the whole sync, resolution included
+    pub duration_ms: u64,
+    pub success: bool,
+    /// Whether this comes from `rv sync --dry-run`, in which case nothing was installed
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub simulated: bool,
+    /// Set when the sync failed for a reason not tied to a specific package
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub packages: Vec<PackageReport>,
+}
+
+impl SyncReport {
+    pub fn new(r_version: &str, finished_at: String, duration: Duration) -> Self {
+        Self {
+            rv_version: env!("CARGO_PKG_VERSION").to_string(),
+            r_version: r_version.to_string(),
+            finished_at,
+            duration_ms: duration.as_millis() as u64,
+            success: true,
+            simulated: false,
+            error: None,
+            packages: Vec::new(),
+        }
+    }
+
+    pub fn add_changes(&mut self, changes: &[SyncChange], cache: &DiskCache) {
+        self.packages.extend(
+            changes
+                .iter()
+                .map(|change| PackageReport::from_change(change, cache)),
+        );
+    }
+
+    /// Packages skipped are not in the library so the sync is not considered successful
+    pub fn add_skipped(&mut self, skipped: &[(String, String)]) {
+        if skipped.is_empty() {
+            return;
+        }
+        self.success = false;
+        self.packages.extend(skipped.iter().map(|(name, reason)| {
+            PackageReport::not_installed(name, PackageOutcome::Skipped, reason.clone())
+        }));
+    }
+
+    /// Marks the sync as failed, with one entry per package that failed to install if the
+    /// error is about installations
+    pub fn set_error(&mut self, error: &SyncError) {
+        self.success = false;
+        match &error.source {
+            SyncErrorKind::SyncFailed(errors) => self.packages.extend(
+                errors
+                    .errors
+                    .iter()
+                    .map(|(name, e)| PackageReport::failed(name, e)),
+            ),
+            _ => self.error = Some(error.to_string()),
+        }
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), std::io::Error> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let content = serde_json::to_string_pretty(self).expect("valid json");
+        fs::write(path, content)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, std::io::Error> {
+        let content = fs::read_to_string(path.as_ref())?;
+        serde_json::from_str(&content).map_err(std::io::Error::other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sync::changes::CacheSource;
+    use crate::sync::errors::SyncErrors;
+
+    #[test]
+    fn can_roundtrip_sync_report() {
+        let cache_dir = tempfile::tempdir().unwrap();
+        let cache = DiskCache::new_in_dir(
+            &"4.4.1".parse().unwrap(),
+            crate::SystemInfo::new(crate::OsType::Linux("ubuntu"), None, None, "24.04"),
+            cache_dir.path(),
+        )
+        .unwrap();
+        let installed = SyncChange::installed(
+            "rlang",
+            "1.1.4",
+            Source::Repository {
+                repository: "https://cran.r-project.org".parse().unwrap(),
+            },
+            PackageType::Binary,
+            Duration::from_millis(120),
+            vec!["libxml2".to_string()],
+            Some(CacheSource::Global),
+            false,
+        );
+        let changes = vec![installed, SyncChange::removed("cli")];
+        let mut report = SyncReport::new(
+            "4.4",
+            "2025-01-01T00:00:00Z".to_string(),
+            Duration::from_secs(2),
+        );
+        report.add_changes(&changes, &cache);
+        report.set_error(&SyncError {
+            source: SyncErrorKind::SyncFailed(SyncErrors {
+                errors: vec![(
+                    "xml2".to_string(),
+                    SyncError {
+                        source: SyncErrorKind::InvalidPackage {
+                            path: PathBuf::from("xml2"),
+                            error: "no DESCRIPTION".to_string(),
+                        },
+                    },
+                )],
+            }),
+        });
+
+        let outcomes: Vec<_> = report
+            .packages
+            .iter()
+            .map(|p| (p.name.as_str(), p.outcome, p.cache_hit))
+            .collect();
+        assert_eq!(
+            outcomes,
+            vec![
+                ("rlang", PackageOutcome::Installed, true),
+                ("cli", PackageOutcome::Removed, false),
+                ("xml2", PackageOutcome::Failed, false),
+            ]
+        );
+        assert!(!report.success);
+        assert_eq!(report.packages[0].duration_ms, Some(120));
+        assert_eq!(report.packages[0].sys_deps[0].name, "libxml2");
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("rv").join("last-sync.json");
+        report.save(&path).unwrap();
+        assert_eq!(SyncReport::load(&path).unwrap(), report);
+    }
+
+    #[test]
+    fn skipped_packages_fail_the_report() {
+        let mut report = SyncReport::new(
+            "4.4",
+            "2025-01-01T00:00:00Z".to_string(),
+            Duration::from_secs(2),
+        );
+        report.add_skipped(&[]);
+        assert!(report.success);
+
+        report.add_skipped(&[("xml2".to_string(), "Simulated failure".to_string())]);
+        assert!(!report.success);
+        assert_eq!(report.packages[0].outcome, PackageOutcome::Skipped);
+        assert_eq!(
+            report.packages[0].error.as_deref(),
+            Some("Simulated failure")
+        );
+    }
+}
@@ -0,0 +1,123 @@
+//! Optional signing of the binary packages rv adds to the cache, so a compromised shared cache
+//! host can't silently inject modified binaries into every project using it.
+//! When `RV_CACHE_SIGNING_KEY` is set, rv writes a HMAC-SHA256 of the files of each binary it
+//! builds or downloads into the cache and checks it before linking a binary already present in
+//! the cache, eg from the global cache filled by another machine.
+//! A signature that doesn't match is an error, and so is a missing one unless
+//! `RV_CACHE_ALLOW_UNSIGNED` is set to migrate a cache filled before the key was set.
+use std::path::Path;
+
+use fs_err as fs;
+
+use crate::consts::{
+    CACHE_ALLOW_UNSIGNED_ENV_VAR_NAME, CACHE_SIGNING_KEY_ENV_VAR_NAME, SIGNATURE_FILENAME,
+};
+use crate::utils::is_env_var_truthy;
+use crate::validation::{hash_package_files, hmac_sha256};
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ArtifactSigning {
+    key: Vec<u8>,
+    allow_unsigned: bool,
+}
+
+impl ArtifactSigning {
+    /// Returns the signing settings from the environment, if a key is set
+    pub(crate) fn from_env() -> Option<Self> {
+        let key = std::env::var(CACHE_SIGNING_KEY_ENV_VAR_NAME).ok()?;
+        if key.is_empty() {
+            return None;
+        }
+        Some(Self {
+            key: key.into_bytes(),
+            allow_unsigned: is_env_var_truthy(CACHE_ALLOW_UNSIGNED_ENV_VAR_NAME),
+        })
+    }
+
+    fn signature(&self, package_dir: &Path) -> Result<String, SignatureError> {
+        let hash = hash_package_files(package_dir, &[SIGNATURE_FILENAME])
+            .ok_or_else(|| SignatureError::Unreadable(package_dir.display().to_string()))?;
+        Ok(hex::encode(hmac_sha256(&self.key, hash.as_bytes())))
+    }
+
+    /// Writes the signature of the package folder in it. Needs to be called once every file of
+    /// the package is in the cache.
+    pub(crate) fn sign(&self, package_dir: &Path) -> Result<(), SignatureError> {
+        let signature = self.signature(package_dir)?;
+        fs::write(package_dir.join(SIGNATURE_FILENAME), signature)?;
+        Ok(())
+    }
+
+    /// Checks that the package folder matches its signature
+    pub(crate) fn verify(&self, package_dir: &Path) -> Result<(), SignatureError> {
+        let name = package_dir.display().to_string();
+        let Ok(recorded) = fs::read_to_string(package_dir.join(SIGNATURE_FILENAME)) else {
+            if !self.allow_unsigned {
+                return Err(SignatureError::Missing(name));
+            }
+            log::warn!("The binary in {name} is not signed, its content can't be verified");
+            return Ok(());
+        };
+        if recorded.trim() != self.signature(package_dir)? {
+            return Err(SignatureError::Mismatch(name));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SignatureError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error("Failed to read the files of the binary in {0}")]
+    Unreadable(String),
+    #[error(
+        "The binary in {0} is not signed. Remove it from the cache to rebuild it, or set {CACHE_ALLOW_UNSIGNED_ENV_VAR_NAME} to accept unsigned binaries while migrating the cache"
+    )]
+    Missing(String),
+    #[error(
+        "The binary in {0} doesn't match its signature: it was modified after being added to the cache or signed with another key"
+    )]
+    Mismatch(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signing(key: &str, allow_unsigned: bool) -> ArtifactSigning {
+        ArtifactSigning {
+            key: key.as_bytes().to_vec(),
+            allow_unsigned,
+        }
+    }
+
+    #[test]
+    fn detects_modified_binaries() {
+        let dir = tempfile::tempdir().unwrap();
+        let pkg = dir.path().join("pkg");
+        fs::create_dir_all(pkg.join("libs")).unwrap();
+        fs::write(pkg.join("DESCRIPTION"), "Package: pkg").unwrap();
+        fs::write(pkg.join("libs").join("pkg.so"), "binary").unwrap();
+
+        let signing = signing("secret", false);
+        assert!(matches!(
+            signing.verify(&pkg),
+            Err(SignatureError::Missing(_))
+        ));
+        assert!(self::signing("secret", true).verify(&pkg).is_ok());
+
+        signing.sign(&pkg).unwrap();
+        assert!(signing.verify(&pkg).is_ok());
+        assert!(matches!(
+            self::signing("other", false).verify(&pkg),
+            Err(SignatureError::Mismatch(_))
+        ));
+
+        fs::write(pkg.join("libs").join("pkg.so"), "injected").unwrap();
+        assert!(matches!(
+            signing.verify(&pkg),
+            Err(SignatureError::Mismatch(_))
+        ));
+    }
+}
@@ -10,6 +10,7 @@ use crate::git::{GitReference, GitRemote};
 use crate::library::LocalMetadata;
 use crate::lockfile::Source;
 use crate::sync::LinkMode;
+use crate::sync::build_info::save_build_info;
 use crate::sync::errors::SyncError;
 use crate::{Cancellation, CommandExecutor, RCmd, ResolvedDependency};
 
@@ -24,7 +25,8 @@ pub(crate) fn install_package(
     strip: bool,
     cancellation: Arc<Cancellation>,
 ) -> Result<(), SyncError> {
-    let (local_paths, global_paths) = cache.get_package_paths(&pkg.source, None, None);
+    let (local_paths, global_paths) =
+        cache.get_package_paths(&pkg.source, Some(&pkg.name), None, pkg.kind);
 
     // We will have the source version since we needed to clone it to get the DESCRIPTION file
     if !pkg.cache_status.binary_available() {
@@ -69,8 +71,17 @@ pub(crate) fn install_package(
         let log_path = cache.local().get_build_log_path(&pkg.source, None, None);
         if let Some(parent) = log_path.parent() {
             fs::create_dir_all(parent)?;
-            let mut f = fs::File::create(log_path)?;
+            let mut f = fs::File::create(&log_path)?;
             f.write_all(output.as_bytes())?;
+            if let Err(e) = save_build_info(
+                &log_path,
+                r_cmd,
+                cache.system_info(),
+                &pkg.env_vars,
+                configure_args,
+            ) {
+                log::warn!("Could not save the build info of {}: {e}", pkg.name);
+            }
         }
 
         let metadata = LocalMetadata::Sha(sha.to_owned());
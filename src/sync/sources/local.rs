@@ -8,6 +8,7 @@ use crate::fs::{mtime_recursive, untar_archive};
 use crate::library::LocalMetadata;
 use crate::lockfile::Source;
 use crate::sync::LinkMode;
+use crate::sync::build_info::save_build_info;
 use crate::sync::errors::SyncError;
 use crate::{Cancellation, DiskCache, RCmd, ResolvedDependency, is_binary_package};
 
@@ -101,8 +102,17 @@ pub(crate) fn install_package(
         let log_path = cache.get_build_log_path(&pkg.source, None, None);
         if let Some(parent) = log_path.parent() {
             fs::create_dir_all(parent)?;
-            let mut f = fs::File::create(log_path)?;
+            let mut f = fs::File::create(&log_path)?;
             f.write_all(output.as_bytes())?;
+            if let Err(e) = save_build_info(
+                &log_path,
+                r_cmd,
+                &cache.system_info,
+                &pkg.env_vars,
+                configure_args,
+            ) {
+                log::warn!("Could not save the build info of {}: {e}", pkg.name);
+            }
         }
     } else {
         // Tarball source package: install directly from extracted path
@@ -123,8 +133,17 @@ pub(crate) fn install_package(
         let log_path = cache.get_build_log_path(&pkg.source, None, None);
         if let Some(parent) = log_path.parent() {
             fs::create_dir_all(parent)?;
-            let mut f = fs::File::create(log_path)?;
+            let mut f = fs::File::create(&log_path)?;
             f.write_all(output.as_bytes())?;
+            if let Err(e) = save_build_info(
+                &log_path,
+                r_cmd,
+                &cache.system_info,
+                &pkg.env_vars,
+                configure_args,
+            ) {
+                log::warn!("Could not save the build info of {}: {e}", pkg.name);
+            }
         }
     }
 
@@ -6,14 +6,18 @@ use std::path::Path;
 use std::sync::Arc;
 use url::Url;
 
+use crate::cache::utils::get_current_system_path;
 use crate::cache::{Cache, InstallationStatus};
 use crate::consts::BUILT_FROM_SOURCE_FILENAME;
 use crate::events;
 use crate::http::Http;
+use crate::lockfile::Source;
 use crate::package::PackageType;
 use crate::repository_urls::TarballUrls;
 use crate::sync::LinkMode;
+use crate::sync::build_info::save_build_info;
 use crate::sync::errors::{SyncError, SyncErrorKind};
+use crate::sync::remote_build::{BuildRequest, RemoteBuilder};
 use crate::{
     Cancellation, HttpDownload, PackagePaths, RCmd, ResolvedDependency, get_tarball_urls,
     is_binary_package,
@@ -28,10 +32,17 @@ pub(crate) fn install_package(
     strip: bool,
     cancellation: Arc<Cancellation>,
 ) -> Result<(), SyncError> {
-    let (local_paths, global_paths) =
-        cache.get_package_paths(&pkg.source, Some(&pkg.name), Some(&pkg.version.original));
+    let (local_paths, global_paths) = cache.get_package_paths(
+        &pkg.source,
+        Some(&pkg.name),
+        Some(&pkg.version.original),
+        pkg.kind,
+    );
 
     let compile_package = || -> Result<(), SyncError> {
+        if build_remotely(pkg, cache, configure_args, &local_paths) {
+            return Ok(());
+        }
         let source_path = local_paths.source.join(pkg.name.as_ref());
         log::debug!("Compiling package from {}", source_path.display());
         match events::with_task(crate::sync::tasks::compile_task(&pkg.name), || {
@@ -55,8 +66,17 @@ pub(crate) fn install_package(
                 );
                 if let Some(parent) = log_path.parent() {
                     fs::create_dir_all(parent)?;
-                    let mut f = fs::File::create(log_path)?;
+                    let mut f = fs::File::create(&log_path)?;
                     f.write_all(output.as_bytes())?;
+                    if let Err(e) = save_build_info(
+                        &log_path,
+                        r_cmd,
+                        cache.system_info(),
+                        &pkg.env_vars,
+                        configure_args,
+                    ) {
+                        log::warn!("Could not save the build info of {}: {e}", pkg.name);
+                    }
                 }
                 // Create the marker file for local compilation
                 let _ = fs::File::create(
@@ -124,6 +144,68 @@ pub(crate) fn install_package(
     Ok(())
 }
 
+/// Tries to get the package compiled by the remote builder, if one is set.
+/// Packages with custom configure args or env vars are always compiled locally since the
+/// builder would not know about them.
+fn build_remotely(
+    pkg: &ResolvedDependency,
+    cache: &Cache,
+    configure_args: &[String],
+    local_paths: &PackagePaths,
+) -> bool {
+    let Some(builder) = RemoteBuilder::from_env() else {
+        return false;
+    };
+    let Source::Repository { repository } = &pkg.source else {
+        return false;
+    };
+    if !configure_args.is_empty() || !pkg.env_vars.is_empty() {
+        log::debug!(
+            "Not using the remote builder for {}: it has custom configure args or env vars",
+            pkg.name
+        );
+        return false;
+    }
+
+    let platform = get_current_system_path(cache.system_info(), *cache.r_version())
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/");
+    let request = BuildRequest {
+        package: &pkg.name,
+        version: &pkg.version.original,
+        repository: repository.as_str(),
+        platform,
+    };
+    log::debug!("Building {} with the remote builder {builder}", pkg.name);
+    match builder.build(&request, &local_paths.binary) {
+        Ok(()) => {
+            let log_path = cache.local().get_build_log_path(
+                &pkg.source,
+                Some(pkg.name.as_ref()),
+                Some(&pkg.version.original),
+            );
+            if let Some(parent) = log_path.parent()
+                && fs::create_dir_all(parent).is_ok()
+            {
+                let _ = fs::write(
+                    &log_path,
+                    format!("Built by the remote builder {builder}\n"),
+                );
+            }
+            true
+        }
+        Err(e) => {
+            log::warn!(
+                "Remote build of {} with {builder} failed, compiling it locally: {e}",
+                pkg.name
+            );
+            false
+        }
+    }
+}
+
 fn download_package(
     http: &impl HttpDownload,
     urls: &TarballUrls,
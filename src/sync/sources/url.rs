@@ -8,6 +8,7 @@ use crate::events;
 use crate::library::LocalMetadata;
 use crate::package::PackageType;
 use crate::sync::LinkMode;
+use crate::sync::build_info::save_build_info;
 use crate::sync::errors::SyncError;
 use crate::{Cancellation, DiskCache, RCmd, ResolvedDependency};
 
@@ -20,7 +21,7 @@ pub(crate) fn install_package(
     strip: bool,
     cancellation: Arc<Cancellation>,
 ) -> Result<(), SyncError> {
-    let pkg_paths = cache.get_package_paths(&pkg.source, None, None);
+    let pkg_paths = cache.get_package_paths(&pkg.source, Some(&pkg.name), None, pkg.kind);
     let download_path = pkg_paths.source.join(pkg.name.as_ref());
 
     // If we have a binary, copy it since we don't keep cache around for binary URL packages
@@ -58,8 +59,17 @@ pub(crate) fn install_package(
         let log_path = cache.get_build_log_path(&pkg.source, None, None);
         if let Some(parent) = log_path.parent() {
             fs::create_dir_all(parent)?;
-            let mut f = fs::File::create(log_path)?;
+            let mut f = fs::File::create(&log_path)?;
             f.write_all(output.as_bytes())?;
+            if let Err(e) = save_build_info(
+                &log_path,
+                r_cmd,
+                &cache.system_info,
+                &pkg.env_vars,
+                configure_args,
+            ) {
+                log::warn!("Could not save the build info of {}: {e}", pkg.name);
+            }
         }
     }
 
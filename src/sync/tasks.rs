@@ -17,6 +17,11 @@ pub(crate) fn clone_task(name: &str) -> events::Task {
     install_task(name).child("clone", "Cloning git repository")
 }
 
+/// New compilations waiting for memory to be available, see [`crate::sync::memory`]
+pub(crate) fn memory_wait_task() -> events::Task {
+    sync_task().child("memory", "Waiting for memory")
+}
+
 /// Compiling a source package, nested under that package's install task.
 pub(crate) fn compile_task(name: &str) -> events::Task {
     install_task(name).child("compile", "Compiling")
@@ -144,6 +144,23 @@ impl SystemInfo {
         self.arch.as_deref()
     }
 
+    /// Use the architecture of the R binary rather than the OS one, they can differ on macOS
+    /// when running an x86_64 R through Rosetta on Apple Silicon.
+    /// R reports `aarch64` while macOS, CRAN and our library paths use `arm64`.
+    pub fn set_r_arch(&mut self, arch: &str) {
+        let arch = match (self.os_type, arch) {
+            (OsType::MacOs, "aarch64") => "arm64",
+            _ => arch,
+        };
+        if self.arch() != Some(arch) {
+            log::debug!(
+                "R is built for {arch}, using it instead of the system architecture {:?}",
+                self.arch
+            );
+            self.arch = Some(arch.to_string());
+        }
+    }
+
     /// Extract major version number from Version enum
     pub(crate) fn major_version(&self) -> Option<u64> {
         match &self.version {
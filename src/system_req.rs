@@ -22,7 +22,7 @@ const KNOWN_THINGS_IN_PATH: &[&str] = &[
     "google-chrome",
 ];
 
-#[derive(Serialize, Clone, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum SysInstallationStatus {
     Present,
@@ -40,7 +40,28 @@ impl fmt::Display for SysInstallationStatus {
     }
 }
 
-#[derive(Debug, Clone, Serialize)]
+/// How the installation status of a system dependency was determined
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DetectionMethod {
+    /// Queried with `dpkg-query`
+    Dpkg,
+    /// Queried with `rpm -q`
+    Rpm,
+    /// Looked up in the PATH, for tools not always installed by the package manager
+    Path,
+    /// The system is not supported or the package manager could not be queried
+    NotChecked,
+}
+
+/// The installation status of a system dependency and how it was found
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SysDepCheck {
+    pub status: SysInstallationStatus,
+    pub method: DetectionMethod,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct SysDep {
     pub name: String,
     pub status: SysInstallationStatus,
@@ -202,22 +223,37 @@ pub fn check_installation_status(
     system_info: &SystemInfo,
     sys_deps: &HashSet<&str>,
 ) -> HashMap<String, SysInstallationStatus> {
+    check_installation(system_info, sys_deps)
+        .into_iter()
+        .map(|(name, check)| (name, check.status))
+        .collect()
+}
+
+/// Same as [`check_installation_status`] but also returns how each status was determined
+pub fn check_installation(
+    system_info: &SystemInfo,
+    sys_deps: &HashSet<&str>,
+) -> HashMap<String, SysDepCheck> {
     if !is_supported(system_info) {
         return HashMap::new();
     }
 
-    let mut out = HashMap::from_iter(
-        sys_deps
-            .iter()
-            .map(|x| (x.to_string(), SysInstallationStatus::Unknown)),
-    );
+    let mut out = HashMap::from_iter(sys_deps.iter().map(|x| {
+        (
+            x.to_string(),
+            SysDepCheck {
+                status: SysInstallationStatus::Unknown,
+                method: DetectionMethod::NotChecked,
+            },
+        )
+    }));
     if sys_deps.is_empty() {
         return out;
     }
 
     log::debug!("Checking installation status for {:?}", sys_deps);
     let from_env = std::env::var(SYS_DEPS_CHECK_IN_PATH_ENV_VAR_NAME).unwrap_or_default();
-    match system_info.sysreq_data().0 {
+    let package_manager = match system_info.sysreq_data().0 {
         "ubuntu" | "debian" => {
             // Running dpkg-query -W -f='${Package}\n' {..pkg_list} and read stdout
             let command = Command::new("dpkg-query")
@@ -229,10 +265,12 @@ pub fn check_installation_status(
 
             let stdout = String::from_utf8(command.stdout).unwrap();
             for line in stdout.lines() {
-                if let Some(status) = out.get_mut(line.trim()) {
-                    *status = SysInstallationStatus::Present;
+                if let Some(check) = out.get_mut(line.trim()) {
+                    check.status = SysInstallationStatus::Present;
+                    check.method = DetectionMethod::Dpkg;
                 }
             }
+            DetectionMethod::Dpkg
         }
 
         "centos" | "redhat" | "rockylinux" | "opensuse" | "sle" => {
@@ -258,9 +296,10 @@ pub fn check_installation_status(
                 if !line.is_empty() {
                     // Extract package name (everything before first hyphen followed by a digit)
                     if let Some(pkg_name) = extract_rpm_package_name(line)
-                        && let Some(status) = out.get_mut(pkg_name)
+                        && let Some(check) = out.get_mut(pkg_name)
                     {
-                        *status = SysInstallationStatus::Present;
+                        check.status = SysInstallationStatus::Present;
+                        check.method = DetectionMethod::Rpm;
                     }
                 }
             }
@@ -271,38 +310,43 @@ pub fn check_installation_status(
                 // Format: "package NAME is not installed"
                 if line.contains("is not installed")
                     && let Some(pkg_name) = line.split_whitespace().nth(1)
-                    && let Some(status) = out.get_mut(pkg_name)
-                    && status == &SysInstallationStatus::Unknown
+                    && let Some(check) = out.get_mut(pkg_name)
+                    && check.status == SysInstallationStatus::Unknown
                 {
-                    *status = SysInstallationStatus::Absent;
+                    check.status = SysInstallationStatus::Absent;
+                    check.method = DetectionMethod::Rpm;
                 }
             }
+            DetectionMethod::Rpm
         }
 
-        _ => (),
+        _ => DetectionMethod::NotChecked,
     };
 
     let mut to_check_in_path: Vec<_> = from_env.split(",").map(|x| x.trim()).collect();
     to_check_in_path.extend_from_slice(KNOWN_THINGS_IN_PATH);
 
-    for (name, status) in out
+    for (name, check) in out
         .iter_mut()
-        .filter(|(_, v)| v == &&SysInstallationStatus::Unknown)
+        .filter(|(_, v)| v.status == SysInstallationStatus::Unknown)
     {
         if to_check_in_path.contains(&name.as_str()) {
+            check.method = DetectionMethod::Path;
             if which(name).is_ok() {
-                *status = SysInstallationStatus::Present;
+                check.status = SysInstallationStatus::Present;
             } else {
-                *status = SysInstallationStatus::Absent;
+                check.status = SysInstallationStatus::Absent;
             }
         }
     }
 
-    for (_, status) in out
+    // Not found by the package manager
+    for (_, check) in out
         .iter_mut()
-        .filter(|(_, x)| **x == SysInstallationStatus::Unknown)
+        .filter(|(_, x)| x.status == SysInstallationStatus::Unknown)
     {
-        *status = SysInstallationStatus::Absent;
+        check.status = SysInstallationStatus::Absent;
+        check.method = package_manager;
     }
 
     out
@@ -0,0 +1,178 @@
+//! Fakes and fixtures to run resolution and sync without network access or R, for the tests of
+//! rv and of crates building on it. Only available with the `test-utils` feature.
+use std::collections::HashMap;
+use std::io::Write;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use tempfile::TempDir;
+use url::Url;
+
+use crate::{
+    Cache, CommandExecutor, HttpDownload, HttpError, HttpErrorKind, RepositoryDatabase, SystemInfo,
+    Version,
+};
+
+/// What [`FakeGit`] returns for every command, which is used as the SHA of git dependencies
+pub const FAKE_GIT_SHA: &str = "somethinglikeasha";
+/// The SHA256 [`FakeHttp`] returns for extracted tarballs
+pub const FAKE_TARBALL_SHA: &str = "SOME_SHA";
+
+/// A git executor that doesn't run anything and returns [`FAKE_GIT_SHA`]. Git dependencies
+/// can be resolved with it if their DESCRIPTION file is already in the cache.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FakeGit;
+
+impl CommandExecutor for FakeGit {
+    fn execute(&self, _: &mut Command) -> Result<String, std::io::Error> {
+        Ok(FAKE_GIT_SHA.to_string())
+    }
+}
+
+/// An HTTP client serving the responses registered with [`FakeHttp::with_response`] and a 404
+/// for anything else. Tarballs are not extracted.
+#[derive(Debug, Clone, Default)]
+pub struct FakeHttp {
+    responses: HashMap<String, Vec<u8>>,
+}
+
+impl FakeHttp {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_response(mut self, url: &str, content: impl Into<Vec<u8>>) -> Self {
+        self.responses.insert(url.to_string(), content.into());
+        self
+    }
+}
+
+impl HttpDownload for FakeHttp {
+    fn download<W: Write>(
+        &self,
+        url: &Url,
+        w: &mut W,
+        _: Vec<(&str, String)>,
+    ) -> Result<u64, HttpError> {
+        let Some(content) = self.responses.get(url.as_str()) else {
+            return Err(HttpError {
+                url: url.to_string(),
+                source: HttpErrorKind::Http(404),
+            });
+        };
+        w.write_all(content)
+            .map_err(|e| HttpError::from_io(url.as_str(), e))?;
+        Ok(content.len() as u64)
+    }
+
+    fn download_and_untar(
+        &self,
+        _: &Url,
+        _: impl AsRef<Path>,
+        _: bool,
+        _: Option<&Path>,
+    ) -> Result<(Option<PathBuf>, String), HttpError> {
+        Ok((None, FAKE_TARBALL_SHA.to_string()))
+    }
+}
+
+/// A cache in a temporary directory, removed when dropped. The global cache is not used.
+#[derive(Debug)]
+pub struct TempCache {
+    cache: Cache,
+    dir: TempDir,
+}
+
+impl TempCache {
+    pub fn new(r_version: &Version, system_info: SystemInfo) -> std::io::Result<Self> {
+        let dir = tempfile::tempdir()?;
+        let cache = Cache::new_isolated(r_version, system_info, dir.path())
+            .map_err(std::io::Error::other)?;
+        Ok(Self { cache, dir })
+    }
+
+    pub fn path(&self) -> &Path {
+        self.dir.path()
+    }
+}
+
+impl Deref for TempCache {
+    type Target = Cache;
+
+    fn deref(&self) -> &Self::Target {
+        &self.cache
+    }
+}
+
+impl RepositoryDatabase {
+    /// A database with the source packages of a PACKAGES file content
+    pub fn from_packages_str(url: &str, content: &str) -> Self {
+        let mut db = Self::new(url);
+        db.parse_source(content);
+        db
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ConfigDependency, Resolver};
+
+    const PACKAGES: &str = r#"Package: cli
+Version: 3.6.3
+Depends: R (>= 3.4)
+Imports: utils
+
+Package: rlang
+Version: 1.1.4
+Imports: cli, utils
+"#;
+
+    #[test]
+    fn can_resolve_with_fakes() {
+        let r_version: Version = "4.4.1".parse().unwrap();
+        let cache =
+            TempCache::new(&r_version, SystemInfo::from_os_info()).expect("can create cache");
+        let databases = vec![(
+            RepositoryDatabase::from_packages_str("http://cran/", PACKAGES),
+            false,
+        )];
+        let builtin = HashMap::new();
+        let env_vars = HashMap::new();
+        let project_dir = tempfile::tempdir().unwrap();
+        let resolver = Resolver::new(
+            project_dir.path(),
+            &databases,
+            vec!["http://cran/"].into_iter().collect(),
+            &r_version,
+            &builtin,
+            None,
+            &env_vars,
+        );
+        let dependencies = vec![ConfigDependency::Simple("rlang".to_string())];
+        let http = FakeHttp::new();
+        let resolution = resolver.resolve(&dependencies, &[], &cache, &FakeGit, &http);
+
+        assert!(resolution.is_success());
+        let mut names: Vec<_> = resolution.found.iter().map(|d| d.name.as_ref()).collect();
+        names.sort();
+        assert_eq!(names, vec!["cli", "rlang"]);
+    }
+
+    #[test]
+    fn fake_http_serves_registered_responses() {
+        let http = FakeHttp::new().with_response("https://example.com/PACKAGES", "Package: cli");
+        let mut out = Vec::new();
+        let url = Url::parse("https://example.com/PACKAGES").unwrap();
+        assert_eq!(http.download(&url, &mut out, vec![]).unwrap(), 12);
+        assert_eq!(out, b"Package: cli");
+
+        let missing = Url::parse("https://example.com/other").unwrap();
+        assert!(
+            http.download(&missing, &mut out, vec![])
+                .unwrap_err()
+                .is_not_found()
+        );
+    }
+}
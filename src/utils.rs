@@ -1,17 +1,101 @@
+#[cfg(not(target_arch = "wasm32"))]
 use std::borrow::Cow;
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::{Condvar, Mutex};
 use std::time::Duration;
 
+#[cfg(not(target_arch = "wasm32"))]
 use indicatif::{ProgressBar, ProgressStyle};
 
-use crate::consts::NUM_CPUS_ENV_VAR_NAME;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::consts::{NUM_COMPILE_JOBS_ENV_VAR_NAME, NUM_CPUS_ENV_VAR_NAME};
 
-pub(crate) fn get_max_workers() -> usize {
-    std::env::var(NUM_CPUS_ENV_VAR_NAME)
+#[cfg(not(target_arch = "wasm32"))]
+fn read_positive_env_var(name: &str) -> Option<usize> {
+    std::env::var(name)
         .ok()
         .and_then(|x| x.parse::<usize>().ok())
-        .unwrap_or_else(num_cpus::get)
+        .filter(|x| *x > 0)
+}
+
+/// How many packages can be installed at the same time. Most installs only download and link
+/// binaries so this is mostly bound by IO.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn get_max_workers() -> usize {
+    read_positive_env_var(NUM_CPUS_ENV_VAR_NAME).unwrap_or_else(num_cpus::get)
+}
+
+/// How many packages can be compiled at the same time, which is CPU bound.
+/// Defaults to the number of cores not already busy according to the 1 minute load average,
+/// so a sync on a loaded machine doesn't make things worse.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn get_max_compile_jobs() -> usize {
+    read_positive_env_var(NUM_COMPILE_JOBS_ENV_VAR_NAME)
+        .unwrap_or_else(|| compile_jobs_for_load(num_cpus::get(), load_average()))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn compile_jobs_for_load(cores: usize, load: Option<f64>) -> usize {
+    let busy = load.map(|l| l.round().max(0.0) as usize).unwrap_or(0);
+    cores.saturating_sub(busy).max(1)
+}
+
+#[cfg(unix)]
+fn load_average() -> Option<f64> {
+    let mut loads = [0f64; 1];
+    // SAFETY: we pass a buffer of the size we give
+    let n = unsafe { libc::getloadavg(loads.as_mut_ptr(), 1) };
+    (n == 1).then_some(loads[0])
+}
+
+#[cfg(all(not(unix), not(target_arch = "wasm32")))]
+fn load_average() -> Option<f64> {
+    None
+}
+
+/// A counting semaphore, to cap how many threads do something at the same time
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug)]
+pub(crate) struct Semaphore {
+    available: Mutex<usize>,
+    released: Condvar,
 }
 
+#[cfg(not(target_arch = "wasm32"))]
+impl Semaphore {
+    pub(crate) fn new(permits: usize) -> Self {
+        Self {
+            available: Mutex::new(permits.max(1)),
+            released: Condvar::new(),
+        }
+    }
+
+    /// Blocks until a permit is available. The permit is released when the guard is dropped.
+    pub(crate) fn acquire(&self) -> SemaphoreGuard<'_> {
+        let mut available = self.available.lock().unwrap();
+        while *available == 0 {
+            available = self.released.wait(available).unwrap();
+        }
+        *available -= 1;
+        SemaphoreGuard { semaphore: self }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug)]
+pub(crate) struct SemaphoreGuard<'a> {
+    semaphore: &'a Semaphore,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Drop for SemaphoreGuard<'_> {
+    fn drop(&mut self) {
+        *self.semaphore.available.lock().unwrap() += 1;
+        self.semaphore.released.notify_one();
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
 pub(crate) fn create_spinner(visible: bool, message: impl Into<Cow<'static, str>>) -> ProgressBar {
     if visible {
         let pb = ProgressBar::new(10);
@@ -33,3 +117,93 @@ pub(crate) fn is_env_var_truthy(name: &str) -> bool {
 
     val == "true" || val == "1"
 }
+
+/// Formats a duration for display, eg `234ms`, `1.2s`, `3m 12s` or `1h 4m`
+pub fn format_duration(d: Duration) -> String {
+    let ms = d.as_millis();
+    let secs = d.as_secs();
+    if ms < 1000 {
+        format!("{ms}ms")
+    } else if secs < 60 {
+        format!("{:.1}s", ms as f64 / 1000.0)
+    } else if secs < 60 * 60 {
+        format!("{}m {}s", secs / 60, secs % 60)
+    } else {
+        format!("{}h {}m", secs / 3600, (secs % 3600) / 60)
+    }
+}
+
+/// Formats a number of bytes for display using binary units, eg `512 B` or `1.4 GiB`
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["KiB", "MiB", "GiB", "TiB", "PiB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{value:.1} {}", UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compile_jobs_leave_busy_cores_alone() {
+        assert_eq!(compile_jobs_for_load(8, None), 8);
+        assert_eq!(compile_jobs_for_load(8, Some(0.2)), 8);
+        assert_eq!(compile_jobs_for_load(8, Some(2.6)), 5);
+        assert_eq!(compile_jobs_for_load(8, Some(12.0)), 1);
+    }
+
+    #[test]
+    fn semaphore_caps_concurrency() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let semaphore = Semaphore::new(2);
+        let running = AtomicUsize::new(0);
+        let max_running = AtomicUsize::new(0);
+        std::thread::scope(|s| {
+            for _ in 0..6 {
+                s.spawn(|| {
+                    let _permit = semaphore.acquire();
+                    let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    max_running.fetch_max(now, Ordering::SeqCst);
+                    std::thread::sleep(Duration::from_millis(20));
+                    running.fetch_sub(1, Ordering::SeqCst);
+                });
+            }
+        });
+        assert_eq!(max_running.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn can_format_durations() {
+        let cases = [
+            (Duration::from_millis(234), "234ms"),
+            (Duration::from_millis(1234), "1.2s"),
+            (Duration::from_secs(192), "3m 12s"),
+            (Duration::from_secs(3840), "1h 4m"),
+        ];
+        for (duration, expected) in cases {
+            assert_eq!(format_duration(duration), expected);
+        }
+    }
+
+    #[test]
+    fn can_format_bytes() {
+        let cases = [
+            (512, "512 B"),
+            (1536, "1.5 KiB"),
+            (3 * 1024 * 1024, "3.0 MiB"),
+            (1_503_238_553, "1.4 GiB"),
+        ];
+        for (bytes, expected) in cases {
+            assert_eq!(format_bytes(bytes), expected);
+        }
+    }
+}
@@ -0,0 +1,358 @@
+//! A validation report documents the environment of a project (R, platform, packages with their
+//! source and the hash of what is installed, system dependencies) in a form that can be attached
+//! to regulatory submissions, eg for GxP validation.
+//! The report is sealed with the SHA-256 of its content and, if a key is provided, a
+//! HMAC-SHA256 so a reviewer can check it was not edited after being generated.
+use std::fmt::Write;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use walkdir::WalkDir;
+
+use crate::lockfile::Source;
+use crate::system_req::SysDep;
+use crate::{OsType, SystemInfo};
+
+const VALIDATION_FORMAT_VERSION: u32 = 1;
+const HMAC_BLOCK_SIZE: usize = 64;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ValidatedPackage {
+    pub name: String,
+    pub version: String,
+    pub source: Source,
+    /// The version found in the library, if the package is installed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub installed_version: Option<String>,
+    /// SHA-256 over the relative path and content of every file of the installed package
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_sha256: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sys_deps: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ValidationPlatform {
+    pub os: String,
+    pub os_version: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub arch: Option<String>,
+    /// `{R_Version}/{arch}/{library_identifier}`, identifying which binaries can be used
+    pub library_platform: String,
+}
+
+impl ValidationPlatform {
+    pub fn new(system_info: &SystemInfo, library_platform: String) -> Self {
+        let os = match system_info.os_type {
+            OsType::Linux(distrib) if !distrib.is_empty() => distrib.to_string(),
+            os_type => os_type.family().to_string(),
+        };
+        Self {
+            os,
+            os_version: system_info.version.to_string(),
+            arch: system_info.arch().map(|s| s.to_string()),
+            library_platform,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReportSeal {
+    /// SHA-256 of the report serialized as JSON without its seal
+    pub sha256: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hmac_sha256: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ValidationReport {
+    pub format_version: u32,
+    pub rv_version: String,
+    /// RFC 3339 timestamp
+    pub generated_at: String,
+    pub project: String,
+    /// The R version of the project
+    pub r_version: String,
+    /// The version of the R installation found, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub r_installation: Option<String>,
+    pub platform: ValidationPlatform,
+    pub config_sha256: String,
+    pub lockfile_sha256: String,
+    pub packages: Vec<ValidatedPackage>,
+    pub system_dependencies: Vec<SysDep>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seal: Option<ReportSeal>,
+}
+
+impl ValidationReport {
+    pub fn new(
+        project: &str,
+        r_version: &str,
+        generated_at: String,
+        platform: ValidationPlatform,
+        config: &[u8],
+        lockfile: &[u8],
+    ) -> Self {
+        Self {
+            format_version: VALIDATION_FORMAT_VERSION,
+            rv_version: env!("CARGO_PKG_VERSION").to_string(),
+            generated_at,
+            project: project.to_string(),
+            r_version: r_version.to_string(),
+            r_installation: None,
+            platform,
+            config_sha256: hex::encode(Sha256::digest(config)),
+            lockfile_sha256: hex::encode(Sha256::digest(lockfile)),
+            packages: Vec::new(),
+            system_dependencies: Vec::new(),
+            seal: None,
+        }
+    }
+
+    fn content(&self) -> Vec<u8> {
+        let unsealed = Self {
+            seal: None,
+            ..self.clone()
+        };
+        serde_json::to_vec(&unsealed).expect("valid json")
+    }
+
+    /// Computes the seal of the report. Needs to be called after all the fields are set.
+    pub fn seal(&mut self, key: Option<&[u8]>) {
+        let content = self.content();
+        self.seal = Some(ReportSeal {
+            sha256: hex::encode(Sha256::digest(&content)),
+            hmac_sha256: key.map(|k| hex::encode(hmac_sha256(k, &content))),
+        });
+    }
+
+    /// Whether the report matches its seal. The HMAC is only checked if a key is given.
+    pub fn verify(&self, key: Option<&[u8]>) -> bool {
+        let Some(seal) = &self.seal else {
+            return false;
+        };
+        let content = self.content();
+        if seal.sha256 != hex::encode(Sha256::digest(&content)) {
+            return false;
+        }
+        match key {
+            Some(k) => seal.hmac_sha256.as_deref() == Some(&hex::encode(hmac_sha256(k, &content))),
+            None => true,
+        }
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut out = String::new();
+        writeln!(out, "# Environment validation report: {}\n", self.project).unwrap();
+
+        writeln!(out, "## Environment\n").unwrap();
+        writeln!(out, "| Field | Value |\n| --- | --- |").unwrap();
+        let mut fields = vec![
+            ("Generated at", self.generated_at.clone()),
+            ("rv version", self.rv_version.clone()),
+            ("R version (project)", self.r_version.clone()),
+            (
+                "R version (installed)",
+                self.r_installation
+                    .clone()
+                    .unwrap_or_else(|| "not found".to_string()),
+            ),
+            (
+                "Operating system",
+                format!("{} {}", self.platform.os, self.platform.os_version),
+            ),
+        ];
+        if let Some(arch) = &self.platform.arch {
+            fields.push(("Architecture", arch.clone()));
+        }
+        fields.extend([
+            ("Library platform", self.platform.library_platform.clone()),
+            ("Config SHA-256", format!("`{}`", self.config_sha256)),
+            ("Lockfile SHA-256", format!("`{}`", self.lockfile_sha256)),
+        ]);
+        for (field, value) in fields {
+            writeln!(out, "| {field} | {value} |").unwrap();
+        }
+
+        writeln!(out, "\n## Packages ({})\n", self.packages.len()).unwrap();
+        writeln!(
+            out,
+            "| Package | Version | Installed | Source | Content SHA-256 |\n| --- | --- | --- | --- | --- |"
+        )
+        .unwrap();
+        for pkg in &self.packages {
+            writeln!(
+                out,
+                "| {} | {} | {} | {} | {} |",
+                pkg.name,
+                pkg.version,
+                pkg.installed_version.as_deref().unwrap_or("no"),
+                pkg.source.to_string().trim().replace('|', "\\|"),
+                pkg.content_sha256
+                    .as_deref()
+                    .map(|h| format!("`{h}`"))
+                    .unwrap_or_default(),
+            )
+            .unwrap();
+        }
+
+        writeln!(
+            out,
+            "\n## System dependencies ({})\n",
+            self.system_dependencies.len()
+        )
+        .unwrap();
+        if self.system_dependencies.is_empty() {
+            writeln!(out, "None detected.").unwrap();
+        } else {
+            writeln!(out, "| Name | Status |\n| --- | --- |").unwrap();
+            for sys_dep in &self.system_dependencies {
+                writeln!(out, "| {} | {:?} |", sys_dep.name, sys_dep.status).unwrap();
+            }
+        }
+
+        if let Some(seal) = &self.seal {
+            writeln!(out, "\n## Seal\n").unwrap();
+            writeln!(out, "- SHA-256: `{}`", seal.sha256).unwrap();
+            if let Some(hmac) = &seal.hmac_sha256 {
+                writeln!(out, "- HMAC-SHA256: `{hmac}`").unwrap();
+            }
+            writeln!(
+                out,
+                "\nThe seal covers the JSON version of this report, which is the reference document."
+            )
+            .unwrap();
+        }
+
+        out
+    }
+}
+
+/// Hashes the relative path and content of every file in the package folder, in a stable order.
+/// Symlinks are followed since packages are usually linked from the cache.
+pub fn hash_installed_package(path: impl AsRef<Path>) -> Option<String> {
+    hash_package_files(path.as_ref(), &[])
+}
+
+/// Same as [`hash_installed_package`], leaving out the files at the root of the package with
+/// one of the `ignored` names
+pub(crate) fn hash_package_files(path: &Path, ignored: &[&str]) -> Option<String> {
+    if !path.is_dir() {
+        return None;
+    }
+    let mut hasher = Sha256::new();
+    for entry in WalkDir::new(path)
+        .follow_links(true)
+        .sort_by_file_name()
+        .into_iter()
+    {
+        let entry = entry.ok()?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let relative = entry.path().strip_prefix(path).ok()?;
+        if relative
+            .to_str()
+            .is_some_and(|name| ignored.contains(&name))
+        {
+            continue;
+        }
+        hasher.update(relative.to_string_lossy().replace('\\', "/").as_bytes());
+        hasher.update([0]);
+        hasher.update(fs_err::read(entry.path()).ok()?);
+        hasher.update([0]);
+    }
+    Some(hex::encode(hasher.finalize()))
+}
+
+/// RFC 2104
+pub(crate) fn hmac_sha256(key: &[u8], message: &[u8]) -> Vec<u8> {
+    let mut block = [0u8; HMAC_BLOCK_SIZE];
+    if key.len() > HMAC_BLOCK_SIZE {
+        let hashed = Sha256::digest(key);
+        block[..hashed.len()].copy_from_slice(&hashed);
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+
+    let mut inner = Sha256::new();
+    inner.update(block.map(|b| b ^ 0x36));
+    inner.update(message);
+    let mut outer = Sha256::new();
+    outer.update(block.map(|b| b ^ 0x5c));
+    outer.update(inner.finalize());
+    outer.finalize().to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hmac_matches_rfc_4231() {
+        assert_eq!(
+            hex::encode(hmac_sha256(b"Jefe", b"what do ya want for nothing?")),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn can_seal_and_verify_report() {
+        let system_info = SystemInfo::new(
+            OsType::Linux("ubuntu"),
+            Some("x86_64".to_string()),
+            Some("noble".to_string()),
+            "24.04",
+        );
+        let mut report = ValidationReport::new(
+            "study",
+            "4.4",
+            "2025-01-01T00:00:00Z".to_string(),
+            ValidationPlatform::new(&system_info, "4.4/x86_64/noble".to_string()),
+            b"config",
+            b"lockfile",
+        );
+        // Keep the snapshot stable across releases
+        report.rv_version = "0.0.0".to_string();
+        report.packages.push(ValidatedPackage {
+            name: "rlang".to_string(),
+            version: "1.1.4".to_string(),
+            source: Source::Repository {
+                repository: "https://cran.r-project.org".parse().unwrap(),
+            },
+            installed_version: Some("1.1.4".to_string()),
+            content_sha256: Some("abc".to_string()),
+            sys_deps: Vec::new(),
+        });
+        report.seal(Some(b"secret"));
+
+        let loaded: ValidationReport =
+            serde_json::from_str(&serde_json::to_string(&report).unwrap()).unwrap();
+        assert!(loaded.verify(None));
+        assert!(loaded.verify(Some(b"secret")));
+        assert!(!loaded.verify(Some(b"other")));
+
+        let mut edited = loaded.clone();
+        edited.packages[0].version = "1.1.5".to_string();
+        assert!(!edited.verify(None));
+
+        insta::assert_snapshot!(report.to_markdown());
+    }
+
+    #[test]
+    fn package_hash_depends_on_content() {
+        let dir = tempfile::tempdir().unwrap();
+        let pkg = dir.path().join("rlang");
+        fs_err::create_dir_all(pkg.join("R")).unwrap();
+        fs_err::write(pkg.join("DESCRIPTION"), "Package: rlang").unwrap();
+        fs_err::write(pkg.join("R").join("rlang"), "code").unwrap();
+        let before = hash_installed_package(&pkg).unwrap();
+        assert_eq!(hash_installed_package(&pkg).unwrap(), before);
+
+        fs_err::write(pkg.join("R").join("rlang"), "other code").unwrap();
+        assert_ne!(hash_installed_package(&pkg).unwrap(), before);
+        assert!(hash_installed_package(dir.path().join("missing")).is_none());
+    }
+}
@@ -0,0 +1,133 @@
+//! Checks that the library paths set by the activation .Rprofile work when R is started from
+//! cmd and PowerShell, including from network shares.
+#![cfg(windows)]
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use assert_cmd::cargo;
+use tempfile::TempDir;
+
+fn r_version() -> String {
+    let output = Command::new("Rscript")
+        .args(["-e", r#"cat(R.version$major, R.version$minor, sep = ".")"#])
+        .output()
+        .expect("Rscript to be installed");
+    let version = String::from_utf8_lossy(&output.stdout).to_string();
+    // Only keep major.minor
+    version.splitn(3, '.').take(2).collect::<Vec<_>>().join(".")
+}
+
+fn create_activated_project(cache: &Path) -> TempDir {
+    let temp = TempDir::new().unwrap();
+    let config = temp.path().join("rproject.toml");
+    std::fs::write(
+        &config,
+        format!(
+            r#"[project]
+name = "test-activate"
+r_version = "{}"
+repositories = []
+dependencies = []
+"#,
+            r_version()
+        ),
+    )
+    .unwrap();
+    let mut cmd = cargo::cargo_bin_cmd!();
+    cmd.env("RV_CACHE_DIR", cache);
+    cmd.args(["--config-file", config.to_str().unwrap(), "activate"]);
+    cmd.assert().success();
+    temp
+}
+
+/// PATH with the folder of the rv binary being tested first so the .Rprofile finds it
+fn path_with_rv() -> String {
+    let rv = PathBuf::from(env!("CARGO_BIN_EXE_rv"));
+    format!(
+        "{};{}",
+        rv.parent().unwrap().display(),
+        std::env::var("PATH").unwrap_or_default()
+    )
+}
+
+const PRINT_LIB_PATHS: &str = "cat(.libPaths()[1])";
+
+fn first_lib_path(mut cmd: Command, cache: &Path) -> String {
+    let output = cmd
+        .env("PATH", path_with_rv())
+        .env("RV_CACHE_DIR", cache)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "R failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8_lossy(&output.stdout).trim().to_string()
+}
+
+fn cmd_rscript(dir: &str) -> Command {
+    let mut cmd = Command::new("cmd");
+    // pushd maps network shares to a drive letter since cmd can't use them as working directory
+    cmd.args([
+        "/C",
+        &format!(r#"pushd "{dir}" && Rscript -e "{PRINT_LIB_PATHS}""#),
+    ]);
+    cmd
+}
+
+fn powershell_rscript(dir: &str) -> Command {
+    let mut cmd = Command::new("powershell");
+    cmd.args([
+        "-NoProfile",
+        "-Command",
+        &format!(r#"Set-Location -LiteralPath '{dir}'; Rscript -e '{PRINT_LIB_PATHS}'"#),
+    ]);
+    cmd
+}
+
+fn assert_rv_library(lib_path: &str) {
+    assert!(!lib_path.contains('\\'), "{lib_path}");
+    assert!(lib_path.contains("/rv/library/"), "{lib_path}");
+}
+
+#[test]
+fn activated_library_from_cmd_and_powershell() {
+    let cache = TempDir::new().unwrap();
+    let project = create_activated_project(cache.path());
+    let dir = project.path().to_str().unwrap();
+
+    let from_cmd = first_lib_path(cmd_rscript(dir), cache.path());
+    let from_powershell = first_lib_path(powershell_rscript(dir), cache.path());
+    assert_rv_library(&from_cmd);
+    assert_eq!(from_cmd.to_lowercase(), from_powershell.to_lowercase());
+}
+
+#[test]
+fn activated_library_from_network_share() {
+    let cache = TempDir::new().unwrap();
+    let project = create_activated_project(cache.path());
+    // Use the administrative share of the drive to access the project as a UNC path
+    let local = project.path().to_str().unwrap();
+    let (drive, rest) = local.split_once(":\\").unwrap();
+    let unc = format!(r"\\localhost\{drive}$\{rest}");
+    if !Path::new(&unc).exists() {
+        eprintln!("Administrative shares are not available, skipping");
+        return;
+    }
+
+    // cmd maps the share to a drive letter
+    let from_cmd = first_lib_path(cmd_rscript(&unc), cache.path());
+    assert_rv_library(&from_cmd);
+    assert!(!from_cmd.starts_with("//"), "{from_cmd}");
+
+    // PowerShell keeps the UNC path
+    let from_powershell = first_lib_path(powershell_rscript(&unc), cache.path());
+    assert_rv_library(&from_powershell);
+    assert!(
+        from_powershell
+            .to_lowercase()
+            .starts_with(&format!("//localhost/{}$/", drive.to_lowercase())),
+        "{from_powershell}"
+    );
+}
@@ -0,0 +1,73 @@
+#![cfg(feature = "test-utils")]
+
+mod common;
+
+const REPO_URL: &str = "https://archived.test/repo";
+
+// `a` has a newer version in the repository and `b` was removed from it
+const LOCKFILE: &str = r#"version = 2
+r_version = "4.5"
+repositories = ["https://archived.test/repo"]
+
+[[packages]]
+name = "a"
+version = "1.0.0"
+source = { repository = "https://archived.test/repo" }
+force_source = false
+dependencies = ["b"]
+
+[[packages]]
+name = "b"
+version = "2.0.0"
+source = { repository = "https://archived.test/repo" }
+force_source = false
+dependencies = []
+"#;
+
+#[test]
+fn plan_warns_about_archived_packages() {
+    let project = common::project_with_repo(
+        REPO_URL,
+        "Package: a\nVersion: 1.1.0\nDepends: b\nNeedsCompilation: no\n",
+        &["a"],
+    );
+    project.write("rv.lock", LOCKFILE);
+
+    let plan = |extra_args: &[&str]| {
+        let output = project
+            .rv()
+            .args(["plan", "--no-cache"])
+            .args(extra_args)
+            .output()
+            .unwrap();
+        (
+            output.status.success(),
+            String::from_utf8(output.stderr).unwrap(),
+        )
+    };
+
+    let (success, stderr) = plan(&[]);
+    assert!(success, "stderr:\n{stderr}");
+    assert!(
+        stderr.contains(&format!(
+            "WARNING: a 1.0.0 is archived on {REPO_URL}, it will be installed from {REPO_URL}/src/contrib/Archive/a/a_1.0.0.tar.gz. Version 1.1.0 is available, run `rv upgrade` to use it\n"
+        )),
+        "stderr:\n{stderr}"
+    );
+    assert!(
+        stderr.contains(&format!(
+            "WARNING: b 2.0.0 is archived on {REPO_URL}, it will be installed from {REPO_URL}/src/contrib/Archive/b/b_2.0.0.tar.gz. The package is not available there anymore: consider replacing it or pinning it with {{ name = \"b\", url = \"{REPO_URL}/src/contrib/Archive/b/b_2.0.0.tar.gz\" }}\n"
+        )),
+        "stderr:\n{stderr}"
+    );
+
+    // Without the lockfile, b can't be found but we can tell why
+    let (success, stderr) = plan(&["--upgrade"]);
+    assert!(!success);
+    assert!(
+        stderr.contains(&format!(
+            "b [required by: a]: not found in the repositories, it was probably archived. The locked version 2.0.0 is available at {REPO_URL}/src/contrib/Archive/b/b_2.0.0.tar.gz\n"
+        )),
+        "stderr:\n{stderr}"
+    );
+}
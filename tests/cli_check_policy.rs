@@ -0,0 +1,48 @@
+#![cfg(feature = "test-utils")]
+
+use std::path::PathBuf;
+
+mod common;
+
+const CONFIG: &str = r#"[project]
+name = "check-policy"
+r_version = "4.5"
+repositories = []
+dependencies = ["mitpkg", "agplpkg"]
+allowed_licenses = ["MIT", "GPL (>= 2)"]
+"#;
+
+#[test]
+fn check_policy_reports_licenses_not_allowed() {
+    let project = common::TestProject::new(CONFIG);
+    let output = project.rv().arg("library").output().unwrap();
+    let library = PathBuf::from(String::from_utf8(output.stdout).unwrap().trim());
+    for (name, license) in [("mitpkg", "MIT + file LICENSE"), ("agplpkg", "AGPL-3")] {
+        std::fs::create_dir_all(library.join(name)).unwrap();
+        std::fs::write(
+            library.join(name).join("DESCRIPTION"),
+            format!("Package: {name}\nVersion: 1.0.0\nLicense: {license}\n"),
+        )
+        .unwrap();
+    }
+
+    let sarif = project.path().join("policy.sarif");
+    let output = project
+        .rv()
+        .arg("check-policy")
+        .arg("--sarif")
+        .arg(&sarif)
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "agplpkg 1.0.0: license `AGPL-3` is not allowed\n"
+    );
+
+    let sarif: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(sarif).unwrap()).unwrap();
+    let results = sarif["runs"][0]["results"].as_array().unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0]["ruleId"], "license-not-allowed");
+}
@@ -0,0 +1,58 @@
+#![cfg(feature = "test-utils")]
+
+mod common;
+
+const REPO_URL: &str = "https://explain-source.test/repo";
+
+#[test]
+fn explain_source_shows_config_lockfile_and_roots() {
+    let project = common::TestProject::new(&format!(
+        r#"[project]
+name = "explain-source"
+r_version = "4.5"
+repositories = [{{ alias = "test", url = "{REPO_URL}" }}]
+dependencies = [
+    "a",
+    {{ name = "b", repository = "test" }}, # pinned
+]
+"#
+    ));
+    project.add_repository(
+        REPO_URL,
+        "Package: a\nVersion: 1.0.0\nImports: c\nNeedsCompilation: no\n\n\
+         Package: b\nVersion: 2.0.0\nImports: c\nNeedsCompilation: no\n\n\
+         Package: c\nVersion: 3.0.0\nNeedsCompilation: no\n",
+    );
+
+    let explain = |package: &str| {
+        project
+            .rv()
+            .args(["--json", "explain-source", package])
+            .output()
+            .unwrap()
+    };
+
+    let output = explain("b");
+    assert!(output.status.success());
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(
+        json["config_entry"],
+        r#"{ name = "b", repository = "test" }"#
+    );
+    assert_eq!(json["required_by"], serde_json::json!(["b"]));
+    assert_eq!(json["resolved"]["version"], "2.0.0");
+    assert_eq!(
+        json["resolved"]["url"],
+        format!("{REPO_URL}/src/contrib/b_2.0.0.tar.gz")
+    );
+    assert!(json.get("lockfile_entry").is_none());
+    assert!(json.get("installed").is_none());
+
+    let output = explain("c");
+    assert!(output.status.success());
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert!(json.get("config_entry").is_none());
+    assert_eq!(json["required_by"], serde_json::json!(["a", "b"]));
+
+    assert!(!explain("unknown").status.success());
+}
@@ -37,6 +37,7 @@ dependencies = []
 fn rv_cmd(cache: &Path, config: &Path) -> assert_cmd::Command {
     let mut cmd = cargo::cargo_bin_cmd!();
     cmd.env("RV_CACHE_DIR", cache);
+    cmd.env_remove("RENV_PATHS_LIBRARY");
     cmd.args(["--config-file", config.to_str().unwrap()]);
     cmd
 }
@@ -169,3 +170,31 @@ fn test_info_library_respects_env_var() {
         "rv info --library should respect RV_LIBRARY_DIR during activation"
     );
 }
+
+/// renv's library root is honored, keeping rv's platform folders in it, unless rv's own
+/// override is set
+#[test]
+fn test_renv_paths_library_is_used_as_root() {
+    let cache = TempDir::new().unwrap();
+    let (temp_dir, config_path) = create_test_project(None);
+    let project_dir = normalize_path(&temp_dir.path().to_string_lossy());
+
+    let mut cmd = rv_cmd(cache.path(), &config_path);
+    cmd.env_remove("RV_LIBRARY_DIR");
+    cmd.env("RENV_PATHS_LIBRARY", "renv/library");
+    cmd.arg("library");
+    let output = cmd.output().unwrap();
+    let library_path = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    assert!(
+        library_path.starts_with(&format!("{project_dir}/renv/library/4.5")),
+        "library should be in the renv library root, got: {library_path}"
+    );
+
+    let mut cmd = rv_cmd(cache.path(), &config_path);
+    cmd.env("RV_LIBRARY_DIR", "custom");
+    cmd.env("RENV_PATHS_LIBRARY", "renv/library");
+    cmd.arg("library");
+    let output = cmd.output().unwrap();
+    let library_path = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    assert_eq!(library_path, format!("{project_dir}/custom"));
+}
@@ -0,0 +1,67 @@
+#![cfg(feature = "test-utils")]
+
+use std::fs;
+
+mod common;
+
+use common::TestProject;
+
+const REPO_URL: &str = "https://plan-fingerprint.test/repo";
+
+const LOCKFILE: &str = r#"version = 2
+r_version = "4.5"
+repositories = ["https://plan-fingerprint.test/repo"]
+
+[[packages]]
+name = "b"
+version = "2.0.0"
+source = { repository = "https://plan-fingerprint.test/repo" }
+force_source = false
+dependencies = []
+
+[[packages]]
+name = "a"
+version = "1.0.0"
+source = { repository = "https://plan-fingerprint.test/repo" }
+force_source = false
+dependencies = ["b"]
+dependency_types = { b = "depends" }
+"#;
+
+fn fingerprint(project: &TestProject, extra_args: &[&str]) -> (bool, String) {
+    let output = project
+        .rv()
+        .args(["plan", "--fingerprint"])
+        .args(extra_args)
+        .output()
+        .unwrap();
+    (
+        output.status.success(),
+        String::from_utf8(output.stdout).unwrap().trim().to_string(),
+    )
+}
+
+#[test]
+fn plan_fingerprint_is_the_same_from_lockfile_or_resolution() {
+    let project = TestProject::new(&common::config(REPO_URL, &["a"]));
+
+    // No lockfile and no database: it needs to resolve but can't
+    let (success, _) = fingerprint(&project, &["--locked"]);
+    assert!(!success);
+
+    let db_path = project.add_repository(
+        REPO_URL,
+        "Package: a\nVersion: 1.0.0\nDepends: b\nNeedsCompilation: no\n\nPackage: b\nVersion: 2.0.0\nNeedsCompilation: no\n",
+    );
+
+    let (success, resolved) = fingerprint(&project, &[]);
+    assert!(success);
+    assert_eq!(resolved.len(), 64, "{resolved}");
+
+    // The lockfile is used as is, without needing the database
+    project.write("rv.lock", LOCKFILE);
+    fs::remove_file(&db_path).unwrap();
+    let (success, locked) = fingerprint(&project, &["--locked"]);
+    assert!(success);
+    assert_eq!(locked, resolved);
+}
@@ -0,0 +1,71 @@
+#![cfg(feature = "test-utils")]
+
+mod common;
+
+use common::TestProject;
+
+const OLD_URL: &str = "https://repo-diff.test/2024-01-01";
+const NEW_URL: &str = "https://repo-diff.test/2024-06-01";
+
+fn project() -> TestProject {
+    let project = TestProject::new(&format!(
+        r#"use_lockfile = false
+
+[project]
+name = "repo-diff"
+r_version = "4.5"
+repositories = [
+  {{ alias = "current", url = "{OLD_URL}" }}
+]
+dependencies = ["a", "b"]
+"#
+    ));
+    project.add_repository(
+        OLD_URL,
+        "Package: a\nVersion: 1.0.0\n\nPackage: b\nVersion: 1.0.0\n\nPackage: other\nVersion: 1.0.0\n",
+    );
+    project.add_repository(
+        NEW_URL,
+        "Package: a\nVersion: 1.1.0\n\nPackage: other\nVersion: 2.0.0\n",
+    );
+    project
+}
+
+fn repo_diff(project: &TestProject, extra_args: &[&str]) -> String {
+    let output = project
+        .rv()
+        .args(["repo", "diff", "current", NEW_URL])
+        .args(extra_args)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "stderr:\n{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn repo_diff_only_shows_project_packages() {
+    let stdout = repo_diff(&project(), &[]);
+    assert_eq!(
+        stdout,
+        "- b 1.0.0\n~ a 1.0.0 -> 1.1.0\n\n0 added, 1 removed, 1 upgraded, 0 downgraded\n"
+    );
+}
+
+#[test]
+fn repo_diff_can_show_all_packages() {
+    let stdout = repo_diff(&project(), &["--all", "--json"]);
+    let diff: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    assert_eq!(diff["old_url"], OLD_URL);
+    assert_eq!(diff["new_url"], NEW_URL);
+    let upgraded: Vec<_> = diff["upgraded"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|c| c["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(upgraded, vec!["a", "other"]);
+}
@@ -0,0 +1,33 @@
+#![cfg(feature = "test-utils")]
+
+mod common;
+
+const REPO_URL: &str = "https://resolve-verbose.test/repo";
+
+#[test]
+fn resolve_verbose_prints_each_package() {
+    let project = common::project_with_repo(
+        REPO_URL,
+        "Package: a\nVersion: 1.0.0\nDepends: b\nNeedsCompilation: no\n\nPackage: b\nVersion: 2.0.0\nNeedsCompilation: no\n",
+        &["a", "missing"],
+    );
+
+    let output = project
+        .rv()
+        .args(["--resolve-verbose", "plan"])
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains(&format!("Resolved a 1.0.0 from {REPO_URL}\n")),
+        "stderr:\n{stderr}"
+    );
+    assert!(
+        stderr.contains(&format!("Resolved b 2.0.0 from {REPO_URL}\n")),
+        "stderr:\n{stderr}"
+    );
+    assert!(
+        stderr.contains("Failed to resolve missing [listed in rproject.toml]\n"),
+        "stderr:\n{stderr}"
+    );
+}
@@ -0,0 +1,109 @@
+#![cfg(feature = "test-utils")]
+
+use rv::SyncReport;
+
+mod common;
+
+use common::TestProject;
+
+fn chain_project() -> TestProject {
+    let repo_url = "https://simulate.test/repo";
+    let project = TestProject::new(&format!(
+        r#"use_lockfile = false
+
+[project]
+name = "simulate"
+r_version = "4.5"
+repositories = [
+  {{ alias = "local", url = "{repo_url}" }}
+]
+dependencies = ["a"]
+"#
+    ));
+    // a -> b -> c
+    project.add_repository(
+        repo_url,
+        r#"Package: a
+Version: 1.0.0
+Depends: R (>= 4.1), b
+NeedsCompilation: no
+License: MIT + file LICENSE
+
+Package: b
+Version: 1.0.0
+Depends: R (>= 4.1), c
+NeedsCompilation: no
+License: MIT + file LICENSE
+
+Package: c
+Version: 1.0.0
+Depends: R (>= 4.1)
+NeedsCompilation: no
+License: MIT + file LICENSE
+"#,
+    );
+    project
+}
+
+fn simulate(project: &TestProject, extra_args: &[&str]) -> std::process::Output {
+    project
+        .rv()
+        .args(["sync", "--dry-run"])
+        .args(extra_args)
+        .output()
+        .unwrap()
+}
+
+fn load_report(project: &TestProject) -> SyncReport {
+    SyncReport::load(project.path().join("rv").join("last-sync-simulated.json")).unwrap()
+}
+
+#[test]
+fn dry_run_sync_installs_nothing() {
+    let project = chain_project();
+
+    let output = simulate(&project, &[]);
+    assert!(
+        output.status.success(),
+        "stderr:\n{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let report = load_report(&project);
+    assert!(report.success);
+    assert!(report.simulated);
+    let mut names: Vec<_> = report.packages.iter().map(|p| p.name.as_str()).collect();
+    names.sort();
+    assert_eq!(names, vec!["a", "b", "c"]);
+    // The report of the last real sync is kept
+    assert!(!project.path().join("rv").join("last-sync.json").exists());
+
+    // Nothing was installed
+    let library = walkdir::WalkDir::new(project.path().join("rv").join("library"))
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .any(|e| e.file_name() == "c");
+    assert!(!library);
+}
+
+#[test]
+fn dry_run_sync_fails_simulated_packages() {
+    let project = chain_project();
+
+    let output = simulate(&project, &["--simulate-failures", "b"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Failed to install b"), "stderr:\n{stderr}");
+    assert!(stderr.contains("Simulated failure"), "stderr:\n{stderr}");
+
+    // Dependents of the failed package are never started
+    let report = load_report(&project);
+    assert!(!report.success);
+    assert!(report.simulated);
+    assert_eq!(report.packages.len(), 1);
+    assert_eq!(report.packages[0].name, "b");
+    assert_eq!(
+        report.packages[0].error.as_deref(),
+        Some("Simulated failure")
+    );
+}
@@ -0,0 +1,64 @@
+#![cfg(all(feature = "cli", feature = "test-utils"))]
+
+use std::sync::Mutex;
+
+use rv::cli::{Context, RCommandLookup, SyncStamp};
+
+mod common;
+
+use common::TestProject;
+
+const LOCKFILE: &str = r#"version = 2
+r_version = "4.5"
+repositories = []
+packages = []
+"#;
+
+/// The stamp is written using the process environment, shared by the tests
+static ENV: Mutex<()> = Mutex::new(());
+
+/// A project stamped as if it was synced against `first.test`, R isn't needed for that
+fn stamped_project() -> TestProject {
+    let project = TestProject::new(
+        r#"[project]
+name = "sync-stamp"
+r_version = "4.5"
+repositories = [{ alias = "ppm", url = "https://${RV_TEST_PPM_HOST}/cran" }]
+dependencies = []
+"#,
+    );
+    project.write("rv.lock", LOCKFILE);
+
+    let _guard = ENV.lock().unwrap();
+    unsafe {
+        std::env::set_var("RV_CACHE_DIR", project.cache_dir());
+        std::env::set_var("RV_TEST_PPM_HOST", "first.test");
+    }
+    let context = Context::new(&project.config_path(), RCommandLookup::Skip).unwrap();
+    SyncStamp::save(&context, &project.config_path());
+    project
+}
+
+fn sync(project: &TestProject, host: &str, args: &[&str]) -> String {
+    let output = project
+        .rv()
+        .env("RV_TEST_PPM_HOST", host)
+        .args(args)
+        .arg("sync")
+        .output()
+        .unwrap();
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn sync_stamp_follows_expanded_repository_urls() {
+    let project = stamped_project();
+    assert_eq!(sync(&project, "first.test", &[]), "Nothing to do\n");
+    assert!(!sync(&project, "second.test", &[]).contains("Nothing to do"));
+}
+
+#[test]
+fn sync_stamp_is_ignored_with_resolve_verbose() {
+    let project = stamped_project();
+    assert!(!sync(&project, "first.test", &["--resolve-verbose"]).contains("Nothing to do"));
+}
@@ -0,0 +1,48 @@
+#![cfg(feature = "test-utils")]
+
+mod common;
+
+const REPO_URL: &str = "https://tree-edge-types.test/repo";
+
+#[test]
+fn tree_shows_and_filters_edge_types() {
+    let project = common::project_with_repo(
+        REPO_URL,
+        "Package: a\nVersion: 1.0.0\nDepends: b\nImports: c\nLinkingTo: d\nNeedsCompilation: yes\n\n\
+         Package: b\nVersion: 1.0.0\nNeedsCompilation: no\n\n\
+         Package: c\nVersion: 1.0.0\nNeedsCompilation: no\n\n\
+         Package: d\nVersion: 1.0.0\nNeedsCompilation: no\n",
+        &["a"],
+    );
+
+    let tree = |extra_args: &[&str]| {
+        let output = project
+            .rv()
+            .args(["tree", "--hide-system-deps"])
+            .args(extra_args)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        // Only keep the names and edge types
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .lines()
+            .map(|l| l.split(" [").next().unwrap().to_string())
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(tree(&[]), vec!["▶ a", "├─ b", "├─ c", "└─ d"]);
+    assert_eq!(
+        tree(&["--edge-types"]),
+        vec![
+            "▶ a",
+            "├─ b (depends)",
+            "├─ c (imports)",
+            "└─ d (linking_to)"
+        ]
+    );
+    assert_eq!(
+        tree(&["--only-edge-types", "linking_to,depends"]),
+        vec!["▶ a", "├─ b (depends)", "└─ d (linking_to)"]
+    );
+}
@@ -0,0 +1,91 @@
+//! A project using fake repositories whose databases are already in a temporary cache, so the
+//! CLI can resolve it without network access. Each test crate only uses some of it.
+//! Test crates using it need the `test-utils` feature.
+#![allow(dead_code)]
+
+use std::path::{Path, PathBuf};
+
+use assert_cmd::cargo;
+use rv::test_utils::TempCache;
+use rv::{RepositoryDatabase, SystemInfo, Version};
+use tempfile::TempDir;
+
+pub const R_VERSION: &str = "4.5";
+
+pub struct TestProject {
+    dir: TempDir,
+    cache: TempCache,
+}
+
+impl TestProject {
+    /// A project with the given `rproject.toml` content and an empty cache
+    pub fn new(config: &str) -> Self {
+        let dir = TempDir::new().unwrap();
+        let r_version: Version = R_VERSION.parse().unwrap();
+        let cache = TempCache::new(&r_version, SystemInfo::from_os_info()).unwrap();
+        let project = Self { dir, cache };
+        project.write("rproject.toml", config);
+        project
+    }
+
+    pub fn path(&self) -> &Path {
+        self.dir.path()
+    }
+
+    pub fn config_path(&self) -> PathBuf {
+        self.path().join("rproject.toml")
+    }
+
+    pub fn cache_dir(&self) -> &Path {
+        self.cache.path()
+    }
+
+    pub fn write(&self, name: &str, content: &str) {
+        std::fs::write(self.path().join(name), content).unwrap();
+    }
+
+    /// Puts the database of a repository with the content of its PACKAGES file in the cache and
+    /// returns its path
+    pub fn add_repository(&self, url: &str, packages: &str) -> PathBuf {
+        let (db_path, _) = self.cache.local().get_package_db_entry(url);
+        RepositoryDatabase::from_packages_str(url, packages)
+            .persist(&db_path)
+            .unwrap();
+        db_path
+    }
+
+    /// rv running on that project with its cache
+    pub fn rv(&self) -> assert_cmd::Command {
+        let mut cmd = cargo::cargo_bin_cmd!();
+        cmd.env("RV_CACHE_DIR", self.cache.path())
+            .arg("--config-file")
+            .arg(self.config_path());
+        cmd
+    }
+}
+
+/// The config of a project depending on `dependencies` from a single repository with the alias
+/// `test`
+pub fn config(url: &str, dependencies: &[&str]) -> String {
+    let dependencies = dependencies
+        .iter()
+        .map(|d| format!("\"{d}\""))
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!(
+        r#"[project]
+name = "test"
+r_version = "{R_VERSION}"
+repositories = [{{ alias = "test", url = "{url}" }}]
+dependencies = [{dependencies}]
+"#
+    )
+}
+
+/// A project depending on `dependencies` from a repository at `url` with the packages of the
+/// given PACKAGES file content
+pub fn project_with_repo(url: &str, packages: &str, dependencies: &[&str]) -> TestProject {
+    let project = TestProject::new(&config(url, dependencies));
+    project.add_repository(url, packages);
+    project
+}